@@ -0,0 +1,80 @@
+//! Benchmarks for delivering updates through a `DistributingAccumulator`.
+//!
+//! To compare against another revision, run
+//! `cargo bench --bench accumulator -- --save-baseline before` on it
+//! and `cargo bench --bench accumulator -- --baseline before` on this
+//! one.
+
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BatchSize;
+use criterion::Criterion;
+
+use differential_datalog::program::Update;
+
+use distributed_datalog::Accumulator;
+use distributed_datalog::DistributingAccumulator;
+use distributed_datalog::Observable;
+use distributed_datalog::Observer;
+
+/// The number of updates fed through the accumulator per iteration.
+const UPDATES: usize = 1_000_000;
+
+/// An observer that merely counts the updates it receives.
+#[derive(Debug, Default)]
+struct CountingObserver {
+    count: usize,
+}
+
+impl Observer<Update<usize>, ()> for CountingObserver {
+    fn on_start(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn on_commit(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<usize>> + 'a>,
+    ) -> Result<(), ()> {
+        self.count += updates.count();
+        Ok(())
+    }
+
+    fn on_completed(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+}
+
+/// Create an accumulator with the given number of observers.
+fn accumulator(observers: usize) -> DistributingAccumulator<Update<usize>, usize, ()> {
+    let mut accumulator = DistributingAccumulator::new();
+    for _ in 0..observers {
+        let _ = accumulator.subscribe(Box::new(CountingObserver::default()));
+    }
+    accumulator
+}
+
+/// Feed a single transaction of `UPDATES` insertions into the accumulator.
+fn feed(mut accumulator: DistributingAccumulator<Update<usize>, usize, ()>) {
+    let updates = (0..UPDATES).map(|v| Update::Insert { relid: 1, v });
+    let _ = accumulator.on_start();
+    let _ = accumulator.on_updates(Box::new(updates));
+    let _ = accumulator.on_commit();
+}
+
+fn fan_out(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fan_out");
+    let _ = group.sample_size(10);
+    for observers in &[1, 2] {
+        let _ = group.bench_function(format!("{}_observers", observers), |b| {
+            b.iter_batched(|| accumulator(*observers), feed, BatchSize::LargeInput)
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, fan_out);
+criterion_main!(benches);
@@ -0,0 +1,28 @@
+use std::error::Error;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+
+use differential_datalog::program::RelId;
+
+/// The error reported for the deletion of a value that is not part of
+/// the accumulated state, if such deletions are rejected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AbsentValueError {
+    /// The relation the value was deleted from.
+    pub relid: RelId,
+    /// The debug representation of the deleted value or key.
+    pub value: String,
+}
+
+impl Display for AbsentValueError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "value {} is not present in relation {}",
+            self.value, self.relid
+        )
+    }
+}
+
+impl Error for AbsentValueError {}
@@ -12,25 +12,79 @@
 //! The TxnDistributor is the inverse of the `TxnMux` class, it listens to a single observable and
 //! is able to send data to multiple observers.
 
+use log::trace;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fmt::Debug;
+use std::fmt::Display;
+use std::fmt::Write as _;
 use std::hash::Hash;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Result as IoResult;
+use std::io::Write;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Mutex;
-
-use log::trace;
+use std::sync::TryLockError;
+use std::time::Duration;
+use std::time::Instant;
 use uid::Id;
 
 use differential_datalog::program::RelId;
 use differential_datalog::program::Update;
 
+use crate::CallbackObserver;
 use crate::Observer;
 use crate::ObserverBox;
+use crate::SharedObserver;
 use crate::{Observable, UpdatesObservable};
 
+use crate::accumulate::ack::AckSignal;
+use crate::accumulate::delta::DeltaObserver;
+use crate::accumulate::history::History;
+use crate::accumulate::min_batch::MinBatchObserver;
+use crate::accumulate::modify::ModifyingObserver;
+use crate::accumulate::retry::RetryingObserver;
+use crate::accumulate::sampling::SamplingObserver;
+use crate::accumulate::snapshot::diff_states;
+use crate::accumulate::snapshot::diff_weighted_states;
+use crate::accumulate::stream::StreamObserver;
+use crate::accumulate::txndistributor::delivering;
+use crate::accumulate::txndistributor::is_delivering;
 use crate::accumulate::AccumulatingObserver;
+use crate::accumulate::AccumulatorBuilder;
+use crate::accumulate::AccumulatorError;
+use crate::accumulate::AccumulatorSnapshot;
+use crate::accumulate::AckHandle;
+use crate::accumulate::ApproxSize;
+use crate::accumulate::ConcurrentInput;
+use crate::accumulate::DuplicatePolicy;
+use crate::accumulate::FilteringObserver;
+use crate::accumulate::HeartbeatTimer;
+use crate::accumulate::LatchObservable;
+use crate::accumulate::ProtocolViolation;
+use crate::accumulate::RelStats;
+use crate::accumulate::ReplayingObservable;
+use crate::accumulate::RetryPolicy;
+use crate::accumulate::SnapshotCodec;
+use crate::accumulate::SnapshotTimer;
+use crate::accumulate::StateHandle;
+use crate::accumulate::StreamConfig;
+use crate::accumulate::SubStats;
 use crate::accumulate::TxnDistributor;
+use crate::accumulate::WouldBlock;
+
+/// A mapping from the IDs of relations to their names, used to present
+/// relations in a human readable form, e.g., when dumping the state of
+/// a `DistributingAccumulator`.
+pub type RelationRegistry = HashMap<RelId, String>;
 
 /// A trait object that acts as a proxy between an observable and observer.
 /// It accumulates the updates to maintain the current state of the data.
@@ -47,6 +101,71 @@ where
 
     /// Return the current state of the data.
     fn get_current_state(&self) -> HashMap<RelId, HashSet<V>>;
+
+    /// Return the current state of the data along with the net
+    /// multiplicity of each value. Values with a net weight of zero
+    /// are not included.
+    ///
+    /// The default implementation assigns a weight of one to every
+    /// value of the current state.
+    fn get_current_state_weighted(&self) -> HashMap<RelId, HashMap<V, isize>> {
+        self.get_current_state()
+            .into_iter()
+            .filter(|(_, vs)| !vs.is_empty())
+            .map(|(relid, vs)| (relid, vs.into_iter().map(|v| (v, 1)).collect()))
+            .collect()
+    }
+
+    /// Check whether the given value is part of the current state of a
+    /// relation, without copying the state.
+    fn contains(&self, relid: RelId, value: &V) -> bool;
+
+    /// Return the net multiplicity of the values in the current state
+    /// of a relation, without copying the state.
+    fn relation_size(&self, relid: RelId) -> usize;
+
+    /// Invoke the given function on every value of the current state,
+    /// along with its relation, without copying the state, e.g., for
+    /// streaming the state to an exporter. Values are visited in an
+    /// arbitrary order.
+    ///
+    /// The state is locked while `f` runs, so updates committed
+    /// concurrently are blocked until all values were visited and `f`
+    /// must not access the accumulator itself.
+    fn for_each_value<F>(&self, f: F)
+    where
+        F: FnMut(RelId, &V);
+
+    /// Return the current state of the data as insertions of its values,
+    /// as sent to an observer upon subscription, e.g., for a consumer
+    /// interested in the state just once. No subscription is created.
+    fn snapshot_once(&self) -> Vec<Update<V>> {
+        self.get_current_state()
+            .into_iter()
+            .flat_map(|(relid, vs)| vs.into_iter().map(move |v| Update::Insert { relid, v }))
+            .collect()
+    }
+}
+
+/// The stage of its lifecycle an accumulator is in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Lifecycle {
+    /// The accumulator accepts subscriptions.
+    Active,
+    /// The upstream completed and no new one started a transaction yet.
+    Completed,
+    /// The accumulator was shut down for good.
+    ShutDown,
+}
+
+/// The options of a subscription made directly to a
+/// `DistributingAccumulator`.
+#[derive(Clone, Copy, Debug)]
+struct SubscribeOptions {
+    /// Whether to send the accumulated state to the observer first.
+    replay: bool,
+    /// The priority of the observer, if not the default one.
+    priority: Option<i32>,
 }
 
 /// An Accumulator implementation that can have multiple observers (can be subscribed to more
@@ -63,7 +182,54 @@ where
     /// Component responsible for accumulating the data.
     observer: AccumulatingObserver<T, V, E>,
     /// Component responsible for distributing the output to multiple observers.
-    distributor: Arc<Mutex<TxnDistributor<T, E>>>,
+    distributor: TxnDistributor<T, E>,
+    /// The number of updates of the transaction in progress that had
+    /// already been forwarded when an observer subscribed, for each
+    /// subscription made while the transaction was in progress.
+    joined: HashMap<usize, usize>,
+    /// The timers emitting the changes of the state to the observables
+    /// created via `create_snapshot_observable`.
+    snapshot_timers: Vec<SnapshotTimer>,
+    /// The timers emitting heartbeats to the observables created via
+    /// `create_heartbeat_observable`.
+    heartbeat_timers: Vec<HeartbeatTimer>,
+    /// The number of transactions committed so far, for the heartbeat
+    /// timers to detect idle intervals.
+    committed: Arc<AtomicUsize>,
+    /// The policy for retrying deliveries to observers subscribed
+    /// directly, if any.
+    retry: Option<RetryPolicy>,
+    /// The most recent transactions received, if recorded.
+    history: Option<History<V>>,
+    /// The function sorting the updates sent to initialize observers,
+    /// if they are sorted.
+    sort_init_updates: Option<fn(&mut [Update<V>])>,
+    /// The maximum number of updates per `on_updates` call when sending
+    /// the accumulated state to new observers, if limited.
+    chunk_size: Option<usize>,
+    /// The stage of its lifecycle the accumulator is in.
+    lifecycle: Lifecycle,
+    /// The state last forwarded to observers, if forwarding is paused.
+    paused: Option<HashMap<RelId, HashSet<V>>>,
+    /// The observer pairing up the updates forwarded to the
+    /// distributor, if changes of values are forwarded as `Modify`
+    /// updates.
+    modifier: Option<SharedObserver<ObserverBox<T, E>>>,
+    /// The distributors of the named output streams, by name.
+    streams: HashMap<String, TxnDistributor<T, E>>,
+    /// The acknowledgments of the observers subscribed via
+    /// `subscribe_acked`, by subscription.
+    acks: HashMap<usize, AckHandle>,
+    /// The signal notified of the acknowledgments of the observers
+    /// subscribed via `subscribe_acked`.
+    ack_signal: Arc<AckSignal>,
+    /// The names of relations, for presentation purposes only.
+    registry: Option<RelationRegistry>,
+    /// The maximum number of transactions in flight when starting a
+    /// transaction, if limited, along with the conversion of exceeding
+    /// it into our error type, if starting fails rather than blocks
+    /// then.
+    max_in_flight: Option<(u64, Option<fn(WouldBlock) -> E>)>,
 }
 
 impl<V, E> Accumulator<V, E> for DistributingAccumulator<Update<V>, V, E>
@@ -72,481 +238,4928 @@ where
     E: Debug + Send + 'static,
 {
     fn new() -> Self {
-        let id = Id::<()>::new().get();
-        trace!("DistributingAccumulator({})::new", id);
-
-        // Instantiate AccumulatingObserver and TxnDistributor and subscribe the latter to the former
-        let mut observer = AccumulatingObserver::new();
-        let distributor = Arc::new(Mutex::new(TxnDistributor::new()));
-        let _subscription = observer.subscribe(Box::new(distributor.clone()));
-
-        Self {
-            id,
-            observer,
-            distributor,
-        }
+        AccumulatorBuilder::default().build()
     }
 
     /// Creates a new `Observable` for this accumulator without the currently accumulated state.
     /// I.e. if subscribed to, the subscriber only receives transactions occurring after the subscription.
     fn create_observable(&mut self) -> UpdatesObservable<Update<V>, E> {
         trace!("DistributingAccumulator({})::create_observable()", self.id);
-        self.distributor.lock().unwrap().create_observable()
+        self.distributor.create_observable()
     }
 
     fn get_current_state(&self) -> HashMap<RelId, HashSet<V>> {
         trace!("DistributingAccumulator({})::get_current_state()", self.id);
         self.observer.get_current_state()
     }
-}
-
-/// The methods for the Observable trait are delegated to the TxnDistributor
-impl<V, E> Observable<Update<V>, E> for DistributingAccumulator<Update<V>, V, E>
-where
-    V: Debug + Send + Clone + Eq + Hash + 'static,
-    E: Debug + Send + 'static,
-{
-    type Subscription = usize;
-
-    fn subscribe(
-        &mut self,
-        mut observer: ObserverBox<Update<V>, E>,
-    ) -> Result<Self::Subscription, ObserverBox<Update<V>, E>> {
-        trace!("DistributingAccumulator({})::subscribe()", self.id);
-        // get lock for distributor, it must not receive updates while initializing the observable
-        let mut distributor = self.distributor.lock().unwrap();
 
-        // update new observer with currently accumulated state
-        let mut init_updates = self
-            .get_current_state()
-            .into_iter()
-            .flat_map(|(relid, vs)| {
-                vs.into_iter()
-                    .map(|v| Update::Insert { relid, v })
-                    .collect::<Vec<_>>()
-            })
-            .collect::<Vec<_>>();
+    fn get_current_state_weighted(&self) -> HashMap<RelId, HashMap<V, isize>> {
+        trace!(
+            "DistributingAccumulator({})::get_current_state_weighted()",
+            self.id
+        );
+        self.observer.get_current_state_weighted()
+    }
 
-        if !init_updates.is_empty() {
-            let updates = init_updates.drain(..);
-            trace!(
-                "DistributingAccumulator({:?}) sending init_updates to observer: {:?}",
-                self.id,
-                updates
-            );
-            let _ = observer.on_start();
-            let _ = observer.on_updates(Box::new(updates));
-            let _ = observer.on_commit();
-        }
+    fn contains(&self, relid: RelId, value: &V) -> bool {
+        trace!(
+            "DistributingAccumulator({})::contains({})",
+            self.id,
+            self.relation_name(relid)
+        );
+        self.observer.contains(relid, value)
+    }
 
-        distributor.subscribe(observer)
+    fn for_each_value<F>(&self, f: F)
+    where
+        F: FnMut(RelId, &V),
+    {
+        trace!("DistributingAccumulator({})::for_each_value()", self.id);
+        self.observer.for_each_value(f)
     }
 
-    fn unsubscribe(
-        &mut self,
-        subscription: &Self::Subscription,
-    ) -> Option<ObserverBox<Update<V>, E>> {
+    fn relation_size(&self, relid: RelId) -> usize {
         trace!(
-            "DistributingAccumulator({})::unsubscribe({})",
+            "DistributingAccumulator({})::relation_size({})",
             self.id,
-            subscription
+            self.relation_name(relid)
         );
-        self.distributor.unsubscribe(subscription)
+        self.observer.relation_size(relid)
+    }
+
+    fn snapshot_once(&self) -> Vec<Update<V>> {
+        trace!("DistributingAccumulator({})::snapshot_once()", self.id);
+        self.insertions(self.get_current_state())
     }
 }
 
-/// All calls except `on_completed` of the Observer trait are delegated to the AccumulatingObserver.
-/// `on_completed` triggers the deletion of the accumulated state for all observers.
-impl<V, E> Observer<Update<V>, E> for DistributingAccumulator<Update<V>, V, E>
+/// Sort insertions by relation and then by value.
+pub(crate) fn sort_insertions<V>(updates: &mut [Update<V>])
 where
-    V: Debug + Send + Eq + Hash + Clone + 'static,
+    V: Debug + Ord,
+{
+    updates.sort_by(|u1, u2| match (u1, u2) {
+        (Update::Insert { relid: r1, v: v1 }, Update::Insert { relid: r2, v: v2 }) => {
+            (r1, v1).cmp(&(r2, v2))
+        }
+        (u1, u2) => panic!("Operations {:?} and {:?} not allowed", u1, u2),
+    })
+}
+
+impl<V, E> DistributingAccumulator<Update<V>, V, E>
+where
+    V: Debug + Send + Clone + Eq + Hash + 'static,
     E: Debug + Send + 'static,
 {
-    fn on_start(&mut self) -> Result<(), E> {
-        trace!("DistributingAccumulator({})::on_start", self.id);
-        self.observer.on_start()
+    /// Create a new accumulator that holds back the updates of a
+    /// transaction until it is committed and then forwards only their
+    /// net effect to observers. E.g., inserting and deleting the same
+    /// value within a transaction results in no updates being
+    /// forwarded, while two insertions and one deletion result in a
+    /// single insertion.
+    pub fn new_coalescing() -> Self {
+        Self::with_observer(AccumulatingObserver::new_coalescing())
     }
 
-    fn on_commit(&mut self) -> Result<(), E> {
-        trace!("DistributingAccumulator({})::on_commit", self.id);
-        self.observer.on_commit()
+    /// Create a new accumulator holding the given state, as if it had
+    /// been inserted in a committed transaction, e.g., to bootstrap it
+    /// with known data. Every value is accumulated exactly once, i.e.,
+    /// a single deletion removes it, and subscribers are sent the
+    /// state as usual.
+    pub fn with_initial_state(state: HashMap<RelId, HashSet<V>>) -> Self {
+        let weights = state
+            .iter()
+            .map(|(relid, vs)| (*relid, vs.iter().map(|v| (v.clone(), 1)).collect()))
+            .collect();
+        let mut accumulator = Self::new();
+        accumulator.restore(AccumulatorSnapshot {
+            data: state,
+            weights,
+            buffer: None,
+        });
+        accumulator
     }
 
-    fn on_updates<'a>(
-        &mut self,
-        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
-    ) -> Result<(), E> {
-        trace!("DistributingAccumulator({})::on_updates", self.id);
-        self.observer.on_updates(updates)
+    /// Delete the values that were last inserted longer than the time
+    /// set via `AccumulatorBuilder::ttl` before `now`, forwarding the
+    /// deletions to observers as a transaction of their own. Has no
+    /// effect while a transaction is in progress.
+    pub fn expire_now(&mut self, now: Instant) -> Result<(), E> {
+        trace!("DistributingAccumulator({})::expire_now", self.id);
+        self.observer.expire_now(now)
     }
 
-    /// sends a deletion update to all observers, thus clearing the accumulated state.
-    fn on_completed(&mut self) -> Result<(), E> {
-        trace!("DistributingAccumulator({})::on_completed", self.id);
-        let mut distributor = self.distributor.lock().unwrap();
-        let _ = distributor.on_completed();
-
-        let mut delete_updates = self
-            .get_current_state()
-            .into_iter()
-            .flat_map(|(relid, vs)| {
-                vs.into_iter()
-                    .map(|v| Update::DeleteValue { relid, v })
-                    .collect::<Vec<_>>()
-            })
-            .collect::<Vec<_>>();
-
-        if !delete_updates.is_empty() {
-            let updates = delete_updates.drain(..);
-            trace!(
-                "DistributingAccumulator({:?}) clearing state of observers: {:?}",
-                self.id,
-                updates
-            );
-            let _ = distributor.on_start();
-            let _ = distributor.on_updates(Box::new(updates));
-            let _ = distributor.on_commit();
-        }
+    /// Retrieve the most recent transactions received along with their
+    /// sequence numbers, oldest first, if recorded as configured via
+    /// `AccumulatorBuilder::history`. The updates are those received
+    /// from the upstream, before any translation or filtering.
+    pub fn recent_transactions(&self) -> Vec<(u64, Vec<Update<V>>)> {
+        trace!(
+            "DistributingAccumulator({})::recent_transactions()",
+            self.id
+        );
+        self.history
+            .as_ref()
+            .map(History::transactions)
+            .unwrap_or_default()
+    }
 
-        self.observer.on_completed()
+    /// Register a handler that is invoked with the relation and the
+    /// value of every insertion rejected because of the limit set via
+    /// `AccumulatorBuilder::max_values_per_relation`.
+    pub fn on_overflow<F>(&mut self, handler: F)
+    where
+        F: FnMut(RelId, V) + Send + 'static,
+    {
+        trace!("DistributingAccumulator({})::on_overflow", self.id);
+        self.observer.on_overflow(handler)
     }
-}
 
-#[cfg(test)]
-pub mod tests {
-    use super::*;
+    /// Forward the current batch of transactions to observers without
+    /// waiting for it to fill up. Has no effect while a transaction is
+    /// in progress or when not batching.
+    pub fn flush(&mut self) -> Result<(), E> {
+        trace!("DistributingAccumulator({})::flush", self.id);
+        self.observer.flush()
+    }
 
-    use std::sync::Arc;
-    use std::sync::Mutex;
-    use std::vec::IntoIter;
+    /// Block until all observers processed the updates forwarded so far,
+    /// e.g., before taking a coordinated checkpoint. A pending batch of
+    /// transactions is flushed beforehand. Observers processing events
+    /// asynchronously, such as a `ChannelObserver`, are waited for to
+    /// drain their queues, while for all others this returns right away.
+    pub fn barrier(&mut self) -> Result<(), E> {
+        trace!("DistributingAccumulator({})::barrier", self.id);
+        self.flush()?;
+        self.distributor.on_barrier()
+    }
 
-    use crate::accumulate::{eq_updates, UpdatesMockObserver};
-    use crate::MockObserver;
+    /// Stop forwarding updates to observers, e.g., for a maintenance
+    /// window. Updates received while paused are still accumulated,
+    /// but are only forwarded by `resume`. A pending batch of
+    /// transactions is flushed beforehand. Observers subscribing while
+    /// paused are sent the state as of the pause. Fails with
+    /// `AccumulatorError::InTransaction` if a transaction is in
+    /// progress.
+    pub fn pause(&mut self) -> Result<(), AccumulatorError<E>> {
+        trace!("DistributingAccumulator({})::pause", self.id);
+        if self.observer.in_transaction() {
+            return Err(AccumulatorError::InTransaction);
+        }
+        if self.paused.is_none() {
+            self.flush().map_err(AccumulatorError::Observer)?;
+            let _ = self.observer.unsubscribe(&());
+            self.paused = Some(self.get_current_state());
+        }
+        Ok(())
+    }
 
-    fn get_usize_updates_1() -> Box<IntoIter<Update<usize>>> {
-        Box::new(
-            vec![
-                Update::Insert { relid: 1, v: 1 },
-                Update::Insert { relid: 2, v: 2 },
-                Update::Insert { relid: 3, v: 3 },
-            ]
-            .into_iter(),
-        )
+    /// Resume forwarding updates to observers, sending them the net
+    /// effect of the updates received while paused as a single
+    /// transaction. Has no effect when not paused. Fails with
+    /// `AccumulatorError::InTransaction` if a transaction is in
+    /// progress.
+    pub fn resume(&mut self) -> Result<(), AccumulatorError<E>> {
+        trace!("DistributingAccumulator({})::resume", self.id);
+        if self.observer.in_transaction() {
+            return Err(AccumulatorError::InTransaction);
+        }
+        let paused = match self.paused.take() {
+            Some(paused) => paused,
+            None => return Ok(()),
+        };
+        let mut downstream = self.downstream();
+        let updates = diff_states(&paused, &self.get_current_state());
+        let result = if updates.is_empty() {
+            Ok(())
+        } else {
+            downstream
+                .on_start()
+                .and_then(|_| downstream.on_updates(Box::new(updates.into_iter())))
+                .and_then(|_| downstream.on_commit())
+        };
+        let _ = self.observer.subscribe(downstream);
+        result.map_err(AccumulatorError::Observer)
     }
 
-    fn get_usize_updates_2() -> Box<IntoIter<Update<usize>>> {
-        Box::new(
-            vec![
-                Update::Insert { relid: 1, v: 2 },
-                Update::Insert { relid: 1, v: 3 },
-                Update::Insert { relid: 2, v: 3 },
-            ]
-            .into_iter(),
-        )
+    /// Forward changes of the values of the given relations as `Modify`
+    /// updates, pairing them up by the keys the given functions
+    /// extract, as configured via `AccumulatorBuilder::modify_events`.
+    pub(crate) fn set_modify_keys(&mut self, modify_keys: HashMap<RelId, fn(&V) -> V>)
+    where
+        V: Sync,
+    {
+        let modifier = ModifyingObserver::new(self.distributor.clone(), modify_keys);
+        self.modifier = Some(Arc::new(Mutex::new(Box::new(modifier))));
+        let _ = self.observer.unsubscribe(&());
+        let _ = self.observer.subscribe(self.downstream());
     }
 
-    fn get_usize_updates_3() -> Box<IntoIter<Update<usize>>> {
-        Box::new(
-            vec![
-                Update::Insert { relid: 4, v: 1 },
-                Update::Insert { relid: 4, v: 2 },
-                Update::Insert { relid: 4, v: 3 },
-                Update::Insert { relid: 4, v: 4 },
-            ]
-            .into_iter(),
-        )
+    /// Retrieve the observer to forward the output of the
+    /// `AccumulatingObserver` to.
+    fn downstream(&self) -> ObserverBox<Update<V>, E> {
+        match &self.modifier {
+            Some(modifier) => Box::new(modifier.clone()),
+            None => Box::new(self.distributor.clone()),
+        }
     }
 
-    fn get_usize_delete_updates_1() -> Box<IntoIter<Update<usize>>> {
-        Box::new(
-            vec![
-                Update::DeleteValue { relid: 1, v: 1 },
-                Update::DeleteValue { relid: 2, v: 2 },
-                Update::DeleteValue { relid: 3, v: 3 },
-            ]
-            .into_iter(),
-        )
+    /// Wait until fewer than the maximum number of transactions are in
+    /// flight, if limited, or fail if we are not to block.
+    fn await_in_flight(&self) -> Result<(), E> {
+        let (max, would_block) = match self.max_in_flight {
+            Some(max_in_flight) => max_in_flight,
+            None => return Ok(()),
+        };
+        let sequence = self.observer.sequence();
+        let in_flight = || {
+            sequence
+                .committed()
+                .saturating_sub(self.committed_watermark())
+        };
+        let in_flight_now = in_flight();
+        if in_flight_now < max {
+            return Ok(());
+        }
+        if let Some(convert) = would_block {
+            return Err(convert(WouldBlock {
+                in_flight: in_flight_now,
+                max,
+            }));
+        }
+        trace!(
+            "DistributingAccumulator({}) waiting for {} transactions in flight",
+            self.id,
+            in_flight_now
+        );
+        self.ack_signal.wait_while(|| in_flight() >= max);
+        Ok(())
     }
 
-    /// Test subscribing and unsubscribing for a `DistributingAccumulator`.
-    /// A subscription can occur directly via `subscribe` or via `create_observable`.
-    #[test]
-    fn subscribe_unsubscribe() {
-        let mut accumulator = DistributingAccumulator::<Update<()>, (), ()>::new();
-        let observer = Box::new(MockObserver::new());
+    /// Retrieve the name of the given relation from the registry,
+    /// falling back to its ID.
+    fn relation_name(&self, relid: RelId) -> String {
+        self.registry
+            .as_ref()
+            .and_then(|registry| registry.get(&relid))
+            .cloned()
+            .unwrap_or_else(|| relid.to_string())
+    }
 
-        let subscription = accumulator.subscribe(observer);
-        assert!(subscription.is_ok());
-        assert!(accumulator.unsubscribe(&subscription.unwrap()).is_some());
+    /// Check whether forwarding updates to observers is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_some()
+    }
 
-        let mut observable = accumulator.create_observable();
-        let observer = Box::new(MockObserver::new());
+    /// Retrieve the accumulator's ID, as used in traces.
+    pub fn id(&self) -> usize {
+        self.id
+    }
 
-        let subscription = observable.subscribe(observer);
-        assert!(subscription.is_ok());
-        assert!(observable.unsubscribe(&subscription.unwrap()).is_some());
+    /// Create a new accumulator distributing the output of the given
+    /// `AccumulatingObserver`.
+    pub(crate) fn with_observer(observer: AccumulatingObserver<Update<V>, V, E>) -> Self {
+        Self::with_observer_and_id(observer, Id::<()>::new().get())
     }
 
-    /// Test multiple direct subscriptions via `subscribe` to a `AccumulatingObserver`.
-    #[test]
-    fn multiple_subscribe_direct_distributor() {
-        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
-        let mock1 = Arc::new(Mutex::new(MockObserver::new()));
-        let mock2 = Arc::new(Mutex::new(MockObserver::new()));
+    /// Create a new accumulator distributing the output of the given
+    /// `AccumulatingObserver`, with the options of the given builder.
+    pub(crate) fn from_builder(
+        observer: AccumulatingObserver<Update<V>, V, E>,
+        builder: AccumulatorBuilder<V, E>,
+    ) -> Self {
+        let id = builder.id.unwrap_or_else(|| Id::<()>::new().get());
+        let mut accumulator = Self::with_observer_and_id(observer, id);
+        accumulator.retry = builder.retry;
+        accumulator.history = builder.history.map(History::new);
+        accumulator.sort_init_updates = builder.sort_init_updates;
+        accumulator.chunk_size = builder.chunk_size;
+        accumulator.registry = builder.registry;
+        accumulator.max_in_flight = builder.max_in_flight;
+        if let Some((modify_keys, install)) = builder.modify_events {
+            install(&mut accumulator, modify_keys);
+        }
+        accumulator
+    }
 
-        assert!(accumulator.subscribe(Box::new(mock1.clone())).is_ok());
-        assert!(accumulator.subscribe(Box::new(mock2.clone())).is_ok());
+    /// Create a new accumulator with the given ID rather than a unique
+    /// one, so that tests can rely on the ID, e.g., in log output.
+    #[cfg(any(test, feature = "test"))]
+    pub fn new_with_id(id: usize) -> Self {
+        AccumulatorBuilder::default().id(id).build()
+    }
 
-        assert_eq!(accumulator.on_start(), Ok(()));
-        assert_eq!(mock1.lock().unwrap().called_on_start, 1);
-        assert_eq!(mock2.lock().unwrap().called_on_start, 1);
+    /// Create a new accumulator with the given ID distributing the
+    /// output of the given `AccumulatingObserver`.
+    fn with_observer_and_id(
+        mut observer: AccumulatingObserver<Update<V>, V, E>,
+        id: usize,
+    ) -> Self {
+        trace!("DistributingAccumulator({})::new", id);
 
-        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
-        assert_eq!(mock1.lock().unwrap().called_on_updates, 3);
-        assert_eq!(mock2.lock().unwrap().called_on_updates, 3);
+        // Subscribe a new TxnDistributor to the AccumulatingObserver
+        let distributor = TxnDistributor::new();
+        let _subscription = observer.subscribe(Box::new(distributor.clone()));
 
-        assert_eq!(accumulator.on_commit(), Ok(()));
-        assert_eq!(mock1.lock().unwrap().called_on_commit, 1);
-        assert_eq!(mock2.lock().unwrap().called_on_commit, 1);
+        Self {
+            id,
+            observer,
+            distributor,
+            joined: HashMap::new(),
+            snapshot_timers: Vec::new(),
+            heartbeat_timers: Vec::new(),
+            committed: Arc::new(AtomicUsize::new(0)),
+            retry: None,
+            history: None,
+            sort_init_updates: None,
+            chunk_size: None,
+            lifecycle: Lifecycle::Active,
+            paused: None,
+            modifier: None,
+            streams: HashMap::new(),
+            acks: HashMap::new(),
+            ack_signal: Arc::new(AckSignal::default()),
+            registry: None,
+            max_in_flight: None,
+        }
+    }
 
-        assert_eq!(accumulator.on_completed(), Ok(()));
-        assert_eq!(mock1.lock().unwrap().called_on_start, 2);
-        assert_eq!(mock2.lock().unwrap().called_on_start, 2);
-        assert_eq!(mock1.lock().unwrap().called_on_updates, 6);
-        assert_eq!(mock2.lock().unwrap().called_on_updates, 6);
-        assert_eq!(mock1.lock().unwrap().called_on_commit, 2);
-        assert_eq!(mock2.lock().unwrap().called_on_commit, 2);
-        assert_eq!(mock1.lock().unwrap().called_on_completed, 1);
-        assert_eq!(mock2.lock().unwrap().called_on_completed, 1);
+    /// Register a handler that is invoked with the subscription and the
+    /// error of every observer failing to process an event, e.g., to
+    /// tear down the subscription of a disconnected observer. Errors
+    /// reported to the handler are not propagated to the upstream.
+    ///
+    /// Without a handler the first error is propagated, after all
+    /// observers have seen the event.
+    pub fn on_observer_error<F>(&mut self, handler: F)
+    where
+        F: Fn(usize, E) + Send + 'static,
+    {
+        trace!("DistributingAccumulator({})::on_observer_error", self.id);
+        self.distributor.on_observer_error(handler)
     }
 
-    /// Test multiple indirect subscriptions via `create_observable` to a `DistributingAccumulator`.
-    #[test]
-    fn multiple_subscribe_indirect_distributor() {
-        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
-        let mock1 = Arc::new(Mutex::new(MockObserver::new()));
-        let mock2 = Arc::new(Mutex::new(MockObserver::new()));
-        let mut observable1 = accumulator.create_observable();
-        let mut observable2 = accumulator.create_observable();
+    /// Register a handler that is invoked with the subscription of every
+    /// observer panicking while processing an event, along with the
+    /// panic's message. Such observers are unsubscribed, while the
+    /// remaining ones continue to receive events.
+    pub fn on_observer_panic<F>(&mut self, handler: F)
+    where
+        F: Fn(usize, String) + Send + 'static,
+    {
+        trace!("DistributingAccumulator({})::on_observer_panic", self.id);
+        self.distributor.on_observer_panic(handler)
+    }
 
-        assert!(observable1.subscribe(Box::new(mock1.clone())).is_ok());
-        assert!(observable2.subscribe(Box::new(mock2.clone())).is_ok());
+    /// Create a `LatchObservable` collecting the net effect of the
+    /// transactions committed from now on, for a consumer polling for
+    /// changes rather than observing each of them.
+    pub fn create_latch_observable(&mut self) -> LatchObservable<V> {
+        trace!(
+            "DistributingAccumulator({})::create_latch_observable()",
+            self.id
+        );
+        LatchObservable::new(&mut self.distributor)
+    }
 
-        assert_eq!(accumulator.on_start(), Ok(()));
-        assert_eq!(mock1.lock().unwrap().called_on_start, 1);
-        assert_eq!(mock2.lock().unwrap().called_on_start, 1);
+    /// Creates a new `Observable` for this accumulator that only
+    /// forwards updates to relations in `relids`. Transactions that do
+    /// not touch any of these relations are not forwarded at all, i.e.,
+    /// a subscriber does not see their `on_start` and `on_commit`
+    /// events either. An empty set forwards nothing.
+    ///
+    /// Any number of observers may subscribe to the observable. Each
+    /// of them is sent the currently accumulated state of these
+    /// relations upon subscription, as the first subscriber of an
+    /// observable created via `create_observable_with_replay` is.
+    pub fn create_observable_filtered(
+        &mut self,
+        relids: HashSet<RelId>,
+    ) -> ReplayingObservable<V, E> {
+        trace!(
+            "DistributingAccumulator({})::create_observable_filtered({:?})",
+            self.id,
+            relids
+        );
+        let state = self.state_handle();
+        ReplayingObservable::filtered(&mut self.distributor, state, move |u: &Update<V>| {
+            relids.contains(&u.relid())
+        })
+    }
 
-        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
-        assert_eq!(mock1.lock().unwrap().called_on_updates, 3);
-        assert_eq!(mock2.lock().unwrap().called_on_updates, 3);
+    /// Creates a new `Observable` for this accumulator that only
+    /// forwards insertions, e.g., for append-only sinks. All deletions
+    /// are dropped, including those `on_completed` sends to clear the
+    /// state, and transactions without any insertions are not forwarded
+    /// at all, as for `create_observable_filtered`.
+    ///
+    /// Just like `create_observable`, the currently accumulated state
+    /// is not replayed to a subscriber.
+    pub fn create_insert_only_observable(&mut self) -> UpdatesObservable<Update<V>, E> {
+        trace!(
+            "DistributingAccumulator({})::create_insert_only_observable()",
+            self.id
+        );
+        self.distributor.create_observable_with(|observer| {
+            let predicate = Box::new(|u: &Update<V>| matches!(u, Update::Insert { .. }));
+            Box::new(FilteringObserver::new(observer, predicate))
+        })
+    }
 
-        assert_eq!(accumulator.on_commit(), Ok(()));
-        assert_eq!(mock1.lock().unwrap().called_on_commit, 1);
-        assert_eq!(mock2.lock().unwrap().called_on_commit, 1);
+    /// Split our output by relation, creating an `Observable` for each
+    /// of the given relations that only forwards the updates to it, as
+    /// `create_observable_filtered` does, including the state of the
+    /// relation sent upon subscription. Updates to other relations are
+    /// not forwarded through any of these.
+    pub fn split_by_relation(
+        &mut self,
+        relids: &[RelId],
+    ) -> HashMap<RelId, ReplayingObservable<V, E>> {
+        trace!(
+            "DistributingAccumulator({})::split_by_relation({:?})",
+            self.id,
+            relids
+        );
+        relids
+            .iter()
+            .map(|relid| {
+                let relids = Some(*relid).into_iter().collect();
+                (*relid, self.create_observable_filtered(relids))
+            })
+            .collect()
     }
 
-    /// Test pass-through filter behaviour for transactions via a `DistributingAccumulator`.
+    /// Create a new `Observable` forwarding only every `every_n`th
+    /// transaction, in its entirety, and dropping all others, e.g., to
+    /// monitor a sample of the transactions at low overhead. The
+    /// accumulated state is not affected and not replayed to a
+    /// subscriber.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `every_n` is zero.
+    pub fn create_sampling_observable(
+        &mut self,
+        every_n: usize,
+    ) -> UpdatesObservable<Update<V>, E> {
+        trace!(
+            "DistributingAccumulator({})::create_sampling_observable({})",
+            self.id,
+            every_n
+        );
+        self.distributor
+            .create_observable_with(|observer| Box::new(SamplingObserver::new(observer, every_n)))
+    }
+
+    /// Create a new `Observable` whose first subscriber is sent the
+    /// accumulated state as a transaction of its own upon subscription,
+    /// as `subscribe` does, while it behaves like one created via
+    /// `create_observable` otherwise. If a transaction is in progress
+    /// at that time, the state is sent right before the next
+    /// transaction started instead. Any number of observers may
+    /// subscribe, but later subscribers are not sent the state.
+    pub fn create_observable_with_replay(&mut self) -> ReplayingObservable<V, E> {
+        trace!(
+            "DistributingAccumulator({})::create_observable_with_replay()",
+            self.id
+        );
+        let state = self.state_handle();
+        ReplayingObservable::new(&mut self.distributor, state)
+    }
+
+    /// Creates a new `Observable` for the named output stream `name`,
+    /// e.g., "audit" or "live", carrying the updates selected and
+    /// transformed as configured. Each stream is fed by a subscription
+    /// of its own and distributes its updates to all observables
+    /// created for it, so that streams can be set up independently of
+    /// each other. If a stream of the given name exists already, a
+    /// further observable of it is created and `config` is ignored.
+    ///
+    /// Just like `create_observable`, the currently accumulated state
+    /// is not replayed to a subscriber.
+    pub fn create_named_stream(
+        &mut self,
+        name: &str,
+        config: StreamConfig<V>,
+    ) -> UpdatesObservable<Update<V>, E> {
+        trace!(
+            "DistributingAccumulator({})::create_named_stream({}, {:?})",
+            self.id,
+            name,
+            config
+        );
+        if let Some(stream) = self.streams.get_mut(name) {
+            return stream.create_observable();
+        }
+
+        let mut stream = TxnDistributor::new();
+        let observer = StreamObserver::new(name, stream.clone(), &config);
+        let filtering = FilteringObserver::new(observer, config.predicate());
+        let _ = self.distributor.subscribe(Box::new(filtering));
+        let observable = stream.create_observable();
+        let _ = self.streams.insert(name.to_string(), stream);
+        observable
+    }
+
+    /// Creates a new `Observable` for this accumulator that forwards
+    /// only the net effect of each transaction once it is committed,
+    /// i.e., values inserted and deleted again within a transaction
+    /// are not forwarded at all, and neither are transactions without
+    /// any net effect. The net effect is determined against the state
+    /// before the transaction, i.e., insertions of values already
+    /// present and deletions of values absent are not forwarded
+    /// either. This is the per-observable counterpart of
+    /// `new_coalescing`.
+    ///
+    /// Just like `create_observable`, the currently accumulated state
+    /// is not replayed to a subscriber.
+    pub fn create_committed_delta_observable(&mut self) -> UpdatesObservable<Update<V>, E> {
+        trace!(
+            "DistributingAccumulator({})::create_committed_delta_observable()",
+            self.id
+        );
+        let state = self.state_handle();
+        self.distributor
+            .create_observable_with(|observer| Box::new(DeltaObserver::new(observer, state)))
+    }
+
+    /// Creates a new `Observable` for this accumulator that, instead of
+    /// forwarding each transaction, emits the changes of the
+    /// accumulated state since its last emission every `interval`, as
+    /// a single transaction. The first emission comprises the entire
+    /// state and intervals without any changes are skipped. Changes
+    /// that cancel each other out within an interval are not emitted at
+    /// all, e.g., to reduce churn for visualization.
+    ///
+    /// Emissions are performed by a dedicated thread, which is stopped
+    /// when the accumulator is dropped.
+    pub fn create_snapshot_observable(
+        &mut self,
+        interval: Duration,
+    ) -> UpdatesObservable<Update<V>, E> {
+        trace!(
+            "DistributingAccumulator({})::create_snapshot_observable({:?})",
+            self.id,
+            interval
+        );
+        let observable = UpdatesObservable {
+            observer: SharedObserver::default(),
+        };
+        let timer = SnapshotTimer::new(self.state_handle(), observable.observer.clone(), interval);
+        self.snapshot_timers.push(timer);
+        observable
+    }
+
+    /// Create a new `Observable` receiving a heartbeat, i.e., an empty
+    /// transaction, at the end of every `interval` in which we did not
+    /// commit any transaction, e.g., for observers to detect that the
+    /// accumulator is alive while there are no updates. Heartbeats do
+    /// not alter the accumulated state and are not sent to any other
+    /// observers.
+    ///
+    /// Heartbeats are emitted by a dedicated thread, which is stopped
+    /// when the accumulator is dropped.
+    pub fn create_heartbeat_observable(
+        &mut self,
+        interval: Duration,
+    ) -> UpdatesObservable<Update<V>, E> {
+        trace!(
+            "DistributingAccumulator({})::create_heartbeat_observable({:?})",
+            self.id,
+            interval
+        );
+        let observable = UpdatesObservable {
+            observer: SharedObserver::default(),
+        };
+        let timer = HeartbeatTimer::new(
+            self.committed.clone(),
+            observable.observer.clone(),
+            interval,
+        );
+        self.heartbeat_timers.push(timer);
+        observable
+    }
+
+    /// Create a new `Observable` combining our transactions into
+    /// batches of at least `min_batch` updates, each forwarded as a
+    /// single transaction, e.g., for efficient writes downstream. A
+    /// batch is flushed regardless of its size once its first
+    /// transaction was held back for `max_latency`, and the remainder
+    /// is flushed upon completion.
+    ///
+    /// Batches held back for too long are flushed by a dedicated
+    /// thread, which is stopped when the observable's subscription to
+    /// the accumulator ends. It sleeps while no batch is held back, so
+    /// a `max_latency` of zero merely flushes every batch right away.
+    /// Just like `create_observable`, the currently accumulated state
+    /// is not replayed to a subscriber.
+    pub fn create_min_batch_observable(
+        &mut self,
+        min_batch: usize,
+        max_latency: Duration,
+    ) -> UpdatesObservable<Update<V>, E> {
+        trace!(
+            "DistributingAccumulator({})::create_min_batch_observable({}, {:?})",
+            self.id,
+            min_batch,
+            max_latency
+        );
+        self.distributor.create_observable_with(|observer| {
+            Box::new(MinBatchObserver::new(observer, min_batch, max_latency))
+        })
+    }
+
+    /// Subscribe an observer, sending it the currently accumulated state
+    /// as a transaction of its own first, as `subscribe` does. Along
+    /// with the subscription, the number of updates sent as part of
+    /// that transaction is returned, e.g., to throttle subsequent
+    /// updates accordingly.
+    pub fn subscribe_counted(
+        &mut self,
+        observer: ObserverBox<Update<V>, E>,
+    ) -> Result<(usize, usize), ObserverBox<Update<V>, E>> {
+        trace!("DistributingAccumulator({})::subscribe_counted()", self.id);
+        self.subscribe_with(
+            observer,
+            SubscribeOptions {
+                replay: true,
+                priority: None,
+            },
+        )
+    }
+
+    /// Subscribe an observer that only receives the updates satisfying
+    /// the given predicate, e.g., to select values by their fields. The
+    /// predicate applies to the currently accumulated state sent to the
+    /// observer first, as `subscribe` does, and to deletions alike, so
+    /// that the observer's view of the state stays consistent, provided
+    /// the predicate only depends on the value of an update.
+    /// Transactions without any matching updates are not forwarded at
+    /// all. Unsubscribing returns the observer wrapped in a
+    /// `FilteringObserver`.
+    pub fn subscribe_filtered<P>(
+        &mut self,
+        observer: ObserverBox<Update<V>, E>,
+        predicate: P,
+    ) -> Result<usize, ObserverBox<Update<V>, E>>
+    where
+        P: Fn(&Update<V>) -> bool + Send + 'static,
+    {
+        trace!("DistributingAccumulator({})::subscribe_filtered()", self.id);
+        let filtering = FilteringObserver::new(observer, Box::new(predicate));
+        self.subscribe_counted(Box::new(filtering))
+            .map(|(subscription, _)| subscription)
+    }
+
+    /// Subscribe an observer collecting all updates it receives,
+    /// including those sending it the currently accumulated state first,
+    /// as `subscribe` does, into a shared buffer, e.g., to inspect them
+    /// in tests. Returns the subscription along with the buffer. Fails
+    /// with `AccumulatorError::Inactive` if the accumulator completed or
+    /// was shut down.
+    pub fn subscribe_collect(
+        &mut self,
+    ) -> Result<(usize, Arc<Mutex<Vec<Update<V>>>>), AccumulatorError<E>> {
+        trace!("DistributingAccumulator({})::subscribe_collect()", self.id);
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let collected = buffer.clone();
+        let observer = CallbackObserver::new(move |updates| {
+            collected.lock().unwrap().extend(updates);
+        });
+        let (subscription, _) = self
+            .subscribe_counted(Box::new(observer))
+            .map_err(|_| AccumulatorError::Inactive)?;
+        Ok((subscription, buffer))
+    }
+
+    /// Subscribe an observer to an accumulator shared between threads,
+    /// sending it the currently accumulated state first, as `subscribe`
+    /// does. An observer of the accumulator subscribing another one
+    /// from within its callback would deadlock on the lock held by
+    /// whoever is delivering the event, so an accumulator that is
+    /// locked while an event is being delivered on the current thread
+    /// is reported as `AccumulatorError::Reentrant` rather than waited
+    /// for. The observer is handed back if it could not be subscribed.
+    pub fn subscribe_shared(
+        accumulator: &Mutex<Self>,
+        observer: ObserverBox<Update<V>, E>,
+    ) -> Result<usize, (AccumulatorError<E>, ObserverBox<Update<V>, E>)> {
+        let mut accumulator = match accumulator.try_lock() {
+            Ok(accumulator) => accumulator,
+            Err(TryLockError::WouldBlock) if is_delivering() => {
+                return Err((AccumulatorError::Reentrant, observer))
+            }
+            Err(TryLockError::WouldBlock) => match accumulator.lock() {
+                Ok(accumulator) => accumulator,
+                Err(_) => return Err((AccumulatorError::Poisoned, observer)),
+            },
+            Err(TryLockError::Poisoned(_)) => return Err((AccumulatorError::Poisoned, observer)),
+        };
+        trace!(
+            "DistributingAccumulator({})::subscribe_shared()",
+            accumulator.id
+        );
+        accumulator
+            .subscribe(observer)
+            .map_err(|observer| (AccumulatorError::Inactive, observer))
+    }
+
+    /// Subscribe the observer created by the given function, sending it
+    /// the currently accumulated state first, as `subscribe` does. The
+    /// function is provided with a handle for the observer to
+    /// acknowledge the transactions it durably processed, e.g., from
+    /// within its `on_commit`, by the sequence number it was handed via
+    /// `on_start_seq`. Until it does, the observer holds back the
+    /// `committed_watermark`. The transactions committed before the
+    /// subscription are considered acknowledged.
+    pub fn subscribe_acked<F>(&mut self, create: F) -> Result<usize, ObserverBox<Update<V>, E>>
+    where
+        F: FnOnce(AckHandle) -> ObserverBox<Update<V>, E>,
+    {
+        trace!("DistributingAccumulator({})::subscribe_acked()", self.id);
+        let ack = AckHandle::new(self.observer.sequence(), self.ack_signal.clone());
+        let (subscription, _) = self.subscribe_counted(create(ack.clone()))?;
+        let _ = self.acks.insert(subscription, ack);
+        Ok(subscription)
+    }
+
+    /// Retrieve the sequence number of the last transaction acknowledged
+    /// by all observers subscribed via `subscribe_acked`, i.e., of the
+    /// last transaction durably processed downstream, with transactions
+    /// numbered as they are handed to observers via `on_start_seq`.
+    /// Without such observers, this is the sequence number of the last
+    /// transaction whose commit was forwarded.
+    pub fn committed_watermark(&self) -> u64 {
+        trace!(
+            "DistributingAccumulator({})::committed_watermark()",
+            self.id
+        );
+        let subscriptions = self.distributor.subscription_ids();
+        self.acks
+            .iter()
+            .filter(|(subscription, _)| subscriptions.contains(subscription))
+            .map(|(_, ack)| ack.acked())
+            .min()
+            .unwrap_or_else(|| self.observer.sequence().committed())
+    }
+
+    /// Subscribe an observer with the given priority, sending it the
+    /// currently accumulated state first, as `subscribe` does. Within
+    /// each transaction, observers receive events in descending order
+    /// of their priority, e.g., so that an observer persisting updates
+    /// sees them before one triggering side effects. Observers of the
+    /// same priority receive events in the order they subscribed. The
+    /// priority of observers subscribed otherwise is zero.
+    pub fn subscribe_with_priority(
+        &mut self,
+        observer: ObserverBox<Update<V>, E>,
+        priority: i32,
+    ) -> Result<usize, ObserverBox<Update<V>, E>> {
+        trace!(
+            "DistributingAccumulator({})::subscribe_with_priority({})",
+            self.id,
+            priority
+        );
+        let (subscription, _) = self.subscribe_with(
+            observer,
+            SubscribeOptions {
+                replay: true,
+                priority: Some(priority),
+            },
+        )?;
+        Ok(subscription)
+    }
+
+    /// Send the currently accumulated state to a new observer as a
+    /// transaction of its own, followed by `on_init_complete`,
+    /// returning the number of updates sent.
+    fn send_init_updates(&self, observer: &mut ObserverBox<Update<V>, E>) -> usize {
+        // the distributor cannot receive updates while we are initializing
+        // the observer, because we are borrowed mutably
+
+        // update new observer with currently accumulated state, or the
+        // state the other observers know of while paused
+        let init_updates = match &self.paused {
+            Some(paused) => self.insertions(paused.clone()),
+            None => self.snapshot_once(),
+        };
+
+        let count = init_updates.len();
+        if !init_updates.is_empty() {
+            trace!(
+                "DistributingAccumulator({:?}) sending init_updates to observer: {:?}",
+                self.id,
+                init_updates
+            );
+            delivering(|| {
+                let _ = observer.on_start();
+                match self.chunk_size {
+                    Some(chunk_size) => {
+                        let mut updates = init_updates.into_iter();
+                        while updates.len() > 0 {
+                            let chunk = updates.by_ref().take(chunk_size).collect::<Vec<_>>();
+                            let _ = observer.on_updates(Box::new(chunk.into_iter()));
+                        }
+                    }
+                    None => {
+                        let _ = observer.on_updates(Box::new(init_updates.into_iter()));
+                    }
+                }
+                let _ = observer.on_commit();
+            });
+        }
+        let _ = delivering(|| observer.on_init_complete());
+        count
+    }
+
+    /// Subscribe an observer without sending it the currently
+    /// accumulated state, i.e., the observer only receives transactions
+    /// occurring after the subscription. This is the direct-subscribe
+    /// counterpart of subscribing to an observable created via
+    /// `create_observable`.
+    pub fn subscribe_no_replay(
+        &mut self,
+        observer: ObserverBox<Update<V>, E>,
+    ) -> Result<usize, ObserverBox<Update<V>, E>> {
+        trace!(
+            "DistributingAccumulator({})::subscribe_no_replay()",
+            self.id
+        );
+        let (subscription, _) = self.subscribe_with(
+            observer,
+            SubscribeOptions {
+                replay: false,
+                priority: None,
+            },
+        )?;
+        Ok(subscription)
+    }
+
+    /// Subscribe an observer with the given options, sending it the
+    /// currently accumulated state first if it is to be replayed.
+    /// Returns the subscription along with the number of updates sent
+    /// to initialize the observer.
+    fn subscribe_with(
+        &mut self,
+        mut observer: ObserverBox<Update<V>, E>,
+        options: SubscribeOptions,
+    ) -> Result<(usize, usize), ObserverBox<Update<V>, E>> {
+        if self.lifecycle != Lifecycle::Active {
+            return Err(observer);
+        }
+        let count = if options.replay {
+            self.send_init_updates(&mut observer)
+        } else {
+            0
+        };
+        let subscription = self.subscribe_distributor(observer)?;
+        if options.replay {
+            self.distributor.record_init_updates(subscription, count);
+        }
+        if let Some(priority) = options.priority {
+            self.distributor.set_priority(subscription, priority);
+        }
+        self.record_join(subscription);
+        Ok((subscription, count))
+    }
+
+    /// Feed the output of this accumulator into the given downstream
+    /// accumulator. The state of the downstream accumulator is first
+    /// brought in line with ours, by inserting the values it misses and
+    /// deleting the values we lack, after which it receives all of our
+    /// transactions. When our upstream completes, the downstream
+    /// accumulator receives the deletion of our state, but does not
+    /// complete itself, so that its observers see each value deleted
+    /// exactly once.
+    ///
+    /// Returns the subscription of the downstream accumulator. Fails
+    /// with `AccumulatorError::InTransaction` if a transaction is in
+    /// progress and with `AccumulatorError::Inactive` if the accumulator
+    /// completed or was shut down, in which case the downstream
+    /// accumulator is left untouched, or with the error the downstream
+    /// accumulator reported while being brought in line with us.
+    pub fn chain_to(
+        &mut self,
+        downstream: &SharedObserver<Self>,
+    ) -> Result<usize, AccumulatorError<E>> {
+        trace!("DistributingAccumulator({})::chain_to()", self.id);
+        if self.observer.in_transaction() {
+            return Err(AccumulatorError::InTransaction);
+        }
+        if self.lifecycle != Lifecycle::Active {
+            return Err(AccumulatorError::Inactive);
+        }
+
+        let mut chained = ChainedObserver(downstream.clone());
+        let updates = diff_states(
+            &downstream.lock().unwrap().get_current_state(),
+            &self.get_current_state(),
+        );
+        if !updates.is_empty() {
+            chained.on_start().map_err(AccumulatorError::Observer)?;
+            chained
+                .on_updates(Box::new(updates.into_iter()))
+                .map_err(AccumulatorError::Observer)?;
+            chained.on_commit().map_err(AccumulatorError::Observer)?;
+        }
+        self.subscribe_no_replay(Box::new(chained))
+            .map_err(|_| AccumulatorError::Inactive)
+    }
+
+    /// Obtain a handle for multiple threads to push updates into the
+    /// given accumulator concurrently. The updates pushed through all
+    /// clones of the handle are committed as a single transaction by
+    /// each `commit`, so that transactions remain well-formed.
+    pub fn concurrent_input(accumulator: &SharedObserver<Self>) -> ConcurrentInput<V, E> {
+        ConcurrentInput::new(Box::new(accumulator.clone()))
+    }
+
+    /// Subscribe an observer to the distributor, wrapped so that failed
+    /// deliveries are retried if so configured.
+    fn subscribe_distributor(
+        &mut self,
+        observer: ObserverBox<Update<V>, E>,
+    ) -> Result<usize, ObserverBox<Update<V>, E>> {
+        match self.retry {
+            Some(policy) => Ok(self.distributor.subscribe_with(|cancel| {
+                Box::new(RetryingObserver::new(observer, policy, cancel))
+            })),
+            None => self.distributor.subscribe(observer),
+        }
+    }
+
+    /// Cancel a subscription, returning the observer along with the
+    /// updates of the transaction in progress it has received so far,
+    /// e.g., to hand them off to a replacement observer. If no
+    /// transaction is in progress, no updates are returned.
+    pub fn unsubscribe_drain(
+        &mut self,
+        subscription: &usize,
+    ) -> Option<(ObserverBox<Update<V>, E>, Vec<Update<V>>)> {
+        trace!(
+            "DistributingAccumulator({})::unsubscribe_drain({})",
+            self.id,
+            subscription
+        );
+        let observer = self.distributor.unsubscribe(subscription)?;
+        let _ = self.acks.remove(subscription);
+        let skip = self.joined.remove(subscription).unwrap_or(0);
+        Some((observer, self.observer.forwarded_updates(skip)))
+    }
+
+    /// Re-send the current values of the given relation to the
+    /// observer of the given subscription only, as a self-contained
+    /// transaction, e.g., to let it recover from a transient fault.
+    /// Nothing is sent if there is no such subscription. A pending
+    /// batch of transactions is flushed beforehand. Fails with
+    /// `AccumulatorError::InTransaction` if a transaction is in
+    /// progress.
+    pub fn replay_relation(
+        &mut self,
+        subscription: &usize,
+        relid: RelId,
+    ) -> Result<(), AccumulatorError<E>> {
+        trace!(
+            "DistributingAccumulator({})::replay_relation({}, {})",
+            self.id,
+            subscription,
+            self.relation_name(relid)
+        );
+        if self.observer.in_transaction() {
+            return Err(AccumulatorError::InTransaction);
+        }
+        self.flush().map_err(AccumulatorError::Observer)?;
+
+        let updates = self
+            .observer
+            .state_handle()
+            .get_relation(relid)
+            .into_iter()
+            .map(|v| Update::Insert { relid, v })
+            .collect::<Vec<_>>();
+
+        let result = self.distributor.deliver_to(*subscription, |observer| {
+            observer.on_start()?;
+            if !updates.is_empty() {
+                observer.on_updates(Box::new(updates.into_iter()))?;
+            }
+            observer.on_commit()
+        });
+        result.unwrap_or(Ok(())).map_err(AccumulatorError::Observer)
+    }
+
+    /// Convert the given state into insertions, sorted if so
+    /// configured.
+    fn insertions(&self, state: HashMap<RelId, HashSet<V>>) -> Vec<Update<V>> {
+        let mut updates = state
+            .into_iter()
+            .flat_map(|(relid, vs)| vs.into_iter().map(move |v| Update::Insert { relid, v }))
+            .collect::<Vec<_>>();
+        if let Some(sort) = self.sort_init_updates {
+            sort(&mut updates);
+        }
+        updates
+    }
+
+    /// Let a new subscription take part in the transaction in progress,
+    /// if any, starting it for the observer, and remember how many of
+    /// the transaction's updates the subscription missed.
+    fn record_join(&mut self, subscription: usize) {
+        let forwarded = self.observer.forwarded_count();
+        if forwarded > 0 {
+            let _ = self.joined.insert(subscription, forwarded);
+        }
+        let _ = self.distributor.join_transaction(subscription);
+    }
+
+    /// Retrieve the number of observers currently attached, be it
+    /// directly through `subscribe` or through an observable created
+    /// via `create_observable`.
+    pub fn observer_count(&self) -> usize {
+        trace!("DistributingAccumulator({})::observer_count()", self.id);
+        self.distributor.observer_count()
+    }
+
+    /// Retrieve the IDs of all live subscriptions, in ascending order.
+    pub fn subscription_ids(&self) -> Vec<usize> {
+        trace!("DistributingAccumulator({})::subscription_ids()", self.id);
+        self.distributor.subscription_ids()
+    }
+
+    /// Shut down the accumulator: clear the state of all observers as
+    /// `on_completed` does and cancel all subscriptions, returning the
+    /// observers in the order they subscribed, e.g., to attach them
+    /// elsewhere. Observers are completed in reverse of the order they
+    /// subscribed in, as by `on_completed`. Errors reported by observers
+    /// are ignored.
+    pub fn shutdown(mut self) -> Vec<ObserverBox<Update<V>, E>> {
+        trace!("DistributingAccumulator({})::shutdown()", self.id);
+        self.close()
+    }
+
+    /// Shut down the accumulator as `shutdown` does, but keep it
+    /// around, e.g., while it is still shared with others. Observers
+    /// attempting to subscribe afterwards are handed back and events
+    /// are rejected by the fallible methods, such as `try_on_start`.
+    pub fn close(&mut self) -> Vec<ObserverBox<Update<V>, E>> {
+        trace!("DistributingAccumulator({})::close()", self.id);
+        let _ = self.on_completed();
+        self.lifecycle = Lifecycle::ShutDown;
+        self.distributor
+            .subscription_ids()
+            .iter()
+            .filter_map(|subscription| self.distributor.unsubscribe(subscription))
+            .collect()
+    }
+
+    /// Retrieve statistics about the updates processed so far, per
+    /// relation. The insertion and deletion counters are cumulative over
+    /// the accumulator's lifetime and survive `on_completed`. They only
+    /// cover committed transactions.
+    pub fn stats(&self) -> HashMap<RelId, RelStats> {
+        trace!("DistributingAccumulator({})::stats()", self.id);
+        self.observer.stats()
+    }
+
+    /// Retrieve the statistics of each subscription, i.e., when it was
+    /// created, the number of updates sent to initialize its observer
+    /// with the state accumulated before, and the number of updates
+    /// distributed to it since.
+    pub fn subscription_stats(&self) -> HashMap<usize, SubStats> {
+        trace!("DistributingAccumulator({})::subscription_stats()", self.id);
+        self.distributor.subscription_stats()
+    }
+
+    /// Retrieve the updates that would have been forwarded to observers
+    /// so far, if built with `AccumulatorBuilder::dry_run`.
+    pub fn dry_run_updates(&self) -> &[Update<V>] {
+        self.observer.dry_run_updates()
+    }
+
+    /// Check whether a transaction is in progress, i.e., whether we
+    /// received an `on_start` not yet followed by an `on_commit` or
+    /// `on_completed`.
+    pub fn in_transaction(&self) -> bool {
+        trace!("DistributingAccumulator({})::in_transaction()", self.id);
+        self.observer.in_transaction()
+    }
+
+    /// Check that the accumulator is able to process an event, given
+    /// whether a transaction is expected to be in progress, or report
+    /// the violation otherwise.
+    fn check_event(
+        &self,
+        in_transaction: bool,
+        violation: ProtocolViolation,
+    ) -> Result<(), AccumulatorError<E>> {
+        if self.lifecycle == Lifecycle::ShutDown {
+            Err(AccumulatorError::Shutdown)
+        } else if self.observer.is_poisoned() {
+            Err(AccumulatorError::Poisoned)
+        } else if self.observer.in_transaction() != in_transaction {
+            Err(AccumulatorError::ProtocolViolation(violation))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Start a transaction as `on_start` does, reporting the failure
+    /// to do so in detail instead of panicking on protocol violations.
+    pub fn try_on_start(&mut self) -> Result<(), AccumulatorError<E>> {
+        trace!("DistributingAccumulator({})::try_on_start", self.id);
+        self.check_event(false, ProtocolViolation::StartInTransaction)?;
+        self.on_start().map_err(AccumulatorError::Observer)
+    }
+
+    /// Process updates as `on_updates` does, reporting the failure to
+    /// do so in detail instead of panicking on protocol violations.
+    pub fn try_on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), AccumulatorError<E>> {
+        trace!("DistributingAccumulator({})::try_on_updates", self.id);
+        self.check_event(true, ProtocolViolation::UpdatesWithoutStart)?;
+        self.on_updates(updates).map_err(AccumulatorError::Observer)
+    }
+
+    /// Commit a transaction as `on_commit` does, reporting the failure
+    /// to do so in detail instead of panicking on protocol violations.
+    pub fn try_on_commit(&mut self) -> Result<(), AccumulatorError<E>> {
+        trace!("DistributingAccumulator({})::try_on_commit", self.id);
+        self.check_event(true, ProtocolViolation::CommitWithoutStart)?;
+        self.on_commit().map_err(AccumulatorError::Observer)
+    }
+
+    /// Retrieve the number of times the upstream completed.
+    pub fn completed_count(&self) -> u64 {
+        trace!("DistributingAccumulator({})::completed_count()", self.id);
+        self.observer.completed_count()
+    }
+
+    /// Retrieve a read-only handle to the accumulated state, e.g., to
+    /// query it from other threads. Transactions committed later on
+    /// become visible through the handle.
+    pub fn state_handle(&self) -> StateHandle<V> {
+        trace!("DistributingAccumulator({})::state_handle()", self.id);
+        self.observer.state_handle()
+    }
+
+    /// Estimate the number of bytes occupied by the accumulated state,
+    /// e.g., for autoscaling decisions, as
+    /// `AccumulatingObserver::estimated_memory_bytes` does.
+    pub fn estimated_memory_bytes(&self) -> usize
+    where
+        V: ApproxSize,
+    {
+        trace!(
+            "DistributingAccumulator({})::estimated_memory_bytes()",
+            self.id
+        );
+        self.observer.estimated_memory_bytes()
+    }
+
+    /// Take a snapshot of the accumulated state, e.g., to checkpoint it.
+    pub fn snapshot(&self) -> AccumulatorSnapshot<V> {
+        trace!("DistributingAccumulator({})::snapshot()", self.id);
+        self.observer.snapshot()
+    }
+
+    /// Replace the accumulated state with the one captured in the given
+    /// snapshot. Subsequent subscribers receive the restored state.
+    pub fn restore(&mut self, snapshot: AccumulatorSnapshot<V>) {
+        trace!("DistributingAccumulator({})::restore()", self.id);
+        self.observer.restore(snapshot)
+    }
+
+    /// Take a snapshot of the accumulated state, as `snapshot` does,
+    /// and write it to the given writer in the format of the codec `C`.
+    pub fn snapshot_to_writer<C, W>(&self, writer: W) -> Result<(), String>
+    where
+        C: SnapshotCodec,
+        W: Write,
+        V: Serialize,
+    {
+        trace!("DistributingAccumulator({})::snapshot_to_writer()", self.id);
+        C::encode(writer, &self.snapshot())
+    }
+
+    /// Replace the accumulated state with the snapshot read from the
+    /// given reader in the format of the codec `C`, as written by
+    /// `snapshot_to_writer`. The state is left untouched if the
+    /// snapshot cannot be read.
+    pub fn restore_from_reader<C, R>(&mut self, reader: R) -> Result<(), String>
+    where
+        C: SnapshotCodec,
+        R: Read,
+        V: DeserializeOwned,
+    {
+        trace!(
+            "DistributingAccumulator({})::restore_from_reader()",
+            self.id
+        );
+        let snapshot = C::decode(reader)?;
+        self.restore(snapshot);
+        Ok(())
+    }
+
+    /// Check whether the given accumulator holds the same state as we
+    /// do. The multiplicity of each value is only compared if we count
+    /// it, i.e., under `DuplicatePolicy::CountWeight`, otherwise the
+    /// accumulators are compared by their values only. The states are
+    /// equal exactly if `state_diff` is empty.
+    pub fn state_eq(&self, other: &impl Accumulator<V, E>) -> bool {
+        trace!("DistributingAccumulator({})::state_eq()", self.id);
+        if self.counts_weights() {
+            self.get_current_state_weighted() == other.get_current_state_weighted()
+        } else {
+            let non_empty = |state: HashMap<RelId, HashSet<V>>| {
+                state
+                    .into_iter()
+                    .filter(|(_, vs)| !vs.is_empty())
+                    .collect::<HashMap<_, _>>()
+            };
+            non_empty(self.get_current_state()) == non_empty(other.get_current_state())
+        }
+    }
+
+    /// Compute the updates making the state of the given accumulator
+    /// match ours, as `AccumulatorSnapshot::diff` does for snapshots.
+    /// Under `DuplicatePolicy::CountWeight`, values are inserted or
+    /// deleted as often as their multiplicities differ.
+    pub fn state_diff(&self, other: &impl Accumulator<V, E>) -> Vec<Update<V>> {
+        trace!("DistributingAccumulator({})::state_diff()", self.id);
+        if self.counts_weights() {
+            diff_weighted_states(
+                &other.get_current_state_weighted(),
+                &self.get_current_state_weighted(),
+            )
+        } else {
+            diff_states(&other.get_current_state(), &self.get_current_state())
+        }
+    }
+
+    /// Check whether we keep track of the multiplicity of each value.
+    fn counts_weights(&self) -> bool {
+        self.observer.duplicate_policy() == DuplicatePolicy::CountWeight
+    }
+}
+
+impl<V, E> DistributingAccumulator<Update<V>, V, E>
+where
+    V: Debug + Send + Clone + Ord + Hash + 'static,
+    E: Debug + Send + 'static,
+{
+    /// Render the accumulated state in a human readable form, meant for
+    /// debugging. Relations are listed in ascending order of their IDs,
+    /// each with a header stating its name, if registered via
+    /// `AccumulatorBuilder::with_registry`, and the number of values
+    /// followed by the sorted values, one per line. Empty relations are
+    /// omitted.
+    pub fn dump_state(&self) -> String {
+        trace!("DistributingAccumulator({})::dump_state()", self.id);
+        let state = self
+            .get_current_state()
+            .into_iter()
+            .filter(|(_, vs)| !vs.is_empty())
+            .collect::<BTreeMap<_, _>>();
+
+        let mut dump = String::new();
+        for (relid, vs) in state {
+            let _ = writeln!(
+                dump,
+                "relation {} ({} values):",
+                self.relation_name(relid),
+                vs.len()
+            );
+            for v in vs.into_iter().collect::<BTreeSet<_>>() {
+                let _ = writeln!(dump, "  {:?}", v);
+            }
+        }
+        dump
+    }
+
+    /// Write the accumulated state as a DDlog command file, which can be
+    /// replayed into the standalone DDlog CLI, e.g., for debugging. The
+    /// file consists of a single transaction with an `insert` command
+    /// per value, naming relations as in the given registry. Relations
+    /// and values are written in ascending order. If a relation holding
+    /// values is missing from the registry, an error of kind
+    /// `InvalidInput` is returned and nothing is written, as the CLI
+    /// only accepts relations by name.
+    pub fn export_commands(&self, registry: &RelationRegistry, w: &mut dyn Write) -> IoResult<()>
+    where
+        V: Display,
+    {
+        trace!("DistributingAccumulator({})::export_commands()", self.id);
+        let mut state = BTreeMap::<RelId, BTreeSet<V>>::new();
+        let mut missing = None;
+        self.for_each_value(|relid, v| {
+            if registry.contains_key(&relid) {
+                let _ = state.entry(relid).or_default().insert(v.clone());
+            } else {
+                missing = Some(missing.map_or(relid, |missing: RelId| missing.min(relid)));
+            }
+        });
+        if let Some(relid) = missing {
+            return Err(IoError::new(
+                ErrorKind::InvalidInput,
+                format!("relation {} is missing from the registry", relid),
+            ));
+        }
+
+        writeln!(w, "start;")?;
+        for (relid, vs) in state {
+            for v in vs {
+                writeln!(w, "insert {}[{}];", registry[&relid], v)?;
+            }
+        }
+        writeln!(w, "commit;")
+    }
+}
+
+/// The methods for the Observable trait are delegated to the TxnDistributor
+impl<V, E> Observable<Update<V>, E> for DistributingAccumulator<Update<V>, V, E>
+where
+    V: Debug + Send + Clone + Eq + Hash + 'static,
+    E: Debug + Send + 'static,
+{
+    type Subscription = usize;
+
+    fn subscribe(
+        &mut self,
+        observer: ObserverBox<Update<V>, E>,
+    ) -> Result<Self::Subscription, ObserverBox<Update<V>, E>> {
+        trace!("DistributingAccumulator({})::subscribe()", self.id);
+        self.subscribe_counted(observer)
+            .map(|(subscription, _)| subscription)
+    }
+
+    fn unsubscribe(
+        &mut self,
+        subscription: &Self::Subscription,
+    ) -> Option<ObserverBox<Update<V>, E>> {
+        trace!(
+            "DistributingAccumulator({})::unsubscribe({})",
+            self.id,
+            subscription
+        );
+        let _ = self.joined.remove(subscription);
+        let _ = self.acks.remove(subscription);
+        self.distributor.unsubscribe(subscription)
+    }
+}
+
+/// All calls except `on_completed` of the Observer trait are delegated to the AccumulatingObserver.
+/// `on_completed` triggers the deletion of the accumulated state for all observers.
+impl<V, E> Observer<Update<V>, E> for DistributingAccumulator<Update<V>, V, E>
+where
+    V: Debug + Send + Eq + Hash + Clone + 'static,
+    E: Debug + Send + 'static,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("DistributingAccumulator({})::on_start", self.id);
+        #[cfg(feature = "tracing")]
+        let span = tracing::trace_span!("on_start", accumulator_id = self.id);
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+
+        self.await_in_flight()?;
+        self.joined.clear();
+        if self.lifecycle == Lifecycle::Completed {
+            self.lifecycle = Lifecycle::Active;
+        }
+        if let Some(history) = &mut self.history {
+            history.start();
+        }
+        self.observer.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("DistributingAccumulator({})::on_commit", self.id);
+        #[cfg(feature = "tracing")]
+        let span = tracing::trace_span!(
+            "on_commit",
+            accumulator_id = self.id,
+            update_count = self.observer.transaction_size()
+        );
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+
+        self.joined.clear();
+        if let Some(history) = &mut self.history {
+            history.commit();
+        }
+        self.observer.on_commit()?;
+        let _ = self.committed.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("DistributingAccumulator({})::on_updates", self.id);
+        // record the updates in the history as they are consumed
+        let updates: Box<dyn Iterator<Item = Update<V>> + '_> = match &mut self.history {
+            Some(history) => Box::new(updates.inspect(move |u| history.record(u))),
+            None => updates,
+        };
+        #[cfg(feature = "tracing")]
+        let span = tracing::trace_span!(
+            "on_updates",
+            accumulator_id = self.id,
+            update_count = tracing::field::Empty
+        );
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+        // count the updates as they are consumed
+        #[cfg(feature = "tracing")]
+        let count = std::cell::Cell::new(0);
+        #[cfg(feature = "tracing")]
+        let updates = Box::new(updates.inspect(|_| count.set(count.get() + 1)));
+
+        let result = self.observer.on_updates(updates);
+        #[cfg(feature = "tracing")]
+        let _ = span.record("update_count", count.get());
+        result
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("DistributingAccumulator({})::on_abort", self.id);
+        #[cfg(feature = "tracing")]
+        let span = tracing::trace_span!(
+            "on_abort",
+            accumulator_id = self.id,
+            update_count = self.observer.transaction_size()
+        );
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+
+        self.joined.clear();
+        if let Some(history) = &mut self.history {
+            history.abort();
+        }
+        self.observer.on_abort()
+    }
+
+    fn on_barrier(&mut self) -> Result<(), E> {
+        self.barrier()
+    }
+
+    /// sends a deletion update to all observers, thus clearing the accumulated state.
+    ///
+    /// Observers are completed in reverse of the order they subscribed
+    /// in, unless their priorities say otherwise.
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("DistributingAccumulator({})::on_completed", self.id);
+        #[cfg(feature = "tracing")]
+        let span = tracing::trace_span!(
+            "on_completed",
+            accumulator_id = self.id,
+            update_count = tracing::field::Empty
+        );
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+
+        // a transaction still in progress is aborted, reverting the
+        // updates of it our observers received already
+        if self.observer.in_transaction() {
+            self.joined.clear();
+            if let Some(history) = &mut self.history {
+                history.abort();
+            }
+        }
+        let _ = self.observer.abort_transaction();
+        // the state is cleared anyway, so take it out instead of copying
+        // it, and produce the deletions from it lazily; if paused, our
+        // observers only know of the state as of the pause
+        let mut state = self.observer.take_state();
+        if let Some(paused) = self.paused.take() {
+            let _ = self.observer.subscribe(self.downstream());
+            state = paused;
+        }
+
+        let distributor = &mut self.distributor;
+        let _ = distributor.on_completed();
+        #[cfg(feature = "tracing")]
+        let _ = span.record(
+            "update_count",
+            state.values().map(HashSet::len).sum::<usize>(),
+        );
+        if state.values().any(|vs| !vs.is_empty()) {
+            trace!(
+                "DistributingAccumulator({:?}) clearing state of observers",
+                self.id
+            );
+            let delete_updates = state.into_iter().flat_map(|(relid, vs)| {
+                vs.into_iter()
+                    .map(move |v| Update::DeleteValue { relid, v })
+            });
+
+            let _ = distributor.on_start();
+            let _ = distributor.on_updates(Box::new(delete_updates));
+            let _ = distributor.on_commit();
+        }
+
+        if self.lifecycle == Lifecycle::Active {
+            self.lifecycle = Lifecycle::Completed;
+        }
+        self.observer.on_completed()
+    }
+}
+
+/// An observer feeding the transactions of an accumulator into another
+/// one, as set up by `DistributingAccumulator::chain_to`. Completion is
+/// not forwarded, as it is conveyed by the deletion of the state that
+/// the upstream accumulator emits when completing.
+#[derive(Debug)]
+struct ChainedObserver<O>(SharedObserver<O>);
+
+impl<O, V, E> Observer<Update<V>, E> for ChainedObserver<O>
+where
+    O: Observer<Update<V>, E>,
+    V: Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.0.on_start()
+    }
+
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        self.0.on_start_seq(seq)
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.0.on_commit()
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        self.0.on_updates(updates)
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.0.on_abort()
+    }
+
+    fn on_barrier(&mut self) -> Result<(), E> {
+        self.0.on_barrier()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    use maplit::hashmap;
+    use maplit::hashset;
+
+    use std::panic::catch_unwind;
+    use std::panic::AssertUnwindSafe;
+    use std::sync::atomic::AtomicBool;
+    use std::thread::sleep;
+    use std::thread::spawn;
+    use std::vec::IntoIter;
+
+    use crate::accumulate::DuplicateError;
+    use crate::accumulate::FaultyObserver;
+    use crate::accumulate::ObserverCall;
+    use crate::accumulate::RecordedEvent;
+    use crate::accumulate::RecordingObserver;
+    use crate::accumulate::{eq_updates, UpdatesMockObserver};
+    use crate::await_expected;
+    use crate::CallbackObserver;
+    use crate::MockObserver;
+
+    #[cfg(feature = "tracing")]
+    use tracing::field::Field;
+    #[cfg(feature = "tracing")]
+    use tracing::field::Visit;
+    #[cfg(feature = "tracing")]
+    use tracing::span::Attributes;
+    #[cfg(feature = "tracing")]
+    use tracing::span::Id as SpanId;
+    #[cfg(feature = "tracing")]
+    use tracing::span::Record;
+    #[cfg(feature = "tracing")]
+    use tracing::Event;
+    #[cfg(feature = "tracing")]
+    use tracing::Metadata;
+    #[cfg(feature = "tracing")]
+    use tracing::Subscriber;
+
+    pub fn get_usize_updates_1() -> Box<IntoIter<Update<usize>>> {
+        Box::new(
+            vec![
+                Update::Insert { relid: 1, v: 1 },
+                Update::Insert { relid: 2, v: 2 },
+                Update::Insert { relid: 3, v: 3 },
+            ]
+            .into_iter(),
+        )
+    }
+
+    fn get_usize_updates_2() -> Box<IntoIter<Update<usize>>> {
+        Box::new(
+            vec![
+                Update::Insert { relid: 1, v: 2 },
+                Update::Insert { relid: 1, v: 3 },
+                Update::Insert { relid: 2, v: 3 },
+            ]
+            .into_iter(),
+        )
+    }
+
+    pub fn get_usize_updates_3() -> Box<IntoIter<Update<usize>>> {
+        Box::new(
+            vec![
+                Update::Insert { relid: 4, v: 1 },
+                Update::Insert { relid: 4, v: 2 },
+                Update::Insert { relid: 4, v: 3 },
+                Update::Insert { relid: 4, v: 4 },
+            ]
+            .into_iter(),
+        )
+    }
+
+    fn get_usize_delete_updates_1() -> Box<IntoIter<Update<usize>>> {
+        Box::new(
+            vec![
+                Update::DeleteValue { relid: 1, v: 1 },
+                Update::DeleteValue { relid: 2, v: 2 },
+                Update::DeleteValue { relid: 3, v: 3 },
+            ]
+            .into_iter(),
+        )
+    }
+
+    /// Test subscribing and unsubscribing for a `DistributingAccumulator`.
+    /// A subscription can occur directly via `subscribe` or via `create_observable`.
+    #[test]
+    fn subscribe_unsubscribe() {
+        let mut accumulator = DistributingAccumulator::<Update<()>, (), ()>::new();
+        let observer = Box::new(MockObserver::new());
+
+        let subscription = accumulator.subscribe(observer);
+        assert!(subscription.is_ok());
+        assert!(accumulator.unsubscribe(&subscription.unwrap()).is_some());
+
+        let mut observable = accumulator.create_observable();
+        let observer = Box::new(MockObserver::new());
+
+        let subscription = observable.subscribe(observer);
+        assert!(subscription.is_ok());
+        assert!(observable.unsubscribe(&subscription.unwrap()).is_some());
+    }
+
+    /// Test multiple direct subscriptions via `subscribe` to a `AccumulatingObserver`.
+    #[test]
+    fn multiple_subscribe_direct_distributor() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mock1 = Arc::new(Mutex::new(MockObserver::new()));
+        let mock2 = Arc::new(Mutex::new(MockObserver::new()));
+
+        assert!(accumulator.subscribe(Box::new(mock1.clone())).is_ok());
+        assert!(accumulator.subscribe(Box::new(mock2.clone())).is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_start, 0);
+        assert_eq!(mock2.lock().unwrap().called_on_start, 0);
+
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_start, 1);
+        assert_eq!(mock2.lock().unwrap().called_on_start, 1);
+        assert_eq!(mock1.lock().unwrap().called_on_updates, 3);
+        assert_eq!(mock2.lock().unwrap().called_on_updates, 3);
+
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_commit, 1);
+        assert_eq!(mock2.lock().unwrap().called_on_commit, 1);
+
+        assert_eq!(accumulator.on_completed(), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_start, 2);
+        assert_eq!(mock2.lock().unwrap().called_on_start, 2);
+        assert_eq!(mock1.lock().unwrap().called_on_updates, 6);
+        assert_eq!(mock2.lock().unwrap().called_on_updates, 6);
+        assert_eq!(mock1.lock().unwrap().called_on_commit, 2);
+        assert_eq!(mock2.lock().unwrap().called_on_commit, 2);
+        assert_eq!(mock1.lock().unwrap().called_on_completed, 1);
+        assert_eq!(mock2.lock().unwrap().called_on_completed, 1);
+    }
+
+    /// Test multiple indirect subscriptions via `create_observable` to a `DistributingAccumulator`.
+    #[test]
+    fn multiple_subscribe_indirect_distributor() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mock1 = Arc::new(Mutex::new(MockObserver::new()));
+        let mock2 = Arc::new(Mutex::new(MockObserver::new()));
+        let mut observable1 = accumulator.create_observable();
+        let mut observable2 = accumulator.create_observable();
+
+        assert!(observable1.subscribe(Box::new(mock1.clone())).is_ok());
+        assert!(observable2.subscribe(Box::new(mock2.clone())).is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_start, 0);
+        assert_eq!(mock2.lock().unwrap().called_on_start, 0);
+
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_start, 1);
+        assert_eq!(mock2.lock().unwrap().called_on_start, 1);
+        assert_eq!(mock1.lock().unwrap().called_on_updates, 3);
+        assert_eq!(mock2.lock().unwrap().called_on_updates, 3);
+
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_commit, 1);
+        assert_eq!(mock2.lock().unwrap().called_on_commit, 1);
+    }
+
+    /// Test pass-through filter behaviour for transactions via a `DistributingAccumulator`.
+    #[test]
+    fn transparent_transactions_proxy() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mock1 = Arc::new(Mutex::new(MockObserver::new()));
+        let mock2 = Arc::new(Mutex::new(MockObserver::new()));
+        let mut observable = accumulator.create_observable();
+
+        assert!(observable.subscribe(Box::new(mock1.clone())).is_ok());
+        assert!(accumulator.subscribe(Box::new(mock2.clone())).is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_start, 0);
+        assert_eq!(mock2.lock().unwrap().called_on_start, 0);
+
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_start, 1);
+        assert_eq!(mock2.lock().unwrap().called_on_start, 1);
+        assert_eq!(mock1.lock().unwrap().called_on_updates, 3);
+        assert_eq!(mock2.lock().unwrap().called_on_updates, 3);
+
+        assert_eq!(accumulator.on_updates(get_usize_updates_2()), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_updates, 6);
+        assert_eq!(mock2.lock().unwrap().called_on_updates, 6);
+
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_commit, 1);
+        assert_eq!(mock2.lock().unwrap().called_on_commit, 1);
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_start, 1);
+        assert_eq!(mock2.lock().unwrap().called_on_start, 1);
+
+        assert_eq!(accumulator.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_start, 2);
+        assert_eq!(mock2.lock().unwrap().called_on_start, 2);
+        assert_eq!(mock1.lock().unwrap().called_on_updates, 10);
+        assert_eq!(mock2.lock().unwrap().called_on_updates, 10);
+
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_commit, 2);
+        assert_eq!(mock2.lock().unwrap().called_on_commit, 2);
+
+        assert_eq!(accumulator.on_completed(), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_start, 3);
+        assert_eq!(mock2.lock().unwrap().called_on_start, 3);
+        assert_eq!(mock1.lock().unwrap().called_on_updates, 20);
+        assert_eq!(mock2.lock().unwrap().called_on_updates, 20);
+        assert_eq!(mock1.lock().unwrap().called_on_commit, 3);
+        assert_eq!(mock2.lock().unwrap().called_on_commit, 3);
+        assert_eq!(mock1.lock().unwrap().called_on_completed, 1);
+        assert_eq!(mock2.lock().unwrap().called_on_completed, 1);
+    }
+
+    /// when a new downstream consumer subscribes, it should be updated with the current values
+    #[test]
+    fn test_fix_up_updates() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mock1 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let mock2 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let mock3 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+
+        // start with one observer and give it updates
+        assert!(accumulator.subscribe(Box::new(mock1.clone())).is_ok());
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        let received_updates = mock1.lock().unwrap().received_updates.clone();
+        assert_eq!(received_updates.len(), 3);
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 1, v: 1 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 2, v: 2 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 3, v: 3 })));
+
+        // create an un-accumulated observable
+        let mut observable = accumulator.create_observable();
+
+        // provide second batch of updates to the accumulator
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_2()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        // the observable does not see any updates until subscribed too
+        assert!(observable.subscribe(Box::new(mock2.clone())).is_ok());
+        let received_updates = mock2.lock().unwrap().received_updates.clone();
+        assert_eq!(received_updates.len(), 0);
+
+        // the following updates should be received via the create_observable
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        let received_updates = mock2.lock().unwrap().received_updates.clone();
+        assert_eq!(received_updates.len(), 4);
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 1 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 2 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 3 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 4 })));
+
+        // a new observer via subscribe() should receive all updates
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_delete_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        assert!(accumulator.subscribe(Box::new(mock3.clone())).is_ok());
+        let received_updates = mock3.lock().unwrap().received_updates.clone();
+        assert_eq!(received_updates.len(), 7);
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 1, v: 2 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 1, v: 3 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 2, v: 3 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 1 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 2 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 3 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 4 })));
+    }
+
+    /// a downstream consumer subscribing without replay should only receive new updates
+    #[test]
+    fn test_no_replay_updates() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mock1 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let mock2 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+
+        // start with one observer and give it updates
+        assert!(accumulator.subscribe(Box::new(mock1.clone())).is_ok());
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        let received_updates = mock1.lock().unwrap().received_updates.clone();
+        assert_eq!(received_updates.len(), 3);
+
+        // a new observer does not receive the accumulated state
+        assert!(accumulator
+            .subscribe_no_replay(Box::new(mock2.clone()))
+            .is_ok());
+        let received_updates = mock2.lock().unwrap().received_updates.clone();
+        assert_eq!(received_updates.len(), 0);
+        assert_eq!(mock2.lock().unwrap().called_on_start, 0);
+
+        // the following updates should be received
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        let received_updates = mock2.lock().unwrap().received_updates.clone();
+        assert_eq!(received_updates.len(), 4);
+        assert!(received_updates.iter().all(|u| u.relid() == 4));
+    }
+
+    /// when an upstream source calls `on_completed`,
+    /// the accumulated state should be removed from observers
+    #[test]
+    fn test_delete_updates() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mock1 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let mock2 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+
+        assert!(accumulator.subscribe(Box::new(mock1.clone())).is_ok());
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_delete_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        assert!(accumulator.subscribe(Box::new(mock2.clone())).is_ok());
+        let received_updates = mock2.lock().unwrap().received_updates.clone();
+        assert_eq!(received_updates.len(), 4);
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 1 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 2 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 3 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 4 })));
+
+        assert_eq!(accumulator.on_completed(), Ok(()));
+
+        let received_updates = mock1.lock().unwrap().received_updates.clone();
+        assert_eq!(received_updates.len(), 14); // 3 + 4 inserts, 3 manual deletions, 4 automatic deletions
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::DeleteValue { relid: 4, v: 1 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::DeleteValue { relid: 4, v: 2 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::DeleteValue { relid: 4, v: 3 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::DeleteValue { relid: 4, v: 4 })));
+
+        let received_updates = mock2.lock().unwrap().received_updates.clone();
+        assert_eq!(received_updates.len(), 8); // 4 inserts, 4 automatic deletions
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::DeleteValue { relid: 4, v: 1 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::DeleteValue { relid: 4, v: 2 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::DeleteValue { relid: 4, v: 3 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::DeleteValue { relid: 4, v: 4 })));
+    }
+
+    /// the weighted state reflects the net multiplicity of each value
+    #[test]
+    fn weighted_state() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_2()), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let weighted = accumulator.get_current_state_weighted();
+        assert_eq!(
+            weighted[&1],
+            vec![(1, 2), (2, 1), (3, 1)].into_iter().collect()
+        );
+        assert_eq!(weighted[&2], vec![(2, 2), (3, 1)].into_iter().collect());
+        assert_eq!(weighted[&3], vec![(3, 2)].into_iter().collect());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_delete_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_delete_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_delete_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let weighted = accumulator.get_current_state_weighted();
+        assert_eq!(
+            weighted[&1],
+            vec![(1, -1), (2, 1), (3, 1)].into_iter().collect()
+        );
+        assert_eq!(weighted[&2], vec![(2, -1), (3, 1)].into_iter().collect());
+        assert_eq!(weighted[&3], vec![(3, -1)].into_iter().collect());
+    }
+
+    /// values whose weight drops to zero are pruned from the weighted state
+    #[test]
+    fn weighted_state_pruning() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(accumulator.get_current_state_weighted().len(), 3);
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_delete_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert!(accumulator.get_current_state_weighted().is_empty());
+    }
+
+    /// An observer that fails to process any updates.
+    #[derive(Debug)]
+    struct FailingObserver;
+
+    impl Observer<Update<usize>, ()> for FailingObserver {
+        fn on_start(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn on_updates<'a>(
+            &mut self,
+            _updates: Box<dyn Iterator<Item = Update<usize>> + 'a>,
+        ) -> Result<(), ()> {
+            Err(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    /// errors of an observer are propagated without affecting delivery to other observers
+    #[test]
+    fn observer_error_propagation() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+
+        assert!(accumulator.subscribe(Box::new(FailingObserver)).is_ok());
+        assert!(accumulator.subscribe(Box::new(mock.clone())).is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Err(()));
+        assert_eq!(mock.lock().unwrap().called_on_updates, 3);
+        assert_eq!(accumulator.on_commit(), Ok(()));
+    }
+
+    /// errors of an observer are reported to the registered error handler
+    #[test]
+    fn observer_error_handler() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let handler_errors = errors.clone();
+
+        accumulator.on_observer_error(move |subscription, error| {
+            handler_errors.lock().unwrap().push((subscription, error))
+        });
+        let subscription = accumulator.subscribe(Box::new(FailingObserver)).unwrap();
+        assert!(accumulator.subscribe(Box::new(mock.clone())).is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(mock.lock().unwrap().called_on_updates, 3);
+        assert_eq!(*errors.lock().unwrap(), vec![(subscription, ())]);
+
+        // the failing observer can be torn down based on the reported error
+        assert!(accumulator.unsubscribe(&subscription).is_some());
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_2()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(errors.lock().unwrap().len(), 1);
+    }
+
+    /// errors of an observer scripted to fail on a particular batch of
+    /// updates are reported to the error handler, while panics cancel
+    /// its subscription
+    #[test]
+    fn faulty_observer() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, String>::new();
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let handler_errors = errors.clone();
+        accumulator.on_observer_error(move |subscription, error| {
+            handler_errors.lock().unwrap().push((subscription, error))
+        });
+
+        let faulty = Arc::new(Mutex::new(
+            FaultyObserver::<Update<usize>, String>::new()
+                .fail_on(ObserverCall::Updates, 2, "second batch".to_string())
+                .panic_on(ObserverCall::Commit, 3),
+        ));
+        let vanishing = Arc::new(Mutex::new(
+            FaultyObserver::<Update<usize>, String>::new()
+                .delay_on(ObserverCall::Start, 1, Duration::from_millis(1))
+                .disconnect_on(ObserverCall::Updates, 2),
+        ));
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let subscription = accumulator.subscribe(Box::new(faulty.clone())).unwrap();
+        assert!(accumulator.subscribe(Box::new(vanishing.clone())).is_ok());
+        assert!(accumulator.subscribe(Box::new(mock.clone())).is_ok());
+
+        for updates in [get_usize_updates_1(), get_usize_updates_2()] {
+            assert_eq!(accumulator.on_start(), Ok(()));
+            assert_eq!(accumulator.on_updates(updates), Ok(()));
+            assert_eq!(accumulator.on_commit(), Ok(()));
+        }
+        assert_eq!(
+            *errors.lock().unwrap(),
+            vec![(subscription, "second batch".to_string())]
+        );
+        {
+            let faulty = faulty.lock().unwrap();
+            assert_eq!(faulty.called(ObserverCall::Updates), 2);
+            assert_eq!(faulty.called(ObserverCall::Commit), 2);
+            assert_eq!(faulty.received_updates.len(), 3);
+
+            let vanishing = vanishing.lock().unwrap();
+            assert!(vanishing.disconnected);
+            assert_eq!(vanishing.called(ObserverCall::Commit), 2);
+            assert_eq!(vanishing.received_updates.len(), 3);
+        }
+        assert_eq!(mock.lock().unwrap().called_on_updates, 6);
+
+        // the panic of the faulty observer is contained
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_delete_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(accumulator.observer_count(), 2);
+        assert_eq!(mock.lock().unwrap().called_on_commit, 3);
+    }
+
+    /// a restored accumulator replays the restored state to new subscribers
+    #[test]
+    fn snapshot_restore() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let snapshot = accumulator.snapshot();
+        assert!(snapshot.buffer.is_none());
+
+        let mut restored = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        restored.restore(snapshot);
+        assert_eq!(
+            restored.get_current_state(),
+            accumulator.get_current_state()
+        );
+        assert_eq!(
+            restored.get_current_state_weighted(),
+            accumulator.get_current_state_weighted()
+        );
+
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(restored.subscribe(Box::new(mock.clone())).is_ok());
+        let received_updates = mock.lock().unwrap().received_updates.clone();
+        assert_eq!(received_updates.len(), 3);
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 1, v: 1 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 2, v: 2 })));
+        assert!(received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 3, v: 3 })));
+    }
+
+    /// a snapshot survives serialization, including an in-flight transaction
+    #[test]
+    fn snapshot_serialization() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_3()), Ok(()));
+
+        let bytes = bincode::serialize(&accumulator.snapshot()).unwrap();
+        let snapshot = bincode::deserialize::<AccumulatorSnapshot<usize>>(&bytes).unwrap();
+        assert_eq!(snapshot.buffer.as_ref().map(Vec::len), Some(4));
+
+        let mut restored = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        restored.restore(snapshot);
+        assert_eq!(
+            restored.get_current_state(),
+            accumulator.get_current_state()
+        );
+
+        // completing the restored transaction applies its updates
+        assert_eq!(restored.on_commit(), Ok(()));
+        assert_eq!(restored.get_current_state()[&4].len(), 4);
+    }
+
+    /// Test that a filtered observable only sees updates to the relations
+    /// it asked for, and only the transactions touching them.
+    #[test]
+    fn filtered_observable() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let mut relids = HashSet::new();
+        let _ = relids.insert(4);
+        let mut observable = accumulator.create_observable_filtered(relids);
+        assert!(observable.subscribe(Box::new(mock.clone())).is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(mock.lock().unwrap().called_on_start, 0);
+        assert_eq!(mock.lock().unwrap().called_on_commit, 0);
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_2()), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(mock.lock().unwrap().called_on_start, 1);
+        assert_eq!(mock.lock().unwrap().called_on_commit, 1);
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.received_updates.len(), 4);
+        assert!(mock
+            .received_updates
+            .iter()
+            .zip(get_usize_updates_3())
+            .all(|(u1, u2)| eq_updates(u1, &u2)));
+    }
+
+    /// Test that a filtered observable sends each subscriber the state
+    /// of the relations it asked for only.
+    #[test]
+    fn filtered_observable_replay() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mut observable = accumulator.create_observable_filtered(hashset! {4});
+        for _ in 0..2 {
+            let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+            assert!(observable.subscribe(Box::new(mock.clone())).is_ok());
+
+            let mock = mock.lock().unwrap();
+            assert_eq!(mock.called_on_start, 1);
+            assert_eq!(mock.called_on_commit, 1);
+            assert_eq!(mock.received_updates.len(), 4);
+            assert!(mock.received_updates.iter().all(|u| u.relid() == 4));
+            drop(mock);
+        }
+    }
+
+    /// Test that a filtered observable sends the state to and passes
+    /// the events through to multiple subscribers at the same time, and
+    /// hands back the observer as it was subscribed.
+    #[test]
+    fn filtered_observable_subscribers() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mut observable = accumulator.create_observable_filtered(hashset! {1, 4});
+        let mock1 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let mock2 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let subscription1 = observable.subscribe(Box::new(mock1.clone())).unwrap();
+        let subscription2 = observable.subscribe(Box::new(mock2.clone())).unwrap();
+        assert_ne!(subscription1, subscription2);
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        for mock in [&mock1, &mock2] {
+            let mock = mock.lock().unwrap();
+            assert_eq!(mock.called_on_start, 2);
+            assert_eq!(mock.called_on_commit, 2);
+            assert_eq!(mock.received_updates.len(), 5);
+            assert!(eq_updates(
+                &mock.received_updates[0],
+                &Update::Insert { relid: 1, v: 1 }
+            ));
+            assert!(mock.received_updates[1..].iter().all(|u| u.relid() == 4));
+        }
+
+        let mut observer = observable.unsubscribe(&subscription1).unwrap();
+        assert!(observable.unsubscribe(&subscription1).is_none());
+        // the observer handed back receives all updates, unfiltered
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(mock1.lock().unwrap().received_updates.len(), 8);
+    }
+
+    /// Test that a filtered observable with an empty set of relations
+    /// does not forward anything.
+    #[test]
+    fn filtered_observable_empty() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut observable = accumulator.create_observable_filtered(HashSet::new());
+        assert!(observable.subscribe(Box::new(mock.clone())).is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        let mock = *mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, 0);
+        assert_eq!(mock.called_on_updates, 0);
+        assert_eq!(mock.called_on_commit, 0);
+    }
+
+    /// Test that a coalescing accumulator does not forward an insertion
+    /// and deletion of the same value within a transaction, nor the
+    /// transaction itself if nothing remains.
+    #[test]
+    fn coalescing_cancels_out() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new_coalescing();
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(accumulator.subscribe(Box::new(mock.clone())).is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_delete_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        assert_eq!(mock.lock().unwrap().called_on_start, 0);
+        assert_eq!(mock.lock().unwrap().called_on_updates, 0);
+        assert_eq!(mock.lock().unwrap().called_on_commit, 0);
+        assert!(accumulator
+            .get_current_state()
+            .values()
+            .all(HashSet::is_empty));
+    }
+
+    /// Test that a coalescing accumulator forwards the net multiplicity
+    /// of values.
+    #[test]
+    fn coalescing_multiplicities() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new_coalescing();
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(accumulator.subscribe(Box::new(mock.clone())).is_ok());
+
+        let updates = vec![
+            Update::Insert { relid: 1, v: 1 },
+            Update::Insert { relid: 1, v: 1 },
+            Update::DeleteValue { relid: 1, v: 1 },
+            Update::DeleteValue { relid: 2, v: 2 },
+        ];
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(updates.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let expected = [
+            Update::Insert { relid: 1, v: 1 },
+            Update::DeleteValue { relid: 2, v: 2 },
+        ];
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.received_updates.len(), 2);
+        assert!(mock
+            .received_updates
+            .iter()
+            .zip(expected.iter())
+            .all(|(u1, u2)| eq_updates(u1, u2)));
+        assert_eq!(accumulator.get_current_state_weighted()[&1][&1], 1);
+    }
+
+    /// Test introspection of the subscriptions of an accumulator.
+    #[test]
+    fn observer_count() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mut observable = accumulator.create_observable();
+        assert!(observable.subscribe(Box::new(MockObserver::new())).is_ok());
+
+        let subscription1 = accumulator.subscribe(Box::new(MockObserver::new()));
+        let subscription2 = accumulator.subscribe(Box::new(MockObserver::new()));
+        assert!(subscription1.is_ok());
+        assert!(subscription2.is_ok());
+        assert_eq!(accumulator.observer_count(), 3);
+
+        let ids = accumulator.subscription_ids();
+        assert_eq!(ids.len(), 3);
+        assert!(ids.contains(subscription1.as_ref().unwrap()));
+        assert!(ids.contains(subscription2.as_ref().unwrap()));
+
+        assert!(accumulator.unsubscribe(&subscription1.unwrap()).is_some());
+        assert_eq!(accumulator.observer_count(), 2);
+        assert!(!accumulator.subscription_ids().contains(&ids[1]));
+    }
+
+    /// An observer subscribing another observer to a shared accumulator
+    /// from within its `on_updates` callback.
+    #[derive(Debug)]
+    struct ReentrantObserver {
+        accumulator: Arc<Mutex<DistributingAccumulator<Update<usize>, usize, ()>>>,
+        result: Option<Result<usize, AccumulatorError<()>>>,
+    }
+
+    impl Observer<Update<usize>, ()> for ReentrantObserver {
+        fn on_start(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn on_updates<'a>(
+            &mut self,
+            _updates: Box<dyn Iterator<Item = Update<usize>> + 'a>,
+        ) -> Result<(), ()> {
+            if self.result.is_none() {
+                let result = DistributingAccumulator::subscribe_shared(
+                    &self.accumulator,
+                    Box::new(MockObserver::new()),
+                );
+                self.result = Some(result.map_err(|(error, _)| error));
+            }
+            Ok(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    /// Test that an observer subscribing to a shared accumulator while
+    /// it is being delivered updates is reported an error instead of
+    /// deadlocking.
+    #[test]
+    fn reentrant_subscribe() {
+        let accumulator = Arc::new(Mutex::new(DistributingAccumulator::<
+            Update<usize>,
+            usize,
+            (),
+        >::new()));
+        let observer = Arc::new(Mutex::new(ReentrantObserver {
+            accumulator: accumulator.clone(),
+            result: None,
+        }));
+        assert!(DistributingAccumulator::subscribe_shared(
+            &accumulator,
+            Box::new(observer.clone())
+        )
+        .is_ok());
+
+        let done = Arc::new(Mutex::new(false));
+        let _ = {
+            let accumulator = accumulator.clone();
+            let done = done.clone();
+            spawn(move || {
+                let mut accumulator = accumulator.lock().unwrap();
+                assert_eq!(accumulator.on_start(), Ok(()));
+                assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+                assert_eq!(accumulator.on_commit(), Ok(()));
+                *done.lock().unwrap() = true;
+            })
+        };
+
+        await_expected(|| assert!(*done.lock().unwrap()));
+        assert_eq!(
+            observer.lock().unwrap().result,
+            Some(Err(AccumulatorError::Reentrant))
+        );
+        assert_eq!(accumulator.lock().unwrap().observer_count(), 1);
+    }
+
+    /// Test that the per-relation statistics accumulate over multiple
+    /// transactions and survive completion.
+    #[test]
+    fn relation_stats() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_2()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_delete_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let stats = accumulator.stats();
+        let expected = |inserts, deletes, current_size| RelStats {
+            inserts,
+            deletes,
+            current_size,
+        };
+        assert_eq!(stats[&1], expected(3, 1, 2));
+        assert_eq!(stats[&2], expected(2, 1, 1));
+        assert_eq!(stats[&3], expected(1, 1, 0));
+        assert_eq!(stats[&4], expected(4, 0, 4));
+        assert_eq!(accumulator.completed_count(), 0);
+
+        assert_eq!(accumulator.on_completed(), Ok(()));
+        let stats = accumulator.stats();
+        assert_eq!(stats[&1], expected(3, 1, 0));
+        assert_eq!(stats[&4], expected(4, 0, 0));
+        assert_eq!(accumulator.completed_count(), 1);
+    }
+
+    /// Test that unsubscribing mid-transaction returns the updates the
+    /// observer received as part of the transaction.
+    #[test]
+    fn unsubscribe_drain() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let subscription1 = accumulator
+            .subscribe(Box::new(MockObserver::new()))
+            .unwrap();
+        let subscription2 = accumulator
+            .subscribe(Box::new(MockObserver::new()))
+            .unwrap();
+        let subscription3 = accumulator
+            .subscribe(Box::new(MockObserver::new()))
+            .unwrap();
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        // an idle observer has nothing to drain
+        let (_, drained) = accumulator.unsubscribe_drain(&subscription1).unwrap();
+        assert!(drained.is_empty());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_2()), Ok(()));
+        let subscription4 = accumulator
+            .subscribe(Box::new(MockObserver::new()))
+            .unwrap();
+        assert_eq!(accumulator.on_updates(get_usize_updates_3()), Ok(()));
+
+        let (_, drained) = accumulator.unsubscribe_drain(&subscription2).unwrap();
+        assert_eq!(drained.len(), 7);
+        assert!(drained
+            .iter()
+            .zip(get_usize_updates_2().chain(get_usize_updates_3()))
+            .all(|(u1, u2)| eq_updates(u1, &u2)));
+
+        // an observer subscribing mid-transaction only drains what it saw
+        let (_, drained) = accumulator.unsubscribe_drain(&subscription4).unwrap();
+        assert_eq!(drained.len(), 4);
+        assert!(drained.iter().all(|u| u.relid() == 4));
+
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        let (_, drained) = accumulator.unsubscribe_drain(&subscription3).unwrap();
+        assert!(drained.is_empty());
+        assert!(accumulator.unsubscribe_drain(&subscription3).is_none());
+    }
+
+    /// Test the rendering of the accumulated state.
+    #[test]
+    fn dump_state() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_2()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let expected = "\
+relation 1 (3 values):
+  1
+  2
+  3
+relation 2 (2 values):
+  2
+  3
+relation 3 (1 values):
+  3
+";
+        assert_eq!(accumulator.dump_state(), expected);
+    }
+
+    /// Test that registered relation names are used when dumping the
+    /// accumulated state.
+    #[test]
+    fn dump_state_with_registry() {
+        let registry = hashmap! {1 => "Edge".to_string()};
+        let mut accumulator = AccumulatorBuilder::<usize, ()>::default()
+            .with_registry(registry)
+            .build();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let dump = accumulator.dump_state();
+        assert!(dump.contains("relation Edge (1 values):"), "{}", dump);
+        assert!(!dump.contains("relation 1 "), "{}", dump);
+        assert!(dump.contains("relation 2 (1 values):"), "{}", dump);
+    }
+
+    /// Test that the state is exported as a transaction of `insert`
+    /// commands.
+    #[test]
+    fn export_commands() {
+        let mut registry = hashmap! {
+            1 => "Edge".to_string(),
+            2 => "Node".to_string(),
+        };
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_2()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        // relation 3 is not registered
+        let mut commands = Vec::new();
+        let error = accumulator
+            .export_commands(&registry, &mut commands)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(commands.is_empty());
+
+        let _ = registry.insert(3, "Label".to_string());
+        assert!(accumulator
+            .export_commands(&registry, &mut commands)
+            .is_ok());
+        let commands = String::from_utf8(commands).unwrap();
+        assert_eq!(
+            commands,
+            "start;\n\
+             insert Edge[1];\n\
+             insert Edge[2];\n\
+             insert Edge[3];\n\
+             insert Node[2];\n\
+             insert Node[3];\n\
+             insert Label[3];\n\
+             commit;\n"
+        );
+    }
+
+    /// Test that replaying a relation only re-sends its values to the
+    /// given subscription.
+    #[test]
+    fn replay_relation() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mock1 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let mock2 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let subscription = accumulator.subscribe(Box::new(mock1.clone())).unwrap();
+        assert!(accumulator.subscribe(Box::new(mock2.clone())).is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_2()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        mock1.lock().unwrap().received_updates.clear();
+
+        assert_eq!(accumulator.replay_relation(&subscription, 2), Ok(()));
+        let mock1 = mock1.lock().unwrap();
+        assert_eq!(mock1.called_on_start, 2);
+        assert_eq!(mock1.called_on_commit, 2);
+        assert_eq!(mock1.received_updates.len(), 2);
+        assert!(mock1
+            .received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 2, v: 2 })));
+        assert!(mock1
+            .received_updates
+            .iter()
+            .any(|u| eq_updates(u, &Update::Insert { relid: 2, v: 3 })));
+
+        let mock2 = mock2.lock().unwrap();
+        assert_eq!(mock2.called_on_start, 1);
+        assert_eq!(mock2.received_updates.len(), 6);
+        drop(mock1);
+        drop(mock2);
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.replay_relation(&subscription, 2),
+            Err(AccumulatorError::InTransaction)
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+    }
+
+    /// Test that observers subscribing while a batch of transactions is
+    /// open receive balanced lifecycle events: a subscriber of the
+    /// accumulator joins the batch, while a subscriber of an observable
+    /// only receives the next one.
+    #[test]
+    fn batch_transactions_subscribe() {
+        let mut accumulator = AccumulatorBuilder::<usize, ()>::default()
+            .batch_every(2)
+            .build();
+        let mut observable = accumulator.create_observable();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mock1 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let mock2 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(accumulator.subscribe(Box::new(mock1.clone())).is_ok());
+        assert!(observable.subscribe(Box::new(mock2.clone())).is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_2()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mock = mock1.lock().unwrap();
+        assert_eq!(mock.called_on_start, 2);
+        assert_eq!(mock.called_on_commit, 2);
+        assert_eq!(mock.received_updates.len(), 6);
+        drop(mock);
+        let mock = mock2.lock().unwrap();
+        assert_eq!(mock.called_on_start, 0);
+        assert_eq!(mock.called_on_commit, 0);
+        assert!(mock.received_updates.is_empty());
+        drop(mock);
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(accumulator.flush(), Ok(()));
+
+        let mock = mock2.lock().unwrap();
+        assert_eq!(mock.called_on_start, 1);
+        assert_eq!(mock.called_on_commit, 1);
+        assert_eq!(mock.received_updates.len(), 4);
+    }
+
+    /// Test that a batching accumulator merges transactions before
+    /// forwarding them, while keeping its state up to date.
+    #[test]
+    fn batch_transactions() {
+        let mut accumulator = AccumulatorBuilder::<usize, ()>::default()
+            .batch_every(3)
+            .build();
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        assert!(accumulator.subscribe(Box::new(mock.clone())).is_ok());
+
+        let updates = vec![
+            get_usize_updates_1(),
+            get_usize_updates_2(),
+            get_usize_updates_3(),
+        ];
+        for updates in updates {
+            assert_eq!(accumulator.on_start(), Ok(()));
+            assert_eq!(accumulator.on_updates(updates), Ok(()));
+            assert_eq!(accumulator.on_commit(), Ok(()));
+        }
+        assert_eq!(
+            accumulator
+                .get_current_state()
+                .values()
+                .map(HashSet::len)
+                .sum::<usize>(),
+            10
+        );
+        {
+            let mock = mock.lock().unwrap();
+            assert_eq!(mock.called_on_start, 1);
+            assert_eq!(mock.called_on_updates, 10);
+            assert_eq!(mock.called_on_commit, 1);
+        }
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_delete_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(accumulator.get_current_state()[&3].len(), 0);
+        assert_eq!(mock.lock().unwrap().called_on_commit, 1);
+
+        assert_eq!(accumulator.flush(), Ok(()));
+        assert_eq!(mock.lock().unwrap().called_on_start, 2);
+        assert_eq!(mock.lock().unwrap().called_on_commit, 2);
+        assert_eq!(accumulator.flush(), Ok(()));
+        assert_eq!(mock.lock().unwrap().called_on_commit, 2);
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(accumulator.on_completed(), Ok(()));
+        let mock = *mock.lock().unwrap();
+        // the empty transaction is not forwarded, only the deletion of
+        // the remaining state
+        assert_eq!(mock.called_on_start, 3);
+        assert_eq!(mock.called_on_commit, 3);
+    }
+
+    /// Test that updates fed into an accumulator on one thread become
+    /// visible through a state handle polled on another.
+    #[test]
+    fn state_handle() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let handle = accumulator.state_handle();
+        assert!(handle.get_current_state().is_empty());
+
+        let feeder = spawn(move || {
+            assert_eq!(accumulator.on_start(), Ok(()));
+            assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+            assert_eq!(accumulator.on_updates(get_usize_updates_2()), Ok(()));
+            assert_eq!(accumulator.on_commit(), Ok(()));
+            accumulator
+        });
+
+        let reader = handle.clone();
+        await_expected(|| {
+            let relation = reader.get_relation(1);
+            assert_eq!(relation, vec![1, 2, 3].into_iter().collect());
+        });
+        let accumulator = feeder.join().unwrap();
+        assert_eq!(handle.get_current_state(), accumulator.get_current_state());
+        assert!(handle.get_relation(42).is_empty());
+    }
+
+    /// Test that transactions without any updates are not forwarded,
+    /// while the deletion of the state on completion still is.
+    #[test]
+    fn empty_transactions() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        assert!(accumulator.subscribe(Box::new(mock.clone())).is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(mock.lock().unwrap().called_on_start, 0);
+        assert_eq!(mock.lock().unwrap().called_on_commit, 0);
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(Vec::new().into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(mock.lock().unwrap().called_on_start, 1);
+        assert_eq!(mock.lock().unwrap().called_on_commit, 1);
+
+        assert_eq!(accumulator.on_completed(), Ok(()));
+        let mock = *mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, 2);
+        assert_eq!(mock.called_on_updates, 6);
+        assert_eq!(mock.called_on_commit, 2);
+        assert_eq!(mock.called_on_completed, 1);
+    }
+
+    /// Test that a snapshot observable emits the net changes of the
+    /// state per interval rather than every transaction.
+    #[test]
+    fn snapshot_observable() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let interval = Duration::from_millis(200);
+        let mut observable = accumulator.create_snapshot_observable(interval);
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(observable.subscribe(Box::new(mock.clone())).is_ok());
+
+        let updates = vec![
+            get_usize_updates_1(),
+            get_usize_updates_2(),
+            get_usize_delete_updates_1(),
+        ];
+        for updates in updates {
+            assert_eq!(accumulator.on_start(), Ok(()));
+            assert_eq!(accumulator.on_updates(updates), Ok(()));
+            assert_eq!(accumulator.on_commit(), Ok(()));
+        }
+
+        await_expected(|| {
+            let (committed, updates) = {
+                let mock = mock.lock().unwrap();
+                (mock.called_on_commit, mock.received_updates.clone())
+            };
+            assert_eq!(committed, 1);
+            assert_eq!(updates.len(), 3);
+            assert!(updates.iter().all(|u| match u {
+                Update::Insert { relid: 1, v } => *v == 2 || *v == 3,
+                Update::Insert { relid: 2, v } => *v == 3,
+                _ => false,
+            }));
+        });
+
+        // nothing changed since, so nothing is emitted
+        sleep(interval * 2);
+        assert_eq!(mock.lock().unwrap().called_on_commit, 1);
+
+        assert_eq!(accumulator.on_completed(), Ok(()));
+        await_expected(|| {
+            let (committed, deleted) = {
+                let mock = mock.lock().unwrap();
+                let deleted = mock
+                    .received_updates
+                    .iter()
+                    .filter(|u| matches!(u, Update::DeleteValue { .. }))
+                    .count();
+                (mock.called_on_commit, deleted)
+            };
+            assert_eq!(committed, 2);
+            assert_eq!(deleted, 3);
+        });
+    }
+
+    /// Test that shutting down an accumulator completes all observers
+    /// and hands them back.
+    #[test]
+    fn shutdown() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mock1 = Arc::new(Mutex::new(MockObserver::new()));
+        let mock2 = Arc::new(Mutex::new(MockObserver::new()));
+
+        assert!(accumulator.subscribe(Box::new(mock1.clone())).is_ok());
+        assert!(accumulator.subscribe(Box::new(mock2.clone())).is_ok());
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mut observers = accumulator.shutdown();
+        assert_eq!(observers.len(), 2);
+        assert_eq!(mock1.lock().unwrap().called_on_completed, 1);
+        assert_eq!(mock2.lock().unwrap().called_on_completed, 1);
+        // the state was cleared through one more transaction
+        assert_eq!(mock1.lock().unwrap().called_on_commit, 2);
+        assert_eq!(mock1.lock().unwrap().called_on_updates, 6);
+
+        // the observers are no longer attached and can be reused
+        assert_eq!(observers[0].on_start(), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_start, 3);
+        assert_eq!(mock2.lock().unwrap().called_on_start, 2);
+    }
+
+    /// Test that observers attempting to subscribe to a completed or
+    /// shut down accumulator are handed back.
+    #[test]
+    fn subscribe_after_completion() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(accumulator.on_completed(), Ok(()));
+        assert!(accumulator
+            .subscribe(Box::new(MockObserver::new()))
+            .is_err());
+
+        // a new upstream starting a transaction revives the accumulator
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert!(accumulator.subscribe(Box::new(MockObserver::new())).is_ok());
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        assert_eq!(accumulator.close().len(), 1);
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        assert!(accumulator.subscribe(Box::new(mock.clone())).is_err());
+        assert!(accumulator
+            .subscribe_no_replay(Box::new(mock.clone()))
+            .is_err());
+        assert!(accumulator
+            .subscribe_with_priority(Box::new(mock.clone()), 1)
+            .is_err());
+        assert_eq!(accumulator.observer_count(), 0);
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert!(accumulator.subscribe(Box::new(mock)).is_err());
+    }
+
+    /// Test that the number of updates sent to initialize an observer
+    /// is reported.
+    #[test]
+    fn subscribe_counted() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mock1 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let mock2 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+
+        let (_, count) = accumulator.subscribe_counted(Box::new(mock1)).unwrap();
+        assert_eq!(count, 0);
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let (subscription, count) = accumulator
+            .subscribe_counted(Box::new(mock2.clone()))
+            .unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(mock2.lock().unwrap().received_updates.len(), 3);
+        assert_eq!(accumulator.subscription_ids()[1], subscription);
+    }
+
+    /// Test that the updates sent to initialize an observer and those
+    /// distributed to it afterwards are accounted for separately.
+    #[test]
+    fn subscription_stats() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let before = Instant::now();
+        let (early, _) = accumulator
+            .subscribe_counted(Box::new(MockObserver::new()))
+            .unwrap();
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let late = accumulator
+            .subscribe(Box::new(MockObserver::new()))
+            .unwrap();
+        let stats = accumulator.subscription_stats();
+        assert_eq!(stats[&late].init_updates, 3);
+        assert_eq!(stats[&late].live_updates, 0);
+
+        let updates = vec![Update::Insert { relid: 1, v: 7 }];
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(updates.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let stats = accumulator.subscription_stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[&early].init_updates, 0);
+        assert_eq!(stats[&early].live_updates, 4);
+        assert_eq!(stats[&late].init_updates, 3);
+        assert_eq!(stats[&late].live_updates, 1);
+        assert!(stats[&early].created_at >= before);
+        assert!(stats[&late].created_at >= stats[&early].created_at);
+
+        let _ = accumulator.unsubscribe(&early);
+        assert!(!accumulator.subscription_stats().contains_key(&early));
+    }
+
+    /// Test that the committed watermark is held back by the observer
+    /// acknowledging the fewest transactions.
+    #[test]
+    fn committed_watermark() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(accumulator.committed_watermark(), 0);
+
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let mut fast = None;
+        let _ = accumulator
+            .subscribe_acked(|ack| {
+                fast = Some(ack);
+                Box::new(mock.clone())
+            })
+            .unwrap();
+        let fast = fast.unwrap();
+        let mut slow = None;
+        let subscription = accumulator
+            .subscribe_acked(|ack| {
+                slow = Some(ack);
+                Box::new(MockObserver::new())
+            })
+            .unwrap();
+        let slow = slow.unwrap();
+
+        for _ in 0..3 {
+            assert_eq!(accumulator.on_start(), Ok(()));
+            assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+            assert_eq!(accumulator.on_commit(), Ok(()));
+        }
+        assert_eq!(mock.lock().unwrap().received_sequences, vec![1, 2, 3]);
+        fast.ack(3);
+        slow.ack(1);
+        assert_eq!(accumulator.committed_watermark(), 1);
+
+        let _ = accumulator.unsubscribe(&subscription);
+        assert_eq!(accumulator.committed_watermark(), 3);
+
+        // transactions not yet started cannot be acknowledged
+        fast.ack(10);
+        assert_eq!(fast.acked(), 3);
+        assert_eq!(accumulator.committed_watermark(), 3);
+    }
+
+    /// An observer acknowledging every transaction upon its commit, by
+    /// the sequence number it was started with.
+    #[derive(Debug)]
+    struct AckingObserver {
+        ack: AckHandle,
+        seq: Option<u64>,
+    }
+
+    impl Observer<Update<usize>, ()> for AckingObserver {
+        fn on_start(&mut self) -> Result<(), ()> {
+            self.seq = None;
+            Ok(())
+        }
+
+        fn on_start_seq(&mut self, seq: u64) -> Result<(), ()> {
+            self.seq = Some(seq);
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), ()> {
+            if let Some(seq) = self.seq.take() {
+                self.ack.ack(seq);
+            }
+            Ok(())
+        }
+
+        fn on_updates<'a>(
+            &mut self,
+            _updates: Box<dyn Iterator<Item = Update<usize>> + 'a>,
+        ) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    /// Test that only transactions actually forwarded are assigned a
+    /// sequence number, so that empty and batched transactions do not
+    /// hold back the committed watermark.
+    #[test]
+    fn acked_sequences() {
+        let mut accumulator = AccumulatorBuilder::<usize, ()>::default()
+            .batch_every(2)
+            .build();
+        let subscription = accumulator
+            .subscribe_acked(|ack| Box::new(AckingObserver { ack, seq: None }))
+            .unwrap();
+
+        // an empty transaction is not forwarded at all
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(accumulator.observer.sequence().started(), 0);
+
+        // two transactions forwarded as a single batch
+        for _ in 0..2 {
+            assert_eq!(accumulator.on_start(), Ok(()));
+            assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+            assert_eq!(accumulator.on_commit(), Ok(()));
+        }
+        assert_eq!(accumulator.observer.sequence().committed(), 1);
+        assert_eq!(accumulator.committed_watermark(), 1);
+
+        // a batch not yet committed is not acknowledged
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(accumulator.committed_watermark(), 1);
+        assert_eq!(accumulator.flush(), Ok(()));
+        assert_eq!(accumulator.committed_watermark(), 2);
+        assert!(accumulator.unsubscribe(&subscription).is_some());
+    }
+
+    /// Test that starting a transaction blocks while the maximum number
+    /// of transactions are in flight, until one is acknowledged.
+    #[test]
+    fn max_in_flight_blocking() {
+        let mut accumulator = AccumulatorBuilder::<usize, ()>::default()
+            .max_in_flight_transactions(1)
+            .build();
+        let mut ack = None;
+        let _ = accumulator
+            .subscribe_acked(|handle| {
+                ack = Some(handle);
+                Box::new(MockObserver::new())
+            })
+            .unwrap();
+        let ack = ack.unwrap();
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let started = Arc::new(AtomicBool::new(false));
+        let thread_started = started.clone();
+        let upstream = spawn(move || {
+            assert_eq!(accumulator.on_start(), Ok(()));
+            thread_started.store(true, Ordering::SeqCst);
+            assert_eq!(accumulator.on_commit(), Ok(()));
+        });
+        sleep(Duration::from_millis(100));
+        assert!(!started.load(Ordering::SeqCst));
+
+        ack.ack(1);
+        upstream.join().unwrap();
+        assert!(started.load(Ordering::SeqCst));
+    }
+
+    /// Test that starting a transaction fails while the maximum number
+    /// of transactions are in flight, if so configured.
+    #[test]
+    fn max_in_flight_nonblocking() {
+        let mut accumulator = AccumulatorBuilder::<usize, AccumulatorError<()>>::default()
+            .max_in_flight_transactions_nonblocking(1)
+            .build();
+        let mut ack = None;
+        let _ = accumulator
+            .subscribe_acked(|handle| {
+                ack = Some(handle);
+                Box::new(MockObserver::new())
+            })
+            .unwrap();
+        let ack = ack.unwrap();
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(
+            accumulator.on_start(),
+            Err(AccumulatorError::WouldBlock(WouldBlock {
+                in_flight: 1,
+                max: 1,
+            }))
+        );
+
+        ack.ack(1);
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+    }
+
+    /// Test that transactions that are not forwarded, such as empty
+    /// ones, do not count as in flight.
+    #[test]
+    fn max_in_flight_empty_transactions() {
+        let mut accumulator = AccumulatorBuilder::<usize, AccumulatorError<()>>::default()
+            .max_in_flight_transactions_nonblocking(1)
+            .build();
+        let mut ack = None;
+        let _ = accumulator
+            .subscribe_acked(|handle| {
+                ack = Some(handle);
+                Box::new(MockObserver::new())
+            })
+            .unwrap();
+        let ack = ack.unwrap();
+
+        for _ in 0..3 {
+            assert_eq!(accumulator.on_start(), Ok(()));
+            assert_eq!(accumulator.on_commit(), Ok(()));
+        }
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(
+            accumulator.on_start(),
+            Err(AccumulatorError::WouldBlock(WouldBlock {
+                in_flight: 1,
+                max: 1,
+            }))
+        );
+
+        ack.ack(1);
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+    }
+
+    /// Test that the buffer of a collecting subscription holds the
+    /// accumulated state followed by the live updates.
+    #[test]
+    fn subscribe_collect() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let (subscription, buffer) = accumulator.subscribe_collect().unwrap();
+        assert_eq!(buffer.lock().unwrap().len(), 3);
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_delete_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let collected = buffer.lock().unwrap().clone();
+        assert_eq!(collected.len(), 6);
+        let mut init = collected[..3].to_vec();
+        sort_insertions(&mut init);
+        let expected = get_usize_updates_1()
+            .chain(get_usize_delete_updates_1())
+            .collect::<Vec<_>>();
+        assert!(init
+            .iter()
+            .chain(collected[3..].iter())
+            .zip(expected.iter())
+            .all(|(u1, u2)| eq_updates(u1, u2)));
+
+        assert!(accumulator.unsubscribe(&subscription).is_some());
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(buffer.lock().unwrap().len(), 6);
+
+        assert_eq!(accumulator.on_completed(), Ok(()));
+        assert_eq!(
+            accumulator.subscribe_collect().map(|_| ()),
+            Err(AccumulatorError::Inactive)
+        );
+    }
+
+    /// Test that an accumulator created with a given ID carries it and
+    /// otherwise behaves like one created via `new`.
+    #[test]
+    fn new_with_id() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new_with_id(42);
+        assert_eq!(accumulator.id(), 42);
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(accumulator.get_current_state()[&1], hashset! {1});
+    }
+
+    /// Test that the first subscriber of a replaying observable is sent
+    /// the accumulated state, unlike that of a plain observable.
+    #[test]
+    fn observable_with_replay() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mut replaying = accumulator.create_observable_with_replay();
+        let mut plain = accumulator.create_observable();
+        let mock1 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let mock2 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let subscription = replaying.subscribe(Box::new(mock1.clone())).unwrap();
+        assert!(plain.subscribe(Box::new(mock2.clone())).is_ok());
+
+        // the state is sent upon subscription
+        let mock = mock1.lock().unwrap();
+        assert_eq!(mock.called_on_start, 1);
+        assert_eq!(mock.called_on_commit, 1);
+        assert_eq!(mock.received_updates.len(), 3);
+        drop(mock);
+
+        let updates = vec![Update::Insert { relid: 4, v: 4 }];
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(updates.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mock = mock1.lock().unwrap();
+        assert_eq!(mock.called_on_start, 2);
+        assert_eq!(mock.called_on_commit, 2);
+        assert_eq!(mock.received_updates.len(), 4);
+        assert!(mock.received_updates[..3].iter().all(|u| u.relid() != 4));
+        assert!(eq_updates(
+            &mock.received_updates[3],
+            &Update::Insert { relid: 4, v: 4 }
+        ));
+        drop(mock);
+        let mock = mock2.lock().unwrap();
+        assert_eq!(mock.called_on_start, 1);
+        assert_eq!(mock.received_updates.len(), 1);
+        drop(mock);
+
+        // later subscribers are not sent the state, even while the first
+        // one is still subscribed
+        let mock3 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(replaying.subscribe(Box::new(mock3.clone())).is_ok());
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_2()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(mock3.lock().unwrap().called_on_start, 1);
+        assert_eq!(mock3.lock().unwrap().received_updates.len(), 3);
+        assert_eq!(mock1.lock().unwrap().called_on_start, 3);
+        assert_eq!(mock1.lock().unwrap().received_updates.len(), 7);
+
+        let _ = replaying.unsubscribe(&subscription);
+        let mock4 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(replaying.subscribe(Box::new(mock4.clone())).is_ok());
+        assert_eq!(mock4.lock().unwrap().called_on_start, 0);
+    }
+
+    /// Test that the first subscriber of a replaying observable
+    /// subscribing while a transaction is in progress is sent the state
+    /// right before the next transaction, not any of the one in
+    /// progress.
+    #[test]
+    fn observable_with_replay_in_transaction() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mut replaying = accumulator.create_observable_with_replay();
+
+        let mock1 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert!(replaying.subscribe(Box::new(mock1.clone())).is_ok());
+        assert_eq!(accumulator.on_updates(get_usize_updates_2()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mock = mock1.lock().unwrap();
+        assert_eq!(mock.called_on_start, 0);
+        assert_eq!(mock.called_on_commit, 0);
+        assert!(mock.received_updates.is_empty());
+        drop(mock);
+
+        let updates = vec![Update::Insert { relid: 4, v: 4 }];
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(updates.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mock = mock1.lock().unwrap();
+        assert_eq!(mock.called_on_start, 2);
+        assert_eq!(mock.called_on_commit, 2);
+        assert_eq!(
+            mock.received_updates.len(),
+            accumulator
+                .get_current_state()
+                .values()
+                .map(HashSet::len)
+                .sum::<usize>()
+        );
+    }
+
+    /// Test that the fallible event methods report each kind of failure
+    /// by its own error variant.
+    #[test]
+    fn accumulator_errors() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, String>::new();
+        let faulty = FaultyObserver::<Update<usize>, String>::new().fail_on(
+            ObserverCall::Updates,
+            1,
+            "unavailable".to_string(),
+        );
+        assert!(accumulator.subscribe(Box::new(faulty)).is_ok());
+
+        assert_eq!(
+            accumulator.try_on_updates(get_usize_updates_1()),
+            Err(AccumulatorError::ProtocolViolation(
+                ProtocolViolation::UpdatesWithoutStart
+            ))
+        );
+        assert_eq!(
+            accumulator.try_on_commit(),
+            Err(AccumulatorError::ProtocolViolation(
+                ProtocolViolation::CommitWithoutStart
+            ))
+        );
+        assert_eq!(accumulator.try_on_start(), Ok(()));
+        assert_eq!(
+            accumulator.try_on_start(),
+            Err(AccumulatorError::ProtocolViolation(
+                ProtocolViolation::StartInTransaction
+            ))
+        );
+        assert_eq!(
+            accumulator.try_on_updates(get_usize_updates_1()),
+            Err(AccumulatorError::Observer("unavailable".to_string()))
+        );
+        assert_eq!(accumulator.try_on_commit(), Ok(()));
+
+        // panicking while holding the lock of the state poisons it
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            accumulator.for_each_value(|_, _| panic!("inspection failed"))
+        }));
+        assert!(result.is_err());
+        assert_eq!(accumulator.try_on_start(), Err(AccumulatorError::Poisoned));
+
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, String>::new();
+        let _ = accumulator.close();
+        assert_eq!(accumulator.try_on_start(), Err(AccumulatorError::Shutdown));
+    }
+
+    /// Test that the initial state of an accumulator is replayed to
+    /// subscribers and can be deleted from.
+    #[test]
+    fn initial_state() {
+        let state = hashmap! {1 => hashset! {1, 2}};
+        let mut accumulator =
+            DistributingAccumulator::<Update<usize>, usize, ()>::with_initial_state(state.clone());
+        assert_eq!(accumulator.get_current_state(), state);
+
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(accumulator.subscribe(Box::new(mock.clone())).is_ok());
+        {
+            let mock = mock.lock().unwrap();
+            assert_eq!(mock.called_on_commit, 1);
+            assert_eq!(mock.received_updates.len(), 2);
+            assert!(mock
+                .received_updates
+                .iter()
+                .any(|u| eq_updates(u, &Update::Insert { relid: 1, v: 1 })));
+            assert!(mock
+                .received_updates
+                .iter()
+                .any(|u| eq_updates(u, &Update::Insert { relid: 1, v: 2 })));
+        }
+
+        let updates = vec![Update::DeleteValue { relid: 1, v: 1 }];
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(updates.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(
+            accumulator.get_current_state(),
+            hashmap! {1 => hashset! {2}}
+        );
+        assert_eq!(accumulator.relation_size(1), 1);
+        assert_eq!(mock.lock().unwrap().received_updates.len(), 3);
+    }
+
+    /// Test that an insert-only observable never forwards deletions.
+    #[test]
+    fn insert_only_observable() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mut observable = accumulator.create_insert_only_observable();
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(observable.subscribe(Box::new(mock.clone())).is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_delete_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_2()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(accumulator.on_completed(), Ok(()));
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, 2);
+        assert_eq!(mock.called_on_commit, 2);
+        assert_eq!(mock.received_updates.len(), 6);
+        assert!(mock
+            .received_updates
+            .iter()
+            .all(|u| matches!(u, Update::Insert { .. })));
+    }
+
+    /// Test that an observer subscribed with a predicate only receives
+    /// the matching updates, including those replaying the state.
+    #[test]
+    fn subscribe_filtered() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let even = |u: &Update<usize>| match u {
+            Update::Insert { v, .. } | Update::DeleteValue { v, .. } => v % 2 == 0,
+            _ => false,
+        };
+        assert!(accumulator
+            .subscribe_filtered(Box::new(mock.clone()), even)
+            .is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_delete_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_2()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let received = mock.lock().unwrap().received_updates.clone();
+        let expected = [
+            Update::Insert { relid: 2, v: 2 },
+            Update::DeleteValue { relid: 2, v: 2 },
+            Update::Insert { relid: 1, v: 2 },
+        ];
+        assert_eq!(received.len(), expected.len());
+        assert!(received
+            .iter()
+            .zip(expected.iter())
+            .all(|(u1, u2)| eq_updates(u1, u2)));
+        assert_eq!(mock.lock().unwrap().called_on_start, 3);
+    }
+
+    /// Test that splitting by relation delivers the updates of each
+    /// relation through its observable only.
     #[test]
-    fn transparent_transactions_proxy() {
+    fn split_by_relation() {
         let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
-        let mock1 = Arc::new(Mutex::new(MockObserver::new()));
-        let mock2 = Arc::new(Mutex::new(MockObserver::new()));
-        let mut observable = accumulator.create_observable();
+        let mut observables = accumulator.split_by_relation(&[1, 4]);
+        assert_eq!(observables.len(), 2);
+        let mock1 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let mock4 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let observable1 = observables.get_mut(&1).unwrap();
+        assert!(observable1.subscribe(Box::new(mock1.clone())).is_ok());
+        let observable4 = observables.get_mut(&4).unwrap();
+        assert!(observable4.subscribe(Box::new(mock4.clone())).is_ok());
 
-        assert!(observable.subscribe(Box::new(mock1.clone())).is_ok());
-        assert!(accumulator.subscribe(Box::new(mock2.clone())).is_ok());
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mock1 = mock1.lock().unwrap();
+        assert_eq!(mock1.received_updates.len(), 1);
+        assert!(mock1.received_updates.iter().all(|u| u.relid() == 1));
+        let mock4 = mock4.lock().unwrap();
+        assert_eq!(mock4.received_updates.len(), 4);
+        assert!(mock4.received_updates.iter().all(|u| u.relid() == 4));
+    }
+
+    /// Test that observers are told the sequence numbers of transactions
+    /// and that these keep increasing after the upstream completed.
+    #[test]
+    fn transaction_sequences() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let filtered = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(accumulator.subscribe(Box::new(mock.clone())).is_ok());
+        let mut observable = accumulator.create_observable_filtered(hashset! {1});
+        assert!(observable.subscribe(Box::new(filtered.clone())).is_ok());
+
+        for (i, relid) in [1, 2, 1].iter().enumerate() {
+            if i == 2 {
+                assert_eq!(accumulator.on_completed(), Ok(()));
+            }
+            let updates = vec![Update::Insert {
+                relid: *relid,
+                v: i,
+            }];
+            assert_eq!(accumulator.on_start(), Ok(()));
+            assert_eq!(
+                accumulator.on_updates(Box::new(updates.into_iter())),
+                Ok(())
+            );
+            assert_eq!(accumulator.on_commit(), Ok(()));
+        }
+
+        assert_eq!(mock.lock().unwrap().received_sequences, vec![1, 2, 3]);
+        assert_eq!(filtered.lock().unwrap().received_sequences, vec![1, 3]);
+    }
+
+    /// Test that a sampling observable forwards only every Nth
+    /// transaction.
+    #[test]
+    fn sampling_observable() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mut observable = accumulator.create_sampling_observable(2);
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(observable.subscribe(Box::new(mock.clone())).is_ok());
+
+        for relid in 1..=4 {
+            let updates = vec![Update::Insert { relid, v: relid }];
+            assert_eq!(accumulator.on_start(), Ok(()));
+            assert_eq!(
+                accumulator.on_updates(Box::new(updates.into_iter())),
+                Ok(())
+            );
+            assert_eq!(accumulator.on_commit(), Ok(()));
+        }
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, 2);
+        assert_eq!(mock.called_on_commit, 2);
+        assert_eq!(
+            mock.received_updates
+                .iter()
+                .map(|u| u.relid())
+                .collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+        assert_eq!(accumulator.get_current_state().len(), 4);
+    }
+
+    /// Test that a committed delta observable forwards only the net
+    /// effect of transactions.
+    #[test]
+    fn committed_delta_observable() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mut observable = accumulator.create_committed_delta_observable();
+        let delta = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let all = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(observable.subscribe(Box::new(delta.clone())).is_ok());
+        assert!(accumulator
+            .subscribe_no_replay(Box::new(all.clone()))
+            .is_ok());
+
+        let updates = vec![
+            Update::Insert { relid: 1, v: 7 },
+            Update::DeleteValue { relid: 1, v: 7 },
+        ];
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(updates.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        assert_eq!(all.lock().unwrap().received_updates.len(), 2);
+        let mock = delta.lock().unwrap();
+        assert_eq!(mock.called_on_start, 0);
+        assert_eq!(mock.called_on_commit, 0);
+        assert!(mock.received_updates.is_empty());
+        drop(mock);
+
+        let updates = vec![
+            Update::DeleteValue { relid: 1, v: 1 },
+            Update::Insert { relid: 1, v: 8 },
+            Update::Insert { relid: 1, v: 1 },
+        ];
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(updates.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mock = delta.lock().unwrap();
+        assert_eq!(mock.called_on_start, 1);
+        assert_eq!(mock.called_on_commit, 1);
+        assert_eq!(mock.received_updates.len(), 1);
+        assert!(eq_updates(
+            &mock.received_updates[0],
+            &Update::Insert { relid: 1, v: 8 }
+        ));
+    }
+
+    /// Test that a committed delta observable drops insertions of
+    /// values already present and deletions of values absent before the
+    /// transaction.
+    #[test]
+    fn committed_delta_observable_against_state() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mut observable = accumulator.create_committed_delta_observable();
+        let delta = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(observable.subscribe(Box::new(delta.clone())).is_ok());
+
+        let updates = vec![
+            Update::Insert { relid: 1, v: 1 },
+            Update::DeleteValue { relid: 1, v: 9 },
+        ];
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(updates.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mock = delta.lock().unwrap();
+        assert_eq!(mock.called_on_start, 0);
+        assert_eq!(mock.called_on_commit, 0);
+        assert!(mock.received_updates.is_empty());
+        drop(mock);
+
+        let updates = vec![
+            Update::Insert { relid: 1, v: 1 },
+            Update::Insert { relid: 1, v: 8 },
+            Update::DeleteValue { relid: 2, v: 2 },
+            Update::DeleteValue { relid: 2, v: 9 },
+        ];
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(updates.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mock = delta.lock().unwrap();
+        assert_eq!(mock.called_on_start, 1);
+        assert_eq!(mock.called_on_commit, 1);
+        assert_eq!(mock.received_updates.len(), 2);
+        assert!(eq_updates(
+            &mock.received_updates[0],
+            &Update::Insert { relid: 1, v: 8 }
+        ));
+        assert!(eq_updates(
+            &mock.received_updates[1],
+            &Update::DeleteValue { relid: 2, v: 2 }
+        ));
+    }
+
+    /// Test that each named stream carries the updates selected and
+    /// transformed as configured for it.
+    #[test]
+    fn named_streams() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let config = StreamConfig::new()
+            .relids(vec![1].into_iter().collect())
+            .transform(|v| v * 10);
+        let mut audit = accumulator.create_named_stream("audit", config);
+        let mut live = accumulator.create_named_stream("live", StreamConfig::new());
+        let mut audit2 = accumulator.create_named_stream("audit", StreamConfig::new());
+
+        let audit_mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let audit2_mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let live_mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(audit.subscribe(Box::new(audit_mock.clone())).is_ok());
+        assert!(audit2.subscribe(Box::new(audit2_mock.clone())).is_ok());
+        assert!(live.subscribe(Box::new(live_mock.clone())).is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        let updates = vec![Update::Insert { relid: 2, v: 5 }];
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(updates.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        for mock in &[audit_mock, audit2_mock] {
+            let mock = mock.lock().unwrap();
+            assert_eq!(mock.called_on_start, 1);
+            assert_eq!(mock.called_on_commit, 1);
+            assert_eq!(mock.received_updates.len(), 1);
+            assert!(eq_updates(
+                &mock.received_updates[0],
+                &Update::Insert { relid: 1, v: 10 }
+            ));
+        }
+
+        let mock = live_mock.lock().unwrap();
+        assert_eq!(mock.called_on_commit, 2);
+        assert_eq!(mock.received_updates.len(), 4);
+        assert!(eq_updates(
+            &mock.received_updates[0],
+            &Update::Insert { relid: 1, v: 1 }
+        ));
+        // each stream is fed by a single subscription
+        assert_eq!(accumulator.observer_count(), 2);
+    }
+
+    /// Test that observers of a higher priority receive updates before
+    /// those of a lower one, regardless of the order they subscribed in.
+    #[test]
+    fn observer_priorities() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let clock = Arc::new(Mutex::new(0));
+        let ticks = Arc::new(Mutex::new(Vec::new()));
+        let recording = |name: &'static str| {
+            let clock = clock.clone();
+            let ticks = ticks.clone();
+            CallbackObserver::new(move |_| {
+                let mut clock = clock.lock().unwrap();
+                *clock += 1;
+                ticks.lock().unwrap().push((name, *clock));
+            })
+        };
+
+        assert!(accumulator
+            .subscribe_with_priority(Box::new(recording("low")), -1)
+            .is_ok());
+        assert!(accumulator
+            .subscribe_with_priority(Box::new(recording("high")), 10)
+            .is_ok());
+        assert!(accumulator
+            .subscribe(Box::new(recording("default")))
+            .is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let ticks = ticks.lock().unwrap().clone();
+        assert_eq!(ticks, vec![("high", 1), ("default", 2), ("low", 3)]);
+    }
+
+    /// Test that observers are completed in reverse of the order they
+    /// subscribed in.
+    #[test]
+    fn completion_order() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let clock = Arc::new(Mutex::new(0));
+        let ticks = Arc::new(Mutex::new(Vec::new()));
+        let recording = |name: &'static str| {
+            let clock = clock.clone();
+            let ticks = ticks.clone();
+            CallbackObserver::new(|_| ()).with_completed(move || {
+                let mut clock = clock.lock().unwrap();
+                *clock += 1;
+                ticks.lock().unwrap().push((name, *clock));
+            })
+        };
+
+        for name in ["first", "second", "third"] {
+            assert!(accumulator.subscribe(Box::new(recording(name))).is_ok());
+        }
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        let _ = accumulator.shutdown();
+
+        let ticks = ticks.lock().unwrap().clone();
+        assert_eq!(ticks, vec![("third", 1), ("second", 2), ("first", 3)]);
+    }
+
+    /// Test that a new observer is told about the end of the replay of
+    /// the accumulated state once, before any live updates.
+    #[test]
+    fn init_complete() {
+        /// An observer recording the kinds of events it receives.
+        #[derive(Debug, Default)]
+        struct EventLog(Vec<&'static str>);
+
+        impl Observer<Update<usize>, ()> for EventLog {
+            fn on_start(&mut self) -> Result<(), ()> {
+                self.0.push("start");
+                Ok(())
+            }
+
+            fn on_commit(&mut self) -> Result<(), ()> {
+                self.0.push("commit");
+                Ok(())
+            }
+
+            fn on_updates<'a>(
+                &mut self,
+                updates: Box<dyn Iterator<Item = Update<usize>> + 'a>,
+            ) -> Result<(), ()> {
+                self.0.extend(updates.map(|_| "update"));
+                Ok(())
+            }
+
+            fn on_init_complete(&mut self) -> Result<(), ()> {
+                self.0.push("init_complete");
+                Ok(())
+            }
+
+            fn on_completed(&mut self) -> Result<(), ()> {
+                Ok(())
+            }
+        }
+
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let log = Arc::new(Mutex::new(EventLog::default()));
+        assert!(accumulator.subscribe(Box::new(log.clone())).is_ok());
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_2()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let events = log.lock().unwrap().0.clone();
+        assert_eq!(
+            events,
+            [
+                "start",
+                "update",
+                "update",
+                "update",
+                "commit",
+                "init_complete",
+                "start",
+                "update",
+                "update",
+                "update",
+                "commit",
+            ]
+        );
+    }
+
+    /// Test that the accumulated state is sent to a new observer in
+    /// chunks of the configured size, within a single transaction.
+    #[test]
+    fn chunked_init_updates() {
+        let mut accumulator = AccumulatorBuilder::<usize, ()>::default()
+            .chunk_size(2)
+            .build();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let observer = {
+            let batches = batches.clone();
+            CallbackObserver::new(move |batch| batches.lock().unwrap().push(batch.count()))
+        };
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        assert!(accumulator.subscribe(Box::new(observer)).is_ok());
+        assert!(accumulator.subscribe(Box::new(mock.clone())).is_ok());
+
+        assert_eq!(*batches.lock().unwrap(), vec![2, 2]);
+        assert_eq!(mock.lock().unwrap().called_on_start, 1);
+        assert_eq!(mock.lock().unwrap().called_on_updates, 4);
+        assert_eq!(mock.lock().unwrap().called_on_commit, 1);
+    }
+
+    /// Test that all updates of a transaction are delivered in a single
+    /// batch if so configured.
+    #[test]
+    fn atomic_transactions() {
+        let mut accumulator = AccumulatorBuilder::<usize, ()>::default()
+            .atomic_transactions()
+            .build();
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let observer = {
+            let batches = batches.clone();
+            CallbackObserver::new(move |batch| batches.lock().unwrap().push(batch.count()))
+        };
+        assert!(accumulator.subscribe(Box::new(observer)).is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_2()), Ok(()));
+        assert!(batches.lock().unwrap().is_empty());
+
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(*batches.lock().unwrap(), vec![6]);
+        assert_eq!(accumulator.get_current_state()[&1].len(), 3);
+    }
+
+    /// Test point lookups into the state of a `DistributingAccumulator`.
+    #[test]
+    fn contains_and_relation_size() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert!(!accumulator.contains(1, &1));
+        assert_eq!(accumulator.relation_size(1), 0);
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_2()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert!(accumulator.contains(1, &1));
+        assert!(accumulator.contains(1, &3));
+        assert!(!accumulator.contains(1, &4));
+        assert!(!accumulator.contains(5, &1));
+        assert_eq!(accumulator.relation_size(1), 3);
+        assert_eq!(accumulator.relation_size(2), 2);
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_delete_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert!(!accumulator.contains(1, &1));
+        assert!(accumulator.contains(1, &2));
+        assert!(!accumulator.contains(2, &2));
+        assert!(!accumulator.contains(3, &3));
+        assert_eq!(accumulator.relation_size(1), 2);
+        assert_eq!(accumulator.relation_size(2), 1);
+        assert_eq!(accumulator.relation_size(3), 0);
+    }
+
+    /// Test that iterating the state of a `DistributingAccumulator`
+    /// visits each accumulated value exactly once.
+    #[test]
+    fn for_each_value() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        accumulator.for_each_value(|relid, v| panic!("unexpected value {} of {}", v, relid));
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mut visited = Vec::new();
+        accumulator.for_each_value(|relid, v| visited.push((relid, *v)));
+        visited.sort_unstable();
+        assert_eq!(visited, vec![(1, 1), (2, 2), (3, 3)]);
+    }
+
+    /// Test that the estimated memory usage follows the size of the
+    /// accumulated state.
+    #[test]
+    fn estimated_memory_bytes() {
+        let mut accumulator = DistributingAccumulator::<Update<String>, String, ()>::new();
+        assert_eq!(accumulator.estimated_memory_bytes(), 0);
+
+        let updates = (0..10)
+            .map(|i| Update::Insert {
+                relid: i % 2,
+                v: i.to_string().repeat(100),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(updates.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        let full = accumulator.estimated_memory_bytes();
+        assert!(full > 10 * 2 * 100, "{}", full);
+
+        let updates = (0..5)
+            .map(|i| Update::DeleteValue {
+                relid: i % 2,
+                v: i.to_string().repeat(100),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(updates.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        let half = accumulator.estimated_memory_bytes();
+        assert!(half < full, "{} >= {}", half, full);
+        assert!(half > 5 * 2 * 100, "{}", half);
+    }
+
+    /// A `tracing` subscriber recording the names and fields of all
+    /// spans created.
+    #[cfg(feature = "tracing")]
+    #[derive(Clone, Debug, Default)]
+    struct SpanRecorder {
+        spans: Arc<Mutex<Vec<(String, HashMap<String, String>)>>>,
+    }
+
+    #[cfg(feature = "tracing")]
+    struct FieldVisitor<'a>(&'a mut HashMap<String, String>);
+
+    #[cfg(feature = "tracing")]
+    impl Visit for FieldVisitor<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            let _ = self
+                .0
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    impl Subscriber for SpanRecorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> SpanId {
+            let mut fields = HashMap::new();
+            span.record(&mut FieldVisitor(&mut fields));
+            let mut spans = self.spans.lock().unwrap();
+            spans.push((span.metadata().name().to_string(), fields));
+            SpanId::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &SpanId, values: &Record<'_>) {
+            let mut spans = self.spans.lock().unwrap();
+            let (_, fields) = &mut spans[span.into_u64() as usize - 1];
+            values.record(&mut FieldVisitor(fields));
+        }
+
+        fn record_follows_from(&self, _: &SpanId, _: &SpanId) {}
+
+        fn event(&self, _: &Event<'_>) {}
+
+        fn enter(&self, _: &SpanId) {}
+
+        fn exit(&self, _: &SpanId) {}
+    }
+
+    /// Test that the events processed by an accumulator are covered by
+    /// spans carrying the accumulator's ID and the number of updates.
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn tracing_spans() {
+        let recorder = SpanRecorder::default();
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let id = accumulator.id;
+
+        tracing::subscriber::with_default(recorder.clone(), || {
+            assert_eq!(accumulator.on_start(), Ok(()));
+            assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+            assert_eq!(accumulator.on_commit(), Ok(()));
+        });
+
+        let spans = recorder.spans.lock().unwrap();
+        let (_, fields) = spans
+            .iter()
+            .find(|(name, _)| name == "on_updates")
+            .unwrap();
+        assert_eq!(fields["accumulator_id"], id.to_string());
+        assert_eq!(fields["update_count"], "3");
+        assert_eq!(
+            spans.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(),
+            vec!["on_start", "on_updates", "on_commit"]
+        );
+    }
+
+    /// An observer failing to process the first `failures` batches of
+    /// updates it receives.
+    #[derive(Debug, Default)]
+    struct FlakyObserver {
+        failures: usize,
+        called_on_updates: usize,
+        called_on_commit: usize,
+    }
+
+    impl Observer<Update<usize>, String> for FlakyObserver {
+        fn on_start(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), String> {
+            self.called_on_commit += 1;
+            Ok(())
+        }
+
+        fn on_updates<'a>(
+            &mut self,
+            updates: Box<dyn Iterator<Item = Update<usize>> + 'a>,
+        ) -> Result<(), String> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err("transient failure".to_string());
+            }
+            self.called_on_updates += updates.count();
+            Ok(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    /// Feed `get_usize_updates_1` to an accumulator retrying up to three
+    /// attempts, with an observer failing the given number of times
+    /// subscribed.
+    fn retry_flaky(
+        failures: usize,
+    ) -> (
+        DistributingAccumulator<Update<usize>, usize, String>,
+        Arc<Mutex<FlakyObserver>>,
+    ) {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(1),
+            multiplier: 2,
+            max_attempts: 3,
+        };
+        let mut accumulator = AccumulatorBuilder::<usize, String>::default()
+            .retry(policy)
+            .build();
+        let flaky = Arc::new(Mutex::new(FlakyObserver {
+            failures,
+            ..Default::default()
+        }));
+        assert!(accumulator.subscribe(Box::new(flaky.clone())).is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        (accumulator, flaky)
+    }
+
+    /// Test that updates failing to be delivered are retried until they
+    /// are delivered, without cancelling the subscription.
+    #[test]
+    fn retry_delivery() {
+        let (accumulator, flaky) = retry_flaky(2);
+        await_expected(|| {
+            // do not poison the lock by failing while holding it
+            let (updates, commits) = {
+                let flaky = flaky.lock().unwrap();
+                (flaky.called_on_updates, flaky.called_on_commit)
+            };
+            assert_eq!((updates, commits), (3, 1));
+        });
+        assert_eq!(accumulator.observer_count(), 1);
+    }
+
+    /// Test that an observer failing more often than the maximum number
+    /// of attempts is unsubscribed.
+    #[test]
+    fn retry_give_up() {
+        let (accumulator, flaky) = retry_flaky(3);
+        // the observer is dropped once given up on
+        await_expected(|| assert_eq!(Arc::strong_count(&flaky), 1));
+        assert_eq!(accumulator.observer_count(), 0);
+        assert_eq!(flaky.lock().unwrap().called_on_updates, 0);
+    }
+
+    /// Test that `snapshot_once` returns the accumulated state as
+    /// insertions without creating a subscription.
+    #[test]
+    fn snapshot_once() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mut snapshot = accumulator.snapshot_once();
+        snapshot.sort_unstable_by_key(Update::relid);
+        let expected = get_usize_updates_1().collect::<Vec<_>>();
+        assert_eq!(snapshot.len(), 3);
+        assert!(snapshot
+            .iter()
+            .zip(expected.iter())
+            .all(|(u1, u2)| eq_updates(u1, u2)));
+        assert_eq!(accumulator.observer_count(), 0);
+    }
+
+    /// Test that an accumulator chained to another one makes the latter
+    /// follow its state, including the state accumulated before.
+    #[test]
+    fn chain_to() {
+        // relations whose values were all deleted are retained as empty
+        fn non_empty(state: HashMap<RelId, HashSet<usize>>) -> HashMap<RelId, HashSet<usize>> {
+            state.into_iter().filter(|(_, vs)| !vs.is_empty()).collect()
+        }
+
+        let mut upstream = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let downstream = Arc::new(Mutex::new(DistributingAccumulator::<
+            Update<usize>,
+            usize,
+            (),
+        >::new()));
+        assert_eq!(upstream.on_start(), Ok(()));
+        assert_eq!(upstream.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(upstream.on_commit(), Ok(()));
+
+        // values the upstream lacks are removed from the downstream
+        let stale = vec![Update::Insert { relid: 5, v: 5 }];
+        {
+            let mut downstream = downstream.lock().unwrap();
+            assert_eq!(downstream.on_start(), Ok(()));
+            assert_eq!(downstream.on_updates(Box::new(stale.into_iter())), Ok(()));
+            assert_eq!(downstream.on_commit(), Ok(()));
+        }
+
+        assert!(upstream.chain_to(&downstream).is_ok());
+        assert_eq!(
+            non_empty(downstream.lock().unwrap().get_current_state()),
+            upstream.get_current_state()
+        );
 
-        assert_eq!(accumulator.on_start(), Ok(()));
-        assert_eq!(mock1.lock().unwrap().called_on_start, 1);
-        assert_eq!(mock2.lock().unwrap().called_on_start, 1);
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(downstream
+            .lock()
+            .unwrap()
+            .subscribe(Box::new(mock.clone()))
+            .is_ok());
+        assert_eq!(mock.lock().unwrap().received_updates.len(), 3);
 
-        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
-        assert_eq!(mock1.lock().unwrap().called_on_updates, 3);
-        assert_eq!(mock2.lock().unwrap().called_on_updates, 3);
+        assert_eq!(upstream.on_start(), Ok(()));
+        assert_eq!(upstream.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(upstream.on_commit(), Ok(()));
+        assert_eq!(
+            non_empty(downstream.lock().unwrap().get_current_state()),
+            upstream.get_current_state()
+        );
+        assert_eq!(mock.lock().unwrap().received_updates.len(), 7);
 
-        assert_eq!(accumulator.on_updates(get_usize_updates_2()), Ok(()));
-        assert_eq!(mock1.lock().unwrap().called_on_updates, 6);
-        assert_eq!(mock2.lock().unwrap().called_on_updates, 6);
+        // each value is deleted exactly once when the upstream completes
+        assert_eq!(upstream.on_completed(), Ok(()));
+        assert!(downstream
+            .lock()
+            .unwrap()
+            .get_current_state()
+            .values()
+            .all(HashSet::is_empty));
+        assert_eq!(mock.lock().unwrap().received_updates.len(), 14);
+    }
 
-        assert_eq!(accumulator.on_commit(), Ok(()));
-        assert_eq!(mock1.lock().unwrap().called_on_commit, 1);
-        assert_eq!(mock2.lock().unwrap().called_on_commit, 1);
+    /// Test that chaining accumulators fails while a transaction is in
+    /// progress, leaving the downstream accumulator untouched.
+    #[test]
+    fn chain_to_in_transaction() {
+        let mut upstream = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let downstream = Arc::new(Mutex::new(DistributingAccumulator::<
+            Update<usize>,
+            usize,
+            (),
+        >::new()));
+        assert_eq!(upstream.on_start(), Ok(()));
+        assert_eq!(upstream.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(
+            upstream.chain_to(&downstream),
+            Err(AccumulatorError::InTransaction)
+        );
+        assert_eq!(upstream.on_commit(), Ok(()));
+        assert!(downstream.lock().unwrap().get_current_state().is_empty());
 
-        assert_eq!(accumulator.on_start(), Ok(()));
-        assert_eq!(mock1.lock().unwrap().called_on_start, 2);
-        assert_eq!(mock2.lock().unwrap().called_on_start, 2);
+        assert!(upstream.chain_to(&downstream).is_ok());
+        assert_eq!(
+            downstream.lock().unwrap().get_current_state(),
+            upstream.get_current_state()
+        );
+    }
 
-        assert_eq!(accumulator.on_updates(get_usize_updates_3()), Ok(()));
-        assert_eq!(mock1.lock().unwrap().called_on_updates, 10);
-        assert_eq!(mock2.lock().unwrap().called_on_updates, 10);
+    /// Test that values not inserted again within the TTL expire.
+    #[test]
+    fn ttl_expiry() {
+        let ttl = Duration::from_secs(60);
+        let mut accumulator = AccumulatorBuilder::<usize, ()>::default().ttl(ttl).build();
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(accumulator.subscribe(Box::new(mock.clone())).is_ok());
 
+        let start = Instant::now();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
         assert_eq!(accumulator.on_commit(), Ok(()));
-        assert_eq!(mock1.lock().unwrap().called_on_commit, 2);
-        assert_eq!(mock2.lock().unwrap().called_on_commit, 2);
 
-        assert_eq!(accumulator.on_completed(), Ok(()));
-        assert_eq!(mock1.lock().unwrap().called_on_start, 3);
-        assert_eq!(mock2.lock().unwrap().called_on_start, 3);
-        assert_eq!(mock1.lock().unwrap().called_on_updates, 20);
-        assert_eq!(mock2.lock().unwrap().called_on_updates, 20);
-        assert_eq!(mock1.lock().unwrap().called_on_commit, 3);
-        assert_eq!(mock2.lock().unwrap().called_on_commit, 3);
-        assert_eq!(mock1.lock().unwrap().called_on_completed, 1);
-        assert_eq!(mock2.lock().unwrap().called_on_completed, 1);
+        // nothing expires before the TTL passed
+        assert_eq!(accumulator.expire_now(start), Ok(()));
+        assert_eq!(mock.lock().unwrap().received_updates.len(), 3);
+
+        let later = Instant::now() + ttl + Duration::from_secs(1);
+        assert_eq!(accumulator.expire_now(later), Ok(()));
+        let received = mock.lock().unwrap().received_updates.clone();
+        assert_eq!(received.len(), 6);
+        assert!(received[3..]
+            .iter()
+            .all(|u| matches!(u, Update::DeleteValue { .. })));
+        assert!(accumulator
+            .get_current_state()
+            .values()
+            .all(HashSet::is_empty));
     }
 
-    /// when a new downstream consumer subscribes, it should be updated with the current values
+    /// Test that polling a `LatchObservable` yields the net effect of
+    /// the transactions committed since the previous poll.
     #[test]
-    fn test_fix_up_updates() {
+    fn latch_observable() {
         let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
-        let mock1 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
-        let mock2 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
-        let mock3 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let latch = accumulator.create_latch_observable();
+        assert!(latch.poll().is_empty());
 
-        // start with one observer and give it updates
-        assert!(accumulator.subscribe(Box::new(mock1.clone())).is_ok());
         assert_eq!(accumulator.on_start(), Ok(()));
         assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
         assert_eq!(accumulator.on_commit(), Ok(()));
-        let received_updates = mock1.lock().unwrap().received_updates.clone();
-        assert_eq!(received_updates.len(), 3);
-        assert!(received_updates
+        let deletes = vec![Update::DeleteValue { relid: 2, v: 2 }];
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(deletes.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let delta = latch.poll();
+        let expected = [
+            Update::Insert { relid: 1, v: 1 },
+            Update::Insert { relid: 3, v: 3 },
+        ];
+        assert_eq!(delta.len(), expected.len());
+        assert!(delta
             .iter()
-            .any(|u| eq_updates(u, &Update::Insert { relid: 1, v: 1 })));
-        assert!(received_updates
+            .zip(expected.iter())
+            .all(|(u1, u2)| eq_updates(u1, u2)));
+        assert!(latch.poll().is_empty());
+
+        assert_eq!(accumulator.observer_count(), 1);
+        drop(latch);
+        assert_eq!(accumulator.observer_count(), 0);
+    }
+
+    /// Test that only the most recent transactions are recorded, along
+    /// with their sequence numbers.
+    #[test]
+    fn recent_transactions() {
+        let mut accumulator = AccumulatorBuilder::<usize, ()>::default()
+            .history(2)
+            .build();
+        let transactions = vec![
+            get_usize_updates_1(),
+            get_usize_updates_2(),
+            get_usize_updates_3(),
+        ];
+        for updates in transactions {
+            assert_eq!(accumulator.on_start(), Ok(()));
+            assert_eq!(accumulator.on_updates(updates), Ok(()));
+            assert_eq!(accumulator.on_commit(), Ok(()));
+        }
+
+        let recent = accumulator.recent_transactions();
+        assert_eq!(
+            recent.iter().map(|(seq, _)| *seq).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        for ((_, updates), expected) in recent
             .iter()
-            .any(|u| eq_updates(u, &Update::Insert { relid: 2, v: 2 })));
-        assert!(received_updates
+            .zip(vec![get_usize_updates_2(), get_usize_updates_3()])
+        {
+            let expected = expected.collect::<Vec<_>>();
+            assert_eq!(updates.len(), expected.len());
+            assert!(updates
+                .iter()
+                .zip(expected.iter())
+                .all(|(u1, u2)| eq_updates(u1, u2)));
+        }
+    }
+
+    /// Test that sorted init updates are sent in the order of their
+    /// relation and value.
+    #[test]
+    fn sorted_init() {
+        let mut accumulator = AccumulatorBuilder::<usize, ()>::default()
+            .sorted_init()
+            .build();
+        let updates = vec![
+            Update::Insert { relid: 2, v: 7 },
+            Update::Insert { relid: 1, v: 9 },
+            Update::Insert { relid: 2, v: 3 },
+            Update::Insert { relid: 1, v: 4 },
+        ];
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(updates.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(accumulator.subscribe(Box::new(mock.clone())).is_ok());
+        let received = mock.lock().unwrap().received_updates.clone();
+        let expected = [
+            Update::Insert { relid: 1, v: 4 },
+            Update::Insert { relid: 1, v: 9 },
+            Update::Insert { relid: 2, v: 3 },
+            Update::Insert { relid: 2, v: 7 },
+        ];
+        assert_eq!(received.len(), expected.len());
+        assert!(received
             .iter()
-            .any(|u| eq_updates(u, &Update::Insert { relid: 3, v: 3 })));
+            .zip(expected.iter())
+            .all(|(u1, u2)| eq_updates(u1, u2)));
+    }
 
-        // create an un-accumulated observable
-        let mut observable = accumulator.create_observable();
+    /// Test that a transaction is reported to be in progress between
+    /// `on_start` and `on_commit`.
+    #[test]
+    fn in_transaction() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert!(!accumulator.in_transaction());
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert!(accumulator.in_transaction());
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert!(accumulator.in_transaction());
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert!(!accumulator.in_transaction());
 
-        // provide second batch of updates to the accumulator
         assert_eq!(accumulator.on_start(), Ok(()));
-        assert_eq!(accumulator.on_updates(get_usize_updates_2()), Ok(()));
+        assert_eq!(accumulator.on_completed(), Ok(()));
+        assert!(!accumulator.in_transaction());
+    }
+
+    /// Test that updates received while paused are accumulated and
+    /// forwarded exactly once, as a single transaction, on resume.
+    #[test]
+    fn pause_resume() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
         assert_eq!(accumulator.on_commit(), Ok(()));
 
-        // the observable does not see any updates until subscribed too
-        assert!(observable.subscribe(Box::new(mock2.clone())).is_ok());
-        let received_updates = mock2.lock().unwrap().received_updates.clone();
-        assert_eq!(received_updates.len(), 0);
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        assert!(accumulator.subscribe(Box::new(mock.clone())).is_ok());
+        let updates = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(accumulator
+            .subscribe_no_replay(Box::new(updates.clone()))
+            .is_ok());
 
-        // the following updates should be received via the create_observable
+        assert_eq!(accumulator.pause(), Ok(()));
+        assert!(accumulator.is_paused());
+
+        let deletes = vec![Update::DeleteValue { relid: 1, v: 1 }];
         assert_eq!(accumulator.on_start(), Ok(()));
         assert_eq!(accumulator.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(deletes.into_iter())),
+            Ok(())
+        );
         assert_eq!(accumulator.on_commit(), Ok(()));
-        let received_updates = mock2.lock().unwrap().received_updates.clone();
-        assert_eq!(received_updates.len(), 4);
-        assert!(received_updates
-            .iter()
-            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 1 })));
-        assert!(received_updates
-            .iter()
-            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 2 })));
-        assert!(received_updates
-            .iter()
-            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 3 })));
-        assert!(received_updates
-            .iter()
-            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 4 })));
 
-        // a new observer via subscribe() should receive all updates
+        // the pause cannot be ended within a transaction
+        let deletes = vec![Update::DeleteValue { relid: 4, v: 1 }];
         assert_eq!(accumulator.on_start(), Ok(()));
-        assert_eq!(accumulator.on_updates(get_usize_delete_updates_1()), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(deletes.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.resume(), Err(AccumulatorError::InTransaction));
+        assert_eq!(accumulator.pause(), Err(AccumulatorError::InTransaction));
         assert_eq!(accumulator.on_commit(), Ok(()));
+        assert!(accumulator.is_paused());
 
-        assert!(accumulator.subscribe(Box::new(mock3.clone())).is_ok());
-        let received_updates = mock3.lock().unwrap().received_updates.clone();
-        assert_eq!(received_updates.len(), 7);
-        assert!(received_updates
-            .iter()
-            .any(|u| eq_updates(u, &Update::Insert { relid: 1, v: 2 })));
-        assert!(received_updates
-            .iter()
-            .any(|u| eq_updates(u, &Update::Insert { relid: 1, v: 3 })));
-        assert!(received_updates
-            .iter()
-            .any(|u| eq_updates(u, &Update::Insert { relid: 2, v: 3 })));
-        assert!(received_updates
-            .iter()
-            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 1 })));
-        assert!(received_updates
+        assert_eq!(mock.lock().unwrap().called_on_start, 1);
+        assert!(updates.lock().unwrap().received_updates.is_empty());
+        assert_eq!(accumulator.get_current_state()[&4].len(), 3);
+
+        assert_eq!(accumulator.resume(), Ok(()));
+        assert!(!accumulator.is_paused());
+        assert_eq!(accumulator.resume(), Ok(()));
+
+        let mock = *mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, 2);
+        assert_eq!(mock.called_on_commit, 2);
+        // three initial insertions, one deletion and three insertions
+        assert_eq!(mock.called_on_updates, 7);
+
+        let received = updates.lock().unwrap().received_updates.clone();
+        let expected = [
+            Update::DeleteValue { relid: 1, v: 1 },
+            Update::Insert { relid: 4, v: 2 },
+            Update::Insert { relid: 4, v: 3 },
+            Update::Insert { relid: 4, v: 4 },
+        ];
+        assert_eq!(received.len(), expected.len());
+        assert!(expected
             .iter()
-            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 2 })));
-        assert!(received_updates
+            .all(|u1| received.iter().any(|u2| eq_updates(u1, u2))));
+
+        // updates flow again after resuming
+        let deletes = vec![Update::DeleteValue { relid: 2, v: 2 }];
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(deletes.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(updates.lock().unwrap().received_updates.len(), 5);
+    }
+
+    /// Test that accumulators fed identical updates hold equal states
+    /// and that the diff of diverged states reconciles them.
+    #[test]
+    fn state_eq_diff() {
+        let mut accumulator1 = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mut accumulator2 = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        for accumulator in [&mut accumulator1, &mut accumulator2].iter_mut() {
+            assert_eq!(accumulator.on_start(), Ok(()));
+            assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+            assert_eq!(accumulator.on_updates(get_usize_updates_3()), Ok(()));
+            assert_eq!(accumulator.on_commit(), Ok(()));
+        }
+        assert!(accumulator1.state_eq(&accumulator2));
+        assert!(accumulator1.state_diff(&accumulator2).is_empty());
+
+        // the same values with a different multiplicity, which is not
+        // counted
+        assert_eq!(accumulator2.on_start(), Ok(()));
+        assert_eq!(accumulator2.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator2.on_commit(), Ok(()));
+        assert!(accumulator1.state_eq(&accumulator2));
+        assert!(accumulator1.state_diff(&accumulator2).is_empty());
+
+        let deletes = vec![
+            Update::DeleteValue { relid: 4, v: 1 },
+            Update::DeleteValue { relid: 4, v: 2 },
+        ];
+        assert_eq!(accumulator1.on_start(), Ok(()));
+        assert_eq!(
+            accumulator1.on_updates(Box::new(deletes.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator1.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator1.on_commit(), Ok(()));
+
+        let diff = accumulator1.state_diff(&accumulator2);
+        assert_eq!(diff.len(), 2);
+        assert!(diff
             .iter()
-            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 3 })));
-        assert!(received_updates
+            .all(|u| matches!(u, Update::DeleteValue { relid: 4, .. })));
+
+        assert_eq!(accumulator2.on_start(), Ok(()));
+        assert_eq!(accumulator2.on_updates(Box::new(diff.into_iter())), Ok(()));
+        assert_eq!(accumulator2.on_commit(), Ok(()));
+        assert!(accumulator1.state_eq(&accumulator2));
+    }
+
+    /// Test that accumulators counting multiplicities compare them and
+    /// that their diff reconciles them.
+    #[test]
+    fn state_eq_diff_weighted() {
+        let build = || {
+            AccumulatorBuilder::<usize, DuplicateError>::default()
+                .duplicate_policy(DuplicatePolicy::CountWeight)
+                .build()
+        };
+        let mut accumulator1 = build();
+        let mut accumulator2 = build();
+        for accumulator in [&mut accumulator1, &mut accumulator2].iter_mut() {
+            assert_eq!(accumulator.on_start(), Ok(()));
+            assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+            assert_eq!(accumulator.on_commit(), Ok(()));
+        }
+        assert!(accumulator1.state_eq(&accumulator2));
+        assert!(accumulator1.state_diff(&accumulator2).is_empty());
+
+        // the same values with a different multiplicity
+        assert_eq!(accumulator1.on_start(), Ok(()));
+        assert_eq!(accumulator1.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator1.on_commit(), Ok(()));
+        assert!(!accumulator1.state_eq(&accumulator2));
+        let diff = accumulator1.state_diff(&accumulator2);
+        assert_eq!(diff.len(), 3);
+        assert!(diff.iter().all(|u| matches!(u, Update::Insert { .. })));
+        assert!(accumulator2
+            .state_diff(&accumulator1)
             .iter()
-            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 4 })));
+            .all(|u| matches!(u, Update::DeleteValue { .. })));
+
+        assert_eq!(accumulator2.on_start(), Ok(()));
+        assert_eq!(accumulator2.on_updates(Box::new(diff.into_iter())), Ok(()));
+        assert_eq!(accumulator2.on_commit(), Ok(()));
+        assert!(accumulator1.state_eq(&accumulator2));
+        assert!(accumulator1.state_diff(&accumulator2).is_empty());
     }
 
-    /// when an upstream source calls `on_completed`,
-    /// the accumulated state should be removed from observers
+    /// Test that the deletion and insertion of values with the same key
+    /// within a transaction are forwarded as a single `Modify` update.
     #[test]
-    fn test_delete_updates() {
-        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
-        let mock1 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
-        let mock2 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+    fn modify_events() {
+        // values of relation 1 are keyed by their tens digit
+        let mut accumulator = AccumulatorBuilder::<usize, ()>::default()
+            .modify_events(1, |v| v / 10)
+            .build();
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(accumulator.subscribe(Box::new(mock.clone())).is_ok());
 
-        assert!(accumulator.subscribe(Box::new(mock1.clone())).is_ok());
+        let updates = vec![
+            Update::Insert { relid: 1, v: 11 },
+            Update::Insert { relid: 1, v: 25 },
+        ];
         assert_eq!(accumulator.on_start(), Ok(()));
-        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
-        assert_eq!(accumulator.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(updates.into_iter())),
+            Ok(())
+        );
         assert_eq!(accumulator.on_commit(), Ok(()));
 
+        let deletes = vec![Update::DeleteValue { relid: 1, v: 11 }];
+        let inserts = vec![
+            Update::Insert { relid: 2, v: 13 },
+            Update::Insert { relid: 1, v: 12 },
+        ];
         assert_eq!(accumulator.on_start(), Ok(()));
-        assert_eq!(accumulator.on_updates(get_usize_delete_updates_1()), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(deletes.into_iter())),
+            Ok(())
+        );
+        assert_eq!(
+            accumulator.on_updates(Box::new(inserts.into_iter())),
+            Ok(())
+        );
         assert_eq!(accumulator.on_commit(), Ok(()));
 
-        assert!(accumulator.subscribe(Box::new(mock2.clone())).is_ok());
-        let received_updates = mock2.lock().unwrap().received_updates.clone();
-        assert_eq!(received_updates.len(), 4);
-        assert!(received_updates
-            .iter()
-            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 1 })));
-        assert!(received_updates
-            .iter()
-            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 2 })));
-        assert!(received_updates
-            .iter()
-            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 3 })));
-        assert!(received_updates
-            .iter()
-            .any(|u| eq_updates(u, &Update::Insert { relid: 4, v: 4 })));
+        let state = accumulator.get_current_state();
+        assert_eq!(state[&1], vec![12, 25].into_iter().collect());
+
+        let received = mock.lock().unwrap().received_updates.clone();
+        assert_eq!(received.len(), 4);
+        match &received[2] {
+            Update::Modify { relid: 1, k: 1, m } => {
+                let mut v = 11;
+                assert_eq!(m.mutate(&mut v), Ok(()));
+                assert_eq!(v, 12);
+            }
+            update => panic!("unexpected update: {:?}", update),
+        }
+        assert!(matches!(received[3], Update::Insert { relid: 2, v: 13 }));
+    }
 
+    /// Send `count` transactions of two insertions each to the given
+    /// accumulator, starting with the given value.
+    fn send_pairs(
+        accumulator: &mut DistributingAccumulator<Update<usize>, usize, ()>,
+        first: usize,
+        count: usize,
+    ) {
+        for i in 0..count {
+            let v = first + 2 * i;
+            let updates = vec![
+                Update::Insert { relid: 1, v },
+                Update::Insert { relid: 1, v: v + 1 },
+            ];
+            assert_eq!(accumulator.on_start(), Ok(()));
+            assert_eq!(
+                accumulator.on_updates(Box::new(updates.into_iter())),
+                Ok(())
+            );
+            assert_eq!(accumulator.on_commit(), Ok(()));
+        }
+    }
+
+    /// Test that small transactions are combined into a single one once
+    /// they reach the minimum batch size, and that the remainder is
+    /// flushed upon completion.
+    #[test]
+    fn min_batch_observable() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mut observable = accumulator.create_min_batch_observable(5, Duration::from_secs(3600));
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(observable.subscribe(Box::new(mock.clone())).is_ok());
+
+        send_pairs(&mut accumulator, 0, 2);
+        assert_eq!(mock.lock().unwrap().called_on_start, 0);
+        send_pairs(&mut accumulator, 4, 1);
+        assert_eq!(mock.lock().unwrap().called_on_start, 1);
+        assert_eq!(mock.lock().unwrap().called_on_commit, 1);
+        assert_eq!(mock.lock().unwrap().called_on_updates, 6);
+
+        send_pairs(&mut accumulator, 6, 1);
+        assert_eq!(mock.lock().unwrap().called_on_start, 1);
         assert_eq!(accumulator.on_completed(), Ok(()));
+        // the remainder is flushed upon completion, while the deletions
+        // clearing the state form a batch of their own
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, 3);
+        assert_eq!(mock.called_on_updates, 16);
+        assert_eq!(mock.called_on_completed, 1);
+    }
 
-        let received_updates = mock1.lock().unwrap().received_updates.clone();
-        assert_eq!(received_updates.len(), 14); // 3 + 4 inserts, 3 manual deletions, 4 automatic deletions
-        assert!(received_updates
-            .iter()
-            .any(|u| eq_updates(u, &Update::DeleteValue { relid: 4, v: 1 })));
-        assert!(received_updates
-            .iter()
-            .any(|u| eq_updates(u, &Update::DeleteValue { relid: 4, v: 2 })));
-        assert!(received_updates
-            .iter()
-            .any(|u| eq_updates(u, &Update::DeleteValue { relid: 4, v: 3 })));
-        assert!(received_updates
-            .iter()
-            .any(|u| eq_updates(u, &Update::DeleteValue { relid: 4, v: 4 })));
+    /// Test that a batch below the minimum size is flushed once it was
+    /// held back for the maximum latency.
+    #[test]
+    fn min_batch_max_latency() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mut observable =
+            accumulator.create_min_batch_observable(100, Duration::from_millis(50));
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(observable.subscribe(Box::new(mock.clone())).is_ok());
 
-        let received_updates = mock2.lock().unwrap().received_updates.clone();
-        assert_eq!(received_updates.len(), 8); // 4 inserts, 4 automatic deletions
-        assert!(received_updates
-            .iter()
-            .any(|u| eq_updates(u, &Update::DeleteValue { relid: 4, v: 1 })));
-        assert!(received_updates
-            .iter()
-            .any(|u| eq_updates(u, &Update::DeleteValue { relid: 4, v: 2 })));
-        assert!(received_updates
-            .iter()
-            .any(|u| eq_updates(u, &Update::DeleteValue { relid: 4, v: 3 })));
-        assert!(received_updates
-            .iter()
-            .any(|u| eq_updates(u, &Update::DeleteValue { relid: 4, v: 4 })));
+        send_pairs(&mut accumulator, 0, 1);
+        assert_eq!(mock.lock().unwrap().called_on_start, 0);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while mock.lock().unwrap().called_on_commit == 0 && Instant::now() < deadline {
+            sleep(Duration::from_millis(10));
+        }
+        assert_eq!(mock.lock().unwrap().called_on_start, 1);
+        assert_eq!(mock.lock().unwrap().called_on_updates, 2);
+    }
+
+    /// Test that a maximum latency of zero flushes every batch right
+    /// away, including those committed after the thread went idle.
+    #[test]
+    fn min_batch_zero_latency() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mut observable = accumulator.create_min_batch_observable(100, Duration::ZERO);
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(observable.subscribe(Box::new(mock.clone())).is_ok());
+
+        for (i, start) in [0, 2].iter().enumerate() {
+            send_pairs(&mut accumulator, *start, 1);
+            await_expected(|| {
+                let (committed, updates) = {
+                    let mock = mock.lock().unwrap();
+                    (mock.called_on_commit, mock.called_on_updates)
+                };
+                assert_eq!(committed, i + 1);
+                assert_eq!(updates, 2 * (i + 1));
+            });
+            sleep(Duration::from_millis(20));
+        }
+    }
+
+    /// Test that updates pushed by multiple threads concurrently are
+    /// committed as well-formed transactions.
+    #[test]
+    fn concurrent_input() {
+        let accumulator = Arc::new(Mutex::new(DistributingAccumulator::<
+            Update<usize>,
+            usize,
+            (),
+        >::new()));
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(accumulator
+            .lock()
+            .unwrap()
+            .subscribe(Box::new(mock.clone()))
+            .is_ok());
+        let input = DistributingAccumulator::concurrent_input(&accumulator);
+
+        let producers = (1..=4)
+            .map(|relid| {
+                let input = input.clone();
+                spawn(move || {
+                    for v in 0..25 {
+                        input.push(relid, Update::Insert { relid, v });
+                        if v % 5 == 4 {
+                            assert_eq!(input.commit(), Ok(()));
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        assert_eq!(input.commit(), Ok(()));
+
+        let expected = (1..=4)
+            .map(|relid| (relid, (0..25).collect::<HashSet<_>>()))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(accumulator.lock().unwrap().get_current_state(), expected);
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, mock.called_on_commit);
+        assert_eq!(mock.called_on_updates, 100);
+    }
+
+    /// Test that observers of a heartbeat observable receive empty
+    /// transactions while the accumulator is idle, without the
+    /// accumulated state or its other observers being affected.
+    #[test]
+    fn heartbeat_observable() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        assert!(accumulator.subscribe(Box::new(mock.clone())).is_ok());
+        let interval = Duration::from_millis(20);
+        let mut observable = accumulator.create_heartbeat_observable(interval);
+        let heartbeats = Arc::new(Mutex::new(RecordingObserver::new(Box::new(
+            MockObserver::new(),
+        ))));
+        assert!(observable.subscribe(Box::new(heartbeats.clone())).is_ok());
+
+        await_expected(|| {
+            let events = heartbeats.lock().unwrap().events().to_vec();
+            assert!(events.len() >= 4);
+            assert!(events
+                .iter()
+                .all(|e| matches!(e, RecordedEvent::Start | RecordedEvent::Commit)));
+        });
+
+        let mock = *mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, 1);
+        assert_eq!(mock.called_on_commit, 1);
+        assert_eq!(accumulator.get_current_state().len(), 3);
     }
 }
@@ -0,0 +1,156 @@
+use std::error::Error;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+
+/// The sequence numbers an accumulator assigns to the transactions it
+/// forwards to its observers, numbered from one in the order they were
+/// started downstream. Transactions that are not forwarded, e.g.,
+/// because they are empty or merged into a batch, do not receive a
+/// sequence number of their own.
+#[derive(Debug, Default)]
+pub(crate) struct Sequence {
+    /// The sequence number of the last transaction started downstream.
+    started: AtomicU64,
+    /// The sequence number of the last transaction whose commit was
+    /// forwarded successfully.
+    committed: AtomicU64,
+}
+
+impl Sequence {
+    /// Assign the next sequence number to a transaction being started
+    /// downstream.
+    pub fn start(&self) -> u64 {
+        self.started.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Record that the commit of the transaction with the given
+    /// sequence number was forwarded successfully.
+    pub fn commit(&self, sequence: u64) {
+        let _ = self.committed.fetch_max(sequence, Ordering::SeqCst);
+    }
+
+    /// Retrieve the sequence number of the last transaction started
+    /// downstream.
+    pub fn started(&self) -> u64 {
+        self.started.load(Ordering::SeqCst)
+    }
+
+    /// Retrieve the sequence number of the last transaction whose commit
+    /// was forwarded successfully.
+    pub fn committed(&self) -> u64 {
+        self.committed.load(Ordering::SeqCst)
+    }
+}
+
+/// A handle for an observer to acknowledge that it durably processed
+/// the transactions of an accumulator, as handed out by
+/// `DistributingAccumulator::subscribe_acked`. Transactions are
+/// identified by the sequence number the observer is handed along with
+/// their start via `on_start_seq`, so that an observer processing
+/// events asynchronously acknowledges exactly the transactions it
+/// received.
+#[derive(Clone, Debug)]
+pub struct AckHandle {
+    /// The sequence numbers of the transactions forwarded by the
+    /// accumulator.
+    sequence: Arc<Sequence>,
+    /// The sequence number of the last transaction acknowledged.
+    acked: Arc<AtomicU64>,
+    /// The signal to notify the accumulator of acknowledgments with.
+    signal: Arc<AckSignal>,
+}
+
+impl AckHandle {
+    /// Create a new `AckHandle` for an observer subscribing to an
+    /// accumulator forwarding transactions with the given sequence
+    /// numbers. The transactions committed so far are considered
+    /// acknowledged.
+    pub(crate) fn new(sequence: Arc<Sequence>, signal: Arc<AckSignal>) -> Self {
+        let acked = sequence.committed();
+        Self {
+            sequence,
+            acked: Arc::new(AtomicU64::new(acked)),
+            signal,
+        }
+    }
+
+    /// Acknowledge all transactions up to and including the one with the
+    /// given sequence number. Acknowledging an earlier transaction than
+    /// before has no effect, and transactions not yet started cannot be
+    /// acknowledged.
+    pub fn ack(&self, sequence: u64) {
+        let sequence = sequence.min(self.sequence.started());
+        if self.acked.fetch_max(sequence, Ordering::SeqCst) < sequence {
+            self.signal.notify();
+        }
+    }
+
+    /// Retrieve the sequence number of the last transaction
+    /// acknowledged.
+    pub fn acked(&self) -> u64 {
+        self.acked.load(Ordering::SeqCst)
+    }
+}
+
+/// The signal an accumulator waits on for the observers subscribed via
+/// `subscribe_acked` to acknowledge transactions.
+#[derive(Debug, Default)]
+pub(crate) struct AckSignal {
+    /// The lock the condition waited for is checked under.
+    lock: Mutex<()>,
+    /// The condition variable notified of acknowledgments.
+    acked: Condvar,
+}
+
+impl AckSignal {
+    /// Wake up all threads waiting for an acknowledgment.
+    pub fn notify(&self) {
+        let _guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        self.acked.notify_all();
+    }
+
+    /// Block the current thread while the given condition holds,
+    /// checking it again after every acknowledgment.
+    pub fn wait_while<F>(&self, mut condition: F)
+    where
+        F: FnMut() -> bool,
+    {
+        let mut guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        while condition() {
+            guard = self
+                .acked
+                .wait(guard)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
+
+/// The error reported for starting a transaction while the maximum
+/// number of transactions are in flight, i.e., committed but not yet
+/// acknowledged, if starting fails rather than blocks then.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WouldBlock {
+    /// The number of transactions in flight.
+    pub in_flight: u64,
+    /// The maximum number of transactions in flight.
+    pub max: u64,
+}
+
+impl Display for WouldBlock {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "{} transactions are in flight, at most {} are allowed",
+            self.in_flight, self.max
+        )
+    }
+}
+
+impl Error for WouldBlock {}
@@ -0,0 +1,789 @@
+//! A variant of the `DistributingAccumulator` that decouples its
+//! observers from each other. Every subscribed observer is wrapped in
+//! a `QueuedObserver`, which hands events off to a dedicated delivery
+//! thread through a bounded queue. A slow observer thus only stalls its
+//! own delivery thread and not the shared path through the accumulator.
+//! What happens once its queue is full is governed by the
+//! `OverflowPolicy`.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::channel;
+use std::sync::mpsc::sync_channel;
+use std::sync::mpsc::Sender;
+use std::sync::mpsc::SyncSender;
+use std::sync::mpsc::TrySendError;
+use std::sync::Arc;
+use std::thread::spawn;
+use std::thread::JoinHandle;
+
+use log::error;
+use log::trace;
+use uid::Id;
+
+use differential_datalog::program::RelId;
+use differential_datalog::program::Update;
+
+use crate::Observable;
+use crate::Observer;
+use crate::ObserverBox;
+use crate::UpdatesObservable;
+
+use crate::accumulate::Accumulator;
+use crate::accumulate::DistributingAccumulator;
+
+/// The maximum number of events queued up per observer of a
+/// `BoundedDistributingAccumulator` created via `Accumulator::new`.
+const DEFAULT_CAPACITY: usize = 1024;
+
+/// The policy to apply when an observer's queue is full.
+pub enum OverflowPolicy<T, E> {
+    /// Wait for the observer to make room in its queue. The wait
+    /// happens on a staging thread of the observer's own, which buffers
+    /// the events in the meantime, so that a slow observer does not
+    /// stall delivery to the others. Note that the buffer is unbounded.
+    Block,
+    /// Drop the observer's subscription and hand the observer to the
+    /// provided callback once its delivery thread has processed all
+    /// events queued up to that point.
+    Drop(Arc<dyn Fn(ObserverBox<T, E>) + Send + Sync>),
+}
+
+impl<T, E> Clone for OverflowPolicy<T, E> {
+    fn clone(&self) -> Self {
+        match self {
+            OverflowPolicy::Block => OverflowPolicy::Block,
+            OverflowPolicy::Drop(callback) => OverflowPolicy::Drop(callback.clone()),
+        }
+    }
+}
+
+// Manual implementation of `Debug` because the callback of the `Drop`
+// variant is not debug printable.
+impl<T, E> Debug for OverflowPolicy<T, E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            OverflowPolicy::Block => f.write_str("Block"),
+            OverflowPolicy::Drop(_) => f.write_str("Drop"),
+        }
+    }
+}
+
+/// An event as sent from a `QueuedObserver` to its delivery thread.
+#[derive(Debug)]
+enum Event<T> {
+    Start,
+    StartSeq(u64),
+    Updates(Vec<T>),
+    Commit,
+    Abort,
+    Barrier(SyncSender<()>),
+    InitComplete,
+    Completed,
+}
+
+/// The sending end of the hand-off from a `QueuedObserver` to its
+/// delivery thread.
+#[derive(Debug)]
+enum Handoff<T> {
+    /// The bounded queue, into which events are put without waiting,
+    /// as per the `Drop` policy.
+    Queue(SyncSender<Event<T>>),
+    /// The unbounded channel to the staging thread waiting for room in
+    /// the bounded queue, as per the `Block` policy.
+    Staging(Sender<Event<T>>),
+}
+
+/// An observer that forwards all events through a bounded queue to a
+/// thread delivering them to the actual observer.
+#[derive(Debug)]
+struct QueuedObserver<T, E> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The sending end of the hand-off to the delivery thread, or
+    /// `None` if we no longer forward any events.
+    sender: Option<Handoff<T>>,
+    /// Whether the queue overflowed and the observer got dropped.
+    dropped: Arc<AtomicBool>,
+    _phantom: PhantomData<E>,
+}
+
+impl<T, E> QueuedObserver<T, E>
+where
+    T: Debug + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    /// Create a new `QueuedObserver` wrapping the given observer, along
+    /// with the delivery thread and, for the `Block` policy, the staging
+    /// thread. The delivery thread hands back the observer once the
+    /// `QueuedObserver` is gone and all events were delivered, unless
+    /// it was passed on to the overflow callback.
+    fn new(
+        mut observer: ObserverBox<T, E>,
+        capacity: usize,
+        policy: OverflowPolicy<T, E>,
+    ) -> (Self, JoinHandle<Option<ObserverBox<T, E>>>) {
+        let id = Id::<()>::new().get();
+        trace!("QueuedObserver({})::new", id);
+
+        let (sender, receiver) = sync_channel(capacity);
+        let dropped = Arc::new(AtomicBool::new(false));
+        let thread_dropped = dropped.clone();
+        let thread_policy = policy.clone();
+
+        let thread = spawn(move || {
+            for event in receiver {
+                let result = match event {
+                    Event::Start => observer.on_start(),
+                    Event::StartSeq(seq) => observer.on_start_seq(seq),
+                    Event::Updates(updates) => observer.on_updates(Box::new(updates.into_iter())),
+                    Event::Commit => observer.on_commit(),
+                    Event::Abort => observer.on_abort(),
+                    Event::Barrier(done) => {
+                        let result = observer.on_barrier();
+                        let _ = done.send(());
+                        result
+                    }
+                    Event::InitComplete => observer.on_init_complete(),
+                    Event::Completed => observer.on_completed(),
+                };
+                if let Err(e) = result {
+                    error!("QueuedObserver({}) failed to deliver event: {:?}", id, e);
+                }
+            }
+
+            match thread_policy {
+                OverflowPolicy::Drop(callback) if thread_dropped.load(Ordering::SeqCst) => {
+                    callback(observer);
+                    None
+                }
+                _ => Some(observer),
+            }
+        });
+
+        let sender = match policy {
+            OverflowPolicy::Block => {
+                let (staging, staged) = channel();
+                // the staging thread terminates along with the channel,
+                // closing the queue in turn
+                let _ = spawn(move || {
+                    for event in staged {
+                        if sender.send(event).is_err() {
+                            break;
+                        }
+                    }
+                });
+                Handoff::Staging(staging)
+            }
+            OverflowPolicy::Drop(_) => Handoff::Queue(sender),
+        };
+
+        let queued = Self {
+            id,
+            sender: Some(sender),
+            dropped,
+            _phantom: PhantomData,
+        };
+        (queued, thread)
+    }
+
+    /// Hand an event off to the delivery thread, applying the overflow
+    /// policy if the queue is full.
+    fn push(&mut self, event: Event<T>) {
+        let keep = match &self.sender {
+            Some(Handoff::Staging(sender)) => sender.send(event).is_ok(),
+            Some(Handoff::Queue(sender)) => match sender.try_send(event) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    trace!("QueuedObserver({}) overflowed", self.id);
+                    self.dropped.store(true, Ordering::SeqCst);
+                    false
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            },
+            None => return,
+        };
+
+        if !keep {
+            let _ = self.sender.take();
+        }
+    }
+}
+
+impl<T, E> Observer<T, E> for QueuedObserver<T, E>
+where
+    T: Debug + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("QueuedObserver({})::on_start", self.id);
+        self.push(Event::Start);
+        Ok(())
+    }
+
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        trace!("QueuedObserver({})::on_start_seq({})", self.id, seq);
+        self.push(Event::StartSeq(seq));
+        Ok(())
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("QueuedObserver({})::on_commit", self.id);
+        self.push(Event::Commit);
+        Ok(())
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        trace!("QueuedObserver({})::on_updates", self.id);
+        self.push(Event::Updates(updates.collect()));
+        Ok(())
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("QueuedObserver({})::on_abort", self.id);
+        self.push(Event::Abort);
+        Ok(())
+    }
+
+    /// Block until the delivery thread processed all events queued up
+    /// so far, or the observer got dropped.
+    fn on_barrier(&mut self) -> Result<(), E> {
+        trace!("QueuedObserver({})::on_barrier", self.id);
+        let (done, drained) = sync_channel(1);
+        self.push(Event::Barrier(done));
+        // the notification is dropped along with an event discarded
+        let _ = drained.recv();
+        Ok(())
+    }
+
+    fn on_init_complete(&mut self) -> Result<(), E> {
+        trace!("QueuedObserver({})::on_init_complete", self.id);
+        self.push(Event::InitComplete);
+        Ok(())
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("QueuedObserver({})::on_completed", self.id);
+        self.push(Event::Completed);
+        Ok(())
+    }
+}
+
+/// The bookkeeping for a single subscription to a
+/// `BoundedDistributingAccumulator`.
+#[derive(Debug)]
+struct Queue<T, E> {
+    /// Whether the queue overflowed and the observer got dropped.
+    dropped: Arc<AtomicBool>,
+    /// The thread delivering events to the observer.
+    thread: JoinHandle<Option<ObserverBox<T, E>>>,
+}
+
+/// A `DistributingAccumulator` that delivers to each of its observers
+/// through a bounded queue of configurable depth, so that a slow
+/// observer does not delay delivery to the others.
+#[derive(Debug)]
+pub struct BoundedDistributingAccumulator<V, E>
+where
+    V: Debug + Eq + Hash + Send,
+    E: Debug + Send,
+{
+    /// The accumulator's unique ID.
+    id: usize,
+    /// The accumulator doing the actual work.
+    accumulator: DistributingAccumulator<Update<V>, V, E>,
+    /// The maximum number of events queued up per observer.
+    capacity: usize,
+    /// The policy to apply when an observer's queue is full.
+    policy: OverflowPolicy<Update<V>, E>,
+    /// The queues of all subscribed observers, indexed by subscription.
+    queues: BTreeMap<usize, Queue<Update<V>, E>>,
+}
+
+impl<V, E> BoundedDistributingAccumulator<V, E>
+where
+    V: Debug + Send + Clone + Eq + Hash + 'static,
+    E: Debug + Send + 'static,
+{
+    /// Create a new `BoundedDistributingAccumulator` queueing up to
+    /// `capacity` events per observer and applying the given policy
+    /// once an observer's queue is full.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize, policy: OverflowPolicy<Update<V>, E>) -> Self {
+        assert!(capacity > 0, "queue capacity must not be zero");
+        let id = Id::<()>::new().get();
+        trace!("BoundedDistributingAccumulator({})::new", id);
+
+        Self {
+            id,
+            accumulator: DistributingAccumulator::new(),
+            capacity,
+            policy,
+            queues: BTreeMap::new(),
+        }
+    }
+
+    /// Remove the subscriptions of all observers whose queue
+    /// overflowed.
+    fn prune(&mut self) {
+        let dropped = self
+            .queues
+            .iter()
+            .filter(|(_, queue)| queue.dropped.load(Ordering::SeqCst))
+            .map(|(subscription, _)| *subscription)
+            .collect::<Vec<_>>();
+
+        for subscription in dropped {
+            trace!(
+                "BoundedDistributingAccumulator({}) dropping subscription {}",
+                self.id,
+                subscription
+            );
+            // The delivery thread hands the observer to the overflow
+            // callback on its own; we do not wait for it.
+            let _ = self.queues.remove(&subscription);
+            let _ = self.accumulator.unsubscribe(&subscription);
+        }
+    }
+}
+
+impl<V, E> Accumulator<V, E> for BoundedDistributingAccumulator<V, E>
+where
+    V: Debug + Send + Clone + Eq + Hash + 'static,
+    E: Debug + Send + 'static,
+{
+    /// Creates a new `BoundedDistributingAccumulator` with a default
+    /// queue capacity, blocking once an observer's queue is full.
+    fn new() -> Self {
+        Self::new(DEFAULT_CAPACITY, OverflowPolicy::Block)
+    }
+
+    /// Creates a new `Observable` for this accumulator without the
+    /// currently accumulated state. Its subscriber is served through a
+    /// queue of its own, just like the observers subscribed to the
+    /// accumulator directly.
+    fn create_observable(&mut self) -> UpdatesObservable<Update<V>, E> {
+        trace!(
+            "BoundedDistributingAccumulator({})::create_observable()",
+            self.id
+        );
+        let observable = UpdatesObservable::default();
+        let (queued, thread) = QueuedObserver::new(
+            Box::new(observable.observer.clone()),
+            self.capacity,
+            self.policy.clone(),
+        );
+        let dropped = queued.dropped.clone();
+        // an inactive accumulator has nothing to emit, and the delivery
+        // thread terminates along with the rejected `QueuedObserver`
+        if let Ok(subscription) = self.accumulator.subscribe_no_replay(Box::new(queued)) {
+            let _ = self.queues.insert(subscription, Queue { dropped, thread });
+        }
+        observable
+    }
+
+    fn get_current_state(&self) -> HashMap<RelId, HashSet<V>> {
+        trace!(
+            "BoundedDistributingAccumulator({})::get_current_state()",
+            self.id
+        );
+        self.accumulator.get_current_state()
+    }
+
+    fn get_current_state_weighted(&self) -> HashMap<RelId, HashMap<V, isize>> {
+        trace!(
+            "BoundedDistributingAccumulator({})::get_current_state_weighted()",
+            self.id
+        );
+        self.accumulator.get_current_state_weighted()
+    }
+
+    fn contains(&self, relid: RelId, value: &V) -> bool {
+        trace!(
+            "BoundedDistributingAccumulator({})::contains({})",
+            self.id,
+            relid
+        );
+        self.accumulator.contains(relid, value)
+    }
+
+    fn relation_size(&self, relid: RelId) -> usize {
+        trace!(
+            "BoundedDistributingAccumulator({})::relation_size({})",
+            self.id,
+            relid
+        );
+        self.accumulator.relation_size(relid)
+    }
+
+    fn for_each_value<F>(&self, f: F)
+    where
+        F: FnMut(RelId, &V),
+    {
+        trace!(
+            "BoundedDistributingAccumulator({})::for_each_value()",
+            self.id
+        );
+        self.accumulator.for_each_value(f)
+    }
+
+    fn snapshot_once(&self) -> Vec<Update<V>> {
+        trace!(
+            "BoundedDistributingAccumulator({})::snapshot_once()",
+            self.id
+        );
+        self.accumulator.snapshot_once()
+    }
+}
+
+impl<V, E> Observable<Update<V>, E> for BoundedDistributingAccumulator<V, E>
+where
+    V: Debug + Send + Clone + Eq + Hash + 'static,
+    E: Debug + Send + 'static,
+{
+    type Subscription = usize;
+
+    fn subscribe(
+        &mut self,
+        observer: ObserverBox<Update<V>, E>,
+    ) -> Result<Self::Subscription, ObserverBox<Update<V>, E>> {
+        trace!("BoundedDistributingAccumulator({})::subscribe()", self.id);
+
+        let (queued, thread) = QueuedObserver::new(observer, self.capacity, self.policy.clone());
+        let dropped = queued.dropped.clone();
+        let subscription = self.accumulator.subscribe(Box::new(queued))?;
+        let _ = self.queues.insert(subscription, Queue { dropped, thread });
+
+        self.prune();
+        Ok(subscription)
+    }
+
+    /// Unsubscribe an observer, waiting for its delivery thread to
+    /// process all events queued up so far. A return value of `None`
+    /// indicates that the observer got dropped because its queue
+    /// overflowed.
+    fn unsubscribe(
+        &mut self,
+        subscription: &Self::Subscription,
+    ) -> Option<ObserverBox<Update<V>, E>> {
+        trace!(
+            "BoundedDistributingAccumulator({})::unsubscribe({})",
+            self.id,
+            subscription
+        );
+
+        let queue = self.queues.remove(subscription)?;
+        // Dropping the `QueuedObserver` closes the queue, which in turn
+        // causes the delivery thread to terminate.
+        drop(self.accumulator.unsubscribe(subscription));
+        queue.thread.join().ok().and_then(|observer| observer)
+    }
+}
+
+/// All calls are delegated to the wrapped `DistributingAccumulator`.
+impl<V, E> Observer<Update<V>, E> for BoundedDistributingAccumulator<V, E>
+where
+    V: Debug + Send + Clone + Eq + Hash + 'static,
+    E: Debug + Send + 'static,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("BoundedDistributingAccumulator({})::on_start", self.id);
+        let result = self.accumulator.on_start();
+        self.prune();
+        result
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("BoundedDistributingAccumulator({})::on_commit", self.id);
+        let result = self.accumulator.on_commit();
+        self.prune();
+        result
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("BoundedDistributingAccumulator({})::on_updates", self.id);
+        let result = self.accumulator.on_updates(updates);
+        self.prune();
+        result
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("BoundedDistributingAccumulator({})::on_abort", self.id);
+        let result = self.accumulator.on_abort();
+        self.prune();
+        result
+    }
+
+    fn on_barrier(&mut self) -> Result<(), E> {
+        trace!("BoundedDistributingAccumulator({})::on_barrier", self.id);
+        let result = self.accumulator.on_barrier();
+        self.prune();
+        result
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("BoundedDistributingAccumulator({})::on_completed", self.id);
+        let result = self.accumulator.on_completed();
+        self.prune();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    use crate::accumulate::accumulator::tests::get_usize_updates_1;
+    use crate::await_expected;
+    use crate::MockObserver;
+    use crate::SharedObserver;
+
+    /// An observer that cannot make progress on `on_start` or
+    /// `on_updates` while its gate is locked.
+    #[derive(Debug)]
+    struct GatedObserver {
+        gate: Arc<Mutex<()>>,
+        observer: SharedObserver<MockObserver>,
+    }
+
+    impl Observer<Update<usize>, ()> for GatedObserver {
+        fn on_start(&mut self) -> Result<(), ()> {
+            let _guard = self.gate.lock().unwrap();
+            Observer::<Update<usize>, ()>::on_start(&mut self.observer)
+        }
+
+        fn on_commit(&mut self) -> Result<(), ()> {
+            Observer::<Update<usize>, ()>::on_commit(&mut self.observer)
+        }
+
+        fn on_updates<'a>(
+            &mut self,
+            updates: Box<dyn Iterator<Item = Update<usize>> + 'a>,
+        ) -> Result<(), ()> {
+            let _guard = self.gate.lock().unwrap();
+            self.observer.on_updates(updates)
+        }
+
+        fn on_completed(&mut self) -> Result<(), ()> {
+            Observer::<Update<usize>, ()>::on_completed(&mut self.observer)
+        }
+    }
+
+    /// Test that a blocked observer does not delay delivery to other
+    /// observers when using the `Block` policy.
+    #[test]
+    fn slow_observer_block() {
+        let mut accumulator =
+            BoundedDistributingAccumulator::<usize, ()>::new(8, OverflowPolicy::Block);
+        let gate = Arc::new(Mutex::new(()));
+        let mock1 = Arc::new(Mutex::new(MockObserver::new()));
+        let mock2 = Arc::new(Mutex::new(MockObserver::new()));
+        let slow = GatedObserver {
+            gate: gate.clone(),
+            observer: mock2.clone(),
+        };
+
+        assert!(accumulator.subscribe(Box::new(mock1.clone())).is_ok());
+        let subscription = accumulator.subscribe(Box::new(slow)).unwrap();
+
+        let guard = gate.lock().unwrap();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        await_expected(|| {
+            let mock = *mock1.lock().unwrap();
+            assert_eq!(mock.called_on_start, 1);
+            assert_eq!(mock.called_on_updates, 3);
+            assert_eq!(mock.called_on_commit, 1);
+        });
+        assert_eq!(mock2.lock().unwrap().called_on_updates, 0);
+
+        drop(guard);
+        assert!(accumulator.unsubscribe(&subscription).is_some());
+        assert_eq!(mock2.lock().unwrap().called_on_start, 1);
+        assert_eq!(mock2.lock().unwrap().called_on_updates, 3);
+        assert_eq!(mock2.lock().unwrap().called_on_commit, 1);
+    }
+
+    /// Test that a blocked observer whose queue is full does not delay
+    /// delivery to other observers when using the `Block` policy, and
+    /// receives all events once it makes room again.
+    #[test]
+    fn full_queue_block() {
+        let mut accumulator =
+            BoundedDistributingAccumulator::<usize, ()>::new(1, OverflowPolicy::Block);
+        let gate = Arc::new(Mutex::new(()));
+        let mock1 = Arc::new(Mutex::new(MockObserver::new()));
+        let mock2 = Arc::new(Mutex::new(MockObserver::new()));
+        let slow = GatedObserver {
+            gate: gate.clone(),
+            observer: mock2.clone(),
+        };
+
+        let subscription1 = accumulator.subscribe(Box::new(mock1.clone())).unwrap();
+        let subscription2 = accumulator.subscribe(Box::new(slow)).unwrap();
+
+        // The delivery thread of the slow observer gets stuck in
+        // `on_start` and its queue fills up with the `on_updates`
+        // event, while the remaining events pile up in staging.
+        let guard = gate.lock().unwrap();
+        for _ in 0..2 {
+            assert_eq!(accumulator.on_start(), Ok(()));
+            assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+            assert_eq!(accumulator.on_commit(), Ok(()));
+        }
+
+        await_expected(|| {
+            let mock = *mock1.lock().unwrap();
+            assert_eq!(mock.called_on_start, 2);
+            assert_eq!(mock.called_on_updates, 6);
+            assert_eq!(mock.called_on_commit, 2);
+        });
+        assert_eq!(mock2.lock().unwrap().called_on_updates, 0);
+
+        drop(guard);
+        assert!(accumulator.unsubscribe(&subscription1).is_some());
+        assert!(accumulator.unsubscribe(&subscription2).is_some());
+        let mock = *mock2.lock().unwrap();
+        assert_eq!(mock.called_on_start, 2);
+        assert_eq!(mock.called_on_updates, 6);
+        assert_eq!(mock.called_on_commit, 2);
+    }
+
+    /// Test that the subscriber of an observable created via
+    /// `Accumulator::create_observable` is served through a queue of
+    /// its own and does not receive the accumulated state.
+    #[test]
+    fn create_observable() {
+        let mut accumulator =
+            <BoundedDistributingAccumulator<usize, ()> as Accumulator<_, _>>::new();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mut observable = accumulator.create_observable();
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        assert!(observable.subscribe(Box::new(mock.clone())).is_ok());
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(accumulator.on_barrier(), Ok(()));
+
+        let mock = *mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, 1);
+        assert_eq!(mock.called_on_updates, 3);
+        assert_eq!(mock.called_on_commit, 1);
+        assert_eq!(accumulator.get_current_state().len(), 3);
+    }
+
+    /// Test that a barrier waits for the delivery threads to process
+    /// all events queued up before it.
+    #[test]
+    fn barrier_waits_for_delivery() {
+        let mut accumulator =
+            BoundedDistributingAccumulator::<usize, ()>::new(8, OverflowPolicy::Block);
+        let gate = Arc::new(Mutex::new(()));
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let slow = GatedObserver {
+            gate: gate.clone(),
+            observer: mock.clone(),
+        };
+        assert!(accumulator.subscribe(Box::new(slow)).is_ok());
+
+        let guard = gate.lock().unwrap();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(mock.lock().unwrap().called_on_updates, 0);
+
+        let done = Arc::new(AtomicBool::new(false));
+        let thread_done = done.clone();
+        let thread = spawn(move || {
+            assert_eq!(accumulator.on_barrier(), Ok(()));
+            thread_done.store(true, Ordering::SeqCst);
+        });
+
+        sleep(Duration::from_millis(100));
+        assert!(!done.load(Ordering::SeqCst));
+
+        drop(guard);
+        thread.join().unwrap();
+        assert_eq!(mock.lock().unwrap().called_on_updates, 3);
+        assert_eq!(mock.lock().unwrap().called_on_commit, 1);
+    }
+
+    /// Test that an observer whose queue overflows gets dropped and
+    /// handed to the callback when using the `Drop` policy.
+    #[test]
+    fn slow_observer_drop() {
+        let dropped = Arc::new(Mutex::new(Vec::new()));
+        let callback_dropped = dropped.clone();
+        let policy = OverflowPolicy::Drop(Arc::new(move |observer| {
+            callback_dropped.lock().unwrap().push(observer)
+        }));
+        let mut accumulator = BoundedDistributingAccumulator::<usize, ()>::new(3, policy);
+        let gate = Arc::new(Mutex::new(()));
+        let mock1 = Arc::new(Mutex::new(MockObserver::new()));
+        let mock2 = Arc::new(Mutex::new(MockObserver::new()));
+        let slow = GatedObserver {
+            gate: gate.clone(),
+            observer: mock2.clone(),
+        };
+
+        assert!(accumulator.subscribe(Box::new(mock1.clone())).is_ok());
+        let subscription = accumulator.subscribe(Box::new(slow)).unwrap();
+
+        // The first transaction fits into both queues.
+        let guard = gate.lock().unwrap();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(accumulator.queues.len(), 2);
+
+        await_expected(|| {
+            let mock = *mock1.lock().unwrap();
+            assert_eq!(mock.called_on_start, 1);
+            assert_eq!(mock.called_on_updates, 3);
+            assert_eq!(mock.called_on_commit, 1);
+        });
+
+        // The second one overflows the blocked observer's queue.
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(accumulator.queues.len(), 1);
+
+        await_expected(|| {
+            let mock = *mock1.lock().unwrap();
+            assert_eq!(mock.called_on_start, 2);
+            assert_eq!(mock.called_on_updates, 6);
+            assert_eq!(mock.called_on_commit, 2);
+        });
+
+        drop(guard);
+        await_expected(|| {
+            let len = dropped.lock().unwrap().len();
+            assert_eq!(len, 1);
+        });
+        assert!(accumulator.unsubscribe(&subscription).is_none());
+    }
+}
@@ -0,0 +1,487 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::hash::Hash;
+use std::time::Duration;
+
+use differential_datalog::program::RelId;
+use differential_datalog::program::Update;
+
+use crate::accumulate::accumulator::sort_insertions;
+use crate::accumulate::AbsentValueError;
+use crate::accumulate::AccumulatingObserver;
+use crate::accumulate::DistributingAccumulator;
+use crate::accumulate::DuplicateError;
+use crate::accumulate::DuplicatePolicy;
+use crate::accumulate::EvictionPolicy;
+use crate::accumulate::RelationRegistry;
+use crate::accumulate::RetryPolicy;
+use crate::accumulate::WouldBlock;
+
+/// The function installing the relations whose changes of values are
+/// forwarded as `Modify` updates into an accumulator, along with the
+/// functions extracting the keys of their values.
+pub(crate) type ModifyEvents<V, E> =
+    fn(&mut DistributingAccumulator<Update<V>, V, E>, HashMap<RelId, fn(&V) -> V>);
+
+/// A builder for a `DistributingAccumulator`, collecting its options
+/// in one place. `AccumulatorBuilder::default().build()` is equivalent
+/// to `DistributingAccumulator::new()`.
+pub struct AccumulatorBuilder<V, E> {
+    /// Whether to forward only the net effect of each transaction.
+    coalescing: bool,
+    /// The number of transactions to merge into a single one.
+    batch_every: usize,
+    /// The maximum number of distinct values per relation, if any.
+    max_values_per_relation: Option<usize>,
+    /// The way insertions of values that are already present are
+    /// treated, along with the conversion of rejected insertions into
+    /// the error type, if configured.
+    duplicate_policy: Option<(DuplicatePolicy, fn(DuplicateError) -> E)>,
+    /// Whether to forward the updates of each transaction in a single
+    /// batch.
+    atomic_transactions: bool,
+    /// The way room is made for insertions into full relations, if
+    /// configured.
+    eviction_policy: Option<EvictionPolicy>,
+    /// Whether to suppress updates not changing the state.
+    suppress_redundant: bool,
+    /// The conversion of deletions of absent values into the error
+    /// type, if they are rejected.
+    strict_deletes: Option<fn(AbsentValueError) -> E>,
+    /// Whether to record updates instead of forwarding them.
+    dry_run: bool,
+    /// The time after which values expire, if any.
+    ttl: Option<Duration>,
+    /// The functions extracting the key of a value, by relation.
+    key_funcs: HashMap<RelId, fn(&V) -> V>,
+    /// The function sorting the updates sent to initialize observers,
+    /// if they are to be sorted.
+    pub(crate) sort_init_updates: Option<fn(&mut [Update<V>])>,
+    /// The maximum number of updates per `on_updates` call when sending
+    /// the accumulated state to new observers, if limited.
+    pub(crate) chunk_size: Option<usize>,
+    /// The number of recent transactions to record, if any.
+    pub(crate) history: Option<usize>,
+    /// The policy for retrying deliveries to observers, if any.
+    pub(crate) retry: Option<RetryPolicy>,
+    /// The functions extracting the key of a value, for the relations
+    /// whose changes of values are forwarded as `Modify` updates, along
+    /// with the function installing them, if any.
+    pub(crate) modify_events: Option<(HashMap<RelId, fn(&V) -> V>, ModifyEvents<V, E>)>,
+    /// The names of relations, if registered.
+    pub(crate) registry: Option<RelationRegistry>,
+    /// The maximum number of transactions in flight, if limited, along
+    /// with the conversion of exceeding it into the error type, if
+    /// starting a transaction fails rather than blocks then.
+    pub(crate) max_in_flight: Option<(u64, Option<fn(WouldBlock) -> E>)>,
+    /// The ID of the accumulator, if not a unique one.
+    pub(crate) id: Option<usize>,
+}
+
+impl<V, E> AccumulatorBuilder<V, E>
+where
+    V: Debug + Send + Clone + Eq + Hash + 'static,
+    E: Debug + Send + 'static,
+{
+    /// Forward only the net effect of each transaction, as an
+    /// accumulator created via `DistributingAccumulator::new_coalescing`
+    /// does.
+    pub fn coalescing(mut self, coalescing: bool) -> Self {
+        self.coalescing = coalescing;
+        self
+    }
+
+    /// Merge every `transactions` transactions received into a single
+    /// transaction before forwarding it to observers, e.g., to reduce
+    /// the overhead of an upstream emitting many small transactions.
+    /// The accumulated state is updated as each of the merged
+    /// transactions is committed. A partially filled batch is forwarded
+    /// on `flush` and when the upstream completes.
+    pub fn batch_every(mut self, transactions: usize) -> Self {
+        self.batch_every = transactions;
+        self
+    }
+
+    /// Limit the number of distinct values any single relation may
+    /// hold, to protect against an upstream flooding a relation.
+    /// Insertions exceeding the limit are neither accumulated nor
+    /// forwarded to observers, but reported to the handler registered
+    /// via `DistributingAccumulator::on_overflow`. Deletions free up
+    /// space for further insertions.
+    pub fn max_values_per_relation(mut self, max: usize) -> Self {
+        self.max_values_per_relation = Some(max);
+        self
+    }
+
+    /// Treat insertions of values that are already present according to
+    /// the given policy. Rejected insertions are reported as errors to
+    /// the upstream.
+    pub fn duplicate_policy(mut self, policy: DuplicatePolicy) -> Self
+    where
+        E: From<DuplicateError>,
+    {
+        self.duplicate_policy = Some((policy, E::from));
+        self
+    }
+
+    /// Hold back the updates of each transaction until it is committed
+    /// and then forward them to observers in a single batch, so that
+    /// observers receive exactly one `on_updates` per transaction
+    /// containing any updates, e.g., for consumers relying on seeing
+    /// all updates of a transaction at once.
+    pub fn atomic_transactions(mut self) -> Self {
+        self.atomic_transactions = true;
+        self
+    }
+
+    /// Make room for insertions into relations holding the number of
+    /// values the given policy allows for by evicting other values,
+    /// which observers receive as their deletion.
+    pub fn eviction_policy(mut self, policy: EvictionPolicy) -> Self {
+        self.eviction_policy = Some(policy);
+        self
+    }
+
+    /// Suppress forwarding updates to observers that do not change the
+    /// accumulated state, i.e., insertions of values that are already
+    /// present and deletions of values that are absent, to reduce the
+    /// traffic caused by an idempotent upstream.
+    pub fn suppress_redundant(mut self) -> Self {
+        self.suppress_redundant = true;
+        self
+    }
+
+    /// Reject deletions of values that are not part of the accumulated
+    /// state, reporting them as errors to the upstream instead of
+    /// ignoring them.
+    pub fn strict_deletes(mut self) -> Self
+    where
+        E: From<AbsentValueError>,
+    {
+        self.strict_deletes = Some(E::from);
+        self
+    }
+
+    /// Accumulate updates without forwarding them to observers, but
+    /// record them for retrieval via
+    /// `DistributingAccumulator::dry_run_updates` instead, e.g., to
+    /// estimate the traffic observers would receive with the options in
+    /// effect by feeding a representative stream of updates.
+    pub fn dry_run(mut self) -> Self {
+        self.dry_run = true;
+        self
+    }
+
+    /// Let values expire once they were not inserted again for the given
+    /// time, e.g., for a cache whose entries need to be refreshed
+    /// periodically. Expired values are deleted and the deletions
+    /// forwarded to observers when invoking
+    /// `DistributingAccumulator::expire_now`.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Sort the updates sent to initialize observers upon subscription,
+    /// as well as those returned by `snapshot_once`, by relation and then
+    /// by value, instead of emitting them in an arbitrary order, e.g.,
+    /// for reproducible output.
+    pub fn sorted_init(mut self) -> Self
+    where
+        V: Ord,
+    {
+        self.sort_init_updates = Some(sort_insertions::<V>);
+        self
+    }
+
+    /// Send the accumulated state to new observers in `on_updates` calls
+    /// of at most `chunk_size` updates each, still within a single
+    /// transaction, instead of in a single call, e.g., to spare an
+    /// observer from receiving a large state at once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must not be zero");
+        self.chunk_size = Some(chunk_size);
+        self
+    }
+
+    /// Record the last `transactions` transactions received, along with
+    /// their sequence numbers, for inspection via
+    /// `DistributingAccumulator::recent_transactions`, e.g., when
+    /// debugging. Recording does not affect the forwarding of
+    /// transactions.
+    pub fn history(mut self, transactions: usize) -> Self {
+        self.history = Some(transactions);
+        self
+    }
+
+    /// Retry failed deliveries to observers subscribed directly
+    /// according to the given policy, e.g., to ride out transient
+    /// failures of networked observers. Each such observer is served by
+    /// a dedicated thread, so that a retrying observer does not hold up
+    /// the others, and its errors are not propagated to the upstream.
+    /// An observer that fails to process an event within the maximum
+    /// number of attempts is unsubscribed and dropped.
+    ///
+    /// The accumulated state sent to an observer upon subscription is
+    /// delivered without retries.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Register the function extracting the key of a value of the given
+    /// relation. Updates by key to the relation, i.e., `DeleteKey`,
+    /// `Modify`, and `InsertOrUpdate`, are resolved against the
+    /// accumulated state and forwarded to observers as insertions and
+    /// deletions of values. Without a key function, `InsertOrUpdate` is
+    /// treated as an insertion and the other updates by key treat every
+    /// value as its own key.
+    pub fn key_func(mut self, relid: RelId, key_func: fn(&V) -> V) -> Self {
+        let _ = self.key_funcs.insert(relid, key_func);
+        self
+    }
+
+    /// Forward the deletion of a value of the given relation followed
+    /// by the insertion of a value with the same key, as determined by
+    /// `key_func`, within a transaction to observers as a single
+    /// `Update::Modify`, e.g., for consumers presenting changes of
+    /// values. The `Modify` update carries the key of the values and a
+    /// mutator replacing the old value with the new one. To pair them
+    /// up, updates are held back until their transaction is committed.
+    /// Observers are still sent plain insertions upon subscription.
+    ///
+    /// # Compatibility
+    ///
+    /// `Modify` updates are delivered to all observers, also those of
+    /// the observables created via `create_observable`, and not every
+    /// observer can process them:
+    /// - an `AccumulatingObserver`, e.g., of a chained accumulator, can
+    ///   only resolve them for relations with a key function, see
+    ///   `AccumulatingObserver::key_func`, and treats them as a
+    ///   violation of the protocol otherwise, i.e., panics by default
+    /// - a `MapObservable` panics on them, as their mutator cannot be
+    ///   transformed
+    /// - sinks such as the `JsonObserver` reject them as unsupported
+    ///
+    /// Only enable this mode if all observers handle `Modify` updates
+    /// for the given relation.
+    pub fn modify_events(mut self, relid: RelId, key_func: fn(&V) -> V) -> Self
+    where
+        V: Sync,
+    {
+        let install: ModifyEvents<V, E> = DistributingAccumulator::set_modify_keys;
+        let (keys, _) = self
+            .modify_events
+            .get_or_insert_with(|| (HashMap::new(), install));
+        let _ = keys.insert(relid, key_func);
+        self
+    }
+
+    /// Attach a registry of relation names, which are then used instead
+    /// of the IDs of relations when presenting them, i.e., by
+    /// `DistributingAccumulator::dump_state`, in traces, and in error
+    /// messages. Relations missing from the registry are still
+    /// presented by their IDs.
+    pub fn with_registry(mut self, registry: RelationRegistry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Limit the number of transactions in flight, i.e., forwarded to
+    /// observers but not yet acknowledged by all observers subscribed via
+    /// `DistributingAccumulator::subscribe_acked`, to provide
+    /// back-pressure to the upstream: `on_start` blocks until a
+    /// transaction is acknowledged while the maximum number of
+    /// transactions are in flight. Note that it blocks forever unless
+    /// observers acknowledge transactions from another thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn max_in_flight_transactions(mut self, max: u64) -> Self {
+        assert!(max > 0, "at least one transaction must be in flight");
+        self.max_in_flight = Some((max, None));
+        self
+    }
+
+    /// Limit the number of transactions in flight, as
+    /// `max_in_flight_transactions` does, but let `on_start` fail with
+    /// a `WouldBlock` error instead of blocking.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn max_in_flight_transactions_nonblocking(mut self, max: u64) -> Self
+    where
+        E: From<WouldBlock>,
+    {
+        assert!(max > 0, "at least one transaction must be in flight");
+        self.max_in_flight = Some((max, Some(E::from)));
+        self
+    }
+
+    /// Give the accumulator the given ID rather than a unique one, so
+    /// that tests can rely on the ID, e.g., in log output.
+    #[cfg(any(test, feature = "test"))]
+    pub fn id(mut self, id: usize) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// Create the `DistributingAccumulator` configured.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the batch size is zero.
+    pub fn build(self) -> DistributingAccumulator<Update<V>, V, E> {
+        let mut observer = if self.coalescing {
+            AccumulatingObserver::new_coalescing()
+        } else {
+            AccumulatingObserver::new()
+        };
+        observer.batch_every(self.batch_every);
+        observer.max_values_per_relation(self.max_values_per_relation);
+        if let Some((policy, error)) = self.duplicate_policy {
+            observer.set_duplicate_policy(policy, error);
+        }
+        observer.atomic_transactions(self.atomic_transactions);
+        if let Some(policy) = self.eviction_policy {
+            observer.eviction_policy(policy);
+        }
+        observer.suppress_redundant(self.suppress_redundant);
+        if let Some(error) = self.strict_deletes {
+            observer.set_strict_deletes(error);
+        }
+        observer.dry_run(self.dry_run);
+        observer.ttl(self.ttl);
+        for (relid, key_func) in &self.key_funcs {
+            observer.key_func(*relid, *key_func);
+        }
+        DistributingAccumulator::from_builder(observer, self)
+    }
+}
+
+impl<V, E> Default for AccumulatorBuilder<V, E> {
+    fn default() -> Self {
+        Self {
+            coalescing: false,
+            batch_every: 1,
+            max_values_per_relation: None,
+            duplicate_policy: None,
+            atomic_transactions: false,
+            eviction_policy: None,
+            suppress_redundant: false,
+            strict_deletes: None,
+            dry_run: false,
+            ttl: None,
+            key_funcs: HashMap::new(),
+            sort_init_updates: None,
+            chunk_size: None,
+            history: None,
+            retry: None,
+            modify_events: None,
+            registry: None,
+            max_in_flight: None,
+            id: None,
+        }
+    }
+}
+
+// Manual implementation of `Debug` to not require `V: Debug` and
+// `E: Debug`.
+impl<V, E> Debug for AccumulatorBuilder<V, E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("AccumulatorBuilder")
+            .field("coalescing", &self.coalescing)
+            .field("batch_every", &self.batch_every)
+            .field("max_values_per_relation", &self.max_values_per_relation)
+            .field(
+                "duplicate_policy",
+                &self.duplicate_policy.map(|(policy, _)| policy),
+            )
+            .field("atomic_transactions", &self.atomic_transactions)
+            .field("eviction_policy", &self.eviction_policy)
+            .field("suppress_redundant", &self.suppress_redundant)
+            .field("strict_deletes", &self.strict_deletes.is_some())
+            .field("dry_run", &self.dry_run)
+            .field("ttl", &self.ttl)
+            .field("key_funcs", &self.key_funcs.keys().collect::<Vec<_>>())
+            .field("sorted_init", &self.sort_init_updates.is_some())
+            .field("chunk_size", &self.chunk_size)
+            .field("history", &self.history)
+            .field("retry", &self.retry)
+            .field(
+                "modify_events",
+                &self
+                    .modify_events
+                    .as_ref()
+                    .map(|(keys, _)| keys.keys().collect::<Vec<_>>()),
+            )
+            .field("registry", &self.registry)
+            .field("max_in_flight", &self.max_in_flight.map(|(max, _)| max))
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::Accumulator;
+    use crate::Observable;
+    use crate::Observer;
+
+    /// Test that an accumulator built with coalescing and a limit on the
+    /// number of values per relation exhibits both behaviors.
+    #[test]
+    fn build_coalescing_limited() {
+        let mut accumulator = AccumulatorBuilder::<usize, ()>::default()
+            .coalescing(true)
+            .max_values_per_relation(2)
+            .build();
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(accumulator.subscribe(Box::new(mock.clone())).is_ok());
+
+        let updates = vec![
+            Update::Insert { relid: 1, v: 1 },
+            Update::DeleteValue { relid: 1, v: 1 },
+            Update::Insert { relid: 1, v: 2 },
+            Update::Insert { relid: 1, v: 3 },
+            Update::Insert { relid: 1, v: 4 },
+        ];
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(updates.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        // the insertion and deletion of 1 cancel each other out, 4
+        // exceeds the limit
+        let received = mock.lock().unwrap().received_updates.clone();
+        let mut values = received
+            .iter()
+            .map(|u| match u {
+                Update::Insert { relid: 1, v } => *v,
+                u => panic!("unexpected update {:?}", u),
+            })
+            .collect::<Vec<_>>();
+        values.sort_unstable();
+        assert_eq!(values, vec![2, 3]);
+        assert_eq!(
+            accumulator.get_current_state()[&1],
+            vec![2, 3].into_iter().collect()
+        );
+    }
+}
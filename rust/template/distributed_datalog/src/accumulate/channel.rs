@@ -0,0 +1,368 @@
+//! Adapters for consuming the output of an observable from asynchronous
+//! code. A `ChannelObserver` forwards all events it receives over a
+//! tokio channel, from which a `ChannelObservable` picks them up on a
+//! worker task and emits them to the observer subscribed to it.
+
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::sync::mpsc::sync_channel;
+use std::sync::mpsc::SyncSender;
+
+use log::trace;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::Receiver;
+use tokio::sync::mpsc::Sender;
+use tokio::task::JoinHandle;
+use uid::Id;
+
+use differential_datalog::program::Update;
+
+use crate::Observable;
+use crate::Observer;
+use crate::ObserverBox;
+use crate::OptionalObserver;
+use crate::SharedObserver;
+use crate::UpdatesObservable;
+
+/// An event sent over the channel connecting a `ChannelObserver` with
+/// a `ChannelObservable`.
+#[derive(Debug)]
+pub enum ChannelEvent<V> {
+    /// A transaction was started.
+    Start,
+    /// A batch of updates was received.
+    Updates(Vec<Update<V>>),
+    /// A transaction was committed.
+    Commit,
+    /// The observable completed.
+    Completed,
+    /// A barrier was set up. The sender is notified once all events
+    /// preceding it were emitted.
+    Barrier(SyncSender<()>),
+    /// A transaction was started with the given sequence number.
+    StartSeq(u64),
+    /// The transaction in progress was aborted.
+    Abort,
+    /// The observer was sent the accumulated state upon subscription.
+    InitComplete,
+}
+
+/// An observer that sends all events it receives over a tokio channel.
+///
+/// The observer blocks while the channel is full and so it must not be
+/// invoked from within an asynchronous context. Events sent after the
+/// receiving end of the channel was dropped are discarded.
+#[derive(Debug)]
+pub struct ChannelObserver<V, E> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The sending end of the channel.
+    sender: Sender<ChannelEvent<V>>,
+    _phantom: PhantomData<E>,
+}
+
+impl<V, E> ChannelObserver<V, E>
+where
+    V: Debug,
+{
+    /// Create a new `ChannelObserver` sending events to the given
+    /// channel.
+    pub fn new(sender: Sender<ChannelEvent<V>>) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("ChannelObserver({})::new", id);
+
+        Self {
+            id,
+            sender,
+            _phantom: PhantomData,
+        }
+    }
+
+    fn send(&self, event: ChannelEvent<V>) {
+        if let Err(e) = self.sender.blocking_send(event) {
+            trace!("ChannelObserver({}) discarding {:?}", self.id, e.0);
+        }
+    }
+}
+
+impl<V, E> Observer<Update<V>, E> for ChannelObserver<V, E>
+where
+    V: Debug + Send,
+    E: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("ChannelObserver({})::on_start", self.id);
+        self.send(ChannelEvent::Start);
+        Ok(())
+    }
+
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        trace!("ChannelObserver({})::on_start_seq({})", self.id, seq);
+        self.send(ChannelEvent::StartSeq(seq));
+        Ok(())
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("ChannelObserver({})::on_commit", self.id);
+        self.send(ChannelEvent::Commit);
+        Ok(())
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("ChannelObserver({})::on_updates", self.id);
+        self.send(ChannelEvent::Updates(updates.collect()));
+        Ok(())
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("ChannelObserver({})::on_abort", self.id);
+        self.send(ChannelEvent::Abort);
+        Ok(())
+    }
+
+    /// Block until all events sent so far were emitted by the receiving
+    /// `ChannelObservable`, or it was dropped.
+    fn on_barrier(&mut self) -> Result<(), E> {
+        trace!("ChannelObserver({})::on_barrier", self.id);
+        let (done, drained) = sync_channel(1);
+        self.send(ChannelEvent::Barrier(done));
+        // the notification is dropped along with an event discarded
+        let _ = drained.recv();
+        Ok(())
+    }
+
+    fn on_init_complete(&mut self) -> Result<(), E> {
+        trace!("ChannelObserver({})::on_init_complete", self.id);
+        self.send(ChannelEvent::InitComplete);
+        Ok(())
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("ChannelObserver({})::on_completed", self.id);
+        self.send(ChannelEvent::Completed);
+        Ok(())
+    }
+}
+
+/// An observable emitting the events received over a tokio channel,
+/// typically from a `ChannelObserver`, to the observer subscribed to
+/// it. Events received while no observer is subscribed are discarded.
+#[derive(Debug)]
+pub struct ChannelObservable<V, E> {
+    /// The observable's unique ID.
+    id: usize,
+    /// The observable the worker task emits events to.
+    observable: UpdatesObservable<Update<V>, E>,
+    /// The worker task draining the channel.
+    task: JoinHandle<()>,
+}
+
+impl<V, E> ChannelObservable<V, E>
+where
+    V: Debug + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    /// Create a new `ChannelObservable` draining the given channel on a
+    /// task spawned on the provided runtime.
+    pub fn new(mut receiver: Receiver<ChannelEvent<V>>, handle: &Handle) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("ChannelObservable({})::new", id);
+
+        let observer = SharedObserver::default();
+        let observable = UpdatesObservable {
+            observer: observer.clone(),
+        };
+        let task = handle.spawn(async move {
+            while let Some(event) = receiver.recv().await {
+                Self::emit(id, &observer, event);
+            }
+            trace!("ChannelObservable({}) channel closed", id);
+        });
+
+        Self {
+            id,
+            observable,
+            task,
+        }
+    }
+
+    fn emit(
+        id: usize,
+        observer: &SharedObserver<OptionalObserver<ObserverBox<Update<V>, E>>>,
+        event: ChannelEvent<V>,
+    ) {
+        let mut observer = observer.lock().unwrap();
+        let result = match event {
+            ChannelEvent::Start => observer.on_start(),
+            ChannelEvent::StartSeq(seq) => observer.on_start_seq(seq),
+            ChannelEvent::Updates(updates) => observer.on_updates(Box::new(updates.into_iter())),
+            ChannelEvent::Commit => observer.on_commit(),
+            ChannelEvent::Abort => observer.on_abort(),
+            ChannelEvent::InitComplete => observer.on_init_complete(),
+            ChannelEvent::Completed => observer.on_completed(),
+            ChannelEvent::Barrier(done) => {
+                let result = observer.on_barrier();
+                let _ = done.send(());
+                result
+            }
+        };
+        if let Err(e) = result {
+            trace!("ChannelObservable({}) observer failed: {:?}", id, e);
+        }
+    }
+}
+
+impl<V, E> Drop for ChannelObservable<V, E> {
+    fn drop(&mut self) {
+        trace!("ChannelObservable({})::drop", self.id);
+        self.task.abort();
+    }
+}
+
+impl<V, E> Observable<Update<V>, E> for ChannelObservable<V, E>
+where
+    V: Debug + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    type Subscription = ();
+
+    fn subscribe(
+        &mut self,
+        observer: ObserverBox<Update<V>, E>,
+    ) -> Result<Self::Subscription, ObserverBox<Update<V>, E>> {
+        trace!("ChannelObservable({})::subscribe", self.id);
+        self.observable.subscribe(observer)
+    }
+
+    fn unsubscribe(
+        &mut self,
+        subscription: &Self::Subscription,
+    ) -> Option<ObserverBox<Update<V>, E>> {
+        trace!("ChannelObservable({})::unsubscribe", self.id);
+        self.observable.unsubscribe(subscription)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use std::time::Duration;
+
+    use tokio::runtime::Runtime;
+    use tokio::sync::mpsc::channel;
+
+    use crate::accumulate::accumulator::tests::get_usize_updates_1;
+    use crate::accumulate::accumulator::tests::get_usize_updates_3;
+    use crate::accumulate::eq_updates;
+    use crate::accumulate::FaultyObserver;
+    use crate::accumulate::ObserverCall;
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::await_expected;
+    use crate::Accumulator;
+    use crate::DistributingAccumulator;
+
+    /// Test that updates fed into an accumulator arrive at an observer
+    /// subscribed to a `ChannelObservable`.
+    #[test]
+    fn channel_delivery() {
+        let runtime = Runtime::new().unwrap();
+        let (sender, receiver) = channel(2);
+        let mut observable = ChannelObservable::<_, ()>::new(receiver, runtime.handle());
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(observable.subscribe(Box::new(mock.clone())).is_ok());
+
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let observer = ChannelObserver::new(sender);
+        assert!(accumulator.subscribe(Box::new(observer)).is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        await_expected(|| {
+            let (started, committed, updates) = {
+                let mock = mock.lock().unwrap();
+                let updates = mock.received_updates.clone();
+                (mock.called_on_start, mock.called_on_commit, updates)
+            };
+            assert_eq!(started, 1);
+            assert_eq!(committed, 1);
+            assert_eq!(updates.len(), 3);
+            assert!(updates
+                .iter()
+                .zip(get_usize_updates_1())
+                .all(|(u1, u2)| eq_updates(u1, &u2)));
+        });
+    }
+
+    /// Test that a barrier only returns once all observers subscribed
+    /// to `ChannelObservable`s processed the updates sent before.
+    #[test]
+    fn channel_barrier() {
+        let runtime = Runtime::new().unwrap();
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mut observables = Vec::new();
+        let mut observers = Vec::new();
+        for delay in [10, 50] {
+            let (sender, receiver) = channel(8);
+            let mut observable = ChannelObservable::<_, ()>::new(receiver, runtime.handle());
+            let observer = Arc::new(Mutex::new(
+                FaultyObserver::<Update<usize>, ()>::new().delay_on(
+                    ObserverCall::Commit,
+                    1,
+                    Duration::from_millis(delay),
+                ),
+            ));
+            assert!(observable.subscribe(Box::new(observer.clone())).is_ok());
+            assert!(accumulator
+                .subscribe(Box::new(ChannelObserver::new(sender)))
+                .is_ok());
+            observables.push(observable);
+            observers.push(observer);
+        }
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(accumulator.barrier(), Ok(()));
+
+        for observer in observers {
+            let observer = observer.lock().unwrap();
+            assert_eq!(observer.called(ObserverCall::Commit), 1);
+            assert_eq!(observer.received_updates.len(), 3);
+        }
+    }
+
+    /// Test that an aborted transaction is forwarded as such, so that
+    /// its updates never reach the state of an accumulator subscribed
+    /// to a `ChannelObservable`.
+    #[test]
+    fn channel_abort() {
+        let runtime = Runtime::new().unwrap();
+        let (sender, receiver) = channel(8);
+        let mut observable = ChannelObservable::<_, ()>::new(receiver, runtime.handle());
+        let downstream = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let downstream = Arc::new(Mutex::new(downstream));
+        assert!(observable.subscribe(Box::new(downstream.clone())).is_ok());
+
+        let mut observer = ChannelObserver::<_, ()>::new(sender);
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(observer.on_abort(), Ok(()));
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(observer.on_barrier(), Ok(()));
+
+        let state = downstream.lock().unwrap().get_current_state();
+        assert_eq!(state.len(), 1);
+        assert_eq!(state[&4].len(), 4);
+    }
+}
@@ -0,0 +1,175 @@
+use std::fmt::Debug;
+use std::io::Read;
+use std::io::Write;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// A serialization format for snapshots of accumulators and the
+/// records of a write-ahead log.
+pub trait SnapshotCodec: Debug {
+    /// Serialize the given value to the given writer.
+    fn encode<T, W>(writer: W, value: &T) -> Result<(), String>
+    where
+        T: Serialize + ?Sized,
+        W: Write;
+
+    /// Deserialize a value from the given reader.
+    fn decode<T, R>(reader: R) -> Result<T, String>
+    where
+        T: DeserializeOwned,
+        R: Read;
+}
+
+/// A codec serializing to JSON, e.g., for human readable checkpoints.
+/// The values of the state must serialize to strings or integers, as
+/// they are used as keys of JSON objects.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct JsonCodec;
+
+impl SnapshotCodec for JsonCodec {
+    fn encode<T, W>(writer: W, value: &T) -> Result<(), String>
+    where
+        T: Serialize + ?Sized,
+        W: Write,
+    {
+        serde_json::to_writer(writer, value).map_err(|e| format!("failed to encode JSON: {}", e))
+    }
+
+    fn decode<T, R>(reader: R) -> Result<T, String>
+    where
+        T: DeserializeOwned,
+        R: Read,
+    {
+        serde_json::from_reader(reader).map_err(|e| format!("failed to decode JSON: {}", e))
+    }
+}
+
+/// A codec serializing to bincode, a compact binary format. This is
+/// the format used by `WalObserver::new`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BincodeCodec;
+
+impl SnapshotCodec for BincodeCodec {
+    fn encode<T, W>(writer: W, value: &T) -> Result<(), String>
+    where
+        T: Serialize + ?Sized,
+        W: Write,
+    {
+        bincode::serialize_into(writer, value)
+            .map_err(|e| format!("failed to encode bincode: {}", e))
+    }
+
+    fn decode<T, R>(reader: R) -> Result<T, String>
+    where
+        T: DeserializeOwned,
+        R: Read,
+    {
+        bincode::deserialize_from(reader).map_err(|e| format!("failed to decode bincode: {}", e))
+    }
+}
+
+/// A codec serializing to CBOR, a compact binary format that, unlike
+/// bincode, is self-describing.
+#[cfg(feature = "cbor")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl SnapshotCodec for CborCodec {
+    fn encode<T, W>(writer: W, value: &T) -> Result<(), String>
+    where
+        T: Serialize + ?Sized,
+        W: Write,
+    {
+        serde_cbor::to_writer(writer, &value).map_err(|e| format!("failed to encode CBOR: {}", e))
+    }
+
+    fn decode<T, R>(reader: R) -> Result<T, String>
+    where
+        T: DeserializeOwned,
+        R: Read,
+    {
+        serde_cbor::from_reader(reader).map_err(|e| format!("failed to decode CBOR: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use differential_datalog::program::Update;
+
+    use crate::Accumulator;
+    use crate::DistributingAccumulator;
+    use crate::Observer;
+
+    /// Create an accumulator holding a large state, with some values
+    /// inserted more than once, and a transaction in progress.
+    fn large_accumulator() -> DistributingAccumulator<Update<usize>, usize, ()> {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let updates = (0..1000)
+            .map(|i| Update::Insert {
+                relid: i % 3,
+                v: usize::MAX - i,
+            })
+            .chain((0..10).map(|i| Update::Insert {
+                relid: i % 3,
+                v: usize::MAX - i,
+            }))
+            .collect::<Vec<_>>();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(updates.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let updates = vec![Update::DeleteValue {
+            relid: 0,
+            v: usize::MAX,
+        }];
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(updates.into_iter())),
+            Ok(())
+        );
+        accumulator
+    }
+
+    /// Round-trip the state of the given accumulator through the codec
+    /// `C`, returning the size of the encoded snapshot.
+    fn round_trip<C>(accumulator: &DistributingAccumulator<Update<usize>, usize, ()>) -> usize
+    where
+        C: SnapshotCodec,
+    {
+        let mut bytes = Vec::new();
+        assert_eq!(accumulator.snapshot_to_writer::<C, _>(&mut bytes), Ok(()));
+
+        let mut restored = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(
+            restored.restore_from_reader::<C, _>(bytes.as_slice()),
+            Ok(())
+        );
+        assert!(restored.state_eq(accumulator));
+        assert!(restored.in_transaction());
+        assert_eq!(restored.on_commit(), Ok(()));
+        bytes.len()
+    }
+
+    /// Test that snapshots are restored losslessly by every codec.
+    #[test]
+    fn codec_round_trip() {
+        let accumulator = large_accumulator();
+        assert_eq!(
+            accumulator.get_current_state_weighted()[&1][&(usize::MAX - 1)],
+            2
+        );
+
+        let json = round_trip::<JsonCodec>(&accumulator);
+        let bincode = round_trip::<BincodeCodec>(&accumulator);
+        assert!(bincode < json, "{} >= {}", bincode, json);
+        #[cfg(feature = "cbor")]
+        let _ = round_trip::<CborCodec>(&accumulator);
+    }
+}
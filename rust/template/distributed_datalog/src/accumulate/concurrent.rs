@@ -0,0 +1,97 @@
+use std::fmt::Debug;
+use std::mem::take;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use log::trace;
+use uid::Id;
+
+use differential_datalog::program::RelId;
+use differential_datalog::program::Update;
+
+use crate::Observer;
+use crate::ObserverBox;
+use crate::SharedObserver;
+
+/// A cheaply clonable handle for multiple threads to push updates into
+/// an observer, such as an accumulator, concurrently.
+///
+/// Pushed updates are queued until a commit, which forwards all updates
+/// queued by any of the handles as a single transaction. Commits are
+/// serialized, so the observer always receives well-formed
+/// transactions, no matter how pushes and commits of different threads
+/// interleave.
+#[derive(Debug)]
+pub struct ConcurrentInput<V, E> {
+    /// The handle's unique ID, shared with all clones.
+    id: usize,
+    /// The updates pushed but not yet committed, shared with all clones.
+    queue: Arc<Mutex<Vec<Update<V>>>>,
+    /// The observer we forward the transactions to.
+    observer: SharedObserver<ObserverBox<Update<V>, E>>,
+}
+
+// Manual implementation of `Clone` to not require `V: Clone` and
+// `E: Clone`.
+impl<V, E> Clone for ConcurrentInput<V, E> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            queue: self.queue.clone(),
+            observer: self.observer.clone(),
+        }
+    }
+}
+
+impl<V, E> ConcurrentInput<V, E>
+where
+    V: Debug + Send,
+    E: Debug + Send,
+{
+    /// Create a new `ConcurrentInput` forwarding the pushed updates to
+    /// the given observer.
+    pub fn new(observer: ObserverBox<Update<V>, E>) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("ConcurrentInput({})::new", id);
+
+        Self {
+            id,
+            queue: Arc::new(Mutex::new(Vec::new())),
+            observer: Arc::new(Mutex::new(observer)),
+        }
+    }
+
+    /// Queue an update to the given relation for the next commit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the update is not to the given relation.
+    pub fn push(&self, relid: RelId, update: Update<V>) {
+        trace!("ConcurrentInput({})::push({})", self.id, relid);
+        assert_eq!(
+            update.relid(),
+            relid,
+            "update {:?} is not to relation {}",
+            update,
+            relid
+        );
+        self.queue.lock().unwrap().push(update);
+    }
+
+    /// Forward all queued updates as a single transaction. Nothing is
+    /// forwarded if no updates are queued.
+    pub fn commit(&self) -> Result<(), E> {
+        trace!("ConcurrentInput({})::commit", self.id);
+        // hold the observer while taking the updates, so that
+        // transactions are forwarded in the order their updates were
+        // taken
+        let mut observer = self.observer.lock().unwrap();
+        let updates = take(&mut *self.queue.lock().unwrap());
+        if updates.is_empty() {
+            return Ok(());
+        }
+        observer.on_start()?;
+        observer.on_updates(Box::new(updates.into_iter()))?;
+        observer.on_commit()
+    }
+}
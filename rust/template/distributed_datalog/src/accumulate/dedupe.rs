@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use log::trace;
+use uid::Id;
+
+use differential_datalog::program::Update;
+
+use crate::accumulate::union::Union;
+use crate::accumulate::union::UnionObserver;
+use crate::Observer;
+use crate::ObserverBox;
+
+/// An input port of a `MergeDedupeObserver`, to be subscribed to one of
+/// the two sources. Ports feed the union just like the observers a
+/// `UnionObservable` subscribes to its sources.
+#[derive(Debug)]
+pub struct MergeDedupePort<V, E>(UnionObserver<V, E>);
+
+impl<V, E> Observer<Update<V>, E> for MergeDedupePort<V, E>
+where
+    V: Clone + Debug + Eq + Hash + Send,
+    E: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.0.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.0.on_commit()
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        self.0.on_updates(updates)
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.0.on_abort()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.0.on_completed()
+    }
+}
+
+/// An observer receiving the deduplicated union of two sources through
+/// two input ports, e.g., to feed the output of two accumulators into a
+/// single observer.
+///
+/// Each value is forwarded once, no matter whether it was inserted by
+/// one or both sources, and its deletion is only forwarded once it was
+/// removed from both sources. Deletions of values that were never
+/// inserted are dropped. The wrapped observer completes once both
+/// sources completed.
+#[derive(Debug)]
+pub struct MergeDedupeObserver<V, E> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The union fed by the input ports.
+    merge: Arc<Mutex<Union<V, E>>>,
+}
+
+impl<V, E> MergeDedupeObserver<V, E> {
+    /// Create a new `MergeDedupeObserver` forwarding the deduplicated
+    /// union of its inputs to the given observer.
+    pub fn new(observer: ObserverBox<Update<V>, E>) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("MergeDedupeObserver({})::new", id);
+
+        Self {
+            id,
+            merge: Arc::new(Mutex::new(Union::new(observer, HashMap::new()))),
+        }
+    }
+
+    /// Retrieve the two input ports, to be subscribed to one source
+    /// each.
+    pub fn ports(&self) -> [MergeDedupePort<V, E>; 2] {
+        trace!("MergeDedupeObserver({})::ports", self.id);
+        let port = |input| {
+            MergeDedupePort(UnionObserver::new(
+                Id::<()>::new().get(),
+                input,
+                self.merge.clone(),
+            ))
+        };
+        [port(0), port(1)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::Accumulator;
+    use crate::DistributingAccumulator;
+    use crate::Observable;
+
+    /// Send a transaction consisting of the given update to the given
+    /// accumulator.
+    fn send(
+        accumulator: &mut DistributingAccumulator<Update<usize>, usize, ()>,
+        update: Update<usize>,
+    ) {
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(vec![update].into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+    }
+
+    /// Test that a value inserted by both sources is forwarded once and
+    /// only deleted once it was removed from both.
+    #[test]
+    fn merge_dedupe() {
+        let mut left = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mut right = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let merge = MergeDedupeObserver::new(Box::new(mock.clone()));
+        let [left_port, right_port] = merge.ports();
+        let mut left_observable = left.create_observable();
+        let mut right_observable = right.create_observable();
+        assert!(left_observable.subscribe(Box::new(left_port)).is_ok());
+        assert!(right_observable.subscribe(Box::new(right_port)).is_ok());
+
+        send(&mut left, Update::Insert { relid: 1, v: 5 });
+        send(&mut right, Update::Insert { relid: 1, v: 5 });
+        assert_eq!(mock.lock().unwrap().received_updates.len(), 1);
+        assert!(matches!(
+            mock.lock().unwrap().received_updates[0],
+            Update::Insert { relid: 1, v: 5 }
+        ));
+
+        send(&mut left, Update::DeleteValue { relid: 1, v: 5 });
+        assert_eq!(mock.lock().unwrap().received_updates.len(), 1);
+
+        send(&mut right, Update::DeleteValue { relid: 1, v: 5 });
+        assert_eq!(mock.lock().unwrap().received_updates.len(), 2);
+        assert!(matches!(
+            mock.lock().unwrap().received_updates[1],
+            Update::DeleteValue { relid: 1, v: 5 }
+        ));
+        assert_eq!(mock.lock().unwrap().called_on_start, 2);
+    }
+
+    /// Test that a value inserted repeatedly by one source is deleted
+    /// once both sources removed it, no matter how often it was
+    /// inserted.
+    #[test]
+    fn merge_dedupe_repeated_insert() {
+        let mut left = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mut right = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let merge = MergeDedupeObserver::new(Box::new(mock.clone()));
+        let [left_port, right_port] = merge.ports();
+        let mut left_observable = left.create_observable();
+        let mut right_observable = right.create_observable();
+        assert!(left_observable.subscribe(Box::new(left_port)).is_ok());
+        assert!(right_observable.subscribe(Box::new(right_port)).is_ok());
+
+        send(&mut left, Update::Insert { relid: 1, v: 5 });
+        send(&mut left, Update::Insert { relid: 1, v: 5 });
+        send(&mut right, Update::Insert { relid: 1, v: 5 });
+        assert_eq!(mock.lock().unwrap().received_updates.len(), 1);
+
+        send(&mut left, Update::DeleteValue { relid: 1, v: 5 });
+        assert_eq!(mock.lock().unwrap().received_updates.len(), 1);
+
+        send(&mut right, Update::DeleteValue { relid: 1, v: 5 });
+        assert_eq!(mock.lock().unwrap().received_updates.len(), 2);
+        assert!(matches!(
+            mock.lock().unwrap().received_updates[1],
+            Update::DeleteValue { relid: 1, v: 5 }
+        ));
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use log::trace;
+use uid::Id;
+
+use differential_datalog::program::RelId;
+use differential_datalog::program::Update;
+
+use crate::accumulate::observer::coalesce;
+use crate::accumulate::StateHandle;
+use crate::Observer;
+
+/// An observer holding back the updates of each transaction until it
+/// is committed, in order to forward only their net effect, i.e.,
+/// matching insertions and deletions of a value cancel each other out.
+/// The net effect is determined against the state of the accumulator
+/// before the transaction, i.e., insertions of values already present
+/// and deletions of values absent are dropped. Transactions without
+/// any net effect are not forwarded at all.
+#[derive(Debug)]
+pub(crate) struct DeltaObserver<O, V> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The observer we forward the net effect of transactions to.
+    observer: O,
+    /// The state of the accumulator we observe.
+    state: StateHandle<V>,
+    /// The updates of the transaction in progress, if any.
+    updates: Option<Vec<Update<V>>>,
+    /// Whether each value updated by the transaction in progress was
+    /// present before it, recorded when the value is first updated, as
+    /// the state may already reflect parts of a batched transaction by
+    /// the time it is committed.
+    present: HashMap<(RelId, V), bool>,
+    /// The sequence number the transaction in progress was started
+    /// with, if any.
+    seq: Option<u64>,
+}
+
+impl<O, V> DeltaObserver<O, V>
+where
+    V: Clone + Eq + Hash,
+{
+    /// Create a new `DeltaObserver` forwarding the net effect of
+    /// transactions on the given state to the given observer.
+    pub fn new(observer: O, state: StateHandle<V>) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("DeltaObserver({})::new", id);
+
+        Self {
+            id,
+            observer,
+            state,
+            updates: None,
+            present: HashMap::new(),
+            seq: None,
+        }
+    }
+
+    /// Remember whether the values of the given updates are present,
+    /// unless already recorded for the transaction in progress.
+    fn record_presence(&mut self, updates: &[Update<V>]) {
+        for update in updates {
+            let (relid, v) = match update {
+                Update::Insert { relid, v } | Update::DeleteValue { relid, v } => (*relid, v),
+                _ => continue,
+            };
+            if !self.present.contains_key(&(relid, v.clone())) {
+                let present = self.state.contains(relid, v);
+                let _ = self.present.insert((relid, v.clone()), present);
+            }
+        }
+    }
+
+    /// Reduce the net updates of a transaction to those changing the
+    /// state it was started on, each at most once.
+    fn effective(&mut self, updates: Vec<Update<V>>) -> Vec<Update<V>> {
+        let present = std::mem::take(&mut self.present);
+        let mut seen = HashSet::new();
+        updates
+            .into_iter()
+            .filter(|update| match update {
+                Update::Insert { relid, v } => {
+                    !present.get(&(*relid, v.clone())).copied().unwrap_or(false)
+                        && seen.insert((*relid, v.clone()))
+                }
+                Update::DeleteValue { relid, v } => {
+                    present.get(&(*relid, v.clone())).copied().unwrap_or(true)
+                        && seen.insert((*relid, v.clone()))
+                }
+                _ => true,
+            })
+            .collect()
+    }
+}
+
+impl<O, V, E> Observer<Update<V>, E> for DeltaObserver<O, V>
+where
+    O: Observer<Update<V>, E>,
+    V: Clone + Debug + Eq + Hash + Send,
+    E: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("DeltaObserver({})::on_start", self.id);
+        // the start is forwarded along with the net effect, if any
+        self.updates = Some(Vec::new());
+        self.present.clear();
+        self.seq = None;
+        Ok(())
+    }
+
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        trace!("DeltaObserver({})::on_start_seq({})", self.id, seq);
+        self.updates = Some(Vec::new());
+        self.present.clear();
+        self.seq = Some(seq);
+        Ok(())
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("DeltaObserver({})::on_commit", self.id);
+        let updates = coalesce(self.updates.take().unwrap_or_default().into_iter());
+        let updates = self.effective(updates);
+        let seq = self.seq.take();
+        if updates.is_empty() {
+            return Ok(());
+        }
+
+        match seq {
+            Some(seq) => self.observer.on_start_seq(seq)?,
+            None => self.observer.on_start()?,
+        }
+        self.observer.on_updates(Box::new(updates.into_iter()))?;
+        self.observer.on_commit()
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("DeltaObserver({})::on_updates", self.id);
+        if self.updates.is_none() {
+            // updates outside of a transaction are passed through
+            return self.observer.on_updates(updates);
+        }
+
+        let updates = updates.collect::<Vec<_>>();
+        self.record_presence(&updates);
+        if let Some(buffer) = &mut self.updates {
+            buffer.extend(updates);
+        }
+        Ok(())
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("DeltaObserver({})::on_abort", self.id);
+        // nothing of the transaction was forwarded
+        self.updates = None;
+        self.present.clear();
+        Ok(())
+    }
+
+    fn on_barrier(&mut self) -> Result<(), E> {
+        trace!("DeltaObserver({})::on_barrier", self.id);
+        self.observer.on_barrier()
+    }
+
+    fn on_init_complete(&mut self) -> Result<(), E> {
+        trace!("DeltaObserver({})::on_init_complete", self.id);
+        self.observer.on_init_complete()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("DeltaObserver({})::on_completed", self.id);
+        self.updates = None;
+        self.present.clear();
+        self.observer.on_completed()
+    }
+}
@@ -0,0 +1,45 @@
+use std::error::Error;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+
+use differential_datalog::program::RelId;
+
+/// The way an `AccumulatingObserver` treats the insertion of a value
+/// that is already part of the accumulated state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Accept the insertion. The value is part of the state once, i.e.,
+    /// a single deletion removes it. This is the behavior of an
+    /// observer created via `AccumulatingObserver::new`.
+    Ignore,
+    /// Accept the insertion and increment the value's multiplicity. The
+    /// value is part of the state until it was deleted as often as it
+    /// was inserted.
+    CountWeight,
+    /// Reject the insertion: it is neither accumulated nor forwarded and
+    /// `on_updates` reports a `DuplicateError`.
+    Error,
+}
+
+/// The error reported for the insertion of a value that is already
+/// part of the accumulated state, if such insertions are rejected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateError {
+    /// The relation the value was inserted into.
+    pub relid: RelId,
+    /// The debug representation of the inserted value.
+    pub value: String,
+}
+
+impl Display for DuplicateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "value {} is already present in relation {}",
+            self.value, self.relid
+        )
+    }
+}
+
+impl Error for DuplicateError {}
@@ -0,0 +1,308 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use log::trace;
+use uid::Id;
+
+use differential_datalog::program::RelId;
+use differential_datalog::program::Update;
+
+use crate::accumulate::StateHandle;
+use crate::Observable;
+use crate::ObservableBox;
+use crate::Observer;
+use crate::ObserverBox;
+use crate::OptionalObserver;
+use crate::SharedObserver;
+
+/// An update along with the value it removed from the accumulated
+/// state, as emitted by an `EnrichingObservable`.
+#[derive(Clone, Debug)]
+pub struct EnrichedUpdate<V> {
+    /// The update as received.
+    pub update: Update<V>,
+    /// The full value stored under the key of a deleted value or of a
+    /// key deleted, if the update is a deletion and such a value was
+    /// present.
+    pub removed: Option<V>,
+}
+
+/// The slot holding an observer subscribed to an `EnrichingObservable`,
+/// from which the observer can be reclaimed when unsubscribing.
+type Slot<V, E> = SharedObserver<OptionalObserver<ObserverBox<EnrichedUpdate<V>, E>>>;
+
+/// An observer attaching the values removed by deletions to the updates
+/// it receives before forwarding them.
+#[derive(Debug)]
+struct EnrichingObserver<V, E> {
+    /// The observer's unique ID.
+    id: usize,
+    /// A handle on the accumulated state to look up removed values in.
+    state: StateHandle<V>,
+    /// The function extracting the key identifying a value.
+    key_func: fn(&V) -> V,
+    /// The values stored under the keys touched by the transaction in
+    /// progress, taking its updates into account, as they are not yet
+    /// part of the accumulated state.
+    pending: HashMap<(RelId, V), Option<V>>,
+    /// The observer we forward the enriched updates to, shared with the
+    /// `EnrichingObservable` so that it can reclaim the observer.
+    observer: Slot<V, E>,
+}
+
+impl<V, E> EnrichingObserver<V, E>
+where
+    V: Clone + Eq + Hash,
+{
+    /// Look up the value stored under the given key, if any.
+    fn lookup(&self, relid: RelId, key: &V) -> Option<V> {
+        match self.pending.get(&(relid, key.clone())) {
+            Some(value) => value.clone(),
+            None => {
+                let key_func = self.key_func;
+                self.state.find(relid, |v| key_func(v) == *key)
+            }
+        }
+    }
+
+    /// Attach the value removed by the given update, if any, and record
+    /// its effect on the transaction in progress.
+    fn enrich(&mut self, update: Update<V>) -> EnrichedUpdate<V> {
+        let removed = match &update {
+            Update::Insert { relid, v } | Update::InsertOrUpdate { relid, v } => {
+                let key = (self.key_func)(v);
+                let _ = self.pending.insert((*relid, key), Some(v.clone()));
+                None
+            }
+            Update::DeleteValue { relid, v } => {
+                let key = (self.key_func)(v);
+                let removed = self.lookup(*relid, &key);
+                let _ = self.pending.insert((*relid, key), None);
+                removed
+            }
+            Update::DeleteKey { relid, k } => {
+                let removed = self.lookup(*relid, k);
+                let _ = self.pending.insert((*relid, k.clone()), None);
+                removed
+            }
+            _ => None,
+        };
+        EnrichedUpdate { update, removed }
+    }
+}
+
+impl<V, E> Observer<Update<V>, E> for EnrichingObserver<V, E>
+where
+    V: Clone + Debug + Eq + Hash + Send,
+    E: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("EnrichingObserver({})::on_start", self.id);
+        self.pending.clear();
+        self.observer.on_start()
+    }
+
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        trace!("EnrichingObserver({})::on_start_seq({})", self.id, seq);
+        self.pending.clear();
+        self.observer.on_start_seq(seq)
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("EnrichingObserver({})::on_commit", self.id);
+        self.pending.clear();
+        self.observer.on_commit()
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("EnrichingObserver({})::on_updates", self.id);
+        let enriched = updates.map(|u| self.enrich(u)).collect::<Vec<_>>();
+        self.observer.on_updates(Box::new(enriched.into_iter()))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("EnrichingObserver({})::on_abort", self.id);
+        self.pending.clear();
+        self.observer.on_abort()
+    }
+
+    fn on_barrier(&mut self) -> Result<(), E> {
+        trace!("EnrichingObserver({})::on_barrier", self.id);
+        self.observer.on_barrier()
+    }
+
+    fn on_init_complete(&mut self) -> Result<(), E> {
+        trace!("EnrichingObserver({})::on_init_complete", self.id);
+        self.observer.on_init_complete()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("EnrichingObserver({})::on_completed", self.id);
+        self.observer.on_completed()
+    }
+}
+
+/// An observable emitting the updates of another observable along with
+/// the full values their deletions remove from an accumulated state,
+/// e.g., for a consumer that needs the prior value of a deletion by
+/// key. Values are identified by the key `key_func` extracts from them,
+/// and the removed values are looked up in the state as of the start of
+/// each transaction, taking the updates of the transaction into
+/// account.
+pub struct EnrichingObservable<V, E> {
+    /// The observable's unique ID.
+    id: usize,
+    /// The observable whose updates we enrich.
+    observable: ObservableBox<Update<V>, E>,
+    /// A handle on the accumulated state to look up removed values in.
+    state: StateHandle<V>,
+    /// The function extracting the key identifying a value.
+    key_func: fn(&V) -> V,
+    /// The subscriptions to the wrapped observable along with the
+    /// observers subscribed through them, for each subscription.
+    subscriptions: HashMap<usize, (Box<dyn Any + Send>, Slot<V, E>)>,
+}
+
+impl<V, E> EnrichingObservable<V, E> {
+    /// Create a new `EnrichingObservable` emitting the updates of the
+    /// given observable enriched with the values they remove from the
+    /// state of the given handle.
+    pub fn new(
+        observable: ObservableBox<Update<V>, E>,
+        state: StateHandle<V>,
+        key_func: fn(&V) -> V,
+    ) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("EnrichingObservable({})::new", id);
+
+        Self {
+            id,
+            observable,
+            state,
+            key_func,
+            subscriptions: HashMap::new(),
+        }
+    }
+}
+
+// Manual implementation of `Debug` because the subscriptions are not
+// debug printable.
+impl<V, E> Debug for EnrichingObservable<V, E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("EnrichingObservable")
+            .field("id", &self.id)
+            .field("observable", &self.observable)
+            .finish()
+    }
+}
+
+impl<V, E> Observable<EnrichedUpdate<V>, E> for EnrichingObservable<V, E>
+where
+    V: Clone + Debug + Eq + Hash + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    type Subscription = usize;
+
+    fn subscribe(
+        &mut self,
+        observer: ObserverBox<EnrichedUpdate<V>, E>,
+    ) -> Result<Self::Subscription, ObserverBox<EnrichedUpdate<V>, E>> {
+        let id = Id::<()>::new().get();
+        trace!("EnrichingObservable({})::subscribe({})", self.id, id);
+
+        let observer = Arc::new(Mutex::new(Some(observer)));
+        let enriching = EnrichingObserver {
+            id,
+            state: self.state.clone(),
+            key_func: self.key_func,
+            pending: HashMap::new(),
+            observer: observer.clone(),
+        };
+        match self.observable.subscribe_any(Box::new(enriching)) {
+            Ok(subscription) => {
+                let _ = self.subscriptions.insert(id, (subscription, observer));
+                Ok(id)
+            }
+            Err(_) => Err(observer.lock().unwrap().take().unwrap()),
+        }
+    }
+
+    fn unsubscribe(
+        &mut self,
+        subscription: &Self::Subscription,
+    ) -> Option<ObserverBox<EnrichedUpdate<V>, E>> {
+        trace!(
+            "EnrichingObservable({})::unsubscribe({})",
+            self.id,
+            subscription
+        );
+        let (subscription, observer) = self.subscriptions.remove(subscription)?;
+        let _ = self.observable.unsubscribe_any(subscription.as_ref());
+        let observer = observer.lock().unwrap().take();
+        observer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::accumulate::TxnDistributor;
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::AccumulatorBuilder;
+
+    /// Test that a deletion by key carries the full value it removed.
+    #[test]
+    fn enrich_delete_by_key() {
+        // values are identified by their first field only
+        fn key(v: &(usize, u64)) -> (usize, u64) {
+            (v.0, 0)
+        }
+
+        let mut upstream = TxnDistributor::<Update<(usize, u64)>, ()>::new();
+        let accumulator = AccumulatorBuilder::default().key_func(1, key).build();
+        let state = accumulator.state_handle();
+        let _ = upstream.subscribe(Box::new(accumulator)).unwrap();
+
+        let mut enriching = EnrichingObservable::new(Box::new(upstream.clone()), state, key);
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let _ = enriching.subscribe(Box::new(mock.clone())).unwrap();
+
+        let updates = vec![Update::Insert {
+            relid: 1,
+            v: (1, 100),
+        }];
+        assert_eq!(upstream.on_start(), Ok(()));
+        assert_eq!(upstream.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(upstream.on_commit(), Ok(()));
+
+        let updates = vec![
+            Update::DeleteKey {
+                relid: 1,
+                k: (1, 0),
+            },
+            Update::DeleteKey {
+                relid: 1,
+                k: (2, 0),
+            },
+        ];
+        assert_eq!(upstream.on_start(), Ok(()));
+        assert_eq!(upstream.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(upstream.on_commit(), Ok(()));
+
+        let received = &mock.lock().unwrap().received_updates;
+        assert_eq!(received.len(), 3);
+        assert_eq!(received[0].removed, None);
+        assert_eq!(received[1].removed, Some((1, 100)));
+        assert_eq!(received[2].removed, None);
+    }
+}
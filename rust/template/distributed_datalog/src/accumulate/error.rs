@@ -0,0 +1,80 @@
+use std::error::Error;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+
+use crate::accumulate::AbsentValueError;
+use crate::accumulate::ProtocolViolation;
+use crate::accumulate::WouldBlock;
+
+/// The error reported by the fallible methods of a
+/// `DistributingAccumulator`, such as `try_on_updates`, telling the
+/// failures of observers apart from those of the accumulator itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AccumulatorError<E> {
+    /// A value to be deleted was not present, with deletions of absent
+    /// values rejected.
+    AbsentValue(AbsentValueError),
+    /// The accumulator completed or was shut down and does not accept
+    /// subscriptions.
+    Inactive,
+    /// The operation is not possible while a transaction is in
+    /// progress.
+    InTransaction,
+    /// An observer failed to process an event.
+    Observer(E),
+    /// The accumulated state is inaccessible, as a thread panicked
+    /// while holding its lock.
+    Poisoned,
+    /// The event violated the transaction protocol.
+    ProtocolViolation(ProtocolViolation),
+    /// An observer attempted to subscribe to the accumulator from
+    /// within a callback, while the accumulator was delivering an event.
+    Reentrant,
+    /// The accumulator was shut down.
+    Shutdown,
+    /// Too many transactions were in flight to start another one.
+    WouldBlock(WouldBlock),
+}
+
+impl<E> Display for AccumulatorError<E>
+where
+    E: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            AccumulatorError::AbsentValue(error) => Display::fmt(error, f),
+            AccumulatorError::Inactive => f.write_str("accumulator does not accept subscriptions"),
+            AccumulatorError::InTransaction => f.write_str("a transaction is in progress"),
+            AccumulatorError::Observer(error) => write!(f, "observer failed: {:?}", error),
+            AccumulatorError::Poisoned => f.write_str("accumulated state is poisoned"),
+            AccumulatorError::ProtocolViolation(violation) => Display::fmt(violation, f),
+            AccumulatorError::Reentrant => {
+                f.write_str("cannot subscribe from within a callback of the accumulator")
+            }
+            AccumulatorError::Shutdown => f.write_str("accumulator was shut down"),
+            AccumulatorError::WouldBlock(error) => Display::fmt(error, f),
+        }
+    }
+}
+
+impl<E> Error for AccumulatorError<E> where E: Debug {}
+
+impl<E> From<ProtocolViolation> for AccumulatorError<E> {
+    fn from(violation: ProtocolViolation) -> Self {
+        AccumulatorError::ProtocolViolation(violation)
+    }
+}
+
+impl<E> From<AbsentValueError> for AccumulatorError<E> {
+    fn from(error: AbsentValueError) -> Self {
+        AccumulatorError::AbsentValue(error)
+    }
+}
+
+impl<E> From<WouldBlock> for AccumulatorError<E> {
+    fn from(error: WouldBlock) -> Self {
+        AccumulatorError::WouldBlock(error)
+    }
+}
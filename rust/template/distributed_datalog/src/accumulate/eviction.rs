@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// The way an `AccumulatingObserver` makes room for values inserted
+/// into a relation that is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Do not evict any values. This is the behavior of an observer
+    /// created via `AccumulatingObserver::new`.
+    None,
+    /// Evict the least recently inserted value of a relation holding
+    /// the given number of values when inserting another one, which is
+    /// forwarded as a deletion of the evicted value ahead of the
+    /// insertion. Inserting a value that is already present counts as
+    /// an access, making it the most recently inserted value.
+    Lru(usize),
+}
+
+/// The order in which the values of a relation were last inserted.
+#[derive(Debug)]
+pub(crate) struct Recency<V> {
+    /// The tick of the next insertion.
+    tick: u64,
+    /// The values by the tick they were last inserted at.
+    order: BTreeMap<u64, V>,
+    /// The tick each value was last inserted at.
+    ticks: HashMap<V, u64>,
+    /// The values changed since the last commit along with the tick
+    /// they were inserted at before, in the order of the changes.
+    journal: Vec<(V, Option<u64>)>,
+}
+
+impl<V> Default for Recency<V> {
+    fn default() -> Self {
+        Self {
+            tick: 0,
+            order: BTreeMap::new(),
+            ticks: HashMap::new(),
+            journal: Vec::new(),
+        }
+    }
+}
+
+impl<V> Recency<V>
+where
+    V: Clone + Eq + Hash,
+{
+    /// Record the insertion of the given value, making it the most
+    /// recently inserted one.
+    pub fn touch(&mut self, v: &V) {
+        let previous = self.ticks.insert(v.clone(), self.tick);
+        if let Some(tick) = previous {
+            let _ = self.order.remove(&tick);
+        }
+        self.journal.push((v.clone(), previous));
+        let _ = self.order.insert(self.tick, v.clone());
+        self.tick += 1;
+    }
+
+    /// Forget about the given value.
+    pub fn remove(&mut self, v: &V) {
+        if let Some(tick) = self.ticks.remove(v) {
+            let _ = self.order.remove(&tick);
+            self.journal.push((v.clone(), Some(tick)));
+        }
+    }
+
+    /// Remove and return the least recently inserted value, if any.
+    pub fn pop_oldest(&mut self) -> Option<V> {
+        let tick = *self.order.keys().next()?;
+        let v = self.order.remove(&tick)?;
+        let _ = self.ticks.remove(&v);
+        self.journal.push((v.clone(), Some(tick)));
+        Some(v)
+    }
+
+    /// Make the changes since the last commit permanent.
+    pub fn commit(&mut self) {
+        self.journal.clear();
+    }
+
+    /// Undo the changes since the last commit, e.g., because the
+    /// transaction they were made by was aborted.
+    pub fn rollback(&mut self) {
+        while let Some((v, previous)) = self.journal.pop() {
+            if let Some(tick) = self.ticks.remove(&v) {
+                let _ = self.order.remove(&tick);
+            }
+            if let Some(tick) = previous {
+                let _ = self.order.insert(tick, v.clone());
+                let _ = self.ticks.insert(v, tick);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that values are popped in the order they were last
+    /// inserted in.
+    #[test]
+    fn recency_order() {
+        let mut recency = Recency::default();
+        recency.touch(&1);
+        recency.touch(&2);
+        recency.touch(&3);
+        recency.touch(&1);
+        recency.remove(&2);
+
+        assert_eq!(recency.pop_oldest(), Some(3));
+        assert_eq!(recency.pop_oldest(), Some(1));
+        assert_eq!(recency.pop_oldest(), None);
+    }
+
+    /// Test that rolling back restores the order as of the last commit.
+    #[test]
+    fn recency_rollback() {
+        let mut recency = Recency::default();
+        recency.touch(&1);
+        recency.touch(&2);
+        recency.touch(&3);
+        recency.commit();
+
+        recency.touch(&1);
+        recency.remove(&3);
+        recency.touch(&4);
+        assert_eq!(recency.pop_oldest(), Some(2));
+        recency.rollback();
+
+        assert_eq!(recency.pop_oldest(), Some(1));
+        assert_eq!(recency.pop_oldest(), Some(2));
+        assert_eq!(recency.pop_oldest(), Some(3));
+        assert_eq!(recency.pop_oldest(), None);
+    }
+}
@@ -0,0 +1,186 @@
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+
+use log::trace;
+use uid::Id;
+
+use differential_datalog::program::Update;
+
+use crate::Observer;
+
+/// A predicate deciding whether an update is forwarded.
+pub type UpdatePredicate<V> = Box<dyn Fn(&Update<V>) -> bool + Send>;
+
+/// An observer that forwards only those updates that satisfy a
+/// predicate. The start of a transaction is forwarded lazily, so that
+/// transactions not containing any matching updates do not reach the
+/// wrapped observer at all.
+pub struct FilteringObserver<O, V> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The observer we forward matching updates to.
+    observer: O,
+    /// The predicate deciding which updates to forward.
+    predicate: UpdatePredicate<V>,
+    /// Whether we have seen an `on_start` event that we did not yet
+    /// forward.
+    pending_start: bool,
+    /// The sequence number of the transaction started, if any.
+    seq: Option<u64>,
+    /// Whether we forwarded the start of the current transaction.
+    started: bool,
+}
+
+impl<O, V> FilteringObserver<O, V> {
+    /// Create a new `FilteringObserver` forwarding the updates that
+    /// satisfy `predicate` to `observer`.
+    pub fn new(observer: O, predicate: UpdatePredicate<V>) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("FilteringObserver({})::new", id);
+
+        Self {
+            id,
+            observer,
+            predicate,
+            pending_start: false,
+            seq: None,
+            started: false,
+        }
+    }
+
+    /// Retrieve the wrapped observer.
+    pub fn into_inner(self) -> O {
+        self.observer
+    }
+}
+
+// Manual implementation of `Debug` because the predicate is not debug
+// printable.
+impl<O, V> Debug for FilteringObserver<O, V>
+where
+    O: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("FilteringObserver")
+            .field("id", &self.id)
+            .field("observer", &self.observer)
+            .field("pending_start", &self.pending_start)
+            .field("seq", &self.seq)
+            .field("started", &self.started)
+            .finish()
+    }
+}
+
+impl<O, V, E> Observer<Update<V>, E> for FilteringObserver<O, V>
+where
+    O: Observer<Update<V>, E>,
+    V: Debug + Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("FilteringObserver({})::on_start", self.id);
+        self.pending_start = true;
+        self.seq = None;
+        Ok(())
+    }
+
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        trace!("FilteringObserver({})::on_start_seq({})", self.id, seq);
+        self.pending_start = true;
+        self.seq = Some(seq);
+        Ok(())
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("FilteringObserver({})::on_commit", self.id);
+        self.pending_start = false;
+        if self.started {
+            self.started = false;
+            self.observer.on_commit()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("FilteringObserver({})::on_updates", self.id);
+        let predicate = &self.predicate;
+        let updates = updates.filter(|u| predicate(u)).collect::<Vec<_>>();
+        if updates.is_empty() {
+            return Ok(());
+        }
+
+        if self.pending_start {
+            self.pending_start = false;
+            self.started = true;
+            match self.seq.take() {
+                Some(seq) => self.observer.on_start_seq(seq)?,
+                None => self.observer.on_start()?,
+            }
+        }
+        self.observer.on_updates(Box::new(updates.into_iter()))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("FilteringObserver({})::on_abort", self.id);
+        self.observer.on_abort()
+    }
+
+    fn on_barrier(&mut self) -> Result<(), E> {
+        trace!("FilteringObserver({})::on_barrier", self.id);
+        self.observer.on_barrier()
+    }
+
+    fn on_init_complete(&mut self) -> Result<(), E> {
+        trace!("FilteringObserver({})::on_init_complete", self.id);
+        self.observer.on_init_complete()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("FilteringObserver({})::on_completed", self.id);
+        self.observer.on_completed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use crate::MockObserver;
+
+    /// Test that transactions without matching updates are not
+    /// forwarded.
+    #[test]
+    fn lazy_transactions() {
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let predicate = Box::new(|u: &Update<usize>| u.relid() == 1);
+        let mut observer = FilteringObserver::new(mock.clone(), predicate);
+        let observer = &mut observer as &mut dyn Observer<Update<usize>, ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        let updates = vec![Update::Insert { relid: 2, v: 1 }];
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(mock.lock().unwrap().called_on_start, 0);
+        assert_eq!(mock.lock().unwrap().called_on_updates, 0);
+        assert_eq!(mock.lock().unwrap().called_on_commit, 0);
+
+        assert_eq!(observer.on_start(), Ok(()));
+        let updates = vec![
+            Update::Insert { relid: 1, v: 1 },
+            Update::Insert { relid: 2, v: 2 },
+        ];
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(mock.lock().unwrap().called_on_start, 1);
+        assert_eq!(mock.lock().unwrap().called_on_updates, 1);
+        assert_eq!(mock.lock().unwrap().called_on_commit, 1);
+    }
+}
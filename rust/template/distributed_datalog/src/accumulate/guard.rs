@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+
+use log::trace;
+use log::warn;
+use uid::Id;
+
+use differential_datalog::program::RelId;
+use differential_datalog::program::Update;
+
+use crate::Observer;
+
+/// The error reported for an update of a relation that is not part of
+/// the schema of a `SchemaGuardObserver`, if such updates are
+/// rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnknownRelation {
+    /// The relation the update referred to.
+    pub relid: RelId,
+}
+
+impl Display for UnknownRelation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "received update for unknown relation {}", self.relid)
+    }
+}
+
+impl Error for UnknownRelation {}
+
+/// An observer that only lets updates of a known set of relations
+/// through to the wrapped observer, e.g., to keep a mis-wired upstream
+/// from polluting the state of an accumulator.
+///
+/// By default, updates of other relations are dropped and a warning is
+/// logged. If created via `reject_unknown`, a batch of updates
+/// containing any such update is not forwarded at all, and an
+/// `UnknownRelation` error is reported instead.
+#[derive(Debug)]
+pub struct SchemaGuardObserver<O, E> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The observer we forward updates of known relations to.
+    observer: O,
+    /// The relations updates may refer to.
+    relids: HashSet<RelId>,
+    /// The conversion of an `UnknownRelation` into our error type, if
+    /// updates of unknown relations are rejected.
+    error: Option<fn(UnknownRelation) -> E>,
+}
+
+impl<O, E> SchemaGuardObserver<O, E> {
+    /// Create a new `SchemaGuardObserver` forwarding the updates of
+    /// the given relations to `observer`, dropping all others.
+    pub fn new(observer: O, relids: HashSet<RelId>) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("SchemaGuardObserver({})::new", id);
+
+        Self {
+            id,
+            observer,
+            relids,
+            error: None,
+        }
+    }
+
+    /// Reject batches of updates containing updates of unknown
+    /// relations with an `UnknownRelation` error instead of dropping
+    /// those updates.
+    pub fn reject_unknown(mut self) -> Self
+    where
+        E: From<UnknownRelation>,
+    {
+        self.error = Some(E::from);
+        self
+    }
+}
+
+impl<O, V, E> Observer<Update<V>, E> for SchemaGuardObserver<O, E>
+where
+    O: Observer<Update<V>, E>,
+    V: Debug + Send,
+    E: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("SchemaGuardObserver({})::on_start", self.id);
+        self.observer.on_start()
+    }
+
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        trace!("SchemaGuardObserver({})::on_start_seq({})", self.id, seq);
+        self.observer.on_start_seq(seq)
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("SchemaGuardObserver({})::on_commit", self.id);
+        self.observer.on_commit()
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("SchemaGuardObserver({})::on_updates", self.id);
+        let (known, unknown) =
+            updates.partition::<Vec<_>, _>(|update| self.relids.contains(&update.relid()));
+
+        if let Some(update) = unknown.first() {
+            let relid = update.relid();
+            if let Some(error) = self.error {
+                return Err(error(UnknownRelation { relid }));
+            }
+            warn!(
+                "SchemaGuardObserver({}) dropping {} updates of unknown relations, e.g., {}",
+                self.id,
+                unknown.len(),
+                relid
+            );
+        }
+        self.observer.on_updates(Box::new(known.into_iter()))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("SchemaGuardObserver({})::on_abort", self.id);
+        self.observer.on_abort()
+    }
+
+    fn on_barrier(&mut self) -> Result<(), E> {
+        trace!("SchemaGuardObserver({})::on_barrier", self.id);
+        self.observer.on_barrier()
+    }
+
+    fn on_init_complete(&mut self) -> Result<(), E> {
+        trace!("SchemaGuardObserver({})::on_init_complete", self.id);
+        self.observer.on_init_complete()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("SchemaGuardObserver({})::on_completed", self.id);
+        self.observer.on_completed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use maplit::hashset;
+
+    use crate::accumulate::UpdatesMockObserver;
+
+    /// Test that updates of unknown relations are dropped or rejected,
+    /// while those of known relations pass through.
+    #[test]
+    fn unknown_relations() {
+        let updates = || {
+            vec![
+                Update::Insert { relid: 1, v: 1 },
+                Update::Insert { relid: 3, v: 2 },
+                Update::Insert { relid: 2, v: 3 },
+            ]
+        };
+
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::<Update<usize>>::new()));
+        let mut observer = SchemaGuardObserver::new(mock.clone(), hashset! {1, 2});
+        let observer = &mut observer as &mut dyn Observer<Update<usize>, String>;
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates().into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let received = mock.lock().unwrap().received_updates.clone();
+        assert_eq!(received.len(), 2);
+        assert!(received.iter().all(|u| u.relid() != 3));
+
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::<Update<usize>>::new()));
+        let mut observer =
+            SchemaGuardObserver::<_, UnknownRelation>::new(mock.clone(), hashset! {1, 2})
+                .reject_unknown();
+        let observer = &mut observer as &mut dyn Observer<Update<usize>, _>;
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(updates().into_iter())),
+            Err(UnknownRelation { relid: 3 })
+        );
+        let known = vec![Update::Insert { relid: 2, v: 4 }];
+        assert_eq!(observer.on_updates(Box::new(known.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let received = mock.lock().unwrap().received_updates.clone();
+        assert_eq!(received.len(), 1);
+        assert!(matches!(received[0], Update::Insert { relid: 2, v: 4 }));
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::VecDeque;
+
+use differential_datalog::program::Update;
+
+/// A bounded history of the most recent transactions received by an
+/// accumulator, e.g., for inspecting them when debugging.
+#[derive(Debug)]
+pub(crate) struct History<V> {
+    /// The maximum number of transactions retained.
+    capacity: usize,
+    /// The sequence number of the next transaction to be committed.
+    next: u64,
+    /// The updates of the transaction in progress, if any.
+    pending: Option<Vec<Update<V>>>,
+    /// The retained transactions along with their sequence numbers,
+    /// oldest first.
+    transactions: VecDeque<(u64, Vec<Update<V>>)>,
+}
+
+impl<V> History<V>
+where
+    V: Clone,
+{
+    /// Create a new `History` retaining up to `capacity` transactions.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            next: 1,
+            pending: None,
+            transactions: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Record the start of a transaction.
+    pub fn start(&mut self) {
+        self.pending = Some(Vec::new());
+    }
+
+    /// Record an update of the transaction in progress.
+    pub fn record(&mut self, update: &Update<V>) {
+        if let Some(pending) = &mut self.pending {
+            pending.push(update.clone());
+        }
+    }
+
+    /// Discard the transaction in progress, if any.
+    pub fn abort(&mut self) {
+        self.pending = None;
+    }
+
+    /// Record the commit of the transaction in progress, evicting the
+    /// oldest transaction if the history is full.
+    pub fn commit(&mut self) {
+        if let Some(updates) = self.pending.take() {
+            if self.capacity == 0 {
+                return;
+            }
+            if self.transactions.len() == self.capacity {
+                let _ = self.transactions.pop_front();
+            }
+            self.transactions.push_back((self.next, updates));
+            self.next += 1;
+        }
+    }
+
+    /// Retrieve the retained transactions along with their sequence
+    /// numbers, oldest first.
+    pub fn transactions(&self) -> Vec<(u64, Vec<Update<V>>)> {
+        self.transactions.iter().cloned().collect()
+    }
+}
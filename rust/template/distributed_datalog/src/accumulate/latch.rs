@@ -0,0 +1,146 @@
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::hash::Hash;
+use std::mem::take;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use log::trace;
+use uid::Id;
+
+use differential_datalog::program::Update;
+
+use crate::accumulate::observer::coalesce;
+use crate::accumulate::TxnDistributor;
+use crate::Observer;
+
+/// The updates collected for a `LatchObservable`.
+#[derive(Debug)]
+struct Latch<V> {
+    /// The net effect of the transactions committed since the last
+    /// poll.
+    committed: Vec<Update<V>>,
+    /// The updates of the transaction in progress, if any.
+    pending: Option<Vec<Update<V>>>,
+}
+
+/// An observer collecting the updates of committed transactions into a
+/// `Latch`.
+#[derive(Debug)]
+struct LatchObserver<V> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The latch shared with the `LatchObservable`.
+    latch: Arc<Mutex<Latch<V>>>,
+}
+
+impl<V, E> Observer<Update<V>, E> for LatchObserver<V>
+where
+    V: Clone + Debug + Eq + Hash + Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("LatchObserver({})::on_start", self.id);
+        self.latch.lock().unwrap().pending = Some(Vec::new());
+        Ok(())
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("LatchObserver({})::on_commit", self.id);
+        let mut latch = self.latch.lock().unwrap();
+        if let Some(pending) = latch.pending.take() {
+            let committed = take(&mut latch.committed);
+            latch.committed = coalesce(committed.into_iter().chain(pending));
+        }
+        Ok(())
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("LatchObserver({})::on_updates", self.id);
+        if let Some(pending) = &mut self.latch.lock().unwrap().pending {
+            pending.extend(updates);
+        }
+        Ok(())
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("LatchObserver({})::on_completed", self.id);
+        Ok(())
+    }
+}
+
+/// A pull-based counterpart to an observable, collecting the updates
+/// of an accumulator's transactions as they are committed, for
+/// consumers only interested in the net change since they last looked.
+///
+/// The `LatchObservable` stops collecting updates when dropped.
+pub struct LatchObservable<V> {
+    /// The observable's unique ID.
+    id: usize,
+    /// The latch collecting the updates.
+    latch: Arc<Mutex<Latch<V>>>,
+    /// The function cancelling the subscription of the `LatchObserver`.
+    cancel: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl<V> LatchObservable<V>
+where
+    V: Clone + Debug + Eq + Hash + Send + 'static,
+{
+    /// Create a new `LatchObservable` collecting the updates emitted by
+    /// the given distributor.
+    pub(crate) fn new<E>(distributor: &mut TxnDistributor<Update<V>, E>) -> Self
+    where
+        E: Debug + Send + 'static,
+    {
+        let id = Id::<()>::new().get();
+        trace!("LatchObservable({})::new", id);
+
+        let latch = Arc::new(Mutex::new(Latch {
+            committed: Vec::new(),
+            pending: None,
+        }));
+        let mut cancel = None;
+        let _ = distributor.subscribe_with(|cancel_subscription| {
+            cancel = Some(cancel_subscription);
+            Box::new(LatchObserver {
+                id,
+                latch: latch.clone(),
+            })
+        });
+
+        Self { id, latch, cancel }
+    }
+
+    /// Retrieve the net effect of the transactions committed since the
+    /// previous poll, i.e., insertions and deletions of the same value
+    /// cancel each other out. Updates of a transaction in progress are
+    /// not included.
+    pub fn poll(&self) -> Vec<Update<V>> {
+        trace!("LatchObservable({})::poll", self.id);
+        take(&mut self.latch.lock().unwrap().committed)
+    }
+}
+
+// Manual implementation of `Debug` because the cancellation function
+// is not debug printable.
+impl<V> Debug for LatchObservable<V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("LatchObservable")
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+impl<V> Drop for LatchObservable<V> {
+    fn drop(&mut self) {
+        trace!("LatchObservable({})::drop", self.id);
+        if let Some(cancel) = self.cancel.take() {
+            cancel()
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use log::trace;
+use uid::Id;
+
+use differential_datalog::program::Update;
+
+use crate::Observable;
+use crate::ObservableBox;
+use crate::Observer;
+use crate::ObserverBox;
+use crate::OptionalObserver;
+use crate::SharedObserver;
+
+/// Transform the value of an update, preserving its variant and
+/// relation.
+///
+/// # Panics
+///
+/// Panics on `Update::Modify`, as its mutator cannot be transformed.
+pub(crate) fn map_update<A, B, F>(update: Update<A>, f: &F) -> Update<B>
+where
+    A: Debug,
+    F: Fn(&A) -> B,
+{
+    match update {
+        Update::Insert { relid, v } => Update::Insert { relid, v: f(&v) },
+        Update::InsertOrUpdate { relid, v } => Update::InsertOrUpdate { relid, v: f(&v) },
+        Update::DeleteValue { relid, v } => Update::DeleteValue { relid, v: f(&v) },
+        Update::DeleteKey { relid, k } => Update::DeleteKey { relid, k: f(&k) },
+        update => panic!("Operation {:?} not allowed", update),
+    }
+}
+
+/// The slot holding an observer subscribed to an adapting observable,
+/// such as a `MapObservable`, from which the observer can be reclaimed
+/// when unsubscribing.
+pub(crate) type Slot<V, E> = SharedObserver<OptionalObserver<ObserverBox<Update<V>, E>>>;
+
+/// An observer transforming the values of all updates it receives
+/// before forwarding them.
+struct MapObserver<A, B, F, E> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The function to transform values with.
+    f: Arc<F>,
+    /// The observer we forward the transformed updates to, shared with
+    /// the `MapObservable` so that it can reclaim the observer.
+    observer: Slot<B, E>,
+    _phantom: PhantomData<A>,
+}
+
+// Manual implementation of `Debug` because the function is not debug
+// printable.
+impl<A, B, F, E> Debug for MapObserver<A, B, F, E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("MapObserver").field("id", &self.id).finish()
+    }
+}
+
+impl<A, B, F, E> Observer<Update<A>, E> for MapObserver<A, B, F, E>
+where
+    A: Debug + Send,
+    B: Debug + Send,
+    F: Fn(&A) -> B + Send + Sync,
+    E: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("MapObserver({})::on_start", self.id);
+        self.observer.on_start()
+    }
+
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        trace!("MapObserver({})::on_start_seq({})", self.id, seq);
+        self.observer.on_start_seq(seq)
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("MapObserver({})::on_commit", self.id);
+        self.observer.on_commit()
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<A>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("MapObserver({})::on_updates", self.id);
+        let f = &*self.f;
+        self.observer
+            .on_updates(Box::new(updates.map(|u| map_update(u, f))))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("MapObserver({})::on_abort", self.id);
+        self.observer.on_abort()
+    }
+
+    fn on_barrier(&mut self) -> Result<(), E> {
+        trace!("MapObserver({})::on_barrier", self.id);
+        self.observer.on_barrier()
+    }
+
+    fn on_init_complete(&mut self) -> Result<(), E> {
+        trace!("MapObserver({})::on_init_complete", self.id);
+        self.observer.on_init_complete()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("MapObserver({})::on_completed", self.id);
+        self.observer.on_completed()
+    }
+}
+
+/// An observable emitting the updates of another observable with their
+/// values transformed by a function, e.g., to project or anonymize
+/// values before handing them to an untrusted consumer. The variant and
+/// the relation of each update are preserved, so deletions of
+/// transformed values match their earlier insertions, provided the
+/// function is deterministic.
+pub struct MapObservable<A, B, F, E> {
+    /// The observable's unique ID.
+    id: usize,
+    /// The observable whose updates we transform.
+    observable: ObservableBox<Update<A>, E>,
+    /// The function to transform values with.
+    f: Arc<F>,
+    /// The subscriptions to the wrapped observable along with the
+    /// observers subscribed through them, for each subscription.
+    subscriptions: HashMap<usize, (Box<dyn Any + Send>, Slot<B, E>)>,
+}
+
+impl<A, B, F, E> MapObservable<A, B, F, E> {
+    /// Create a new `MapObservable` emitting the updates of the given
+    /// observable with their values transformed by `f`.
+    pub fn new(observable: ObservableBox<Update<A>, E>, f: F) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("MapObservable({})::new", id);
+
+        Self {
+            id,
+            observable,
+            f: Arc::new(f),
+            subscriptions: HashMap::new(),
+        }
+    }
+}
+
+// Manual implementation of `Debug` because the function is not debug
+// printable.
+impl<A, B, F, E> Debug for MapObservable<A, B, F, E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("MapObservable")
+            .field("id", &self.id)
+            .field("observable", &self.observable)
+            .finish()
+    }
+}
+
+impl<A, B, F, E> Observable<Update<B>, E> for MapObservable<A, B, F, E>
+where
+    A: Debug + Send + 'static,
+    B: Debug + Send + 'static,
+    F: Fn(&A) -> B + Send + Sync + 'static,
+    E: Debug + Send + 'static,
+{
+    type Subscription = usize;
+
+    fn subscribe(
+        &mut self,
+        observer: ObserverBox<Update<B>, E>,
+    ) -> Result<Self::Subscription, ObserverBox<Update<B>, E>> {
+        let id = Id::<()>::new().get();
+        trace!("MapObservable({})::subscribe({})", self.id, id);
+
+        let observer = Arc::new(Mutex::new(Some(observer)));
+        let mapping = MapObserver {
+            id,
+            f: self.f.clone(),
+            observer: observer.clone(),
+            _phantom: PhantomData,
+        };
+        match self.observable.subscribe_any(Box::new(mapping)) {
+            Ok(subscription) => {
+                let _ = self.subscriptions.insert(id, (subscription, observer));
+                Ok(id)
+            }
+            Err(_) => Err(observer.lock().unwrap().take().unwrap()),
+        }
+    }
+
+    fn unsubscribe(
+        &mut self,
+        subscription: &Self::Subscription,
+    ) -> Option<ObserverBox<Update<B>, E>> {
+        trace!("MapObservable({})::unsubscribe({})", self.id, subscription);
+        let (subscription, observer) = self.subscriptions.remove(subscription)?;
+        let _ = self.observable.unsubscribe_any(subscription.as_ref());
+        let observer = observer.lock().unwrap().take();
+        observer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::accumulate::accumulator::tests::get_usize_updates_1;
+    use crate::accumulate::{eq_updates, UpdatesMockObserver};
+    use crate::Accumulator;
+    use crate::DistributingAccumulator;
+
+    /// Test that the updates emitted by a `MapObservable` carry the
+    /// transformed values.
+    #[test]
+    fn map_to_string() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let observable = accumulator.create_observable();
+        let mut mapped = MapObservable::new(Box::new(observable), |v: &usize| v.to_string());
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::<Update<String>>::new()));
+        let subscription = mapped.subscribe(Box::new(mock.clone())).unwrap();
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        let deletes = vec![Update::DeleteValue { relid: 2, v: 2 }];
+        assert_eq!(
+            accumulator.on_updates(Box::new(deletes.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let received = mock.lock().unwrap().received_updates.clone();
+        let expected = [
+            Update::Insert {
+                relid: 1,
+                v: "1".to_string(),
+            },
+            Update::Insert {
+                relid: 2,
+                v: "2".to_string(),
+            },
+            Update::Insert {
+                relid: 3,
+                v: "3".to_string(),
+            },
+            Update::DeleteValue {
+                relid: 2,
+                v: "2".to_string(),
+            },
+        ];
+        assert_eq!(received.len(), expected.len());
+        assert!(received
+            .iter()
+            .zip(expected.iter())
+            .all(|(u1, u2)| eq_updates(u1, u2)));
+
+        assert!(mapped.unsubscribe(&subscription).is_some());
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(mock.lock().unwrap().received_updates.len(), 4);
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use log::trace;
+use uid::Id;
+
+use differential_datalog::program::RelId;
+use differential_datalog::program::Update;
+
+use crate::accumulate::Accumulator;
+use crate::accumulate::DistributingAccumulator;
+use crate::txnmux::TxnMux;
+use crate::Observable;
+use crate::Observer;
+use crate::ObserverBox;
+use crate::SharedObserver;
+use crate::UpdatesObservable;
+
+/// An accumulator that merges the updates of multiple upstream sources
+/// into a single accumulated state and distributes it to its observers.
+///
+/// Each source feeds one of the input ports handed out by `add_source`.
+/// The ports are combined through a `TxnMux`, which serializes the
+/// transactions of the different sources, and the accumulated state is
+/// the union of all of them. The accumulator itself acts as one more
+/// input port when used as an `Observer`.
+///
+/// Note that the accumulated state is not tracked per source: the
+/// completion of any source clears the entire accumulated state.
+#[derive(Debug)]
+pub struct MergingAccumulator<V, E>
+where
+    V: Debug + Eq + Hash + Send,
+    E: Debug + Send,
+{
+    /// The accumulator's unique ID.
+    id: usize,
+    /// The multiplexer combining the transactions of all sources.
+    mux: TxnMux<Update<V>, E>,
+    /// The input port used when we are observing an observable directly.
+    input: ObserverBox<Update<V>, E>,
+    /// The accumulator maintaining the merged state, subscribed to the
+    /// multiplexer.
+    accumulator: SharedObserver<DistributingAccumulator<Update<V>, V, E>>,
+}
+
+impl<V, E> MergingAccumulator<V, E>
+where
+    V: Debug + Send + Clone + Eq + Hash + 'static,
+    E: Debug + Send + 'static,
+{
+    /// Create a new input port. All updates pushed into the returned
+    /// observer are merged into the accumulated state.
+    pub fn add_source(&mut self) -> ObserverBox<Update<V>, E> {
+        trace!("MergingAccumulator({})::add_source()", self.id);
+        self.mux.create_observer()
+    }
+}
+
+impl<V, E> Accumulator<V, E> for MergingAccumulator<V, E>
+where
+    V: Debug + Send + Clone + Eq + Hash + 'static,
+    E: Debug + Send + 'static,
+{
+    fn new() -> Self {
+        let id = Id::<()>::new().get();
+        trace!("MergingAccumulator({})::new", id);
+
+        let accumulator = Arc::new(Mutex::new(DistributingAccumulator::new()));
+        let mut mux = TxnMux::new();
+        let _subscription = mux.subscribe(Box::new(accumulator.clone()));
+        let input = mux.create_observer();
+
+        Self {
+            id,
+            mux,
+            input,
+            accumulator,
+        }
+    }
+
+    fn create_observable(&mut self) -> UpdatesObservable<Update<V>, E> {
+        trace!("MergingAccumulator({})::create_observable()", self.id);
+        self.accumulator.lock().unwrap().create_observable()
+    }
+
+    fn get_current_state(&self) -> HashMap<RelId, HashSet<V>> {
+        trace!("MergingAccumulator({})::get_current_state()", self.id);
+        self.accumulator.lock().unwrap().get_current_state()
+    }
+
+    fn get_current_state_weighted(&self) -> HashMap<RelId, HashMap<V, isize>> {
+        trace!(
+            "MergingAccumulator({})::get_current_state_weighted()",
+            self.id
+        );
+        self.accumulator
+            .lock()
+            .unwrap()
+            .get_current_state_weighted()
+    }
+
+    fn contains(&self, relid: RelId, value: &V) -> bool {
+        trace!("MergingAccumulator({})::contains({})", self.id, relid);
+        self.accumulator.lock().unwrap().contains(relid, value)
+    }
+
+    fn relation_size(&self, relid: RelId) -> usize {
+        trace!("MergingAccumulator({})::relation_size({})", self.id, relid);
+        self.accumulator.lock().unwrap().relation_size(relid)
+    }
+
+    fn for_each_value<F>(&self, f: F)
+    where
+        F: FnMut(RelId, &V),
+    {
+        trace!("MergingAccumulator({})::for_each_value()", self.id);
+        self.accumulator.lock().unwrap().for_each_value(f)
+    }
+}
+
+impl<V, E> Observable<Update<V>, E> for MergingAccumulator<V, E>
+where
+    V: Debug + Send + Clone + Eq + Hash + 'static,
+    E: Debug + Send + 'static,
+{
+    type Subscription = usize;
+
+    fn subscribe(
+        &mut self,
+        observer: ObserverBox<Update<V>, E>,
+    ) -> Result<Self::Subscription, ObserverBox<Update<V>, E>> {
+        trace!("MergingAccumulator({})::subscribe()", self.id);
+        self.accumulator.lock().unwrap().subscribe(observer)
+    }
+
+    fn unsubscribe(
+        &mut self,
+        subscription: &Self::Subscription,
+    ) -> Option<ObserverBox<Update<V>, E>> {
+        trace!(
+            "MergingAccumulator({})::unsubscribe({})",
+            self.id,
+            subscription
+        );
+        self.accumulator.lock().unwrap().unsubscribe(subscription)
+    }
+}
+
+impl<V, E> Observer<Update<V>, E> for MergingAccumulator<V, E>
+where
+    V: Debug + Send + Clone + Eq + Hash + 'static,
+    E: Debug + Send + 'static,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("MergingAccumulator({})::on_start", self.id);
+        self.input.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("MergingAccumulator({})::on_commit", self.id);
+        self.input.on_commit()
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("MergingAccumulator({})::on_updates", self.id);
+        self.input.on_updates(updates)
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("MergingAccumulator({})::on_abort", self.id);
+        self.input.on_abort()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("MergingAccumulator({})::on_completed", self.id);
+        self.input.on_completed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::accumulate::accumulator::tests::get_usize_updates_1;
+    use crate::accumulate::accumulator::tests::get_usize_updates_3;
+    use crate::MockObserver;
+
+    /// Test that the state of a `MergingAccumulator` is the union of
+    /// the updates of all of its sources, even if their transactions
+    /// interleave.
+    #[test]
+    fn merge_sources() {
+        let mut accumulator = MergingAccumulator::<usize, ()>::new();
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        assert!(accumulator.subscribe(Box::new(mock.clone())).is_ok());
+
+        let mut source1 = accumulator.add_source();
+        let mut source2 = accumulator.add_source();
+
+        assert_eq!(source1.on_start(), Ok(()));
+        assert_eq!(source2.on_start(), Ok(()));
+        assert_eq!(source1.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(source2.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(source2.on_commit(), Ok(()));
+        assert_eq!(source1.on_commit(), Ok(()));
+
+        let state = accumulator.get_current_state();
+        assert_eq!(state.values().map(HashSet::len).sum::<usize>(), 7);
+        assert_eq!(state[&4].len(), 4);
+
+        let mock = *mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, 2);
+        assert_eq!(mock.called_on_updates, 7);
+        assert_eq!(mock.called_on_commit, 2);
+    }
+
+    /// Test that a new transaction can be started after aborting one
+    /// and that the aborted one does not reach the accumulated state.
+    #[test]
+    fn start_after_abort() {
+        let mut accumulator = MergingAccumulator::<usize, ()>::new();
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        assert!(accumulator.subscribe(Box::new(mock.clone())).is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_abort(), Ok(()));
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let state = accumulator.get_current_state();
+        assert_eq!(state.values().map(HashSet::len).sum::<usize>(), 4);
+        assert_eq!(mock.lock().unwrap().called_on_start, 1);
+    }
+}
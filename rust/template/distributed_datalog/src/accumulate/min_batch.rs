@@ -0,0 +1,207 @@
+use std::fmt::Debug;
+use std::mem::take;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread::park;
+use std::thread::park_timeout;
+use std::thread::spawn;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::Instant;
+
+use log::error;
+use log::trace;
+use uid::Id;
+
+use differential_datalog::program::Update;
+
+use crate::accumulate::map::Slot;
+use crate::Observer;
+
+/// The updates of the committed transactions not yet flushed.
+#[derive(Debug)]
+struct Batch<V> {
+    /// The updates, in the order they were committed.
+    updates: Vec<Update<V>>,
+    /// The time the first transaction of the batch was committed, if
+    /// any.
+    since: Option<Instant>,
+}
+
+impl<V> Batch<V>
+where
+    V: Debug + Send,
+{
+    /// Forward the batch to the observer in the given slot as a single
+    /// transaction, unless it is empty.
+    fn flush<E>(&mut self, observer: &mut Slot<V, E>) -> Result<(), E>
+    where
+        E: Debug + Send,
+    {
+        self.since = None;
+        if self.updates.is_empty() {
+            return Ok(());
+        }
+        let updates = take(&mut self.updates);
+        observer.on_start()?;
+        observer.on_updates(Box::new(updates.into_iter()))?;
+        observer.on_commit()
+    }
+}
+
+/// An observer combining the transactions it receives into batches of
+/// at least a minimum number of updates, each forwarded as a single
+/// transaction. A thread flushes a batch that was held back for the
+/// maximum latency, no matter its size.
+#[derive(Debug)]
+pub(crate) struct MinBatchObserver<V, E> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The observer we forward the batches to, shared with the thread
+    /// flushing them.
+    observer: Slot<V, E>,
+    /// The minimum number of updates of a batch.
+    min_batch: usize,
+    /// The batch being collected, shared with the thread flushing it.
+    batch: Arc<Mutex<Batch<V>>>,
+    /// The updates of the transaction in progress.
+    pending: Vec<Update<V>>,
+    /// Flag indicating to the thread that it should stop.
+    stopped: Arc<AtomicBool>,
+    /// The thread flushing batches held back for too long.
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<V, E> MinBatchObserver<V, E>
+where
+    V: Debug + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    /// Create a new `MinBatchObserver` forwarding batches of at least
+    /// `min_batch` updates to the observer in the given slot, flushing
+    /// smaller batches once they were held back for `max_latency`.
+    pub fn new(observer: Slot<V, E>, min_batch: usize, max_latency: Duration) -> Self {
+        let id = Id::<()>::new().get();
+        trace!(
+            "MinBatchObserver({})::new({}, {:?})",
+            id,
+            min_batch,
+            max_latency
+        );
+
+        let batch = Arc::new(Mutex::new(Batch {
+            updates: Vec::new(),
+            since: None,
+        }));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_batch = batch.clone();
+        let thread_stopped = stopped.clone();
+        let mut thread_observer = observer.clone();
+        let thread = spawn(move || loop {
+            // without a batch there is nothing to time, so we wait for
+            // `on_commit` to start one
+            let since = thread_batch.lock().unwrap().since;
+            match since {
+                Some(since) => {
+                    park_timeout(max_latency.checked_sub(since.elapsed()).unwrap_or_default())
+                }
+                None => park(),
+            }
+            if thread_stopped.load(Ordering::SeqCst) {
+                break;
+            }
+
+            let mut batch = thread_batch.lock().unwrap();
+            if matches!(batch.since, Some(since) if since.elapsed() >= max_latency) {
+                trace!("MinBatchObserver({}) flushing batch held back too long", id);
+                // there is no caller to report the error to
+                if let Err(e) = batch.flush(&mut thread_observer) {
+                    error!(
+                        "MinBatchObserver({}) failed to flush batch held back too long: {:?}",
+                        id, e
+                    );
+                }
+            }
+        });
+
+        Self {
+            id,
+            observer,
+            min_batch,
+            batch,
+            pending: Vec::new(),
+            stopped,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl<V, E> Observer<Update<V>, E> for MinBatchObserver<V, E>
+where
+    V: Debug + Send,
+    E: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("MinBatchObserver({})::on_start", self.id);
+        self.pending.clear();
+        Ok(())
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("MinBatchObserver({})::on_commit", self.id);
+        let mut batch = self.batch.lock().unwrap();
+        batch.updates.append(&mut self.pending);
+        if batch.since.is_none() {
+            batch.since = Some(Instant::now());
+            // let the thread wait for the maximum latency of the batch
+            if let Some(thread) = &self.thread {
+                thread.thread().unpark();
+            }
+        }
+        if batch.updates.len() >= self.min_batch {
+            batch.flush(&mut self.observer)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("MinBatchObserver({})::on_updates", self.id);
+        self.pending.extend(updates);
+        Ok(())
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("MinBatchObserver({})::on_abort", self.id);
+        self.pending.clear();
+        Ok(())
+    }
+
+    fn on_barrier(&mut self) -> Result<(), E> {
+        trace!("MinBatchObserver({})::on_barrier", self.id);
+        self.batch.lock().unwrap().flush(&mut self.observer)?;
+        self.observer.on_barrier()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("MinBatchObserver({})::on_completed", self.id);
+        self.batch.lock().unwrap().flush(&mut self.observer)?;
+        self.observer.on_completed()
+    }
+}
+
+impl<V, E> Drop for MinBatchObserver<V, E> {
+    fn drop(&mut self) {
+        trace!("MinBatchObserver({})::drop", self.id);
+        self.stopped.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
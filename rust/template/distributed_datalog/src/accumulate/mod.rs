@@ -1,15 +1,113 @@
+mod absent;
 mod accumulator;
+mod ack;
+mod bounded;
+mod builder;
+#[cfg(feature = "tokio")]
+mod channel;
+mod codec;
+mod concurrent;
+mod dedupe;
+mod delta;
+mod duplicate;
+mod enrich;
+mod error;
+mod eviction;
+mod filter;
+mod guard;
+mod history;
+mod latch;
+mod map;
+mod merging;
+mod min_batch;
+mod modify;
 mod observer;
+mod partitioned;
+mod periodic;
+mod protocol;
+mod rate_limit;
+mod recording;
+mod remap;
+mod replaying;
+mod retry;
+mod sampling;
+mod size;
+mod snapshot;
+mod state;
+mod stats;
+mod stream;
 #[cfg(any(test, feature = "test"))]
 mod test;
 mod txndistributor;
+mod union;
+mod wal;
 
+pub use absent::AbsentValueError;
 pub use accumulator::Accumulator;
 pub use accumulator::DistributingAccumulator;
+pub use accumulator::RelationRegistry;
+pub use ack::AckHandle;
+pub use ack::WouldBlock;
+pub use bounded::BoundedDistributingAccumulator;
+pub use bounded::OverflowPolicy;
+pub use builder::AccumulatorBuilder;
+#[cfg(feature = "tokio")]
+pub use channel::ChannelEvent;
+#[cfg(feature = "tokio")]
+pub use channel::ChannelObservable;
+#[cfg(feature = "tokio")]
+pub use channel::ChannelObserver;
+pub use codec::BincodeCodec;
+#[cfg(feature = "cbor")]
+pub use codec::CborCodec;
+pub use codec::JsonCodec;
+pub use codec::SnapshotCodec;
+pub use concurrent::ConcurrentInput;
+pub use dedupe::MergeDedupeObserver;
+pub use dedupe::MergeDedupePort;
+pub use duplicate::DuplicateError;
+pub use duplicate::DuplicatePolicy;
+pub use enrich::EnrichedUpdate;
+pub use enrich::EnrichingObservable;
+pub use error::AccumulatorError;
+pub use eviction::EvictionPolicy;
+pub use filter::FilteringObserver;
+pub use guard::SchemaGuardObserver;
+pub use guard::UnknownRelation;
+pub use latch::LatchObservable;
+pub use map::MapObservable;
+pub use merging::MergingAccumulator;
 pub use observer::AccumulatingObserver;
+pub use partitioned::PartitionedAccumulator;
+pub use periodic::HeartbeatTimer;
+pub use periodic::SnapshotTimer;
+pub use protocol::ProtocolPolicy;
+pub use protocol::ProtocolViolation;
+pub use rate_limit::RateLimitedObservable;
+pub use recording::replay;
+pub use recording::RecordedEvent;
+pub use recording::RecordingObserver;
+pub use remap::RelIdMapObservable;
+pub use remap::RelIdMapObserver;
+pub use replaying::ReplayingObservable;
+pub use retry::RetryPolicy;
+pub use size::ApproxSize;
+pub use snapshot::AccumulatorSnapshot;
+pub use state::StateHandle;
+pub use stats::RelStats;
+pub use stats::SubStats;
+pub use stream::StreamConfig;
 pub use txndistributor::TxnDistributor;
+pub use union::UnionObservable;
+pub use wal::recover;
+pub use wal::recover_with_codec;
+pub use wal::WalObserver;
 
 #[cfg(any(test, feature = "test"))]
 pub use test::eq_updates;
 #[cfg(any(test, feature = "test"))]
+pub use test::FaultyObserver;
+#[cfg(any(test, feature = "test"))]
+pub use test::ObserverCall;
+#[cfg(any(test, feature = "test"))]
 pub use test::UpdatesMockObserver;
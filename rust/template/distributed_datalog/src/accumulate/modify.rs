@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::hash::Hash;
+use std::mem::take;
+use std::sync::Arc;
+
+use log::trace;
+use uid::Id;
+
+use differential_datalog::program::RelId;
+use differential_datalog::program::Update;
+use differential_datalog::record::Mutator;
+
+use crate::Observer;
+
+/// A `Mutator` replacing a value with another one, as emitted by a
+/// `ModifyingObserver` for a value that changed.
+#[derive(Debug)]
+struct ReplaceValue<V>(V);
+
+impl<V> Display for ReplaceValue<V>
+where
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "ReplaceValue({:?})", self.0)
+    }
+}
+
+impl<V> Mutator<V> for ReplaceValue<V>
+where
+    V: Clone + Debug,
+{
+    fn mutate(&self, v: &mut V) -> Result<(), String> {
+        *v = self.0.clone();
+        Ok(())
+    }
+}
+
+/// An observer holding back the updates of each transaction until it
+/// is committed, in order to forward the deletion of a value followed
+/// by the insertion of a value with the same key as a single `Modify`
+/// update, e.g., for consumers presenting changes of values. The
+/// `Modify` update takes the place of the deletion and carries the key
+/// of the values along with a mutator replacing the old value with the
+/// new one. Updates of relations without a key function are forwarded
+/// unchanged.
+#[derive(Debug)]
+pub(crate) struct ModifyingObserver<O, V> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The observer we forward updates to.
+    observer: O,
+    /// The functions extracting the key of a value, per relation.
+    key_funcs: HashMap<RelId, fn(&V) -> V>,
+    /// The updates of the transaction in progress.
+    updates: Vec<Update<V>>,
+}
+
+impl<O, V> ModifyingObserver<O, V> {
+    /// Create a new `ModifyingObserver` forwarding updates to the given
+    /// observer, pairing up the updates of the relations with a key
+    /// function.
+    pub fn new(observer: O, key_funcs: HashMap<RelId, fn(&V) -> V>) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("ModifyingObserver({})::new", id);
+
+        Self {
+            id,
+            observer,
+            key_funcs,
+            updates: Vec::new(),
+        }
+    }
+}
+
+impl<O, V> ModifyingObserver<O, V>
+where
+    V: Clone + Debug + Eq + Hash + Send + Sync + 'static,
+{
+    /// Replace each deletion followed by an insertion of a value with
+    /// the same key by a `Modify` update.
+    fn pair_up(&self, updates: Vec<Update<V>>) -> Vec<Update<V>> {
+        let mut paired = Vec::with_capacity(updates.len());
+        // the position of the deletions not yet paired up, by key
+        let mut deletions = HashMap::<(RelId, V), usize>::new();
+
+        for update in updates {
+            match update {
+                Update::DeleteValue { relid, v } => {
+                    if let Some(key_func) = self.key_funcs.get(&relid) {
+                        let _ = deletions.insert((relid, key_func(&v)), paired.len());
+                    }
+                    paired.push(Some(Update::DeleteValue { relid, v }));
+                }
+                Update::Insert { relid, v } => {
+                    let deletion = self.key_funcs.get(&relid).and_then(|key_func| {
+                        let k = key_func(&v);
+                        deletions
+                            .remove(&(relid, k.clone()))
+                            .map(|index| (index, k))
+                    });
+                    match deletion {
+                        Some((index, k)) => {
+                            paired[index] = Some(Update::Modify {
+                                relid,
+                                k,
+                                m: Arc::new(ReplaceValue(v)),
+                            })
+                        }
+                        None => paired.push(Some(Update::Insert { relid, v })),
+                    }
+                }
+                update => paired.push(Some(update)),
+            }
+        }
+        paired.into_iter().flatten().collect()
+    }
+}
+
+impl<O, V, E> Observer<Update<V>, E> for ModifyingObserver<O, V>
+where
+    O: Observer<Update<V>, E>,
+    V: Clone + Debug + Eq + Hash + Send + Sync + 'static,
+    E: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("ModifyingObserver({})::on_start", self.id);
+        self.updates.clear();
+        self.observer.on_start()
+    }
+
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        trace!("ModifyingObserver({})::on_start_seq({})", self.id, seq);
+        self.updates.clear();
+        self.observer.on_start_seq(seq)
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("ModifyingObserver({})::on_commit", self.id);
+        let updates = take(&mut self.updates);
+        let updates = self.pair_up(updates);
+        if !updates.is_empty() {
+            self.observer.on_updates(Box::new(updates.into_iter()))?;
+        }
+        self.observer.on_commit()
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("ModifyingObserver({})::on_updates", self.id);
+        self.updates.extend(updates);
+        Ok(())
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("ModifyingObserver({})::on_abort", self.id);
+        self.updates.clear();
+        self.observer.on_abort()
+    }
+
+    fn on_barrier(&mut self) -> Result<(), E> {
+        trace!("ModifyingObserver({})::on_barrier", self.id);
+        self.observer.on_barrier()
+    }
+
+    fn on_init_complete(&mut self) -> Result<(), E> {
+        trace!("ModifyingObserver({})::on_init_complete", self.id);
+        self.observer.on_init_complete()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("ModifyingObserver({})::on_completed", self.id);
+        self.updates.clear();
+        self.observer.on_completed()
+    }
+}
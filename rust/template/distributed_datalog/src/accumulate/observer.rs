@@ -1,17 +1,40 @@
 use std::any::Any;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::collections::LinkedList;
 use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
 use std::hash::Hash;
 use std::iter::FromIterator;
+use std::mem::size_of;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
+use std::time::Duration;
+use std::time::Instant;
 
+use log::error;
 use log::trace;
+use log::warn;
 use uid::Id;
 
 use differential_datalog::program::RelId;
 use differential_datalog::program::Update;
 
+use crate::accumulate::ack::Sequence;
+use crate::accumulate::eviction::Recency;
+use crate::accumulate::AbsentValueError;
+use crate::accumulate::AccumulatorSnapshot;
+use crate::accumulate::ApproxSize;
+use crate::accumulate::DuplicateError;
+use crate::accumulate::DuplicatePolicy;
+use crate::accumulate::EvictionPolicy;
+use crate::accumulate::ProtocolPolicy;
+use crate::accumulate::ProtocolViolation;
+use crate::accumulate::RelStats;
+use crate::accumulate::StateHandle;
 use crate::Observable;
 use crate::ObservableBox;
 use crate::Observer;
@@ -19,28 +42,209 @@ use crate::ObserverBox;
 use crate::OptionalObserver;
 use crate::SharedObserver;
 
+/// A handler invoked with the relation and the value of every insertion
+/// rejected because the relation reached its maximum number of values.
+pub struct OverflowHandler<V>(Box<dyn FnMut(RelId, V) + Send>);
+
+impl<V> Debug for OverflowHandler<V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str("OverflowHandler")
+    }
+}
+
+/// A function telling whether two values have the same key, for
+/// identifying values by their key rather than in full.
+struct Identity<V>(Box<dyn Fn(&V, &V) -> bool + Send>);
+
+impl<V> Debug for Identity<V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str("Identity")
+    }
+}
+
+/// An observer forwarding all events to an `AccumulatingObserver`, as
+/// long as the latter is alive. It is subscribed to the upstream of the
+/// accumulator, which in turn keeps the upstream alive, and so it must
+/// not keep the accumulator alive.
+#[derive(Debug)]
+struct Inlet<O>(Weak<Mutex<O>>);
+
+impl<O, T, E> Observer<T, E> for Inlet<O>
+where
+    O: Observer<T, E>,
+    T: Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        match self.0.upgrade() {
+            Some(observer) => observer.lock().unwrap().on_start(),
+            None => Ok(()),
+        }
+    }
+
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        match self.0.upgrade() {
+            Some(observer) => observer.lock().unwrap().on_start_seq(seq),
+            None => Ok(()),
+        }
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        match self.0.upgrade() {
+            Some(observer) => observer.lock().unwrap().on_commit(),
+            None => Ok(()),
+        }
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        match self.0.upgrade() {
+            Some(observer) => observer.lock().unwrap().on_updates(updates),
+            None => Ok(()),
+        }
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        match self.0.upgrade() {
+            Some(observer) => observer.lock().unwrap().on_abort(),
+            None => Ok(()),
+        }
+    }
+
+    fn on_barrier(&mut self) -> Result<(), E> {
+        match self.0.upgrade() {
+            Some(observer) => observer.lock().unwrap().on_barrier(),
+            None => Ok(()),
+        }
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        match self.0.upgrade() {
+            Some(observer) => observer.lock().unwrap().on_completed(),
+            None => Ok(()),
+        }
+    }
+}
+
 /// Wrapper around a `SharedObserver` that inspects the updates to derive the current state.
-/// Apart from that it simply forwards all messages to the observer.
+/// Apart from that it simply forwards all messages to the observer, except for transactions
+/// without any updates, which are not forwarded at all.
 #[derive(Debug)]
 pub struct AccumulatingObserver<T, V, E>
 where
     V: Debug + Eq + Hash,
 {
     id: usize,
-    /// The observable we track and our subscription to it. TODO: use a better data structure
+    /// The observable we track and our subscription to it, if we were
+    /// connected via `reconnect_upstream`.
     subscription: Option<(ObservableBox<T, E>, Box<dyn Any + Send>)>,
     /// The observer we ultimately push our data to.
     observer: SharedObserver<OptionalObserver<ObserverBox<T, E>>>,
-    /// The data we accumulated so far.
-    data: HashMap<RelId, HashSet<V>>,
+    /// The data we accumulated so far, shared with all `StateHandle`s.
+    data: Arc<Mutex<HashMap<RelId, HashSet<V>>>>,
+    /// The multiplicity of each value we accumulated so far. Values
+    /// with a net weight of zero are not retained.
+    weights: HashMap<RelId, HashMap<V, isize>>,
+    /// The net multiplicity of each relation, i.e., the sum of the
+    /// positive weights of its values.
+    sizes: HashMap<RelId, usize>,
     /// Temporary buffer to cache the updates before committing.
     buffer: Option<LinkedList<Vec<T>>>,
+    /// Whether to hold back updates until the transaction is committed
+    /// and forward only their net effect.
+    coalesce: bool,
+    /// Whether to hold back updates until the transaction is committed
+    /// and forward them in a single batch.
+    atomic: bool,
+    /// Cumulative statistics about the updates we processed, per relation.
+    stats: HashMap<RelId, RelStats>,
+    /// The number of `on_completed` events we received.
+    completed_count: u64,
+    /// The number of transactions to merge into a single transaction
+    /// before forwarding its commit.
+    batch_every: usize,
+    /// The number of committed transactions in the batch we did not yet
+    /// forward the commit of.
+    batched: usize,
+    /// Whether we forwarded the start of a batch but not its commit. The
+    /// start is only forwarded along with the first update, so that
+    /// empty transactions do not reach the observer.
+    batch_open: bool,
+    /// The sequence numbers of the batches of transactions forwarded to
+    /// the observer.
+    sequence: Arc<Sequence>,
+    /// The sequence number of the batch we forwarded the start of, if
+    /// it is still open.
+    batch_seq: u64,
+    /// The maximum number of distinct values a single relation may
+    /// hold, if any.
+    max_values_per_relation: Option<usize>,
+    /// The handler to report rejected insertions to, if any.
+    overflow_handler: Option<OverflowHandler<V>>,
+    /// The way values are evicted to make room for insertions.
+    eviction_policy: EvictionPolicy,
+    /// The order in which the values of each relation were last
+    /// inserted, while values are evicted. The changes made by an
+    /// aborted transaction are rolled back.
+    recency: HashMap<RelId, Recency<V>>,
+    /// The way insertions of values that are already present are
+    /// treated.
+    duplicate_policy: DuplicatePolicy,
+    /// The conversion of a rejected duplicate insertion into our error
+    /// type, if such insertions are rejected.
+    duplicate_error: Option<fn(DuplicateError) -> E>,
+    /// The conversion of a rejected deletion of an absent value into
+    /// our error type, if such deletions are rejected.
+    absent_error: Option<fn(AbsentValueError) -> E>,
+    /// Whether to suppress forwarding insertions of values that are
+    /// already present and deletions of values that are absent.
+    suppress_redundant: bool,
+    /// The values of the suppressed insertions of the transaction in
+    /// progress, which still count towards the weights of the values.
+    suppressed: Vec<(RelId, V)>,
+    /// Whether the values touched by the transaction in progress are
+    /// present, taking its updates into account. Only maintained while
+    /// the number of values per relation is limited, duplicate
+    /// insertions are rejected, or redundant updates are suppressed.
+    pending_presence: HashMap<(RelId, V), bool>,
+    /// The number of values of the relations touched by the transaction
+    /// in progress, taking its updates into account.
+    pending_sizes: HashMap<RelId, usize>,
+    /// The functions extracting the key of a value, for relations
+    /// supporting updates by key.
+    key_funcs: HashMap<RelId, fn(&V) -> V>,
+    /// The comparison of the keys identifying the values of any
+    /// relation without a key function of its own, if values are
+    /// identified by their key rather than in full.
+    identity: Option<Identity<V>>,
+    /// The values of the relations updated by key that were touched by
+    /// the transaction in progress, taking its updates into account.
+    pending_relations: HashMap<RelId, HashSet<V>>,
+    /// The number of bytes the accumulated state and the updates of the
+    /// transaction in progress may occupy when accepting updates via
+    /// `try_on_updates`, if limited.
+    budget: Option<usize>,
+    /// The way events violating the transaction protocol are treated.
+    protocol_policy: ProtocolPolicy,
+    /// The conversion of a protocol violation into our error type, if
+    /// violations are reported as errors.
+    protocol_error: Option<fn(ProtocolViolation) -> E>,
+    /// The time after which values expire unless inserted again, if
+    /// any.
+    ttl: Option<Duration>,
+    /// The time each value we accumulated was last inserted at, while
+    /// values expire.
+    inserted_at: HashMap<(RelId, V), Instant>,
+    /// The updates that would have been forwarded to the observer, if
+    /// in dry-run mode.
+    dry_run: Option<Vec<T>>,
 }
 
 impl<T, V, E> AccumulatingObserver<T, V, E>
 where
     V: Clone + Debug + Eq + Hash + Send + 'static,
 {
+    /// Create a new `AccumulatingObserver` without any state and
+    /// without an observer.
     pub fn new() -> Self {
         let id = Id::<()>::new().get();
         trace!("AccumulatingObserver({})::new", id);
@@ -49,17 +253,505 @@ where
             id,
             subscription: None,
             observer: SharedObserver::default(),
-            data: HashMap::new(),
+            data: Arc::new(Mutex::new(HashMap::new())),
+            weights: HashMap::new(),
+            sizes: HashMap::new(),
             buffer: None,
+            coalesce: false,
+            atomic: false,
+            stats: HashMap::new(),
+            completed_count: 0,
+            batch_every: 1,
+            batched: 0,
+            batch_open: false,
+            sequence: Arc::new(Sequence::default()),
+            batch_seq: 0,
+            max_values_per_relation: None,
+            overflow_handler: None,
+            eviction_policy: EvictionPolicy::None,
+            recency: HashMap::new(),
+            duplicate_policy: DuplicatePolicy::Ignore,
+            duplicate_error: None,
+            absent_error: None,
+            suppress_redundant: false,
+            suppressed: Vec::new(),
+            pending_presence: HashMap::new(),
+            pending_sizes: HashMap::new(),
+            key_funcs: HashMap::new(),
+            identity: None,
+            pending_relations: HashMap::new(),
+            budget: None,
+            protocol_policy: ProtocolPolicy::Panic,
+            protocol_error: None,
+            ttl: None,
+            inserted_at: HashMap::new(),
+            dry_run: None,
+        }
+    }
+
+    /// Create a new `AccumulatingObserver` that holds back the updates
+    /// of a transaction until it is committed and then forwards only
+    /// their net effect, i.e., matching insertions and deletions of a
+    /// value cancel each other out.
+    pub fn new_coalescing() -> Self {
+        Self {
+            coalesce: true,
+            ..Self::new()
+        }
+    }
+
+    /// Create a new `AccumulatingObserver` identifying values by the key
+    /// `key_func` extracts from them, e.g., to ignore fields irrelevant
+    /// to the identity of values, such as timestamps. The full values
+    /// are still accumulated and forwarded, but inserting a value
+    /// replaces the value with the same key, if any, which is forwarded
+    /// as its deletion followed by the insertion, and deleting a value
+    /// deletes the value with the same key. The key function registered
+    /// for a relation via `key_func` takes precedence.
+    pub fn new_keyed<F, K>(key_func: F) -> Self
+    where
+        F: Fn(&V) -> K + Send + 'static,
+        K: Eq + Hash,
+    {
+        Self {
+            identity: Some(Identity(Box::new(move |x, y| key_func(x) == key_func(y)))),
+            ..Self::new()
+        }
+    }
+
+    /// Create a new `AccumulatingObserver` treating insertions of values
+    /// that are already present according to the given policy.
+    pub fn new_with_policy(policy: DuplicatePolicy) -> Self
+    where
+        E: From<DuplicateError>,
+    {
+        Self {
+            duplicate_policy: policy,
+            duplicate_error: Some(E::from),
+            ..Self::new()
+        }
+    }
+
+    /// Treat insertions of values that are already present according to
+    /// the given policy, converting rejected insertions into our error
+    /// type with `error`.
+    pub(crate) fn set_duplicate_policy(
+        &mut self,
+        policy: DuplicatePolicy,
+        error: fn(DuplicateError) -> E,
+    ) {
+        trace!(
+            "AccumulatingObserver({})::set_duplicate_policy({:?})",
+            self.id,
+            policy
+        );
+        self.duplicate_policy = policy;
+        self.duplicate_error = Some(error);
+    }
+
+    /// Retrieve the way insertions of values that are already present
+    /// are treated.
+    pub(crate) fn duplicate_policy(&self) -> DuplicatePolicy {
+        self.duplicate_policy
+    }
+
+    /// Reject deletions of values that are not part of the accumulated
+    /// state, taking the transaction in progress into account, instead
+    /// of ignoring them: they are neither accumulated nor forwarded and
+    /// `on_updates` reports an `AbsentValueError`. A `DeleteKey` update
+    /// is rejected if no value has the key.
+    pub fn strict_deletes(&mut self, strict: bool)
+    where
+        E: From<AbsentValueError>,
+    {
+        trace!(
+            "AccumulatingObserver({})::strict_deletes({})",
+            self.id,
+            strict
+        );
+        self.absent_error = if strict { Some(E::from) } else { None };
+    }
+
+    /// Reject deletions of values that are not part of the accumulated
+    /// state, as `strict_deletes` does, converting them into our error
+    /// type with `error`.
+    pub(crate) fn set_strict_deletes(&mut self, error: fn(AbsentValueError) -> E) {
+        trace!("AccumulatingObserver({})::set_strict_deletes", self.id);
+        self.absent_error = Some(error);
+    }
+
+    /// Create a new `AccumulatingObserver` accepting updates via
+    /// `try_on_updates` only as long as the accumulated state and the
+    /// updates of the transaction in progress occupy at most `bytes`
+    /// bytes, as estimated by `footprint`.
+    pub fn new_with_budget(bytes: usize) -> Self {
+        Self {
+            budget: Some(bytes),
+            ..Self::new()
         }
     }
 
+    /// Treat events violating the transaction protocol, e.g., an
+    /// `on_commit` without a preceding `on_start`, according to the
+    /// given policy instead of panicking, e.g., to protect observers
+    /// against a faulty upstream.
+    pub fn protocol_policy(&mut self, policy: ProtocolPolicy)
+    where
+        E: From<ProtocolViolation>,
+    {
+        trace!(
+            "AccumulatingObserver({})::protocol_policy({:?})",
+            self.id,
+            policy
+        );
+        self.protocol_policy = policy;
+        self.protocol_error = Some(E::from);
+    }
+
+    /// Hold back the updates of each transaction until it is committed
+    /// and then forward them in a single batch, so that the observer
+    /// receives exactly one `on_updates` per transaction containing any
+    /// updates.
+    pub fn atomic_transactions(&mut self, atomic: bool) {
+        trace!(
+            "AccumulatingObserver({})::atomic_transactions({})",
+            self.id,
+            atomic
+        );
+        self.atomic = atomic;
+    }
+
+    /// Merge every `transactions` transactions into a single one before
+    /// forwarding it. The accumulated state is updated as each of the
+    /// merged transactions is committed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `transactions` is zero.
+    pub fn batch_every(&mut self, transactions: usize) {
+        trace!(
+            "AccumulatingObserver({})::batch_every({})",
+            self.id,
+            transactions
+        );
+        assert!(transactions > 0, "batch size must be positive");
+        self.batch_every = transactions;
+    }
+
+    /// Limit the number of distinct values any single relation may
+    /// hold. Insertions that would exceed the limit are neither
+    /// accumulated nor forwarded, but reported to the handler registered
+    /// via `on_overflow`. Deletions free up space for further insertions.
+    pub fn max_values_per_relation(&mut self, max: Option<usize>) {
+        trace!(
+            "AccumulatingObserver({})::max_values_per_relation({:?})",
+            self.id,
+            max
+        );
+        self.max_values_per_relation = max;
+    }
+
+    /// Register a handler that is invoked with the relation and the
+    /// value of every insertion rejected because of the limit set via
+    /// `max_values_per_relation`.
+    pub fn on_overflow<F>(&mut self, handler: F)
+    where
+        F: FnMut(RelId, V) + Send + 'static,
+    {
+        trace!("AccumulatingObserver({})::on_overflow", self.id);
+        self.overflow_handler = Some(OverflowHandler(Box::new(handler)));
+    }
+
+    /// Make room for insertions into relations holding the number of
+    /// values the given policy allows for by evicting other values,
+    /// which is forwarded as their deletion.
+    pub fn eviction_policy(&mut self, policy: EvictionPolicy) {
+        trace!(
+            "AccumulatingObserver({})::eviction_policy({:?})",
+            self.id,
+            policy
+        );
+        self.eviction_policy = policy;
+        self.recency.clear();
+    }
+
+    /// Suppress forwarding updates that do not change the accumulated
+    /// state, i.e., insertions of values that are already present and
+    /// deletions of values that are absent, e.g., to reduce the traffic
+    /// caused by an idempotent upstream. Suppressed insertions still
+    /// count towards the weight of their value, suppressed deletions are
+    /// dropped and logged as a warning.
+    pub fn suppress_redundant(&mut self, suppress: bool) {
+        trace!(
+            "AccumulatingObserver({})::suppress_redundant({})",
+            self.id,
+            suppress
+        );
+        self.suppress_redundant = suppress;
+    }
+
+    /// Let values expire once they were not inserted again for the given
+    /// time, see `expire_now`. Values accumulated before are considered
+    /// inserted at the time they are next inserted.
+    pub fn ttl(&mut self, ttl: Option<Duration>) {
+        trace!("AccumulatingObserver({})::ttl({:?})", self.id, ttl);
+        self.ttl = ttl;
+        if ttl.is_none() {
+            self.inserted_at.clear();
+        }
+    }
+
+    /// Enable or disable the dry-run mode, in which updates are
+    /// accumulated as usual, but rather than being forwarded to the
+    /// observer, they are recorded for retrieval via `dry_run_updates`,
+    /// e.g., to estimate the traffic reaching the observer with the
+    /// options in effect. No events at all reach the observer in
+    /// dry-run mode, except for the commit of a batch of transactions
+    /// whose start was forwarded before. Disabling the mode discards
+    /// the recorded updates.
+    pub fn dry_run(&mut self, dry_run: bool) {
+        trace!("AccumulatingObserver({})::dry_run({})", self.id, dry_run);
+        match (dry_run, &self.dry_run) {
+            (true, None) => self.dry_run = Some(Vec::new()),
+            (false, _) => self.dry_run = None,
+            (true, Some(_)) => (),
+        }
+    }
+
+    /// Retrieve the updates that would have been forwarded to the
+    /// observer in dry-run mode so far.
+    pub fn dry_run_updates(&self) -> &[T] {
+        self.dry_run.as_deref().unwrap_or_default()
+    }
+
+    /// Register the function extracting the key of a value of the given
+    /// relation, resolving `DeleteKey`, `Modify`, and replacing
+    /// `InsertOrUpdate` updates for it by key. Without a key function,
+    /// every value is its own key.
+    pub fn key_func(&mut self, relid: RelId, key_func: fn(&V) -> V) {
+        trace!("AccumulatingObserver({})::key_func({})", self.id, relid);
+        let _ = self.key_funcs.insert(relid, key_func);
+    }
+
+    /// Return the current state of the data.
     pub fn get_current_state(&self) -> HashMap<RelId, HashSet<V>> {
         trace!("AccumulatingObserver({})::get_current_state()", self.id);
-        self.data.clone()
+        self.data.lock().unwrap().clone()
+    }
+
+    /// Check whether the given value is part of the accumulated state
+    /// of a relation.
+    pub fn contains(&self, relid: RelId, value: &V) -> bool {
+        trace!("AccumulatingObserver({})::contains({})", self.id, relid);
+        let data = self.data.lock().unwrap();
+        matches!(data.get(&relid), Some(vs) if vs.contains(value))
+    }
+
+    /// Estimate the number of bytes occupied by the accumulated state,
+    /// i.e., by the values of all relations along with their
+    /// multiplicities. The estimate does not account for the overhead
+    /// of the underlying hash tables.
+    pub fn estimated_memory_bytes(&self) -> usize
+    where
+        V: ApproxSize,
+    {
+        trace!(
+            "AccumulatingObserver({})::estimated_memory_bytes()",
+            self.id
+        );
+        let data = self
+            .data
+            .lock()
+            .unwrap()
+            .values()
+            .flatten()
+            .map(ApproxSize::approx_size)
+            .sum::<usize>();
+        let weights = self
+            .weights
+            .values()
+            .flatten()
+            .map(|(v, weight)| v.approx_size() + weight.approx_size())
+            .sum::<usize>();
+        data + weights
+    }
+
+    /// Invoke the given function on every accumulated value, along with
+    /// its relation, without copying the state. The state is locked
+    /// while `f` runs, blocking `StateHandle`s and the commit of
+    /// transactions until all values were visited.
+    pub fn for_each_value<F>(&self, mut f: F)
+    where
+        F: FnMut(RelId, &V),
+    {
+        trace!("AccumulatingObserver({})::for_each_value()", self.id);
+        let data = self.data.lock().unwrap();
+        for (relid, vs) in data.iter() {
+            for v in vs {
+                f(*relid, v)
+            }
+        }
+    }
+
+    /// Retrieve the net multiplicity of the values accumulated for a
+    /// relation.
+    pub fn relation_size(&self, relid: RelId) -> usize {
+        trace!(
+            "AccumulatingObserver({})::relation_size({})",
+            self.id,
+            relid
+        );
+        self.sizes.get(&relid).copied().unwrap_or(0)
+    }
+
+    /// Retrieve a read-only handle to the accumulated state.
+    pub fn state_handle(&self) -> StateHandle<V> {
+        trace!("AccumulatingObserver({})::state_handle()", self.id);
+        StateHandle::new(self.data.clone())
+    }
+
+    /// Retrieve statistics about the updates processed so far, per
+    /// relation. The counters are cumulative over the lifetime of the
+    /// observer, i.e., they are not reset by `on_completed`. Updates are
+    /// only counted once their transaction is committed, so aborted
+    /// transactions do not count.
+    pub fn stats(&self) -> HashMap<RelId, RelStats> {
+        trace!("AccumulatingObserver({})::stats()", self.id);
+        let mut stats = self.stats.clone();
+        for (relid, vs) in self.data.lock().unwrap().iter() {
+            stats.entry(*relid).or_default().current_size = vs.len();
+        }
+        stats
+    }
+
+    /// Retrieve the number of `on_completed` events received so far.
+    pub fn completed_count(&self) -> u64 {
+        self.completed_count
+    }
+
+    /// Remove the accumulated state, leaving the state empty, e.g., to
+    /// process it without copying it.
+    pub fn take_state(&mut self) -> HashMap<RelId, HashSet<V>> {
+        trace!("AccumulatingObserver({})::take_state()", self.id);
+        std::mem::take(&mut *self.data.lock().unwrap())
+    }
+
+    /// Return the current state of the data along with the net
+    /// multiplicity of each value.
+    pub fn get_current_state_weighted(&self) -> HashMap<RelId, HashMap<V, isize>> {
+        trace!(
+            "AccumulatingObserver({})::get_current_state_weighted()",
+            self.id
+        );
+        self.weights.clone()
+    }
+
+    /// Remove all values with a net weight of zero from the weighted
+    /// state, along with relations left without values, returning the
+    /// number of entries reclaimed. Such entries do not arise from
+    /// updates, as values are pruned once their weight drops to zero,
+    /// but may have been restored from a snapshot.
+    pub fn compact(&mut self) -> usize {
+        trace!("AccumulatingObserver({})::compact()", self.id);
+        let mut reclaimed = 0;
+        self.weights.retain(|_, vs| {
+            let len = vs.len();
+            vs.retain(|_, w| *w != 0);
+            reclaimed += len - vs.len();
+            if vs.is_empty() {
+                reclaimed += 1;
+            }
+            !vs.is_empty()
+        });
+        reclaimed
+    }
+}
+
+impl<T, V, E> Default for AccumulatingObserver<T, V, E>
+where
+    V: Clone + Debug + Eq + Hash + Send + 'static,
+{
+    fn default() -> Self {
+        Self::new()
     }
 }
 
+impl<T, V, E> AccumulatingObserver<T, V, E>
+where
+    V: Debug + Eq + Hash,
+{
+    /// Adjust the weight of a value by the given difference, pruning
+    /// it from the weighted state if it drops to zero.
+    fn adjust_weight(&mut self, relid: RelId, v: V, diff: isize) {
+        let weights = self.weights.entry(relid).or_default();
+        let (old, new) = match weights.entry(v) {
+            Entry::Occupied(mut entry) => {
+                let old = *entry.get();
+                *entry.get_mut() += diff;
+                if *entry.get() == 0 {
+                    let _ = entry.remove();
+                }
+                (old, old + diff)
+            }
+            Entry::Vacant(entry) => {
+                let _ = entry.insert(diff);
+                (0, diff)
+            }
+        };
+
+        let size = self.sizes.entry(relid).or_default();
+        *size = *size + new.max(0) as usize - old.max(0) as usize;
+        if *size == 0 {
+            let _ = self.sizes.remove(&relid);
+        }
+
+        if weights.is_empty() {
+            let _ = self.weights.remove(&relid);
+        }
+    }
+}
+
+/// Cancel out matching insertions and deletions of the same value,
+/// returning the net effect of the given updates. Values are reported
+/// in the order they were first encountered, as many times as their
+/// net multiplicity indicates.
+pub(crate) fn coalesce<V, I>(updates: I) -> Vec<Update<V>>
+where
+    V: Clone + Debug + Eq + Hash,
+    I: Iterator<Item = Update<V>>,
+{
+    let mut order = Vec::new();
+    let mut weights = HashMap::new();
+    for update in updates {
+        let (key, diff) = match update {
+            Update::Insert { relid, v } => ((relid, v), 1),
+            Update::DeleteValue { relid, v } => ((relid, v), -1),
+            update => panic!("Operation {:?} not allowed", update),
+        };
+        match weights.entry(key) {
+            Entry::Occupied(mut entry) => *entry.get_mut() += diff,
+            Entry::Vacant(entry) => {
+                order.push(entry.key().clone());
+                let _ = entry.insert(diff);
+            }
+        }
+    }
+
+    order
+        .into_iter()
+        .flat_map(|key| {
+            let weight: isize = weights[&key];
+            let (relid, v) = key;
+            let update = if weight > 0 {
+                Update::Insert { relid, v }
+            } else {
+                Update::DeleteValue { relid, v }
+            };
+            (0..weight.abs()).map(move |_| update.clone())
+        })
+        .collect()
+}
+
 impl<T, V, E> Observable<T, E> for AccumulatingObserver<T, V, E>
 where
     T: Debug + Send + 'static,
@@ -88,124 +780,972 @@ where
     }
 }
 
-/// Forwards the incoming data to the observer while keeping track of the current state
-impl<V, E> Observer<Update<V>, E> for AccumulatingObserver<Update<V>, V, E>
+impl<V, E> AccumulatingObserver<Update<V>, V, E>
 where
-    V: Debug + Send + Eq + Hash + Clone,
-    E: Debug + Send,
+    V: Clone + Debug + Eq + Hash,
 {
-    fn on_start(&mut self) -> Result<(), E> {
-        trace!("AccumulatingObserver({})::on_start", self.id);
-
-        if self.buffer.is_some() {
-            panic!("received multiple on_start events")
-        } else {
-            self.buffer = Some(LinkedList::new());
-            let mut guard = self.observer.lock().unwrap();
-            guard.on_start()
+    /// Take a snapshot of the accumulated state, including the updates
+    /// of the transaction in progress, if any.
+    pub fn snapshot(&self) -> AccumulatorSnapshot<V> {
+        trace!("AccumulatingObserver({})::snapshot()", self.id);
+        AccumulatorSnapshot {
+            data: self.data.lock().unwrap().clone(),
+            weights: self.weights.clone(),
+            buffer: self
+                .buffer
+                .as_ref()
+                .map(|buffer| buffer.iter().flatten().cloned().collect()),
         }
     }
 
-    fn on_commit(&mut self) -> Result<(), E> {
-        trace!("AccumulatingObserver({})::on_commit", self.id);
+    /// Translate an update into insertions and deletions of values, as
+    /// the accumulated state is a set of values per relation. Updates by
+    /// key are resolved against the state including the updates of the
+    /// transaction in progress:
+    /// - `InsertOrUpdate` deletes the values with the same key, if any,
+    ///   and inserts the new value; without a key function it is a plain
+    ///   insertion
+    /// - `DeleteKey` deletes the values with the given key; if values
+    ///   are identified by their key, the key is that of the value given
+    /// - `Modify` deletes the value with the given key and inserts its
+    ///   modified version; modifications failing or not matching any
+    ///   value are dropped
+    ///
+    /// Relations without a key function fall back to treating every
+    /// value as its own key, i.e., `DeleteKey` deletes the value equal
+    /// to the key and `Modify` modifies it.
+    ///
+    /// If values are identified by their key, `Insert` and `DeleteValue`
+    /// are treated like `InsertOrUpdate` and `DeleteKey`, respectively.
+    ///
+    /// Note that values are deleted with a single `DeleteValue`, i.e.,
+    /// values with a multiplicity above one are retained in the weighted
+    /// state.
+    fn translate(&mut self, update: Update<V>) -> Vec<Update<V>> {
+        let relid = update.relid();
+        let key_func = self.key_funcs.get(&relid).copied();
+        if key_func.is_none() && self.identity.is_none() {
+            match update {
+                // resolved below, with every value being its own key
+                Update::DeleteKey { .. } | Update::Modify { .. } => (),
+                Update::InsertOrUpdate { relid, v } => return vec![Update::Insert { relid, v }],
+                update => return vec![update],
+            }
+        }
 
-        if let Some(buffer) = self.buffer.take() {
-            // forward commit signal to observer
-            {
-                let mut guard = self.observer.lock().unwrap();
-                guard.on_commit()?;
+        let identity = &self.identity;
+        let same_key = |x: &V, y: &V| match (key_func, identity) {
+            (Some(key_func), _) => key_func(x) == key_func(y),
+            (None, Some(identity)) => (identity.0)(x, y),
+            (None, None) => x == y,
+        };
+        let has_key = |x: &V, k: &V| match key_func {
+            Some(key_func) => key_func(x) == *k,
+            None => same_key(x, k),
+        };
+
+        // with values identified by their key, insertions and deletions
+        // are updates by key
+        let update = match update {
+            Update::Insert { relid, v } if self.identity.is_some() => {
+                Update::InsertOrUpdate { relid, v }
             }
-            // apply the buffered updates to the accumulated state if successful
-            buffer
-                .into_iter()
-                .flatten()
-                .for_each(|upd: Update<V>| match upd {
-                    Update::Insert { relid, v } => {
-                        let _ = self
-                            .data
-                            .entry(relid)
-                            .and_modify(|set| {
-                                let _ = set.insert(v.clone());
-                            })
-                            .or_insert_with(|| HashSet::from_iter(vec![v.clone()].into_iter()));
-                    }
-                    Update::DeleteValue { relid, v } => {
-                        let _ = self.data.entry(relid).and_modify(|set| {
-                            let _ = set.remove(&v);
-                        });
+            Update::DeleteValue { relid, v } if self.identity.is_some() => Update::DeleteKey {
+                relid,
+                k: match key_func {
+                    Some(key_func) => key_func(&v),
+                    None => v,
+                },
+            },
+            update => update,
+        };
+
+        let data = &self.data;
+        let values = self.pending_relations.entry(relid).or_insert_with(|| {
+            data.lock()
+                .unwrap()
+                .get(&relid)
+                .cloned()
+                .unwrap_or_default()
+        });
+
+        match update {
+            Update::InsertOrUpdate { relid, v } => {
+                let mut updates = values
+                    .iter()
+                    .filter(|x| **x != v && same_key(x, &v))
+                    .map(|x| Update::DeleteValue {
+                        relid,
+                        v: x.clone(),
+                    })
+                    .collect::<Vec<_>>();
+                if !values.contains(&v) {
+                    updates.push(Update::Insert { relid, v });
+                }
+                updates
+            }
+            Update::DeleteKey { relid, k } => values
+                .iter()
+                .filter(|x| has_key(x, &k))
+                .map(|x| Update::DeleteValue {
+                    relid,
+                    v: x.clone(),
+                })
+                .collect(),
+            Update::Modify { relid, k, m } => match values.iter().find(|x| has_key(x, &k)) {
+                Some(old) => {
+                    let mut new = old.clone();
+                    match m.mutate(&mut new) {
+                        Ok(()) if new != *old => vec![
+                            Update::DeleteValue {
+                                relid,
+                                v: old.clone(),
+                            },
+                            Update::Insert { relid, v: new },
+                        ],
+                        Ok(()) => Vec::new(),
+                        Err(e) => {
+                            error!(
+                                "AccumulatingObserver({}): failed to modify value of relation {}: {}",
+                                self.id, relid, e
+                            );
+                            Vec::new()
+                        }
                     }
-                    update => panic!("Operation {:?} not allowed", update),
-                });
+                }
+                None => Vec::new(),
+            },
+            update => vec![update],
+        }
+    }
 
-            Ok(())
-        } else {
-            panic!("on_commit was not preceded by an on_start event")
+    /// Apply the given updates of the transaction in progress to the
+    /// values of the relations updated by key.
+    fn project(&mut self, updates: &[Update<V>]) {
+        for update in updates {
+            if let Some(values) = self.pending_relations.get_mut(&update.relid()) {
+                match update {
+                    Update::Insert { v, .. } => {
+                        let _ = values.insert(v.clone());
+                    }
+                    Update::DeleteValue { v, .. } => {
+                        let _ = values.remove(v);
+                    }
+                    _ => (),
+                }
+            }
         }
     }
 
-    fn on_updates<'a>(
+    /// Remove the insertions exceeding the maximum number of values per
+    /// relation, if any, from the given updates of the transaction in
+    /// progress, reporting them to the overflow handler. Insertions of
+    /// values that are already present are removed as well if they are
+    /// to be rejected, recording the first of them in `duplicate`, as
+    /// are deletions of absent values, recording the first of them in
+    /// `absent`, and so are redundant updates if they are to be
+    /// suppressed.
+    fn admit(
         &mut self,
-        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
-    ) -> Result<(), E> {
-        trace!("AccumulatingObserver({})::on_updates", self.id);
+        updates: Vec<Update<V>>,
+        duplicate: &mut Option<DuplicateError>,
+        absent: &mut Option<AbsentValueError>,
+    ) -> Vec<Update<V>> {
+        let reject_duplicates = self.duplicate_policy == DuplicatePolicy::Error;
+        let reject_absent = self.absent_error.is_some();
+        if self.max_values_per_relation.is_none()
+            && self.eviction_policy == EvictionPolicy::None
+            && !reject_duplicates
+            && !reject_absent
+            && !self.suppress_redundant
+        {
+            return updates;
+        }
 
-        if let Some(ref mut buffer) = self.buffer {
-            // push incoming updates into buffer
-            let upds = updates.collect::<Vec<_>>();
-            buffer.push_back(upds.clone());
+        let data = self.data.clone();
+        let data = data.lock().unwrap();
+        let mut admitted = Vec::with_capacity(updates.len());
+        for update in updates {
+            let (relid, v, insert) = match &update {
+                Update::Insert { relid, v } => (*relid, v, true),
+                Update::DeleteValue { relid, v } => (*relid, v, false),
+                _ => {
+                    admitted.push(update);
+                    continue;
+                }
+            };
 
-            // send updates to observer
-            let mut guard = self.observer.lock().unwrap();
-            guard.on_updates(Box::new(upds.into_iter()))
-        } else {
-            panic!("on_updates was not preceded by an on_start event")
+            let committed = data.get(&relid);
+            if let (EvictionPolicy::Lru(cap), true) = (self.eviction_policy, insert) {
+                if let Some(victim) = self.evict(relid, v, cap, committed) {
+                    trace!(
+                        "AccumulatingObserver({}) evicting value from relation {}",
+                        self.id,
+                        relid
+                    );
+                    admitted.push(Update::DeleteValue { relid, v: victim });
+                }
+            }
+            let present = self
+                .pending_presence
+                .entry((relid, v.clone()))
+                .or_insert_with(|| matches!(committed, Some(vs) if vs.contains(v)));
+            let size = self
+                .pending_sizes
+                .entry(relid)
+                .or_insert_with(|| committed.map_or(0, HashSet::len));
+
+            if insert && *present && reject_duplicates {
+                trace!(
+                    "AccumulatingObserver({}) rejecting duplicate insertion into relation {}",
+                    self.id,
+                    relid
+                );
+                if duplicate.is_none() {
+                    *duplicate = Some(DuplicateError {
+                        relid,
+                        value: format!("{:?}", v),
+                    });
+                }
+                continue;
+            }
+            if !insert && !*present && reject_absent {
+                trace!(
+                    "AccumulatingObserver({}) rejecting deletion of absent value from relation {}",
+                    self.id,
+                    relid
+                );
+                if absent.is_none() {
+                    *absent = Some(AbsentValueError {
+                        relid,
+                        value: format!("{:?}", v),
+                    });
+                }
+                continue;
+            }
+            if insert == *present && self.suppress_redundant {
+                if insert {
+                    trace!(
+                        "AccumulatingObserver({}) suppressing redundant insertion into relation {}",
+                        self.id,
+                        relid
+                    );
+                    self.suppressed.push((relid, v.clone()));
+                } else {
+                    warn!(
+                        "AccumulatingObserver({}) ignoring deletion of absent value {:?} from relation {}",
+                        self.id,
+                        v,
+                        relid
+                    );
+                }
+                continue;
+            }
+            if insert && !*present {
+                if matches!(self.max_values_per_relation, Some(max) if *size >= max) {
+                    trace!(
+                        "AccumulatingObserver({}) rejecting insertion into relation {}",
+                        self.id,
+                        relid
+                    );
+                    if let Some(handler) = &mut self.overflow_handler {
+                        (handler.0)(relid, v.clone());
+                    }
+                    continue;
+                }
+                *present = true;
+                *size += 1;
+            } else if !insert && *present {
+                *present = false;
+                *size -= 1;
+            }
+            if self.eviction_policy != EvictionPolicy::None {
+                let recency = self.recency.entry(relid).or_default();
+                if insert {
+                    recency.touch(v);
+                } else {
+                    recency.remove(v);
+                }
+            }
+            admitted.push(update);
         }
+        admitted
     }
 
-    /// signals that the source has been removed, clears the accumulated state.
-    fn on_completed(&mut self) -> Result<(), E> {
-        trace!("AccumulatingObserver({})::on_completed", self.id);
-        let _ = self.buffer.take();
-        let _ = self.data.drain();
-        Ok(())
+    /// Check whether inserting the given value into a relation holding
+    /// `cap` values requires evicting another value and if so, mark the
+    /// least recently inserted one absent and return it. Values that
+    /// were never inserted while evicting, e.g., because they were
+    /// restored from a snapshot, are evicted once no other values
+    /// remain.
+    fn evict(
+        &mut self,
+        relid: RelId,
+        v: &V,
+        cap: usize,
+        committed: Option<&HashSet<V>>,
+    ) -> Option<V> {
+        let pending_presence = &mut self.pending_presence;
+        let mut present = |v: &V| {
+            *pending_presence
+                .entry((relid, v.clone()))
+                .or_insert_with(|| matches!(committed, Some(vs) if vs.contains(v)))
+        };
+        let size = self
+            .pending_sizes
+            .entry(relid)
+            .or_insert_with(|| committed.map_or(0, HashSet::len));
+        if present(v) || *size < cap {
+            return None;
+        }
+
+        let recency = self.recency.entry(relid).or_default();
+        let victim = loop {
+            match recency.pop_oldest() {
+                Some(victim) if victim != *v && present(&victim) => break Some(victim),
+                Some(_) => continue,
+                None => {
+                    break committed
+                        .into_iter()
+                        .flatten()
+                        .find(|victim| *victim != v && present(victim))
+                        .cloned()
+                }
+            }
+        }?;
+        let _ = self.pending_presence.insert((relid, victim.clone()), false);
+        *size -= 1;
+        Some(victim)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Discard the bookkeeping of the transaction in progress, undoing
+    /// the changes it made to the order in which values are evicted.
+    fn discard_pending(&mut self) {
+        self.pending_presence.clear();
+        self.pending_sizes.clear();
+        self.pending_relations.clear();
+        self.suppressed.clear();
+        for recency in self.recency.values_mut() {
+            recency.rollback();
+        }
+    }
 
-    use std::sync::Arc;
-    use std::sync::Mutex;
-    use std::vec::IntoIter;
+    /// Check whether the updates of a transaction are held back until it
+    /// is committed.
+    fn holds_back(&self) -> bool {
+        self.coalesce || self.atomic
+    }
 
-    use crate::MockObserver;
+    /// Check whether a transaction is in progress.
+    pub fn in_transaction(&self) -> bool {
+        self.buffer.is_some()
+    }
 
-    fn get_usize_insert_updates_1() -> Box<IntoIter<Update<usize>>> {
-        Box::new(
-            vec![
-                Update::Insert { relid: 1, v: 1 },
-                Update::Insert { relid: 2, v: 2 },
-                Update::Insert { relid: 3, v: 3 },
-            ]
-            .into_iter(),
-        )
+    /// Check whether the accumulated state is poisoned, i.e., a thread
+    /// panicked while holding its lock.
+    pub(crate) fn is_poisoned(&self) -> bool {
+        self.data.is_poisoned()
     }
 
-    fn get_usize_insert_updates_2() -> Box<IntoIter<Update<usize>>> {
-        Box::new(
-            vec![
-                Update::Insert { relid: 1, v: 2 },
-                Update::Insert { relid: 1, v: 3 },
-                Update::Insert { relid: 2, v: 3 },
-            ]
-            .into_iter(),
-        )
+    /// Treat an event violating the transaction protocol according to
+    /// the configured policy. The event is to be dropped unless we
+    /// panic.
+    fn protocol_violation(&self, violation: ProtocolViolation) -> Result<(), E> {
+        match (self.protocol_policy, self.protocol_error) {
+            (ProtocolPolicy::Error, Some(convert)) => Err(convert(violation)),
+            (ProtocolPolicy::Ignore, _) => {
+                warn!(
+                    "AccumulatingObserver({}) ignoring event: {}",
+                    self.id, violation
+                );
+                Ok(())
+            }
+            _ => panic!("{}", violation),
+        }
     }
 
-    fn get_usize_insert_updates_3() -> Box<IntoIter<Update<usize>>> {
-        Box::new(
-            vec![
+    /// Forward the start of a batch of transactions, unless we did so
+    /// already or are in dry-run mode.
+    fn start_batch(&mut self) -> Result<(), E>
+    where
+        V: Send,
+        E: Debug + Send,
+    {
+        if self.batch_open || self.dry_run.is_some() {
+            return Ok(());
+        }
+
+        self.batch_open = true;
+        self.batch_seq = self.sequence.start();
+        let mut guard = self.observer.lock().unwrap();
+        guard.on_start_seq(self.batch_seq)
+    }
+
+    /// Forward the commit of the current batch of transactions, which
+    /// only counts as forwarded if the observer accepted it.
+    fn commit_batch(&mut self) -> Result<(), E>
+    where
+        V: Send,
+        E: Debug + Send,
+    {
+        self.batch_open = false;
+        self.batched = 0;
+        self.observer.lock().unwrap().on_commit()?;
+        self.sequence.commit(self.batch_seq);
+        Ok(())
+    }
+
+    /// Retrieve the sequence numbers of the batches of transactions
+    /// forwarded to the observer.
+    pub(crate) fn sequence(&self) -> Arc<Sequence> {
+        self.sequence.clone()
+    }
+
+    /// Forward the given updates to the observer, starting a batch of
+    /// transactions if necessary, or record them in dry-run mode.
+    fn forward(&mut self, updates: Vec<Update<V>>) -> Result<(), E>
+    where
+        V: Send,
+        E: Debug + Send,
+    {
+        if let Some(recorded) = &mut self.dry_run {
+            recorded.extend(updates);
+            return Ok(());
+        }
+
+        self.start_batch()?;
+        let mut guard = self.observer.lock().unwrap();
+        guard.on_updates(Box::new(updates.into_iter()))
+    }
+
+    /// Revert the given updates of a transaction that were forwarded to
+    /// the observer by forwarding their inverse.
+    fn revert(&mut self, buffer: LinkedList<Vec<Update<V>>>) -> Result<(), E>
+    where
+        V: Send,
+        E: Debug + Send,
+    {
+        let inverse = buffer
+            .into_iter()
+            .flatten()
+            .rev()
+            .map(|update| match update {
+                Update::Insert { relid, v } => Update::DeleteValue { relid, v },
+                Update::DeleteValue { relid, v } => Update::Insert { relid, v },
+                update => panic!("Operation {:?} not allowed", update),
+            })
+            .collect::<Vec<_>>();
+        if inverse.is_empty() {
+            return Ok(());
+        }
+        self.forward(inverse)
+    }
+
+    /// Abort the transaction in progress, if any, reverting the updates
+    /// of it that were already forwarded, and forward the commit of the
+    /// current batch of transactions, if any.
+    pub(crate) fn abort_transaction(&mut self) -> Result<(), E>
+    where
+        V: Send,
+        E: Debug + Send,
+    {
+        if let Some(buffer) = self.buffer.take() {
+            trace!("AccumulatingObserver({}) aborting transaction", self.id);
+            self.discard_pending();
+            if !self.holds_back() {
+                self.revert(buffer)?;
+            }
+        }
+        self.flush()
+    }
+
+    /// Forward the commit of the current batch of transactions, if
+    /// any, without waiting for it to fill up. Has no effect while a
+    /// transaction is in progress.
+    pub fn flush(&mut self) -> Result<(), E>
+    where
+        V: Send,
+        E: Debug + Send,
+    {
+        trace!("AccumulatingObserver({})::flush", self.id);
+        if !self.batch_open || self.buffer.is_some() {
+            return Ok(());
+        }
+        self.commit_batch()
+    }
+
+    /// Retrieve the number of updates of the transaction in progress,
+    /// if any.
+    pub fn transaction_size(&self) -> usize {
+        self.buffer
+            .as_ref()
+            .map_or(0, |buffer| buffer.iter().map(Vec::len).sum())
+    }
+
+    /// Estimate the number of bytes occupied by the accumulated state
+    /// and the updates of the transaction in progress, counting the
+    /// size of a value for each of them.
+    pub fn footprint(&self) -> usize {
+        let values = self
+            .data
+            .lock()
+            .unwrap()
+            .values()
+            .map(HashSet::len)
+            .sum::<usize>();
+        (values + self.transaction_size()) * size_of::<V>()
+    }
+
+    /// Retrieve the number of updates of the transaction in progress
+    /// that were forwarded to the observer so far, if any.
+    pub fn forwarded_count(&self) -> usize {
+        match &self.buffer {
+            Some(buffer) if !self.holds_back() => buffer.iter().map(Vec::len).sum(),
+            _ => 0,
+        }
+    }
+
+    /// Retrieve the updates of the transaction in progress that were
+    /// forwarded to the observer so far, skipping the first `skip`
+    /// ones.
+    pub fn forwarded_updates(&self, skip: usize) -> Vec<Update<V>> {
+        match &self.buffer {
+            Some(buffer) if !self.holds_back() => {
+                buffer.iter().flatten().skip(skip).cloned().collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Replace the accumulated state with the one captured in the given
+    /// snapshot. Observers are not notified about the change.
+    pub fn restore(&mut self, snapshot: AccumulatorSnapshot<V>) {
+        trace!("AccumulatingObserver({})::restore()", self.id);
+        let AccumulatorSnapshot {
+            data,
+            weights,
+            buffer,
+        } = snapshot;
+
+        let now = Instant::now();
+        self.inserted_at = match self.ttl {
+            Some(_) => data
+                .iter()
+                .flat_map(|(relid, vs)| vs.iter().map(move |v| ((*relid, v.clone()), now)))
+                .collect(),
+            None => HashMap::new(),
+        };
+        *self.data.lock().unwrap() = data;
+        self.recency.clear();
+        self.pending_presence.clear();
+        self.pending_sizes.clear();
+        self.pending_relations.clear();
+        self.suppressed.clear();
+        self.sizes = weights
+            .iter()
+            .map(|(relid, vs)| {
+                (
+                    *relid,
+                    vs.values().map(|w| w.max(&0)).sum::<isize>() as usize,
+                )
+            })
+            .filter(|(_, size)| *size > 0)
+            .collect();
+        self.weights = weights;
+        self.buffer = buffer.map(|updates| {
+            let mut buffer = LinkedList::new();
+            buffer.push_back(updates);
+            buffer
+        });
+    }
+}
+
+impl<V, E> AccumulatingObserver<Update<V>, V, E>
+where
+    V: Clone + Debug + Eq + Hash + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    /// Subscribe the given accumulator to a new upstream, after
+    /// unsubscribing it from the upstream it was connected to via this
+    /// function before, if any, e.g., to fail over to another source.
+    ///
+    /// The accumulated state is preserved and updates of the new
+    /// upstream are accumulated on top of it. A transaction of the
+    /// previous upstream still in progress is aborted: updates of it
+    /// that were already forwarded are reverted by forwarding their
+    /// inverse, so that observers stay consistent with the accumulated
+    /// state.
+    ///
+    /// This function operates on the shared accumulator, because the
+    /// new upstream may emit events while being subscribed to, which
+    /// requires access to the accumulator. On failure to subscribe, the
+    /// new upstream is handed back and the accumulator is left without
+    /// an upstream.
+    pub fn reconnect_upstream(
+        this: &SharedObserver<Self>,
+        mut upstream: ObservableBox<Update<V>, E>,
+    ) -> Result<(), ObservableBox<Update<V>, E>> {
+        let previous = {
+            let mut accumulator = this.lock().unwrap();
+            trace!(
+                "AccumulatingObserver({})::reconnect_upstream",
+                accumulator.id
+            );
+            if let Err(e) = accumulator.abort_transaction() {
+                trace!(
+                    "AccumulatingObserver({}) failed to abort transaction: {:?}",
+                    accumulator.id,
+                    e
+                );
+            }
+            accumulator.subscription.take()
+        };
+
+        if let Some((mut observable, subscription)) = previous {
+            let _ = observable.unsubscribe_any(subscription.as_ref());
+        }
+
+        // Subscribe without holding the lock, as the upstream may emit
+        // events right away, e.g., to replay its state.
+        let inlet = Inlet(Arc::downgrade(this));
+        match upstream.subscribe_any(Box::new(inlet)) {
+            Ok(subscription) => {
+                this.lock().unwrap().subscription = Some((upstream, subscription));
+                Ok(())
+            }
+            Err(_) => Err(upstream),
+        }
+    }
+
+    /// Process the given updates like `on_updates`, but only as many of
+    /// them as fit into the budget set via `new_with_budget`, and
+    /// retrieve their number. Updates are taken from the front of the
+    /// iterator while the footprint stays within the budget, each
+    /// counting the size of a value; the remaining ones are dropped, so
+    /// the caller has to retain them to retry them later, e.g., after
+    /// the transaction was committed and memory was freed downstream.
+    /// Without a budget, all updates are accepted.
+    pub fn try_on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<usize, E> {
+        trace!("AccumulatingObserver({})::try_on_updates", self.id);
+
+        let accepted = match self.budget {
+            Some(budget) => {
+                let available = budget.saturating_sub(self.footprint());
+                updates
+                    .take(available / size_of::<V>().max(1))
+                    .collect::<Vec<_>>()
+            }
+            None => updates.collect(),
+        };
+        let count = accepted.len();
+        self.on_updates(Box::new(accepted.into_iter()))?;
+        Ok(count)
+    }
+
+    /// Delete the values that were last inserted longer than the time
+    /// set via `ttl` before `now`, forwarding the deletions to the
+    /// observer as a transaction of their own. Values inserted multiple
+    /// times under `DuplicatePolicy::CountWeight` are deleted as often.
+    /// Has no effect while a transaction is in progress.
+    pub fn expire_now(&mut self, now: Instant) -> Result<(), E> {
+        trace!("AccumulatingObserver({})::expire_now", self.id);
+        let ttl = match self.ttl {
+            Some(ttl) if !self.in_transaction() => ttl,
+            _ => return Ok(()),
+        };
+
+        let expired = self
+            .inserted_at
+            .iter()
+            .filter(|(_, inserted_at)| now.saturating_duration_since(**inserted_at) >= ttl)
+            .map(|(value, _)| value.clone())
+            .collect::<Vec<_>>();
+        if expired.is_empty() {
+            return Ok(());
+        }
+
+        let mut deletes = Vec::new();
+        for (relid, v) in expired {
+            let count = match self.duplicate_policy {
+                DuplicatePolicy::CountWeight => self
+                    .weights
+                    .get(&relid)
+                    .and_then(|ws| ws.get(&v))
+                    .map_or(1, |weight| (*weight).max(1) as usize),
+                _ => 1,
+            };
+            deletes.extend((0..count).map(|_| Update::DeleteValue {
+                relid,
+                v: v.clone(),
+            }));
+        }
+        self.on_start()?;
+        self.on_updates(Box::new(deletes.into_iter()))?;
+        self.on_commit()
+    }
+}
+
+/// Forwards the incoming data to the observer while keeping track of the current state
+impl<V, E> Observer<Update<V>, E> for AccumulatingObserver<Update<V>, V, E>
+where
+    V: Debug + Send + Eq + Hash + Clone,
+    E: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("AccumulatingObserver({})::on_start", self.id);
+
+        if self.buffer.is_some() {
+            self.protocol_violation(ProtocolViolation::StartInTransaction)
+        } else {
+            self.buffer = Some(LinkedList::new());
+            self.pending_presence.clear();
+            self.pending_sizes.clear();
+            self.pending_relations.clear();
+            self.suppressed.clear();
+            // the start is forwarded along with the first update
+            Ok(())
+        }
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("AccumulatingObserver({})::on_commit", self.id);
+
+        if let Some(buffer) = self.buffer.take() {
+            let now = Instant::now();
+            // the updates only count once the transaction is committed
+            for upd in buffer.iter().flatten() {
+                match upd {
+                    Update::Insert { relid, .. } => {
+                        self.stats.entry(*relid).or_default().inserts += 1
+                    }
+                    Update::DeleteValue { relid, .. } => {
+                        self.stats.entry(*relid).or_default().deletes += 1
+                    }
+                    _ => (),
+                }
+            }
+            for recency in self.recency.values_mut() {
+                recency.commit();
+            }
+            let updates: Box<dyn Iterator<Item = Update<V>> + '_> = if self.holds_back() {
+                let updates = if self.coalesce {
+                    // forward only the net effect of the transaction
+                    coalesce(buffer.into_iter().flatten())
+                } else {
+                    buffer.into_iter().flatten().collect()
+                };
+                if !updates.is_empty() {
+                    self.forward(updates.clone())?;
+                }
+                Box::new(updates.into_iter())
+            } else {
+                Box::new(buffer.into_iter().flatten())
+            };
+
+            // forward commit signal to observer once the batch is full,
+            // unless no updates were forwarded at all
+            if self.batch_open {
+                self.batched += 1;
+                if self.batched >= self.batch_every {
+                    self.commit_batch()?;
+                }
+            }
+            // apply the buffered updates to the accumulated state if successful
+            let data = self.data.clone();
+            let mut data = data.lock().unwrap();
+            updates.for_each(|upd: Update<V>| match upd {
+                Update::Insert { relid, v } => {
+                    self.adjust_weight(relid, v.clone(), 1);
+                    if self.ttl.is_some() {
+                        let _ = self.inserted_at.insert((relid, v.clone()), now);
+                    }
+                    let _ = data
+                        .entry(relid)
+                        .and_modify(|set| {
+                            let _ = set.insert(v.clone());
+                        })
+                        .or_insert_with(|| HashSet::from_iter(vec![v.clone()]));
+                }
+                Update::DeleteValue { relid, v } => {
+                    self.adjust_weight(relid, v.clone(), -1);
+                    // with weights counted, the value is only removed
+                    // once it was deleted as often as it was inserted
+                    if self.duplicate_policy != DuplicatePolicy::CountWeight
+                        || !matches!(
+                            self.weights.get(&relid).and_then(|ws| ws.get(&v)),
+                            Some(weight) if *weight > 0
+                        )
+                    {
+                        let _ = self.inserted_at.remove(&(relid, v.clone()));
+                        let _ = data.entry(relid).and_modify(|set| {
+                            let _ = set.remove(&v);
+                        });
+                    }
+                }
+                update => panic!("Operation {:?} not allowed", update),
+            });
+            // suppressed insertions only count towards the weights
+            for (relid, v) in std::mem::take(&mut self.suppressed) {
+                if self.ttl.is_some() {
+                    let _ = self.inserted_at.insert((relid, v.clone()), now);
+                }
+                self.adjust_weight(relid, v, 1);
+            }
+
+            Ok(())
+        } else {
+            self.protocol_violation(ProtocolViolation::CommitWithoutStart)
+        }
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("AccumulatingObserver({})::on_updates", self.id);
+
+        if self.buffer.is_none() {
+            return self.protocol_violation(ProtocolViolation::UpdatesWithoutStart);
+        }
+
+        let mut upds = Vec::new();
+        let mut duplicate = None;
+        let mut absent = None;
+        for update in updates {
+            // a deletion by key translates into nothing if no value has
+            // the key
+            let deleted = match &update {
+                Update::DeleteValue { relid, v } if self.absent_error.is_some() => {
+                    Some((*relid, format!("{:?}", v)))
+                }
+                Update::DeleteKey { relid, k } if self.absent_error.is_some() => {
+                    Some((*relid, format!("{:?}", k)))
+                }
+                _ => None,
+            };
+            let translated = self.translate(update);
+            if let Some((relid, value)) = deleted {
+                if translated.is_empty() && absent.is_none() {
+                    absent = Some(AbsentValueError { relid, value });
+                }
+            }
+            let admitted = self.admit(translated, &mut duplicate, &mut absent);
+            self.project(&admitted);
+            upds.extend(admitted);
+        }
+        // push incoming updates into buffer
+        let holds_back = self.holds_back();
+        let buffer = self.buffer.as_mut().unwrap();
+        if holds_back {
+            // updates are forwarded once the transaction is committed
+            buffer.push_back(upds);
+        } else {
+            buffer.push_back(upds.clone());
+            if !upds.is_empty() {
+                // send updates to observer
+                self.forward(upds)?;
+            }
+        }
+
+        // report rejected duplicates and deletions once the admitted
+        // updates were processed
+        if let (Some(duplicate), Some(convert)) = (duplicate, self.duplicate_error) {
+            return Err(convert(duplicate));
+        }
+        match (absent, self.absent_error) {
+            (Some(absent), Some(convert)) => Err(convert(absent)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Discard the updates of the transaction in progress, leaving the
+    /// accumulated state untouched. If some of them were forwarded
+    /// already, the abort is forwarded as well, unless they were merged
+    /// into a batch along with transactions committed before, in which
+    /// case they are reverted instead.
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("AccumulatingObserver({})::on_abort", self.id);
+
+        let buffer = match self.buffer.take() {
+            Some(buffer) => buffer,
+            None => return self.protocol_violation(ProtocolViolation::AbortWithoutStart),
+        };
+        self.discard_pending();
+
+        if self.holds_back() || !self.batch_open || buffer.iter().all(Vec::is_empty) {
+            // nothing of the transaction was forwarded
+            Ok(())
+        } else if self.batched == 0 {
+            // the batch consists of the aborted transaction alone
+            self.batch_open = false;
+            let mut guard = self.observer.lock().unwrap();
+            guard.on_abort()
+        } else {
+            self.revert(buffer)
+        }
+    }
+
+    /// signals that the source has been removed, clears the accumulated state.
+    /// The updates of a transaction still in progress that were forwarded
+    /// already are reverted before the current batch is committed.
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("AccumulatingObserver({})::on_completed", self.id);
+        let result = self.abort_transaction();
+        self.completed_count += 1;
+        self.data.lock().unwrap().clear();
+        self.inserted_at.clear();
+        let _ = self.weights.drain();
+        self.sizes.clear();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::vec::IntoIter;
+
+    use differential_datalog::record::Mutator;
+    use maplit::hashmap;
+    use maplit::hashset;
+
+    use crate::accumulate::AccumulatorError;
+    use crate::accumulate::{eq_updates, UpdatesMockObserver};
+    use crate::Accumulator;
+    use crate::DistributingAccumulator;
+    use crate::MockObserver;
+
+    fn get_usize_insert_updates_1() -> Box<IntoIter<Update<usize>>> {
+        Box::new(
+            vec![
+                Update::Insert { relid: 1, v: 1 },
+                Update::Insert { relid: 2, v: 2 },
+                Update::Insert { relid: 3, v: 3 },
+            ]
+            .into_iter(),
+        )
+    }
+
+    fn get_usize_insert_updates_2() -> Box<IntoIter<Update<usize>>> {
+        Box::new(
+            vec![
+                Update::Insert { relid: 1, v: 2 },
+                Update::Insert { relid: 1, v: 3 },
+                Update::Insert { relid: 2, v: 3 },
+            ]
+            .into_iter(),
+        )
+    }
+
+    fn get_usize_insert_updates_3() -> Box<IntoIter<Update<usize>>> {
+        Box::new(
+            vec![
                 Update::Insert { relid: 4, v: 1 },
                 Update::Insert { relid: 4, v: 2 },
                 Update::Insert { relid: 4, v: 3 },
@@ -257,9 +1797,10 @@ mod tests {
         let _subscription = observer.subscribe(Box::new(mock.clone()));
 
         assert_eq!(observer.on_start(), Ok(()));
-        assert_eq!(mock.lock().unwrap().as_ref().unwrap().called_on_start, 1);
+        assert_eq!(mock.lock().unwrap().as_ref().unwrap().called_on_start, 0);
 
         assert_eq!(observer.on_updates(get_usize_insert_updates_1()), Ok(()));
+        assert_eq!(mock.lock().unwrap().as_ref().unwrap().called_on_start, 1);
         assert_eq!(mock.lock().unwrap().as_ref().unwrap().called_on_updates, 3);
 
         assert_eq!(observer.on_updates(get_usize_insert_updates_2()), Ok(()));
@@ -269,9 +1810,10 @@ mod tests {
         assert_eq!(mock.lock().unwrap().as_ref().unwrap().called_on_commit, 1);
 
         assert_eq!(observer.on_start(), Ok(()));
-        assert_eq!(mock.lock().unwrap().as_ref().unwrap().called_on_start, 2);
+        assert_eq!(mock.lock().unwrap().as_ref().unwrap().called_on_start, 1);
 
         assert_eq!(observer.on_updates(get_usize_insert_updates_3()), Ok(()));
+        assert_eq!(mock.lock().unwrap().as_ref().unwrap().called_on_start, 2);
         assert_eq!(mock.lock().unwrap().as_ref().unwrap().called_on_updates, 10);
 
         assert_eq!(observer.on_commit(), Ok(()));
@@ -295,11 +1837,13 @@ mod tests {
         assert_eq!(observer.on_updates(get_usize_insert_updates_1()), Ok(()));
         assert_eq!(observer.on_updates(get_usize_insert_updates_2()), Ok(()));
 
-        assert!(observer.data.is_empty());
+        assert!(observer.data.lock().unwrap().is_empty());
         assert_eq!(observer.on_commit(), Ok(()));
 
         observer
             .data
+            .lock()
+            .unwrap()
             .iter()
             .for_each(|(relid, values)| match relid {
                 &1 => {
@@ -321,6 +1865,8 @@ mod tests {
         // data must not be updated before commit
         observer
             .data
+            .lock()
+            .unwrap()
             .iter()
             .for_each(|(relid, values)| match relid {
                 &1 => {
@@ -339,6 +1885,8 @@ mod tests {
 
         observer
             .data
+            .lock()
+            .unwrap()
             .iter()
             .for_each(|(relid, values)| match relid {
                 &1 => {
@@ -359,4 +1907,911 @@ mod tests {
                 _ => panic!("Unexpected relid!"),
             });
     }
+
+    /// Test that insertions exceeding the maximum number of values of a
+    /// relation are rejected and reported.
+    #[test]
+    fn max_values_per_relation() {
+        let mut observer = AccumulatingObserver::<Update<usize>, usize, ()>::new();
+        let mock = Arc::new(Mutex::new(Some(MockObserver::default())));
+        let _subscription = observer.subscribe(Box::new(mock.clone()));
+        let overflows = Arc::new(Mutex::new(Vec::new()));
+        observer.max_values_per_relation(Some(2));
+        observer.on_overflow({
+            let overflows = overflows.clone();
+            move |relid, v| overflows.lock().unwrap().push((relid, v))
+        });
+
+        let updates = vec![
+            Update::Insert { relid: 1, v: 1 },
+            Update::Insert { relid: 1, v: 2 },
+            Update::Insert { relid: 1, v: 3 },
+        ];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        assert_eq!(observer.get_current_state()[&1].len(), 2);
+        assert!(!observer.get_current_state()[&1].contains(&3));
+        assert_eq!(mock.lock().unwrap().unwrap().called_on_updates, 2);
+        assert_eq!(*overflows.lock().unwrap(), vec![(1, 3)]);
+
+        // a deletion frees up space for another value
+        let updates = vec![
+            Update::DeleteValue { relid: 1, v: 1 },
+            Update::Insert { relid: 1, v: 3 },
+        ];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        assert_eq!(
+            observer.get_current_state()[&1],
+            vec![2, 3].into_iter().collect()
+        );
+        assert_eq!(overflows.lock().unwrap().len(), 1);
+    }
+
+    /// Test that inserting into a full relation evicts the least
+    /// recently inserted value.
+    #[test]
+    fn eviction_lru() {
+        let mut observer = AccumulatingObserver::<Update<usize>, usize, ()>::new();
+        let mock = Arc::new(Mutex::new(Some(UpdatesMockObserver::new())));
+        let _subscription = observer.subscribe(Box::new(mock.clone()));
+        observer.eviction_policy(EvictionPolicy::Lru(2));
+
+        let updates = vec![
+            Update::Insert { relid: 1, v: 1 },
+            Update::Insert { relid: 1, v: 2 },
+        ];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let updates = vec![Update::Insert { relid: 1, v: 3 }];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let expected = [
+            Update::Insert { relid: 1, v: 1 },
+            Update::Insert { relid: 1, v: 2 },
+            Update::DeleteValue { relid: 1, v: 1 },
+            Update::Insert { relid: 1, v: 3 },
+        ];
+        let received = mock
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .received_updates
+            .clone();
+        assert_eq!(received.len(), expected.len(), "{:?}", received);
+        assert!(received
+            .iter()
+            .zip(expected.iter())
+            .all(|(u1, u2)| eq_updates(u1, u2)));
+        assert_eq!(observer.get_current_state()[&1], hashset! {2, 3});
+    }
+
+    /// Test that an aborted transaction does not affect the order in
+    /// which values are evicted.
+    #[test]
+    fn eviction_lru_abort() {
+        let mut observer = AccumulatingObserver::<Update<usize>, usize, ()>::new();
+        observer.eviction_policy(EvictionPolicy::Lru(2));
+
+        let updates = vec![
+            Update::Insert { relid: 1, v: 1 },
+            Update::Insert { relid: 1, v: 2 },
+        ];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        // inserting 1 again would make 2 the least recently inserted value
+        let updates = vec![
+            Update::Insert { relid: 1, v: 1 },
+            Update::Insert { relid: 1, v: 4 },
+        ];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_abort(), Ok(()));
+
+        let updates = vec![Update::Insert { relid: 1, v: 3 }];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(observer.get_current_state()[&1], hashset! {2, 3});
+    }
+
+    /// Test that `try_on_updates` accepts only the prefix of the updates
+    /// that fits into the budget.
+    #[test]
+    fn try_on_updates_budget() {
+        let budget = 3 * size_of::<usize>() - 1;
+        let mut observer =
+            AccumulatingObserver::<Update<usize>, usize, ()>::new_with_budget(budget);
+        let mock = Arc::new(Mutex::new(Some(MockObserver::default())));
+        let _subscription = observer.subscribe(Box::new(mock.clone()));
+
+        let updates = vec![
+            Update::Insert { relid: 1, v: 1 },
+            Update::Insert { relid: 1, v: 2 },
+            Update::Insert { relid: 1, v: 3 },
+            Update::Insert { relid: 1, v: 4 },
+        ];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.try_on_updates(Box::new(updates.into_iter())),
+            Ok(2)
+        );
+        assert_eq!(observer.transaction_size(), 2);
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        assert_eq!(
+            observer.get_current_state()[&1],
+            vec![1, 2].into_iter().collect()
+        );
+        assert_eq!(mock.lock().unwrap().unwrap().called_on_updates, 2);
+        assert_eq!(observer.footprint(), 2 * size_of::<usize>());
+
+        // the budget is exhausted by the accumulated state
+        let updates = vec![Update::Insert { relid: 1, v: 3 }];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.try_on_updates(Box::new(updates.into_iter())),
+            Ok(0)
+        );
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(observer.get_current_state()[&1].len(), 2);
+    }
+
+    /// Drive an observer treating protocol violations according to the
+    /// given policy through an out-of-order sequence of events followed
+    /// by an ordered transaction, returning the results of the
+    /// out-of-order events.
+    fn out_of_order(policy: ProtocolPolicy) -> Vec<Result<(), ProtocolViolation>> {
+        let mut observer = AccumulatingObserver::<Update<usize>, usize, ProtocolViolation>::new();
+        observer.protocol_policy(policy);
+        let mock = Arc::new(Mutex::new(Some(MockObserver::default())));
+        let _subscription = observer.subscribe(Box::new(mock.clone()));
+
+        let mut results = vec![
+            observer.on_commit(),
+            observer.on_updates(get_usize_insert_updates_1()),
+        ];
+        assert_eq!(observer.on_start(), Ok(()));
+        results.push(observer.on_start());
+        assert_eq!(observer.on_updates(get_usize_insert_updates_1()), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        // the offending events were dropped
+        let mock = mock.lock().unwrap().unwrap();
+        assert_eq!(mock.called_on_start, 1);
+        assert_eq!(mock.called_on_updates, 3);
+        assert_eq!(mock.called_on_commit, 1);
+        assert_eq!(observer.get_current_state().len(), 3);
+        results
+    }
+
+    /// Test that protocol violations are reported if so configured.
+    #[test]
+    fn protocol_policy_error() {
+        assert_eq!(
+            out_of_order(ProtocolPolicy::Error),
+            vec![
+                Err(ProtocolViolation::CommitWithoutStart),
+                Err(ProtocolViolation::UpdatesWithoutStart),
+                Err(ProtocolViolation::StartInTransaction),
+            ]
+        );
+    }
+
+    /// Test that protocol violations are ignored if so configured.
+    #[test]
+    fn protocol_policy_ignore() {
+        assert_eq!(out_of_order(ProtocolPolicy::Ignore), vec![Ok(()); 3]);
+    }
+
+    /// Test that protocol violations cause a panic by default.
+    #[test]
+    #[should_panic(expected = "on_commit was not preceded by an on_start event")]
+    fn protocol_policy_panic() {
+        let mut observer = AccumulatingObserver::<Update<usize>, usize, ()>::new();
+        let _ = observer.on_commit();
+    }
+
+    /// Insert the values of `get_usize_insert_updates_1` twice, in two
+    /// transactions, and then delete them once.
+    fn insert_twice_delete_once<E>(
+        observer: &mut AccumulatingObserver<Update<usize>, usize, E>,
+    ) -> Vec<Result<(), E>>
+    where
+        E: Debug + Send,
+    {
+        let deletes = get_usize_insert_updates_1().map(|u| match u {
+            Update::Insert { relid, v } => Update::DeleteValue { relid, v },
+            u => u,
+        });
+        let mut results = Vec::new();
+        for updates in [
+            get_usize_insert_updates_1(),
+            get_usize_insert_updates_1(),
+            Box::new(deletes.collect::<Vec<_>>().into_iter()),
+        ] {
+            let _ = observer.on_start();
+            results.push(observer.on_updates(updates));
+            let _ = observer.on_commit();
+        }
+        results
+    }
+
+    /// Test that duplicate insertions are accepted but not counted by
+    /// default.
+    #[test]
+    fn duplicate_policy_ignore() {
+        let mut observer =
+            AccumulatingObserver::<Update<usize>, usize, DuplicateError>::new_with_policy(
+                DuplicatePolicy::Ignore,
+            );
+        let mock = Arc::new(Mutex::new(Some(MockObserver::default())));
+        let _subscription = observer.subscribe(Box::new(mock.clone()));
+
+        let results = insert_twice_delete_once(&mut observer);
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(mock.lock().unwrap().unwrap().called_on_updates, 9);
+        assert!(observer.get_current_state().values().all(HashSet::is_empty));
+    }
+
+    /// Test that compaction reclaims zero weight entries, which do not
+    /// linger after insert/delete cycles but may be restored.
+    #[test]
+    fn compact_weights() {
+        let mut observer = AccumulatingObserver::<_, usize, ()>::new();
+        for _ in 0..100 {
+            let updates = vec![
+                Update::Insert { relid: 1, v: 1 },
+                Update::Insert { relid: 2, v: 2 },
+                Update::DeleteValue { relid: 1, v: 1 },
+                Update::DeleteValue { relid: 2, v: 2 },
+            ];
+            assert_eq!(observer.on_start(), Ok(()));
+            assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+            assert_eq!(observer.on_commit(), Ok(()));
+        }
+        assert!(observer.get_current_state_weighted().is_empty());
+        assert_eq!(observer.compact(), 0);
+
+        let mut snapshot = observer.snapshot();
+        snapshot.weights = vec![
+            (1, vec![(1, 0), (2, 1)].into_iter().collect()),
+            (2, vec![(3, 0)].into_iter().collect()),
+        ]
+        .into_iter()
+        .collect();
+        observer.restore(snapshot);
+
+        // two values and the relation left without values
+        assert_eq!(observer.compact(), 3);
+        let weights = observer.get_current_state_weighted();
+        assert_eq!(weights.len(), 1);
+        assert_eq!(weights[&1], vec![(2, 1)].into_iter().collect());
+        assert_eq!(observer.compact(), 0);
+    }
+
+    /// Test that duplicate insertions keep a value present until it was
+    /// deleted as often as it was inserted.
+    #[test]
+    fn duplicate_policy_count_weight() {
+        let mut observer =
+            AccumulatingObserver::<Update<usize>, usize, DuplicateError>::new_with_policy(
+                DuplicatePolicy::CountWeight,
+            );
+
+        let results = insert_twice_delete_once(&mut observer);
+        assert!(results.iter().all(Result::is_ok));
+        let state = observer.get_current_state();
+        assert_eq!(state[&1], vec![1].into_iter().collect());
+        assert_eq!(state[&2], vec![2].into_iter().collect());
+        assert_eq!(state[&3], vec![3].into_iter().collect());
+        assert_eq!(observer.get_current_state_weighted()[&1][&1], 1);
+    }
+
+    /// Test that rejected duplicate insertions are reported and neither
+    /// accumulated nor forwarded.
+    #[test]
+    fn duplicate_policy_error() {
+        let mut observer =
+            AccumulatingObserver::<Update<usize>, usize, DuplicateError>::new_with_policy(
+                DuplicatePolicy::Error,
+            );
+        let mock = Arc::new(Mutex::new(Some(MockObserver::default())));
+        let _subscription = observer.subscribe(Box::new(mock.clone()));
+
+        let results = insert_twice_delete_once(&mut observer);
+        assert_eq!(results[0], Ok(()));
+        assert_eq!(
+            results[1],
+            Err(DuplicateError {
+                relid: 1,
+                value: "1".to_string(),
+            })
+        );
+        assert_eq!(results[2], Ok(()));
+        // the duplicates were dropped, so the deletions clear the state
+        assert_eq!(mock.lock().unwrap().unwrap().called_on_updates, 6);
+        assert!(observer.get_current_state().values().all(HashSet::is_empty));
+        assert!(observer.get_current_state_weighted().is_empty());
+    }
+
+    /// Test that deletions of absent values are ignored by default.
+    #[test]
+    fn lenient_deletes() {
+        let mut observer = AccumulatingObserver::<Update<usize>, usize, AbsentValueError>::new();
+        let mock = Arc::new(Mutex::new(Some(MockObserver::default())));
+        let _subscription = observer.subscribe(Box::new(mock.clone()));
+
+        let updates = vec![Update::DeleteValue { relid: 1, v: 1 }];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(mock.lock().unwrap().unwrap().called_on_updates, 1);
+        assert!(observer.get_current_state().values().all(HashSet::is_empty));
+    }
+
+    /// Test that rejected deletions of absent values are reported and
+    /// neither accumulated nor forwarded.
+    #[test]
+    fn strict_deletes() {
+        let mut observer = AccumulatingObserver::<Update<usize>, usize, AbsentValueError>::new();
+        let mock = Arc::new(Mutex::new(Some(MockObserver::default())));
+        let _subscription = observer.subscribe(Box::new(mock.clone()));
+        observer.strict_deletes(true);
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(get_usize_insert_updates_1()), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let updates = vec![
+            Update::DeleteValue { relid: 1, v: 1 },
+            Update::DeleteValue { relid: 2, v: 1 },
+            Update::DeleteValue { relid: 1, v: 1 },
+        ];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(updates.into_iter())),
+            Err(AbsentValueError {
+                relid: 2,
+                value: "1".to_string(),
+            })
+        );
+        assert_eq!(observer.on_commit(), Ok(()));
+        // only the first deletion was admitted
+        assert_eq!(mock.lock().unwrap().unwrap().called_on_updates, 4);
+        assert_eq!(
+            observer.get_current_state(),
+            hashmap! {
+                1 => HashSet::new(),
+                2 => hashset! {2},
+                3 => hashset! {3},
+            }
+        );
+    }
+
+    /// Test that deletions by a key no value has are rejected with
+    /// strict deletes.
+    #[test]
+    fn strict_deletes_by_key() {
+        let mut observer = AccumulatingObserver::<
+            Update<(usize, u64)>,
+            (usize, u64),
+            AccumulatorError<()>,
+        >::new_keyed(|(id, _)| (*id, 0));
+        observer.strict_deletes(true);
+
+        let updates = vec![
+            Update::Insert {
+                relid: 1,
+                v: (1, 100),
+            },
+            Update::DeleteKey {
+                relid: 1,
+                k: (2, 0),
+            },
+        ];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(updates.into_iter())),
+            Err(AccumulatorError::AbsentValue(AbsentValueError {
+                relid: 1,
+                value: "(2, 0)".to_string(),
+            }))
+        );
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(
+            observer.get_current_state(),
+            hashmap! {1 => hashset! {(1, 100)}}
+        );
+    }
+
+    /// Test that updates not changing the state are not forwarded if
+    /// redundant updates are suppressed.
+    #[test]
+    fn suppress_redundant() {
+        let mut observer = AccumulatingObserver::<Update<usize>, usize, ()>::new();
+        let mock = Arc::new(Mutex::new(Some(UpdatesMockObserver::new())));
+        let _subscription = observer.subscribe(Box::new(mock.clone()));
+        observer.suppress_redundant(true);
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(get_usize_insert_updates_1()), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(
+            mock.lock()
+                .unwrap()
+                .as_ref()
+                .unwrap()
+                .received_updates
+                .len(),
+            3
+        );
+
+        // re-inserting the same values, also within a single
+        // transaction, does not reach the observer
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(get_usize_insert_updates_1()), Ok(()));
+        let updates = vec![
+            Update::Insert { relid: 1, v: 4 },
+            Update::Insert { relid: 1, v: 4 },
+            Update::DeleteValue { relid: 2, v: 5 },
+        ];
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let received = mock
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .received_updates
+            .clone();
+        assert_eq!(received.len(), 4);
+        assert!(eq_updates(&received[3], &Update::Insert { relid: 1, v: 4 }));
+        // the suppressed insertions still count towards the weights
+        let weights = observer.get_current_state_weighted();
+        assert_eq!(weights[&1][&1], 2);
+        assert_eq!(weights[&1][&4], 2);
+        assert!(!weights[&2].contains_key(&5));
+    }
+
+    /// Test that updates are recorded rather than forwarded in dry-run
+    /// mode, with the options in effect still applied.
+    #[test]
+    fn dry_run() {
+        let mut observer = AccumulatingObserver::<Update<usize>, usize, ()>::new_coalescing();
+        let mock = Arc::new(Mutex::new(Some(MockObserver::new())));
+        let _subscription = observer.subscribe(Box::new(mock.clone()));
+        observer.suppress_redundant(true);
+        observer.dry_run(true);
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(get_usize_insert_updates_1()), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let updates = vec![
+            Update::Insert { relid: 1, v: 1 },
+            Update::Insert { relid: 1, v: 4 },
+            Update::DeleteValue { relid: 1, v: 4 },
+            Update::DeleteValue { relid: 2, v: 2 },
+        ];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let expected = [
+            Update::Insert { relid: 1, v: 1 },
+            Update::Insert { relid: 2, v: 2 },
+            Update::Insert { relid: 3, v: 3 },
+            Update::DeleteValue { relid: 2, v: 2 },
+        ];
+        let recorded = observer.dry_run_updates();
+        assert_eq!(recorded.len(), expected.len(), "{:?}", recorded);
+        assert!(recorded
+            .iter()
+            .zip(expected.iter())
+            .all(|(u1, u2)| eq_updates(u1, u2)));
+
+        // the state is accumulated as usual
+        let state = observer.get_current_state();
+        assert_eq!(state[&1], vec![1].into_iter().collect());
+        assert!(state[&2].is_empty());
+        assert_eq!(state[&3], vec![3].into_iter().collect());
+
+        let mock = mock.lock().unwrap().unwrap();
+        assert_eq!(mock.called_on_start, 0);
+        assert_eq!(mock.called_on_updates, 0);
+        assert_eq!(mock.called_on_commit, 0);
+
+        observer.dry_run(false);
+        assert!(observer.dry_run_updates().is_empty());
+    }
+
+    /// Test that aborting a transaction leaves the accumulated state as
+    /// it was before the transaction.
+    #[test]
+    fn abort_transaction() {
+        let mut observer = AccumulatingObserver::<Update<usize>, usize, ProtocolViolation>::new();
+        let mock = Arc::new(Mutex::new(Some(MockObserver::new())));
+        let _subscription = observer.subscribe(Box::new(mock.clone()));
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(get_usize_insert_updates_1()), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        let state = observer.get_current_state();
+        let weights = observer.get_current_state_weighted();
+        let stats = observer.stats();
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(get_usize_insert_updates_2()), Ok(()));
+        assert_eq!(observer.on_updates(get_usize_delete_updates_1()), Ok(()));
+        assert_eq!(observer.on_abort(), Ok(()));
+        assert!(!observer.in_transaction());
+        assert_eq!(observer.get_current_state(), state);
+        assert_eq!(observer.get_current_state_weighted(), weights);
+        assert_eq!(observer.stats(), stats);
+
+        let mock = mock.lock().unwrap().unwrap();
+        assert_eq!(mock.called_on_start, 2);
+        assert_eq!(mock.called_on_commit, 1);
+        assert_eq!(mock.called_on_abort, 1);
+
+        // an abort without a transaction violates the protocol
+        observer.protocol_policy(ProtocolPolicy::Error);
+        assert_eq!(
+            observer.on_abort(),
+            Err(ProtocolViolation::AbortWithoutStart)
+        );
+    }
+
+    /// Test that aborting a transaction merged into a batch with
+    /// committed ones reverts its updates instead.
+    #[test]
+    fn abort_batched_transaction() {
+        let mut observer = AccumulatingObserver::<Update<usize>, usize, ()>::new();
+        let mock = Arc::new(Mutex::new(Some(UpdatesMockObserver::new())));
+        let _subscription = observer.subscribe(Box::new(mock.clone()));
+        observer.batch_every(2);
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(get_usize_insert_updates_1()), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(get_usize_insert_updates_2()), Ok(()));
+        assert_eq!(observer.on_abort(), Ok(()));
+        assert_eq!(observer.flush(), Ok(()));
+
+        let state = observer.get_current_state();
+        assert_eq!(state[&1], vec![1].into_iter().collect());
+        assert_eq!(state[&2], vec![2].into_iter().collect());
+        assert_eq!(state[&3], vec![3].into_iter().collect());
+
+        // the net effect of the updates received is the committed state
+        let received = mock
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .received_updates
+            .clone();
+        let net = coalesce(received.into_iter());
+        assert_eq!(net.len(), 3);
+        assert!(net.iter().all(|u| matches!(u, Update::Insert { .. })));
+    }
+
+    /// Test that completing while a transaction is in progress reverts
+    /// the updates of it that were forwarded before committing the
+    /// batch of transactions.
+    #[test]
+    fn complete_in_transaction() {
+        let mut observer = AccumulatingObserver::<Update<usize>, usize, ()>::new();
+        let mock = Arc::new(Mutex::new(Some(UpdatesMockObserver::new())));
+        let _subscription = observer.subscribe(Box::new(mock.clone()));
+        observer.batch_every(3);
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(get_usize_insert_updates_1()), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(get_usize_insert_updates_2()), Ok(()));
+        assert_eq!(observer.on_completed(), Ok(()));
+        assert!(!observer.in_transaction());
+        assert!(observer.get_current_state().is_empty());
+
+        let mock = mock.lock().unwrap();
+        let mock = mock.as_ref().unwrap();
+        assert_eq!(mock.called_on_start, 1);
+        assert_eq!(mock.called_on_commit, 1);
+        // the net effect of the committed batch is the first transaction
+        let net = coalesce(mock.received_updates.clone().into_iter());
+        assert_eq!(net.len(), 3);
+        assert!(net
+            .iter()
+            .zip(get_usize_insert_updates_1())
+            .all(|(u1, u2)| eq_updates(u1, &u2)));
+    }
+
+    /// A `Mutator` incrementing a value.
+    struct Increment;
+
+    impl std::fmt::Display for Increment {
+        fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+            f.write_str("Increment")
+        }
+    }
+
+    impl Mutator<usize> for Increment {
+        fn mutate(&self, v: &mut usize) -> Result<(), String> {
+            *v += 1;
+            Ok(())
+        }
+    }
+
+    /// Test that all kinds of updates are folded into the accumulated
+    /// state and forwarded as insertions and deletions of values.
+    #[test]
+    fn update_variants() {
+        let mut observer = AccumulatingObserver::<Update<usize>, usize, ()>::new();
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let _subscription = observer.subscribe(Box::new(mock.clone()));
+        // values of relation 1 are keyed by their tens digit
+        observer.key_func(1, |v| v / 10);
+
+        let updates = vec![
+            Update::Insert { relid: 1, v: 11 },
+            Update::Insert { relid: 1, v: 21 },
+            Update::InsertOrUpdate { relid: 1, v: 12 },
+            Update::InsertOrUpdate { relid: 1, v: 31 },
+            Update::InsertOrUpdate { relid: 2, v: 5 },
+        ];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let state = observer.get_current_state();
+        assert_eq!(state[&1], vec![12, 21, 31].into_iter().collect());
+        assert_eq!(state[&2], vec![5].into_iter().collect());
+
+        let updates = vec![
+            Update::DeleteKey { relid: 1, k: 2 },
+            Update::Modify {
+                relid: 1,
+                k: 3,
+                m: Arc::new(Increment) as Arc<dyn Mutator<usize> + Send + Sync>,
+            },
+            Update::Modify {
+                relid: 1,
+                k: 9,
+                m: Arc::new(Increment) as Arc<dyn Mutator<usize> + Send + Sync>,
+            },
+        ];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let state = observer.get_current_state();
+        assert_eq!(state[&1], vec![12, 32].into_iter().collect());
+        let weights = observer.get_current_state_weighted();
+        assert_eq!(weights[&1], vec![(12, 1), (32, 1)].into_iter().collect());
+
+        let expected = vec![
+            Update::Insert { relid: 1, v: 11 },
+            Update::Insert { relid: 1, v: 21 },
+            Update::DeleteValue { relid: 1, v: 11 },
+            Update::Insert { relid: 1, v: 12 },
+            Update::Insert { relid: 1, v: 31 },
+            Update::Insert { relid: 2, v: 5 },
+            Update::DeleteValue { relid: 1, v: 21 },
+            Update::DeleteValue { relid: 1, v: 31 },
+            Update::Insert { relid: 1, v: 32 },
+        ];
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.received_updates.len(), expected.len());
+        assert!(mock
+            .received_updates
+            .iter()
+            .zip(expected.iter())
+            .all(|(u1, u2)| eq_updates(u1, u2)));
+    }
+
+    /// Test that values identified by a key ignoring some of their
+    /// fields replace each other, retaining one value per key.
+    #[test]
+    fn keyed_values() {
+        // values are identified by their first field only
+        let mut observer =
+            AccumulatingObserver::<Update<(usize, u64)>, (usize, u64), ()>::new_keyed(|(id, _)| {
+                *id
+            });
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let _subscription = observer.subscribe(Box::new(mock.clone()));
+
+        let updates = vec![
+            Update::Insert {
+                relid: 1,
+                v: (1, 100),
+            },
+            Update::Insert {
+                relid: 1,
+                v: (2, 100),
+            },
+        ];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let updates = vec![
+            Update::Insert {
+                relid: 1,
+                v: (1, 200),
+            },
+            Update::Insert {
+                relid: 1,
+                v: (2, 100),
+            },
+            Update::DeleteValue {
+                relid: 2,
+                v: (1, 300),
+            },
+        ];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let state = observer.get_current_state();
+        assert_eq!(state[&1], vec![(1, 200), (2, 100)].into_iter().collect());
+        let weights = observer.get_current_state_weighted();
+        assert_eq!(
+            weights[&1],
+            vec![((1, 200), 1), ((2, 100), 1)].into_iter().collect()
+        );
+
+        // a deletion removes the value with the same key
+        let updates = vec![Update::DeleteValue {
+            relid: 1,
+            v: (2, 0),
+        }];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(
+            observer.get_current_state()[&1],
+            vec![(1, 200)].into_iter().collect()
+        );
+
+        let expected = [
+            Update::Insert {
+                relid: 1,
+                v: (1, 100),
+            },
+            Update::Insert {
+                relid: 1,
+                v: (2, 100),
+            },
+            Update::DeleteValue {
+                relid: 1,
+                v: (1, 100),
+            },
+            Update::Insert {
+                relid: 1,
+                v: (1, 200),
+            },
+            Update::DeleteValue {
+                relid: 1,
+                v: (2, 100),
+            },
+        ];
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.received_updates.len(), expected.len());
+        assert!(mock
+            .received_updates
+            .iter()
+            .zip(expected.iter())
+            .all(|(u1, u2)| eq_updates(u1, u2)));
+    }
+
+    /// Test that updates by key to a relation without a key function
+    /// treat every value as its own key.
+    #[test]
+    fn update_by_key_without_key_func() {
+        let mut observer = AccumulatingObserver::<Update<usize>, usize, ()>::new();
+        let updates = vec![
+            Update::Insert { relid: 1, v: 1 },
+            Update::Insert { relid: 1, v: 2 },
+            Update::Insert { relid: 1, v: 5 },
+        ];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let updates = vec![
+            Update::DeleteKey { relid: 1, k: 1 },
+            Update::DeleteKey { relid: 1, k: 3 },
+            Update::Modify {
+                relid: 1,
+                k: 2,
+                m: Arc::new(Increment) as Arc<dyn Mutator<usize> + Send + Sync>,
+            },
+            Update::Modify {
+                relid: 1,
+                k: 9,
+                m: Arc::new(Increment) as Arc<dyn Mutator<usize> + Send + Sync>,
+            },
+            Update::InsertOrUpdate { relid: 1, v: 7 },
+        ];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(
+            observer.get_current_state(),
+            hashmap! {1 => hashset! {3, 5, 7}}
+        );
+    }
+
+    /// Test that an accumulator keeps its state when switching to
+    /// another upstream.
+    #[test]
+    fn reconnect_upstream() {
+        let observer = Arc::new(Mutex::new(
+            AccumulatingObserver::<Update<usize>, usize, ()>::new(),
+        ));
+        let mock = Arc::new(Mutex::new(Some(MockObserver::default())));
+        let _subscription = observer.lock().unwrap().subscribe(Box::new(mock.clone()));
+
+        let source1 = Arc::new(Mutex::new(DistributingAccumulator::<
+            Update<usize>,
+            usize,
+            (),
+        >::new()));
+        let source2 = Arc::new(Mutex::new(DistributingAccumulator::<
+            Update<usize>,
+            usize,
+            (),
+        >::new()));
+
+        let result = AccumulatingObserver::reconnect_upstream(&observer, Box::new(source1.clone()));
+        assert!(result.is_ok());
+        {
+            let mut source1 = source1.lock().unwrap();
+            assert_eq!(source1.on_start(), Ok(()));
+            assert_eq!(source1.on_updates(get_usize_insert_updates_1()), Ok(()));
+            assert_eq!(source1.on_commit(), Ok(()));
+            // a transaction in progress while failing over is aborted
+            assert_eq!(source1.on_start(), Ok(()));
+            assert_eq!(source1.on_updates(get_usize_insert_updates_2()), Ok(()));
+        }
+        assert_eq!(mock.lock().unwrap().unwrap().called_on_updates, 6);
+
+        let result = AccumulatingObserver::reconnect_upstream(&observer, Box::new(source2.clone()));
+        assert!(result.is_ok());
+        assert_eq!(mock.lock().unwrap().unwrap().called_on_updates, 9);
+        assert_eq!(mock.lock().unwrap().unwrap().called_on_commit, 2);
+        {
+            let mut source1 = source1.lock().unwrap();
+            assert_eq!(source1.on_commit(), Ok(()));
+            assert_eq!(source1.observer_count(), 0);
+
+            let mut source2 = source2.lock().unwrap();
+            assert_eq!(source2.on_start(), Ok(()));
+            assert_eq!(source2.on_updates(get_usize_insert_updates_3()), Ok(()));
+            assert_eq!(source2.on_commit(), Ok(()));
+        }
+
+        let state = observer.lock().unwrap().get_current_state();
+        assert_eq!(state.values().map(HashSet::len).sum::<usize>(), 7);
+        assert_eq!(state[&1], vec![1].into_iter().collect());
+        assert_eq!(state[&4].len(), 4);
+        assert_eq!(mock.lock().unwrap().unwrap().called_on_commit, 3);
+    }
 }
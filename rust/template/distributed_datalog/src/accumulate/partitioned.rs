@@ -0,0 +1,412 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::mem::take;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use log::trace;
+use uid::Id;
+
+use differential_datalog::program::RelId;
+use differential_datalog::program::Update;
+
+use crate::accumulate::AccumulatingObserver;
+use crate::accumulate::StateHandle;
+use crate::accumulate::TxnDistributor;
+use crate::Observable;
+use crate::Observer;
+use crate::ObserverBox;
+
+/// The distributors of a `PartitionedAccumulator`, one per relation
+/// with subscribers.
+type Distributors<V, E> = Arc<Mutex<BTreeMap<RelId, TxnDistributor<Update<V>, E>>>>;
+
+/// Invoke the given function on every distributor, returning the first
+/// error encountered once all distributors were invoked.
+fn for_each<V, E, F>(
+    distributors: &mut [(RelId, TxnDistributor<Update<V>, E>)],
+    mut f: F,
+) -> Result<(), E>
+where
+    F: FnMut(&mut TxnDistributor<Update<V>, E>) -> Result<(), E>,
+{
+    let mut result = Ok(());
+    for (_, distributor) in distributors {
+        let next = f(distributor);
+        result = result.and(next);
+    }
+    result
+}
+
+/// An observer routing each update it receives to the distributor of
+/// the update's relation.
+#[derive(Debug)]
+struct Router<V, E> {
+    /// The router's unique ID.
+    id: usize,
+    /// The distributors to route updates to, shared with the
+    /// `PartitionedAccumulator`.
+    distributors: Distributors<V, E>,
+    /// The distributors that saw the start of the transaction in
+    /// progress. Distributors created during a transaction only take
+    /// part in the next one.
+    started: Vec<(RelId, TxnDistributor<Update<V>, E>)>,
+}
+
+impl<V, E> Router<V, E> {
+    /// Retrieve a copy of all distributors, so that we do not hold the
+    /// lock while invoking them.
+    fn distributors(&self) -> Vec<(RelId, TxnDistributor<Update<V>, E>)> {
+        self.distributors
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(relid, distributor)| (*relid, distributor.clone()))
+            .collect()
+    }
+}
+
+impl<V, E> Observer<Update<V>, E> for Router<V, E>
+where
+    V: Clone + Debug + Send,
+    E: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("Router({})::on_start", self.id);
+        self.started = self.distributors();
+        for_each(&mut self.started, |d| d.on_start())
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("Router({})::on_commit", self.id);
+        let mut started = take(&mut self.started);
+        for_each(&mut started, |d| d.on_commit())
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("Router({})::on_updates", self.id);
+        let mut partitions = HashMap::<RelId, Vec<Update<V>>>::new();
+        for update in updates {
+            partitions.entry(update.relid()).or_default().push(update);
+        }
+
+        // updates of relations without subscribers are dropped
+        let mut result = Ok(());
+        for (relid, distributor) in &mut self.started {
+            if let Some(updates) = partitions.remove(relid) {
+                let next = distributor.on_updates(Box::new(updates.into_iter()));
+                result = result.and(next);
+            }
+        }
+        result
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("Router({})::on_abort", self.id);
+        let mut started = take(&mut self.started);
+        for_each(&mut started, |d| d.on_abort())
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("Router({})::on_completed", self.id);
+        for_each(&mut self.distributors(), |d| d.on_completed())
+    }
+}
+
+/// An accumulator distributing the updates of each relation to a
+/// distinct set of observers, e.g., to serve different downstream
+/// groups from one node. Observers subscribe to a single relation via
+/// `subscribe_relation` and only receive the updates of that relation,
+/// starting with its current values.
+///
+/// Transactions without updates of a relation are still forwarded to
+/// the observers of that relation, as empty transactions.
+#[derive(Debug)]
+pub struct PartitionedAccumulator<V, E>
+where
+    V: Debug + Eq + Hash + Send,
+    E: Debug + Send,
+{
+    /// The accumulator's unique ID.
+    id: usize,
+    /// Component responsible for accumulating the data.
+    observer: AccumulatingObserver<Update<V>, V, E>,
+    /// The router distributing the accumulated updates by relation.
+    router: Arc<Mutex<Router<V, E>>>,
+    /// The distributors of the relations with subscribers, shared with
+    /// the router.
+    distributors: Distributors<V, E>,
+}
+
+impl<V, E> PartitionedAccumulator<V, E>
+where
+    V: Debug + Send + Clone + Eq + Hash + 'static,
+    E: Debug + Send + 'static,
+{
+    /// Create a new `PartitionedAccumulator` without any subscribers.
+    pub fn new() -> Self {
+        let id = Id::<()>::new().get();
+        trace!("PartitionedAccumulator({})::new", id);
+
+        let distributors = Distributors::default();
+        let router = Arc::new(Mutex::new(Router {
+            id,
+            distributors: distributors.clone(),
+            started: Vec::new(),
+        }));
+        let mut observer = AccumulatingObserver::new();
+        let _subscription = observer.subscribe(Box::new(router.clone()));
+
+        Self {
+            id,
+            observer,
+            router,
+            distributors,
+        }
+    }
+
+    /// Subscribe an observer to the updates of the given relation,
+    /// sending it the current values of the relation as a transaction
+    /// of its own first. The observer is handed back if a transaction
+    /// is in progress.
+    pub fn subscribe_relation(
+        &mut self,
+        relid: RelId,
+        mut observer: ObserverBox<Update<V>, E>,
+    ) -> Result<usize, ObserverBox<Update<V>, E>> {
+        trace!(
+            "PartitionedAccumulator({})::subscribe_relation({})",
+            self.id,
+            relid
+        );
+        if self.observer.in_transaction() {
+            return Err(observer);
+        }
+
+        let init_updates = self
+            .observer
+            .state_handle()
+            .get_relation(relid)
+            .into_iter()
+            .map(|v| Update::Insert { relid, v })
+            .collect::<Vec<_>>();
+        if !init_updates.is_empty() {
+            let _ = observer.on_start();
+            let _ = observer.on_updates(Box::new(init_updates.into_iter()));
+            let _ = observer.on_commit();
+        }
+
+        let mut distributor = self
+            .distributors
+            .lock()
+            .unwrap()
+            .entry(relid)
+            .or_insert_with(TxnDistributor::new)
+            .clone();
+        distributor.subscribe(observer)
+    }
+
+    /// Cancel the subscription of an observer to the updates of the
+    /// given relation.
+    pub fn unsubscribe_relation(
+        &mut self,
+        relid: RelId,
+        subscription: &usize,
+    ) -> Option<ObserverBox<Update<V>, E>> {
+        trace!(
+            "PartitionedAccumulator({})::unsubscribe_relation({}, {})",
+            self.id,
+            relid,
+            subscription
+        );
+        let mut distributor = self.distributors.lock().unwrap().get(&relid)?.clone();
+        distributor.unsubscribe(subscription)
+    }
+
+    /// Return the current state of the data.
+    pub fn get_current_state(&self) -> HashMap<RelId, HashSet<V>> {
+        self.observer.get_current_state()
+    }
+
+    /// Retrieve a handle to the current state of the data.
+    pub fn state_handle(&self) -> StateHandle<V> {
+        self.observer.state_handle()
+    }
+}
+
+impl<V, E> Default for PartitionedAccumulator<V, E>
+where
+    V: Debug + Send + Clone + Eq + Hash + 'static,
+    E: Debug + Send + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V, E> Observer<Update<V>, E> for PartitionedAccumulator<V, E>
+where
+    V: Debug + Send + Clone + Eq + Hash + 'static,
+    E: Debug + Send + 'static,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("PartitionedAccumulator({})::on_start", self.id);
+        self.observer.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("PartitionedAccumulator({})::on_commit", self.id);
+        self.observer.on_commit()
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("PartitionedAccumulator({})::on_updates", self.id);
+        self.observer.on_updates(updates)
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("PartitionedAccumulator({})::on_abort", self.id);
+        self.observer.on_abort()
+    }
+
+    /// Sends the deletion of its values to the observers of each
+    /// relation, thus clearing the accumulated state.
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("PartitionedAccumulator({})::on_completed", self.id);
+        let _ = self.observer.flush();
+        let mut router = self.router.lock().unwrap();
+        let _ = router.on_completed();
+
+        let state = self.observer.take_state();
+        if state.values().any(|vs| !vs.is_empty()) {
+            let delete_updates = state.into_iter().flat_map(|(relid, vs)| {
+                vs.into_iter()
+                    .map(move |v| Update::DeleteValue { relid, v })
+            });
+
+            let _ = router.on_start();
+            let _ = router.on_updates(Box::new(delete_updates));
+            let _ = router.on_commit();
+        }
+        drop(router);
+
+        self.observer.on_completed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::accumulate::accumulator::tests::get_usize_updates_1;
+    use crate::accumulate::accumulator::tests::get_usize_updates_3;
+    use crate::accumulate::UpdatesMockObserver;
+
+    /// Test that the observers of a relation receive its current values
+    /// upon subscription and only the updates of that relation
+    /// afterwards.
+    #[test]
+    fn relation_scoped_subscriptions() {
+        let mut accumulator = PartitionedAccumulator::<usize, ()>::new();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let mock1 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let mock4 = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let subscription1 = accumulator
+            .subscribe_relation(1, Box::new(mock1.clone()))
+            .unwrap();
+        assert!(accumulator
+            .subscribe_relation(4, Box::new(mock4.clone()))
+            .is_ok());
+
+        let received = mock1.lock().unwrap().received_updates.clone();
+        assert_eq!(received.len(), 1);
+        assert!(matches!(received[0], Update::Insert { relid: 1, v: 1 }));
+        assert!(mock4.lock().unwrap().received_updates.is_empty());
+
+        let deletes = vec![Update::DeleteValue { relid: 1, v: 1 }];
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(deletes.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let received = mock1.lock().unwrap().received_updates.clone();
+        assert_eq!(received.len(), 2);
+        assert!(matches!(
+            received[1],
+            Update::DeleteValue { relid: 1, v: 1 }
+        ));
+        let received = mock4.lock().unwrap().received_updates.clone();
+        assert_eq!(received.len(), 4);
+        assert!(received
+            .iter()
+            .all(|u| matches!(u, Update::Insert { relid: 4, .. })));
+
+        assert!(accumulator
+            .unsubscribe_relation(1, &subscription1)
+            .is_some());
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(mock1.lock().unwrap().received_updates.len(), 2);
+        assert_eq!(accumulator.get_current_state()[&1].len(), 1);
+    }
+
+    /// Test that observers attempting to subscribe while a transaction
+    /// is in progress are handed back.
+    #[test]
+    fn subscribe_in_transaction() {
+        let mut accumulator = PartitionedAccumulator::<usize, ()>::new();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(accumulator
+            .subscribe_relation(1, Box::new(mock.clone()))
+            .is_err());
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert!(mock.lock().unwrap().received_updates.is_empty());
+
+        assert!(accumulator
+            .subscribe_relation(1, Box::new(mock.clone()))
+            .is_ok());
+        assert_eq!(mock.lock().unwrap().received_updates.len(), 1);
+    }
+
+    /// Test that a new transaction can be started after aborting one.
+    #[test]
+    fn start_after_abort() {
+        let mut accumulator = PartitionedAccumulator::<usize, ()>::new();
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(accumulator
+            .subscribe_relation(1, Box::new(mock.clone()))
+            .is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_abort(), Ok(()));
+        assert!(accumulator
+            .get_current_state()
+            .values()
+            .all(HashSet::is_empty));
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(accumulator.get_current_state()[&4].len(), 4);
+    }
+}
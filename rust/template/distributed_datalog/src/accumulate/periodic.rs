@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread::park_timeout;
+use std::thread::spawn;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use log::trace;
+use uid::Id;
+
+use differential_datalog::program::Update;
+
+use crate::accumulate::snapshot::diff_states;
+use crate::accumulate::StateHandle;
+use crate::Observer;
+use crate::ObserverBox;
+use crate::OptionalObserver;
+use crate::SharedObserver;
+
+/// A thread that periodically emits the changes of an accumulator's
+/// state since the last emission, as a single transaction.
+///
+/// The thread is stopped when the timer is dropped.
+#[derive(Debug)]
+pub struct SnapshotTimer {
+    /// The timer's unique ID.
+    id: usize,
+    /// Flag indicating to the thread that it should stop.
+    stopped: Arc<AtomicBool>,
+    /// The thread emitting the changes.
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SnapshotTimer {
+    /// Create a new `SnapshotTimer` emitting the changes of the state
+    /// behind `state` to `observer` every `interval`. The first emission
+    /// comprises the entire state.
+    pub fn new<V, E>(
+        state: StateHandle<V>,
+        observer: SharedObserver<OptionalObserver<ObserverBox<Update<V>, E>>>,
+        interval: Duration,
+    ) -> Self
+    where
+        V: Clone + Debug + Eq + Hash + Send + 'static,
+        E: Debug + Send + 'static,
+    {
+        let id = Id::<()>::new().get();
+        trace!("SnapshotTimer({})::new({:?})", id, interval);
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_stopped = stopped.clone();
+        let thread = spawn(move || {
+            let mut observer = observer;
+            let mut sent = HashMap::new();
+            loop {
+                park_timeout(interval);
+                if thread_stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                let current = state.get_current_state();
+                let updates = diff_states(&sent, &current);
+                sent = current;
+                if updates.is_empty() {
+                    continue;
+                }
+
+                trace!("SnapshotTimer({}) emitting {} updates", id, updates.len());
+                let result = observer
+                    .on_start()
+                    .and_then(|_| observer.on_updates(Box::new(updates.into_iter())))
+                    .and_then(|_| observer.on_commit());
+                if let Err(e) = result {
+                    trace!("SnapshotTimer({}) observer failed: {:?}", id, e);
+                }
+            }
+        });
+
+        Self {
+            id,
+            stopped,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for SnapshotTimer {
+    fn drop(&mut self) {
+        trace!("SnapshotTimer({})::drop", self.id);
+        self.stopped.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A thread that emits an empty transaction, a heartbeat, at the end of
+/// every interval in which an accumulator committed no transactions,
+/// e.g., to let observers tell an idle accumulator from a dead one.
+///
+/// The thread is stopped when the timer is dropped.
+#[derive(Debug)]
+pub struct HeartbeatTimer {
+    /// The timer's unique ID.
+    id: usize,
+    /// Flag indicating to the thread that it should stop.
+    stopped: Arc<AtomicBool>,
+    /// The thread emitting the heartbeats.
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HeartbeatTimer {
+    /// Create a new `HeartbeatTimer` emitting heartbeats to `observer`
+    /// at the end of every `interval` in which the number of committed
+    /// transactions behind `committed` did not change.
+    pub fn new<V, E>(
+        committed: Arc<AtomicUsize>,
+        observer: SharedObserver<OptionalObserver<ObserverBox<Update<V>, E>>>,
+        interval: Duration,
+    ) -> Self
+    where
+        V: Debug + Send + 'static,
+        E: Debug + Send + 'static,
+    {
+        let id = Id::<()>::new().get();
+        trace!("HeartbeatTimer({})::new({:?})", id, interval);
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_stopped = stopped.clone();
+        let thread = spawn(move || {
+            let mut observer = observer;
+            let mut last = committed.load(Ordering::SeqCst);
+            loop {
+                park_timeout(interval);
+                if thread_stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                let current = committed.load(Ordering::SeqCst);
+                if current != last {
+                    last = current;
+                    continue;
+                }
+
+                trace!("HeartbeatTimer({}) emitting heartbeat", id);
+                let result = observer.on_start().and_then(|_| observer.on_commit());
+                if let Err(e) = result {
+                    trace!("HeartbeatTimer({}) observer failed: {:?}", id, e);
+                }
+            }
+        });
+
+        Self {
+            id,
+            stopped,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for HeartbeatTimer {
+    fn drop(&mut self) {
+        trace!("HeartbeatTimer({})::drop", self.id);
+        self.stopped.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
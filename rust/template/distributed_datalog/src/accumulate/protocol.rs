@@ -0,0 +1,52 @@
+use std::error::Error;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+
+/// The way an `AccumulatingObserver` treats events violating the
+/// transaction protocol, e.g., an `on_commit` without a preceding
+/// `on_start`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProtocolPolicy {
+    /// Panic. This is the behavior of an observer created via
+    /// `AccumulatingObserver::new`.
+    Panic,
+    /// Drop the event and report a `ProtocolViolation` to the caller.
+    Error,
+    /// Drop the event and log a warning.
+    Ignore,
+}
+
+/// A violation of the transaction protocol by the events an observer
+/// receives.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProtocolViolation {
+    /// An `on_start` was received while a transaction was in progress.
+    StartInTransaction,
+    /// An `on_commit` was received outside of a transaction.
+    CommitWithoutStart,
+    /// An `on_updates` was received outside of a transaction.
+    UpdatesWithoutStart,
+    /// An `on_abort` was received outside of a transaction.
+    AbortWithoutStart,
+}
+
+impl Display for ProtocolViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let message = match self {
+            ProtocolViolation::StartInTransaction => "received multiple on_start events",
+            ProtocolViolation::CommitWithoutStart => {
+                "on_commit was not preceded by an on_start event"
+            }
+            ProtocolViolation::UpdatesWithoutStart => {
+                "on_updates was not preceded by an on_start event"
+            }
+            ProtocolViolation::AbortWithoutStart => {
+                "on_abort was not preceded by an on_start event"
+            }
+        };
+        f.write_str(message)
+    }
+}
+
+impl Error for ProtocolViolation {}
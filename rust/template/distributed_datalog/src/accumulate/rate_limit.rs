@@ -0,0 +1,437 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::mpsc::channel;
+use std::sync::mpsc::sync_channel;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread::sleep;
+use std::thread::spawn;
+use std::time::Duration;
+use std::time::Instant;
+
+use log::error;
+use log::trace;
+use uid::Id;
+
+use differential_datalog::program::Update;
+
+use crate::accumulate::map::Slot;
+use crate::accumulate::recording::DeliveryEvent;
+use crate::accumulate::RecordedEvent;
+use crate::Observable;
+use crate::ObservableBox;
+use crate::Observer;
+use crate::ObserverBox;
+
+/// A token bucket holding up to a second worth of tokens, each of which
+/// permits the delivery of a single update.
+#[derive(Debug)]
+struct TokenBucket {
+    /// The number of tokens added per second.
+    rate: f64,
+    /// The number of tokens currently available.
+    tokens: f64,
+    /// The time the bucket was last refilled.
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    /// Create a new, full `TokenBucket`.
+    fn new(max_updates_per_sec: usize) -> Self {
+        let rate = max_updates_per_sec as f64;
+        Self {
+            rate,
+            tokens: rate,
+            refilled: Instant::now(),
+        }
+    }
+
+    /// Take up to `max` tokens, waiting for at least one to become
+    /// available, and return the number of tokens taken.
+    fn take(&mut self, max: usize) -> usize {
+        loop {
+            let now = Instant::now();
+            let elapsed = now.duration_since(self.refilled).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+            self.refilled = now;
+
+            if self.tokens >= 1.0 {
+                let taken = (self.tokens as usize).min(max);
+                self.tokens -= taken as f64;
+                return taken;
+            }
+            sleep(Duration::from_secs_f64((1.0 - self.tokens) / self.rate));
+        }
+    }
+}
+
+/// Deliver the events received to the observer in the given slot,
+/// pacing the delivery of updates according to the token bucket.
+fn deliver<V, E>(
+    id: usize,
+    events: Receiver<DeliveryEvent<V>>,
+    mut bucket: TokenBucket,
+    mut observer: Slot<V, E>,
+) where
+    V: Debug + Send,
+    E: Debug + Send,
+{
+    // the thread exits once the `RateLimitingObserver` was dropped
+    for event in events {
+        let result = match event {
+            DeliveryEvent::Recorded(RecordedEvent::Start) => observer.on_start(),
+            DeliveryEvent::Recorded(RecordedEvent::StartSeq(seq)) => observer.on_start_seq(seq),
+            DeliveryEvent::Recorded(RecordedEvent::Updates(updates)) => {
+                let mut updates = updates.into_iter().peekable();
+                let mut result = Ok(());
+                while result.is_ok() && updates.peek().is_some() {
+                    let count = bucket.take(updates.len());
+                    result = observer.on_updates(Box::new(updates.by_ref().take(count)));
+                }
+                result
+            }
+            DeliveryEvent::Recorded(RecordedEvent::Commit) => observer.on_commit(),
+            DeliveryEvent::Recorded(RecordedEvent::Abort) => observer.on_abort(),
+            DeliveryEvent::Recorded(RecordedEvent::Completed) => observer.on_completed(),
+            DeliveryEvent::InitComplete => observer.on_init_complete(),
+            DeliveryEvent::Barrier(done) => {
+                let result = observer.on_barrier();
+                let _ = done.send(());
+                result
+            }
+        };
+        if let Err(e) = result {
+            error!(
+                "RateLimitedObservable({}) failed to deliver event: {:?}",
+                id, e
+            );
+        }
+    }
+}
+
+/// An observer handing the events it receives to the thread delivering
+/// them at a limited rate.
+#[derive(Debug)]
+struct RateLimitingObserver<V> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The channel to the thread delivering the events.
+    events: Sender<DeliveryEvent<V>>,
+}
+
+impl<V> RateLimitingObserver<V> {
+    /// Hand an event to the delivering thread.
+    fn send<E>(&self, event: RecordedEvent<V>) -> Result<(), E> {
+        // the thread only exits once we are dropped
+        let _ = self.events.send(DeliveryEvent::Recorded(event));
+        Ok(())
+    }
+}
+
+impl<V, E> Observer<Update<V>, E> for RateLimitingObserver<V>
+where
+    V: Debug + Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("RateLimitingObserver({})::on_start", self.id);
+        self.send(RecordedEvent::Start)
+    }
+
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        trace!("RateLimitingObserver({})::on_start_seq({})", self.id, seq);
+        self.send(RecordedEvent::StartSeq(seq))
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("RateLimitingObserver({})::on_commit", self.id);
+        self.send(RecordedEvent::Commit)
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("RateLimitingObserver({})::on_updates", self.id);
+        self.send(RecordedEvent::Updates(updates.collect()))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("RateLimitingObserver({})::on_abort", self.id);
+        self.send(RecordedEvent::Abort)
+    }
+
+    /// Block until all events received so far were delivered, at the
+    /// limited rate, to the subscribed observer.
+    fn on_barrier(&mut self) -> Result<(), E> {
+        trace!("RateLimitingObserver({})::on_barrier", self.id);
+        let (done, drained) = sync_channel(1);
+        let _ = self.events.send(DeliveryEvent::Barrier(done));
+        let _ = drained.recv();
+        Ok(())
+    }
+
+    fn on_init_complete(&mut self) -> Result<(), E> {
+        trace!("RateLimitingObserver({})::on_init_complete", self.id);
+        let _ = self.events.send(DeliveryEvent::InitComplete);
+        Ok(())
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("RateLimitingObserver({})::on_completed", self.id);
+        self.send(RecordedEvent::Completed)
+    }
+}
+
+/// An observable emitting the updates of another observable at a
+/// limited rate, e.g., to protect a fragile downstream from bursts.
+///
+/// Delivery is paced by a token bucket permitting a burst of up to a
+/// second worth of updates. Updates exceeding the rate are queued, and
+/// events are delivered on a dedicated thread per subscription, so
+/// errors of the subscribed observer are logged rather than reported
+/// upstream. A transaction's updates may be spread over multiple
+/// `on_updates` calls, but transaction boundaries are preserved.
+#[derive(Debug)]
+pub struct RateLimitedObservable<V, E> {
+    /// The observable's unique ID.
+    id: usize,
+    /// The observable whose updates we pace.
+    observable: ObservableBox<Update<V>, E>,
+    /// The maximum number of updates delivered per second.
+    max_updates_per_sec: usize,
+    /// The subscriptions to the wrapped observable along with the
+    /// observers subscribed through them, for each subscription.
+    subscriptions: HashMap<usize, (Box<dyn Any + Send>, Slot<V, E>)>,
+}
+
+impl<V, E> RateLimitedObservable<V, E> {
+    /// Create a new `RateLimitedObservable` emitting the updates of the
+    /// given observable at a rate of at most `max_updates_per_sec`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_updates_per_sec` is zero.
+    pub fn new(observable: ObservableBox<Update<V>, E>, max_updates_per_sec: usize) -> Self {
+        assert!(max_updates_per_sec > 0, "rate must not be zero");
+
+        let id = Id::<()>::new().get();
+        trace!(
+            "RateLimitedObservable({})::new({})",
+            id,
+            max_updates_per_sec
+        );
+
+        Self {
+            id,
+            observable,
+            max_updates_per_sec,
+            subscriptions: HashMap::new(),
+        }
+    }
+}
+
+impl<V, E> Observable<Update<V>, E> for RateLimitedObservable<V, E>
+where
+    V: Debug + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    type Subscription = usize;
+
+    fn subscribe(
+        &mut self,
+        observer: ObserverBox<Update<V>, E>,
+    ) -> Result<Self::Subscription, ObserverBox<Update<V>, E>> {
+        let id = Id::<()>::new().get();
+        trace!("RateLimitedObservable({})::subscribe({})", self.id, id);
+
+        let observer = Arc::new(Mutex::new(Some(observer)));
+        let (events, receiver) = channel();
+        let limiting = RateLimitingObserver { id, events };
+        match self.observable.subscribe_any(Box::new(limiting)) {
+            Ok(subscription) => {
+                let bucket = TokenBucket::new(self.max_updates_per_sec);
+                let slot = observer.clone();
+                let _ = spawn(move || deliver(id, receiver, bucket, slot));
+                let _ = self.subscriptions.insert(id, (subscription, observer));
+                Ok(id)
+            }
+            Err(_) => Err(observer.lock().unwrap().take().unwrap()),
+        }
+    }
+
+    fn unsubscribe(
+        &mut self,
+        subscription: &Self::Subscription,
+    ) -> Option<ObserverBox<Update<V>, E>> {
+        trace!(
+            "RateLimitedObservable({})::unsubscribe({})",
+            self.id,
+            subscription
+        );
+        let (subscription, observer) = self.subscriptions.remove(subscription)?;
+        let _ = self.observable.unsubscribe_any(subscription.as_ref());
+        let observer = observer.lock().unwrap().take();
+        observer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::accumulate::accumulator::tests::get_usize_updates_1;
+    use crate::accumulate::accumulator::tests::get_usize_updates_3;
+    use crate::await_expected;
+    use crate::Accumulator;
+    use crate::DistributingAccumulator;
+
+    /// An observer recording the time at which it received each event.
+    #[derive(Debug, Default)]
+    struct TimedObserver {
+        events: Vec<(Instant, RecordedEvent<usize>)>,
+    }
+
+    impl Observer<Update<usize>, ()> for TimedObserver {
+        fn on_start(&mut self) -> Result<(), ()> {
+            self.events.push((Instant::now(), RecordedEvent::Start));
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), ()> {
+            self.events.push((Instant::now(), RecordedEvent::Commit));
+            Ok(())
+        }
+
+        fn on_updates<'a>(
+            &mut self,
+            updates: Box<dyn Iterator<Item = Update<usize>> + 'a>,
+        ) -> Result<(), ()> {
+            self.events
+                .push((Instant::now(), RecordedEvent::Updates(updates.collect())));
+            Ok(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), ()> {
+            self.events.push((Instant::now(), RecordedEvent::Completed));
+            Ok(())
+        }
+    }
+
+    /// Test that a burst of updates is spread out over time without
+    /// interleaving the transactions it consists of.
+    #[test]
+    fn rate_limited_burst() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mut limited = RateLimitedObservable::new(Box::new(accumulator.create_observable()), 10);
+        let timed = Arc::new(Mutex::new(TimedObserver::default()));
+        assert!(limited.subscribe(Box::new(timed.clone())).is_ok());
+
+        let start = Instant::now();
+        for txn in 0..3 {
+            let updates = (0..5)
+                .map(|v| Update::Insert {
+                    relid: txn,
+                    v: txn * 5 + v,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(accumulator.on_start(), Ok(()));
+            assert_eq!(
+                accumulator.on_updates(Box::new(updates.into_iter())),
+                Ok(())
+            );
+            assert_eq!(accumulator.on_commit(), Ok(()));
+        }
+
+        await_expected(|| {
+            let commits = timed
+                .lock()
+                .unwrap()
+                .events
+                .iter()
+                .filter(|(_, e)| matches!(e, RecordedEvent::Commit))
+                .count();
+            assert_eq!(commits, 3);
+        });
+
+        let events = timed.lock().unwrap().events.clone();
+        let mut in_transaction = false;
+        let mut values = Vec::new();
+        for (_, event) in &events {
+            match event {
+                RecordedEvent::Start | RecordedEvent::StartSeq(_) => {
+                    assert!(!in_transaction);
+                    in_transaction = true;
+                }
+                RecordedEvent::Updates(updates) => {
+                    assert!(in_transaction);
+                    values.extend(updates.iter().map(|u| match u {
+                        Update::Insert { relid, v } => {
+                            assert_eq!(*relid, v / 5);
+                            *v
+                        }
+                        u => panic!("unexpected update {:?}", u),
+                    }));
+                }
+                RecordedEvent::Commit => {
+                    assert!(in_transaction);
+                    in_transaction = false;
+                }
+                RecordedEvent::Abort => panic!("unexpected abort"),
+                RecordedEvent::Completed => panic!("unexpected completion"),
+            }
+        }
+        assert_eq!(values, (0..15).collect::<Vec<_>>());
+
+        // the first ten updates fit into the bucket, the remaining five
+        // are released at a rate of ten per second
+        let (last, _) = events.last().unwrap();
+        assert!(last.duration_since(start) >= Duration::from_millis(400));
+    }
+
+    /// Test that an aborted transaction is forwarded as such, so that
+    /// its updates never reach the state of a downstream accumulator.
+    #[test]
+    fn rate_limited_abort() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mut limited = RateLimitedObservable::new(Box::new(accumulator.create_observable()), 10);
+        let downstream = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let downstream = Arc::new(Mutex::new(downstream));
+        assert!(limited.subscribe(Box::new(downstream.clone())).is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_abort(), Ok(()));
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        await_expected(|| {
+            let state = downstream.lock().unwrap().get_current_state();
+            assert_eq!(state.len(), 1);
+            assert_eq!(state[&4].len(), 4);
+        });
+    }
+
+    /// Test that a barrier only returns once the updates held back by
+    /// the rate limit were delivered.
+    #[test]
+    fn rate_limited_barrier() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mut limited = RateLimitedObservable::new(Box::new(accumulator.create_observable()), 2);
+        let downstream = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let downstream = Arc::new(Mutex::new(downstream));
+        assert!(limited.subscribe(Box::new(downstream.clone())).is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        assert_eq!(accumulator.barrier(), Ok(()));
+
+        let state = downstream.lock().unwrap().get_current_state();
+        assert_eq!(state.len(), 3);
+    }
+}
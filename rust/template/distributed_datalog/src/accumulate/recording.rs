@@ -0,0 +1,248 @@
+//! Recording of the events an observer receives and their deterministic
+//! replay, e.g., for reproducing a problem observed in the field
+//! against a fresh accumulator.
+
+use std::fmt::Debug;
+use std::sync::mpsc::SyncSender;
+
+use log::trace;
+use serde::Deserialize;
+use serde::Serialize;
+use uid::Id;
+
+use differential_datalog::program::Update;
+
+use crate::Observer;
+use crate::ObserverBox;
+
+/// An event received by a `RecordingObserver`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(bound(
+    serialize = "Update<V>: Serialize",
+    deserialize = "Update<V>: serde::de::DeserializeOwned"
+))]
+pub enum RecordedEvent<V> {
+    /// A transaction was started.
+    Start,
+    /// A batch of updates was received.
+    Updates(Vec<Update<V>>),
+    /// A transaction was committed.
+    Commit,
+    /// The observable completed.
+    Completed,
+    /// A transaction was started with the given sequence number.
+    StartSeq(u64),
+    /// The transaction in progress was aborted.
+    Abort,
+}
+
+/// An event handed to a thread delivering events to an observer
+/// asynchronously.
+#[derive(Debug)]
+pub(crate) enum DeliveryEvent<V> {
+    /// An event to deliver.
+    Recorded(RecordedEvent<V>),
+    /// A barrier was set up. The sender is notified once all events
+    /// preceding it were delivered.
+    Barrier(SyncSender<()>),
+    /// The observer was sent the accumulated state upon subscription.
+    InitComplete,
+}
+
+/// An observer recording all events it receives before forwarding them
+/// to the wrapped observer.
+#[derive(Debug)]
+pub struct RecordingObserver<V, E> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The observer all events are forwarded to.
+    observer: ObserverBox<Update<V>, E>,
+    /// The events recorded so far.
+    events: Vec<RecordedEvent<V>>,
+}
+
+impl<V, E> RecordingObserver<V, E> {
+    /// Create a new `RecordingObserver` forwarding all events to the
+    /// given observer.
+    pub fn new(observer: ObserverBox<Update<V>, E>) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("RecordingObserver({})::new", id);
+
+        Self {
+            id,
+            observer,
+            events: Vec::new(),
+        }
+    }
+
+    /// Retrieve the events recorded so far.
+    pub fn events(&self) -> &[RecordedEvent<V>] {
+        &self.events
+    }
+
+    /// Retrieve the events recorded so far, clearing the recording.
+    pub fn take_events(&mut self) -> Vec<RecordedEvent<V>> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+impl<V, E> Observer<Update<V>, E> for RecordingObserver<V, E>
+where
+    V: Debug + Clone + Send,
+    E: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("RecordingObserver({})::on_start", self.id);
+        self.events.push(RecordedEvent::Start);
+        self.observer.on_start()
+    }
+
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        trace!("RecordingObserver({})::on_start_seq({})", self.id, seq);
+        self.events.push(RecordedEvent::StartSeq(seq));
+        self.observer.on_start_seq(seq)
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("RecordingObserver({})::on_commit", self.id);
+        self.events.push(RecordedEvent::Commit);
+        self.observer.on_commit()
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("RecordingObserver({})::on_updates", self.id);
+        let updates = updates.collect::<Vec<_>>();
+        self.events.push(RecordedEvent::Updates(updates.clone()));
+        self.observer.on_updates(Box::new(updates.into_iter()))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("RecordingObserver({})::on_abort", self.id);
+        self.events.push(RecordedEvent::Abort);
+        self.observer.on_abort()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("RecordingObserver({})::on_completed", self.id);
+        self.events.push(RecordedEvent::Completed);
+        self.observer.on_completed()
+    }
+}
+
+/// Drive the given observer through a sequence of recorded events,
+/// stopping at the first error it reports.
+pub fn replay<V, E, O>(events: &[RecordedEvent<V>], target: &mut O) -> Result<(), E>
+where
+    V: Clone + Send,
+    E: Send,
+    O: Observer<Update<V>, E> + ?Sized,
+{
+    for event in events {
+        match event {
+            RecordedEvent::Start => target.on_start()?,
+            RecordedEvent::StartSeq(seq) => target.on_start_seq(*seq)?,
+            RecordedEvent::Updates(updates) => {
+                target.on_updates(Box::new(updates.iter().cloned()))?
+            }
+            RecordedEvent::Commit => target.on_commit()?,
+            RecordedEvent::Abort => target.on_abort()?,
+            RecordedEvent::Completed => target.on_completed()?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use crate::accumulate::accumulator::tests::get_usize_updates_1;
+    use crate::accumulate::accumulator::tests::get_usize_updates_3;
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::Accumulator;
+    use crate::DistributingAccumulator;
+
+    /// Test that replaying the events recorded in front of an
+    /// accumulator into a fresh one yields the same state.
+    #[test]
+    fn record_and_replay() {
+        let accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let accumulator = Arc::new(Mutex::new(accumulator));
+        let mut recorder = RecordingObserver::new(Box::new(accumulator.clone()));
+
+        assert_eq!(recorder.on_start(), Ok(()));
+        assert_eq!(recorder.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(recorder.on_commit(), Ok(()));
+        assert_eq!(recorder.on_start(), Ok(()));
+        assert_eq!(recorder.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(recorder.on_commit(), Ok(()));
+        assert_eq!(recorder.events().len(), 6);
+
+        let mut replayed = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(replay(recorder.events(), &mut replayed), Ok(()));
+        let state = accumulator.lock().unwrap().get_current_state();
+        assert!(!state.is_empty());
+        assert_eq!(replayed.get_current_state(), state);
+
+        assert_eq!(recorder.on_completed(), Ok(()));
+        let events = recorder.take_events();
+        assert!(recorder.events().is_empty());
+        assert!(matches!(events.last(), Some(RecordedEvent::Completed)));
+
+        let mut replayed = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(replay(&events, &mut replayed), Ok(()));
+        assert_eq!(
+            replayed.get_current_state(),
+            accumulator.lock().unwrap().get_current_state()
+        );
+    }
+
+    /// Test that the sequence numbers of transactions are recorded and
+    /// replayed.
+    #[test]
+    fn record_and_replay_sequence() {
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let mut recorder = RecordingObserver::<_, ()>::new(Box::new(mock.clone()));
+
+        assert_eq!(recorder.on_start_seq(7), Ok(()));
+        assert_eq!(recorder.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(recorder.on_commit(), Ok(()));
+        assert!(matches!(recorder.events()[0], RecordedEvent::StartSeq(7)));
+        assert_eq!(mock.lock().unwrap().received_sequences, vec![7]);
+
+        let mut replayed = UpdatesMockObserver::new();
+        assert_eq!(replay::<_, (), _>(recorder.events(), &mut replayed), Ok(()));
+        assert_eq!(replayed.received_sequences, vec![7]);
+        assert_eq!(replayed.received_updates.len(), 3);
+    }
+
+    /// Test that an aborted transaction is recorded as such and neither
+    /// reaches the wrapped accumulator's state nor a replayed one.
+    #[test]
+    fn record_and_replay_abort() {
+        let accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let accumulator = Arc::new(Mutex::new(accumulator));
+        let mut recorder = RecordingObserver::new(Box::new(accumulator.clone()));
+
+        assert_eq!(recorder.on_start(), Ok(()));
+        assert_eq!(recorder.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(recorder.on_abort(), Ok(()));
+        assert!(matches!(recorder.events()[2], RecordedEvent::Abort));
+        assert!(accumulator.lock().unwrap().get_current_state().is_empty());
+
+        assert_eq!(recorder.on_start(), Ok(()));
+        assert_eq!(recorder.on_updates(get_usize_updates_3()), Ok(()));
+        assert_eq!(recorder.on_commit(), Ok(()));
+        let state = accumulator.lock().unwrap().get_current_state();
+
+        let mut replayed = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(replay(recorder.events(), &mut replayed), Ok(()));
+        assert_eq!(replayed.get_current_state(), state);
+    }
+}
@@ -0,0 +1,263 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use log::trace;
+use uid::Id;
+
+use differential_datalog::program::RelId;
+use differential_datalog::program::Update;
+
+use crate::accumulate::map::Slot;
+use crate::Observable;
+use crate::ObservableBox;
+use crate::Observer;
+use crate::ObserverBox;
+
+/// Change the relation of an update.
+fn with_relid<V>(update: Update<V>, relid: RelId) -> Update<V> {
+    match update {
+        Update::Insert { v, .. } => Update::Insert { relid, v },
+        Update::InsertOrUpdate { v, .. } => Update::InsertOrUpdate { relid, v },
+        Update::DeleteValue { v, .. } => Update::DeleteValue { relid, v },
+        Update::DeleteKey { k, .. } => Update::DeleteKey { relid, k },
+        Update::Modify { k, m, .. } => Update::Modify { relid, k, m },
+    }
+}
+
+/// An observer translating the relation of each update it receives
+/// according to a mapping before forwarding it, e.g., to feed the
+/// output of one program into another one with a different numbering
+/// of relations.
+#[derive(Debug)]
+pub struct RelIdMapObserver<O> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The observer we forward the translated updates to.
+    observer: O,
+    /// The mapping from the relations of the updates we receive to the
+    /// relations of the updates we forward.
+    relids: Arc<HashMap<RelId, RelId>>,
+    /// Whether updates of relations absent from the mapping are
+    /// forwarded unchanged rather than dropped.
+    pass_unmapped: bool,
+}
+
+impl<O> RelIdMapObserver<O> {
+    /// Create a new `RelIdMapObserver` translating relations according
+    /// to `relids`. Updates of relations absent from the mapping are
+    /// forwarded unchanged if `pass_unmapped` is set and dropped
+    /// otherwise.
+    pub fn new(observer: O, relids: HashMap<RelId, RelId>, pass_unmapped: bool) -> Self {
+        Self::with_shared(observer, Arc::new(relids), pass_unmapped)
+    }
+
+    /// Create a new `RelIdMapObserver` sharing the given mapping.
+    fn with_shared(observer: O, relids: Arc<HashMap<RelId, RelId>>, pass_unmapped: bool) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("RelIdMapObserver({})::new", id);
+
+        Self {
+            id,
+            observer,
+            relids,
+            pass_unmapped,
+        }
+    }
+}
+
+impl<O, V, E> Observer<Update<V>, E> for RelIdMapObserver<O>
+where
+    O: Observer<Update<V>, E>,
+    V: Debug + Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("RelIdMapObserver({})::on_start", self.id);
+        self.observer.on_start()
+    }
+
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        trace!("RelIdMapObserver({})::on_start_seq({})", self.id, seq);
+        self.observer.on_start_seq(seq)
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("RelIdMapObserver({})::on_commit", self.id);
+        self.observer.on_commit()
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("RelIdMapObserver({})::on_updates", self.id);
+        let relids = &self.relids;
+        let pass_unmapped = self.pass_unmapped;
+        let updates = updates
+            .filter_map(|u| match relids.get(&u.relid()) {
+                Some(relid) => Some(with_relid(u, *relid)),
+                None if pass_unmapped => Some(u),
+                None => None,
+            })
+            .collect::<Vec<_>>();
+        if updates.is_empty() {
+            return Ok(());
+        }
+        self.observer.on_updates(Box::new(updates.into_iter()))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("RelIdMapObserver({})::on_abort", self.id);
+        self.observer.on_abort()
+    }
+
+    fn on_barrier(&mut self) -> Result<(), E> {
+        trace!("RelIdMapObserver({})::on_barrier", self.id);
+        self.observer.on_barrier()
+    }
+
+    fn on_init_complete(&mut self) -> Result<(), E> {
+        trace!("RelIdMapObserver({})::on_init_complete", self.id);
+        self.observer.on_init_complete()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("RelIdMapObserver({})::on_completed", self.id);
+        self.observer.on_completed()
+    }
+}
+
+/// An observable emitting the updates of another observable with their
+/// relations translated according to a mapping, as performed by a
+/// `RelIdMapObserver`.
+#[derive(Debug)]
+pub struct RelIdMapObservable<V, E> {
+    /// The observable's unique ID.
+    id: usize,
+    /// The observable whose updates we translate.
+    observable: ObservableBox<Update<V>, E>,
+    /// The mapping from the relations of the wrapped observable to the
+    /// relations of the updates we emit.
+    relids: Arc<HashMap<RelId, RelId>>,
+    /// Whether updates of relations absent from the mapping are emitted
+    /// unchanged rather than dropped.
+    pass_unmapped: bool,
+    /// The subscriptions to the wrapped observable along with the
+    /// observers subscribed through them, for each subscription.
+    subscriptions: HashMap<usize, (Box<dyn Any + Send>, Slot<V, E>)>,
+}
+
+impl<V, E> RelIdMapObservable<V, E> {
+    /// Create a new `RelIdMapObservable` emitting the updates of the
+    /// given observable with their relations translated according to
+    /// `relids`. Updates of relations absent from the mapping are
+    /// emitted unchanged if `pass_unmapped` is set and dropped
+    /// otherwise.
+    pub fn new(
+        observable: ObservableBox<Update<V>, E>,
+        relids: HashMap<RelId, RelId>,
+        pass_unmapped: bool,
+    ) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("RelIdMapObservable({})::new", id);
+
+        Self {
+            id,
+            observable,
+            relids: Arc::new(relids),
+            pass_unmapped,
+            subscriptions: HashMap::new(),
+        }
+    }
+}
+
+impl<V, E> Observable<Update<V>, E> for RelIdMapObservable<V, E>
+where
+    V: Debug + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    type Subscription = usize;
+
+    fn subscribe(
+        &mut self,
+        observer: ObserverBox<Update<V>, E>,
+    ) -> Result<Self::Subscription, ObserverBox<Update<V>, E>> {
+        let id = Id::<()>::new().get();
+        trace!("RelIdMapObservable({})::subscribe({})", self.id, id);
+
+        let observer = Arc::new(Mutex::new(Some(observer)));
+        let mapping = RelIdMapObserver::with_shared(
+            observer.clone(),
+            self.relids.clone(),
+            self.pass_unmapped,
+        );
+        match self.observable.subscribe_any(Box::new(mapping)) {
+            Ok(subscription) => {
+                let _ = self.subscriptions.insert(id, (subscription, observer));
+                Ok(id)
+            }
+            Err(_) => Err(observer.lock().unwrap().take().unwrap()),
+        }
+    }
+
+    fn unsubscribe(
+        &mut self,
+        subscription: &Self::Subscription,
+    ) -> Option<ObserverBox<Update<V>, E>> {
+        trace!(
+            "RelIdMapObservable({})::unsubscribe({})",
+            self.id,
+            subscription
+        );
+        let (subscription, observer) = self.subscriptions.remove(subscription)?;
+        let _ = self.observable.unsubscribe_any(subscription.as_ref());
+        let observer = observer.lock().unwrap().take();
+        observer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use maplit::hashmap;
+
+    use crate::accumulate::accumulator::tests::get_usize_updates_1;
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::Accumulator;
+    use crate::DistributingAccumulator;
+
+    /// Feed `get_usize_updates_1` through an accumulator to an observer
+    /// subscribed to a `RelIdMapObservable` mapping relation 1 to 100,
+    /// returning the relations of the updates received.
+    fn remap(pass_unmapped: bool) -> Vec<RelId> {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let observable = accumulator.create_observable();
+        let mut remapped =
+            RelIdMapObservable::new(Box::new(observable), hashmap! {1 => 100}, pass_unmapped);
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(remapped.subscribe(Box::new(mock.clone())).is_ok());
+
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(get_usize_updates_1()), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let received = mock.lock().unwrap().received_updates.clone();
+        received.iter().map(Update::relid).collect()
+    }
+
+    /// Test that unmapped relations are dropped if so configured.
+    #[test]
+    fn remap_drop_unmapped() {
+        assert_eq!(remap(false), vec![100]);
+    }
+
+    /// Test that unmapped relations are passed through if so configured.
+    #[test]
+    fn remap_pass_unmapped() {
+        assert_eq!(remap(true), vec![100, 2, 3]);
+    }
+}
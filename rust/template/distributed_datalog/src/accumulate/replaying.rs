@@ -0,0 +1,390 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use log::trace;
+use uid::Id;
+
+use differential_datalog::program::Update;
+
+use crate::accumulate::FilteringObserver;
+use crate::accumulate::StateHandle;
+use crate::accumulate::TxnDistributor;
+use crate::Observable;
+use crate::Observer;
+use crate::ObserverBox;
+
+/// An observer subscribed to a `ReplayingObservable`.
+#[derive(Debug)]
+enum Target<V, E> {
+    /// An observer receiving all events passed through.
+    Plain(ObserverBox<Update<V>, E>),
+    /// An observer of a filtered observable, receiving only the updates
+    /// satisfying its predicate.
+    Filtered(FilteringObserver<ObserverBox<Update<V>, E>, V>),
+}
+
+impl<V, E> Target<V, E>
+where
+    V: Debug + Send,
+    E: Send,
+{
+    /// Retrieve the observer to deliver events to.
+    fn observer(&mut self) -> &mut dyn Observer<Update<V>, E> {
+        match self {
+            Target::Plain(observer) => observer,
+            Target::Filtered(observer) => observer,
+        }
+    }
+
+    /// Retrieve the observer as it was subscribed.
+    fn into_inner(self) -> ObserverBox<Update<V>, E> {
+        match self {
+            Target::Plain(observer) => observer,
+            Target::Filtered(observer) => observer.into_inner(),
+        }
+    }
+}
+
+/// An observer subscribed to a `ReplayingObservable`.
+#[derive(Debug)]
+struct Subscriber<V, E> {
+    /// The observer subscribed.
+    target: Target<V, E>,
+    /// Whether the observer receives the events passed through, which
+    /// it only does from the start of a transaction on.
+    attached: bool,
+}
+
+/// The observers subscribed to a `ReplayingObservable` and the progress
+/// of sending them the accumulated state.
+#[derive(Debug)]
+struct Replay<V, E> {
+    /// The observers subscribed to the observable, by subscription.
+    subscribers: BTreeMap<usize, Subscriber<V, E>>,
+    /// Whether a transaction is in progress.
+    in_transaction: bool,
+    /// Whether the state was sent already.
+    replayed: bool,
+    /// Whether only the first observer is sent the state.
+    once: bool,
+}
+
+impl<V, E> Replay<V, E>
+where
+    V: Clone + Debug + Eq + Hash + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    /// Let the observers not yet attached receive the events passed
+    /// through from now on, sending each of them the accumulated state
+    /// first unless we did so already and only the first observer is to
+    /// be sent it.
+    fn attach(&mut self, state: &StateHandle<V>) {
+        for subscriber in self.subscribers.values_mut() {
+            if subscriber.attached {
+                continue;
+            }
+            subscriber.attached = true;
+            if self.replayed && self.once {
+                continue;
+            }
+            self.replayed = true;
+
+            let updates = state
+                .get_current_state()
+                .into_iter()
+                .flat_map(|(relid, vs)| vs.into_iter().map(move |v| Update::Insert { relid, v }))
+                .collect::<Vec<_>>();
+            if !updates.is_empty() {
+                let observer = subscriber.target.observer();
+                let _ = observer.on_start();
+                let _ = observer.on_updates(Box::new(updates.into_iter()));
+                let _ = observer.on_commit();
+            }
+        }
+    }
+
+    /// Invoke the given function on each attached observer, reporting
+    /// the first error, if any.
+    fn deliver<F>(&mut self, mut f: F) -> Result<(), E>
+    where
+        F: FnMut(&mut dyn Observer<Update<V>, E>) -> Result<(), E>,
+    {
+        self.subscribers
+            .values_mut()
+            .filter(|subscriber| subscriber.attached)
+            .map(|subscriber| f(subscriber.target.observer()))
+            .fold(Ok(()), Result::and)
+    }
+}
+
+/// An observer passing the events of an accumulator through to the
+/// observers subscribed to a `ReplayingObservable`.
+#[derive(Debug)]
+struct ReplayingObserver<V, E> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The replay shared with the `ReplayingObservable`.
+    replay: Arc<Mutex<Replay<V, E>>>,
+    /// A handle on the accumulated state.
+    state: StateHandle<V>,
+}
+
+impl<V, E> ReplayingObserver<V, E>
+where
+    V: Clone + Debug + Eq + Hash + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    /// Start a transaction, attaching the observers that subscribed
+    /// while the previous one was in progress.
+    fn start<F>(&mut self, f: F) -> Result<(), E>
+    where
+        F: FnMut(&mut dyn Observer<Update<V>, E>) -> Result<(), E>,
+    {
+        let mut replay = self.replay.lock().unwrap();
+        // the state does not yet reflect the transaction being started
+        replay.attach(&self.state);
+        replay.in_transaction = true;
+        replay.deliver(f)
+    }
+}
+
+impl<V, E> Observer<Update<V>, E> for ReplayingObserver<V, E>
+where
+    V: Clone + Debug + Eq + Hash + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("ReplayingObserver({})::on_start", self.id);
+        self.start(|observer| observer.on_start())
+    }
+
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        trace!("ReplayingObserver({})::on_start_seq({})", self.id, seq);
+        self.start(|observer| observer.on_start_seq(seq))
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("ReplayingObserver({})::on_commit", self.id);
+        let mut replay = self.replay.lock().unwrap();
+        replay.in_transaction = false;
+        replay.deliver(|observer| observer.on_commit())
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("ReplayingObserver({})::on_updates", self.id);
+        let updates = updates.collect::<Vec<_>>();
+        self.replay
+            .lock()
+            .unwrap()
+            .deliver(|observer| observer.on_updates(Box::new(updates.iter().cloned())))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("ReplayingObserver({})::on_abort", self.id);
+        let mut replay = self.replay.lock().unwrap();
+        replay.in_transaction = false;
+        replay.deliver(|observer| observer.on_abort())
+    }
+
+    fn on_barrier(&mut self) -> Result<(), E> {
+        trace!("ReplayingObserver({})::on_barrier", self.id);
+        self.replay
+            .lock()
+            .unwrap()
+            .deliver(|observer| observer.on_barrier())
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("ReplayingObserver({})::on_completed", self.id);
+        let mut replay = self.replay.lock().unwrap();
+        replay.in_transaction = false;
+        replay
+            .subscribers
+            .values_mut()
+            .map(|subscriber| subscriber.target.observer().on_completed())
+            .fold(Ok(()), Result::and)
+    }
+}
+
+/// An observable passing the events of an accumulator through to any
+/// number of observers, sending the accumulated state to the first
+/// observer subscribed to it.
+///
+/// The state is sent as a transaction of its own upon subscription. If
+/// a transaction is in progress at that time, the observer is attached
+/// only once it completed, i.e., the state is sent right before the
+/// next transaction started, reflecting the state at that time. The
+/// same holds for observers subscribing later, which are not sent the
+/// state, though.
+///
+/// A filtered `ReplayingObservable` instead sends the state to every
+/// observer subscribed to it, with both the state and the events
+/// passed through restricted to the updates satisfying its predicate.
+///
+/// The `ReplayingObservable` stops passing events through when dropped.
+pub struct ReplayingObservable<V, E> {
+    /// The observable's unique ID.
+    id: usize,
+    /// The replay shared with the `ReplayingObserver`.
+    replay: Arc<Mutex<Replay<V, E>>>,
+    /// A handle on the accumulated state.
+    state: StateHandle<V>,
+    /// The predicate the updates sent to observers have to satisfy, if
+    /// the observable is filtered.
+    predicate: Option<Arc<dyn Fn(&Update<V>) -> bool + Send + Sync>>,
+    /// The function cancelling the subscription of the
+    /// `ReplayingObserver`.
+    cancel: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl<V, E> ReplayingObservable<V, E>
+where
+    V: Clone + Debug + Eq + Hash + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    /// Create a new `ReplayingObservable` passing through the events
+    /// emitted by the given distributor and sending the state of the
+    /// given handle to its first subscriber.
+    pub(crate) fn new(
+        distributor: &mut TxnDistributor<Update<V>, E>,
+        state: StateHandle<V>,
+    ) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("ReplayingObservable({})::new", id);
+        Self::create(id, distributor, state, None)
+    }
+
+    /// Create a new filtered `ReplayingObservable` passing through the
+    /// events emitted by the given distributor and sending the state of
+    /// the given handle to each subscriber, restricted to the updates
+    /// satisfying `predicate`.
+    pub(crate) fn filtered<P>(
+        distributor: &mut TxnDistributor<Update<V>, E>,
+        state: StateHandle<V>,
+        predicate: P,
+    ) -> Self
+    where
+        P: Fn(&Update<V>) -> bool + Send + Sync + 'static,
+    {
+        let id = Id::<()>::new().get();
+        trace!("ReplayingObservable({})::filtered", id);
+        Self::create(id, distributor, state, Some(Arc::new(predicate)))
+    }
+
+    fn create(
+        id: usize,
+        distributor: &mut TxnDistributor<Update<V>, E>,
+        state: StateHandle<V>,
+        predicate: Option<Arc<dyn Fn(&Update<V>) -> bool + Send + Sync>>,
+    ) -> Self {
+        let replay = Arc::new(Mutex::new(Replay {
+            subscribers: BTreeMap::new(),
+            in_transaction: false,
+            replayed: false,
+            once: predicate.is_none(),
+        }));
+        let mut cancel = None;
+        let _ = distributor.subscribe_with(|cancel_subscription| {
+            cancel = Some(cancel_subscription);
+            Box::new(ReplayingObserver {
+                id,
+                replay: replay.clone(),
+                state: state.clone(),
+            })
+        });
+
+        Self {
+            id,
+            replay,
+            state,
+            predicate,
+            cancel,
+        }
+    }
+}
+
+// Manual implementation of `Debug` because the cancellation function
+// is not debug printable.
+impl<V, E> Debug for ReplayingObservable<V, E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("ReplayingObservable")
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+impl<V, E> Drop for ReplayingObservable<V, E> {
+    fn drop(&mut self) {
+        trace!("ReplayingObservable({})::drop", self.id);
+        if let Some(cancel) = self.cancel.take() {
+            cancel()
+        }
+    }
+}
+
+impl<V, E> Observable<Update<V>, E> for ReplayingObservable<V, E>
+where
+    V: Clone + Debug + Eq + Hash + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    type Subscription = usize;
+
+    fn subscribe(
+        &mut self,
+        observer: ObserverBox<Update<V>, E>,
+    ) -> Result<Self::Subscription, ObserverBox<Update<V>, E>> {
+        let subscription = Id::<()>::new().get();
+        trace!(
+            "ReplayingObservable({})::subscribe({})",
+            self.id,
+            subscription
+        );
+
+        let target = match &self.predicate {
+            Some(predicate) => {
+                let predicate = predicate.clone();
+                Target::Filtered(FilteringObserver::new(
+                    observer,
+                    Box::new(move |u: &Update<V>| predicate(u)),
+                ))
+            }
+            None => Target::Plain(observer),
+        };
+        let mut replay = self.replay.lock().unwrap();
+        let _ = replay.subscribers.insert(
+            subscription,
+            Subscriber {
+                target,
+                attached: false,
+            },
+        );
+        if !replay.in_transaction {
+            replay.attach(&self.state);
+        }
+        Ok(subscription)
+    }
+
+    fn unsubscribe(
+        &mut self,
+        subscription: &Self::Subscription,
+    ) -> Option<ObserverBox<Update<V>, E>> {
+        trace!(
+            "ReplayingObservable({})::unsubscribe({})",
+            self.id,
+            subscription
+        );
+        let mut replay = self.replay.lock().unwrap();
+        replay
+            .subscribers
+            .remove(subscription)
+            .map(|subscriber| subscriber.target.into_inner())
+    }
+}
@@ -0,0 +1,351 @@
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::sync::mpsc::channel;
+use std::sync::mpsc::sync_channel;
+use std::sync::mpsc::Sender;
+use std::thread::sleep;
+use std::thread::spawn;
+use std::time::Duration;
+
+use log::trace;
+use log::warn;
+use uid::Id;
+
+use differential_datalog::program::Update;
+
+use crate::accumulate::recording::DeliveryEvent;
+use crate::accumulate::RecordedEvent;
+use crate::Observer;
+use crate::ObserverBox;
+
+/// The policy for retrying the delivery of an event to an observer
+/// that failed to process it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The delay before the first retry.
+    pub base_delay: Duration,
+    /// The factor the delay grows by with each further retry.
+    pub multiplier: u32,
+    /// The maximum number of attempts to deliver an event, including
+    /// the first one, before giving up on the observer.
+    pub max_attempts: usize,
+}
+
+impl RetryPolicy {
+    /// Retrieve the delay before the given retry, counting from zero.
+    fn delay(&self, retry: usize) -> Duration {
+        (0..retry).fold(self.base_delay, |delay, _| delay * self.multiplier)
+    }
+}
+
+/// An observer handing the events it receives to a dedicated thread,
+/// which delivers them to the wrapped observer, retrying failed
+/// deliveries according to a `RetryPolicy`. Once an event could not be
+/// delivered within the maximum number of attempts, the subscription
+/// is cancelled and the wrapped observer is dropped.
+///
+/// As delivery happens asynchronously, errors of the wrapped observer
+/// are never reported to the caller, and a retrying observer does not
+/// hold up the delivery of events to other observers.
+pub(crate) struct RetryingObserver<V> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The channel to the thread delivering the events.
+    events: Sender<DeliveryEvent<V>>,
+}
+
+impl<V> RetryingObserver<V>
+where
+    V: Clone + Debug + Send + 'static,
+{
+    /// Create a new `RetryingObserver` delivering events to the given
+    /// observer according to `policy`, invoking `cancel` once it gives
+    /// up on the observer.
+    pub fn new<E>(
+        mut observer: ObserverBox<Update<V>, E>,
+        policy: RetryPolicy,
+        cancel: Box<dyn FnOnce() + Send>,
+    ) -> Self
+    where
+        E: Debug + Send + 'static,
+    {
+        let id = Id::<()>::new().get();
+        trace!("RetryingObserver({})::new({:?})", id, policy);
+
+        let (events, receiver) = channel::<DeliveryEvent<V>>();
+        let _ = spawn(move || {
+            // the thread exits once the `RetryingObserver` was dropped
+            for event in receiver {
+                let mut attempt = 0;
+                loop {
+                    let result = match &event {
+                        DeliveryEvent::Recorded(event) => match event {
+                            RecordedEvent::Start => observer.on_start(),
+                            RecordedEvent::StartSeq(seq) => observer.on_start_seq(*seq),
+                            RecordedEvent::Updates(updates) => {
+                                observer.on_updates(Box::new(updates.iter().cloned()))
+                            }
+                            RecordedEvent::Commit => observer.on_commit(),
+                            RecordedEvent::Abort => observer.on_abort(),
+                            RecordedEvent::Completed => observer.on_completed(),
+                        },
+                        DeliveryEvent::Barrier(_) => observer.on_barrier(),
+                        DeliveryEvent::InitComplete => observer.on_init_complete(),
+                    };
+                    let error = match result {
+                        Ok(()) => break,
+                        Err(error) => error,
+                    };
+
+                    attempt += 1;
+                    if attempt >= policy.max_attempts {
+                        warn!(
+                            "RetryingObserver({}) giving up after {} attempts: {:?}",
+                            id, attempt, error
+                        );
+                        cancel();
+                        return;
+                    }
+                    let delay = policy.delay(attempt - 1);
+                    trace!(
+                        "RetryingObserver({}) retrying in {:?}: {:?}",
+                        id,
+                        delay,
+                        error
+                    );
+                    sleep(delay);
+                }
+                if let DeliveryEvent::Barrier(done) = event {
+                    let _ = done.send(());
+                }
+            }
+        });
+
+        Self { id, events }
+    }
+
+    /// Hand an event to the delivering thread.
+    fn send<E>(&self, event: RecordedEvent<V>) -> Result<(), E> {
+        // the thread only exits after cancelling the subscription, so
+        // events sent in the meantime can safely be dropped
+        let _ = self.events.send(DeliveryEvent::Recorded(event));
+        Ok(())
+    }
+}
+
+// Manual implementation of `Debug` because the channel does not need
+// to be debug printable.
+impl<V> Debug for RetryingObserver<V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("RetryingObserver")
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+impl<V, E> Observer<Update<V>, E> for RetryingObserver<V>
+where
+    V: Clone + Debug + Send + 'static,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("RetryingObserver({})::on_start", self.id);
+        self.send(RecordedEvent::Start)
+    }
+
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        trace!("RetryingObserver({})::on_start_seq({})", self.id, seq);
+        self.send(RecordedEvent::StartSeq(seq))
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("RetryingObserver({})::on_commit", self.id);
+        self.send(RecordedEvent::Commit)
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("RetryingObserver({})::on_updates", self.id);
+        self.send(RecordedEvent::Updates(updates.collect()))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("RetryingObserver({})::on_abort", self.id);
+        self.send(RecordedEvent::Abort)
+    }
+
+    /// Block until all events received so far were delivered to the
+    /// wrapped observer, or it was given up on.
+    fn on_barrier(&mut self) -> Result<(), E> {
+        trace!("RetryingObserver({})::on_barrier", self.id);
+        let (done, drained) = sync_channel(1);
+        let _ = self.events.send(DeliveryEvent::Barrier(done));
+        // the notification is dropped along with the thread giving up
+        let _ = drained.recv();
+        Ok(())
+    }
+
+    fn on_init_complete(&mut self) -> Result<(), E> {
+        trace!("RetryingObserver({})::on_init_complete", self.id);
+        let _ = self.events.send(DeliveryEvent::InitComplete);
+        Ok(())
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("RetryingObserver({})::on_completed", self.id);
+        self.send(RecordedEvent::Completed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use crate::accumulate::accumulator::tests::get_usize_updates_1;
+    use crate::accumulate::accumulator::tests::get_usize_updates_3;
+    use crate::accumulate::FaultyObserver;
+    use crate::accumulate::ObserverCall;
+    use crate::await_expected;
+    use crate::Accumulator;
+    use crate::DistributingAccumulator;
+
+    /// Test that the delay grows exponentially with each retry.
+    #[test]
+    fn exponential_delay() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(10),
+            multiplier: 3,
+            max_attempts: 4,
+        };
+        assert_eq!(policy.delay(0), Duration::from_millis(10));
+        assert_eq!(policy.delay(1), Duration::from_millis(30));
+        assert_eq!(policy.delay(2), Duration::from_millis(90));
+    }
+
+    /// Test that an aborted transaction is delivered as such, so that
+    /// its updates never reach the state of the wrapped accumulator.
+    #[test]
+    fn retrying_abort() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(1),
+            multiplier: 2,
+            max_attempts: 3,
+        };
+        let downstream = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let downstream = Arc::new(Mutex::new(downstream));
+        let mut observer =
+            RetryingObserver::new(Box::new(downstream.clone()), policy, Box::new(|| ()));
+
+        assert_eq!(Observer::<_, ()>::on_start(&mut observer), Ok(()));
+        assert_eq!(
+            Observer::<_, ()>::on_updates(&mut observer, get_usize_updates_1()),
+            Ok(())
+        );
+        assert_eq!(Observer::<_, ()>::on_abort(&mut observer), Ok(()));
+        assert_eq!(Observer::<_, ()>::on_start(&mut observer), Ok(()));
+        assert_eq!(
+            Observer::<_, ()>::on_updates(&mut observer, get_usize_updates_3()),
+            Ok(())
+        );
+        assert_eq!(Observer::<_, ()>::on_commit(&mut observer), Ok(()));
+
+        await_expected(|| {
+            let state = downstream.lock().unwrap().get_current_state();
+            assert_eq!(state.len(), 1);
+            assert_eq!(state[&4].len(), 4);
+        });
+    }
+
+    /// Test that a barrier only returns once the events received before
+    /// were delivered, including those that had to be retried.
+    #[test]
+    fn retrying_barrier() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(50),
+            multiplier: 2,
+            max_attempts: 3,
+        };
+        let faulty =
+            FaultyObserver::<Update<usize>, ()>::new().fail_on(ObserverCall::Commit, 1, ());
+        let faulty = Arc::new(Mutex::new(faulty));
+        let mut observer = RetryingObserver::new(Box::new(faulty.clone()), policy, Box::new(|| ()));
+
+        assert_eq!(Observer::<_, ()>::on_start(&mut observer), Ok(()));
+        assert_eq!(
+            Observer::<_, ()>::on_updates(&mut observer, get_usize_updates_1()),
+            Ok(())
+        );
+        assert_eq!(Observer::<_, ()>::on_commit(&mut observer), Ok(()));
+        assert_eq!(Observer::<_, ()>::on_barrier(&mut observer), Ok(()));
+
+        let faulty = faulty.lock().unwrap();
+        assert_eq!(faulty.called(ObserverCall::Commit), 2);
+        assert_eq!(faulty.received_updates.len(), 3);
+    }
+
+    /// Test that the end of the replay of the accumulated state is
+    /// delivered to the wrapped observer in order with the other
+    /// events.
+    #[test]
+    fn retrying_init_complete() {
+        /// An observer recording the number of updates it received
+        /// before being told about the end of the replay.
+        #[derive(Debug, Default)]
+        struct InitLog {
+            updates: usize,
+            init_updates: Option<usize>,
+        }
+
+        impl Observer<Update<usize>, ()> for InitLog {
+            fn on_start(&mut self) -> Result<(), ()> {
+                Ok(())
+            }
+
+            fn on_commit(&mut self) -> Result<(), ()> {
+                Ok(())
+            }
+
+            fn on_updates<'a>(
+                &mut self,
+                updates: Box<dyn Iterator<Item = Update<usize>> + 'a>,
+            ) -> Result<(), ()> {
+                self.updates += updates.count();
+                Ok(())
+            }
+
+            fn on_init_complete(&mut self) -> Result<(), ()> {
+                self.init_updates = Some(self.updates);
+                Ok(())
+            }
+
+            fn on_completed(&mut self) -> Result<(), ()> {
+                Ok(())
+            }
+        }
+
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(1),
+            multiplier: 2,
+            max_attempts: 3,
+        };
+        let log = Arc::new(Mutex::new(InitLog::default()));
+        let mut observer = RetryingObserver::new(Box::new(log.clone()), policy, Box::new(|| ()));
+
+        assert_eq!(Observer::<_, ()>::on_start(&mut observer), Ok(()));
+        assert_eq!(
+            Observer::<_, ()>::on_updates(&mut observer, get_usize_updates_1()),
+            Ok(())
+        );
+        assert_eq!(Observer::<_, ()>::on_commit(&mut observer), Ok(()));
+        assert_eq!(Observer::<_, ()>::on_init_complete(&mut observer), Ok(()));
+        assert_eq!(Observer::<_, ()>::on_barrier(&mut observer), Ok(()));
+
+        assert_eq!(log.lock().unwrap().init_updates, Some(3));
+    }
+}
@@ -0,0 +1,123 @@
+use std::fmt::Debug;
+
+use log::trace;
+use uid::Id;
+
+use crate::Observer;
+
+/// An observer forwarding only every Nth transaction it receives in its
+/// entirety, dropping all others, e.g., to monitor a sample of the
+/// transactions at low overhead.
+#[derive(Debug)]
+pub(crate) struct SamplingObserver<O> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The observer we forward the sampled transactions to.
+    observer: O,
+    /// Forward every how many-th transaction.
+    every_n: usize,
+    /// The number of transactions to start until the next one sampled.
+    remaining: usize,
+    /// Whether the transaction in progress is forwarded.
+    sampled: bool,
+}
+
+impl<O> SamplingObserver<O> {
+    /// Create a new `SamplingObserver` forwarding every `every_n`th
+    /// transaction to the given observer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `every_n` is zero.
+    pub fn new(observer: O, every_n: usize) -> Self {
+        assert!(every_n > 0, "cannot sample every 0th transaction");
+        let id = Id::<()>::new().get();
+        trace!("SamplingObserver({})::new({})", id, every_n);
+
+        Self {
+            id,
+            observer,
+            every_n,
+            remaining: every_n,
+            sampled: false,
+        }
+    }
+
+    /// Decide whether the transaction being started is sampled.
+    fn sample(&mut self) -> bool {
+        self.remaining -= 1;
+        self.sampled = self.remaining == 0;
+        if self.sampled {
+            self.remaining = self.every_n;
+        }
+        self.sampled
+    }
+}
+
+impl<O, T, E> Observer<T, E> for SamplingObserver<O>
+where
+    O: Observer<T, E>,
+    T: Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("SamplingObserver({})::on_start", self.id);
+        if self.sample() {
+            self.observer.on_start()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        trace!("SamplingObserver({})::on_start_seq({})", self.id, seq);
+        if self.sample() {
+            self.observer.on_start_seq(seq)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("SamplingObserver({})::on_commit", self.id);
+        if std::mem::take(&mut self.sampled) {
+            self.observer.on_commit()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        trace!("SamplingObserver({})::on_updates", self.id);
+        if self.sampled {
+            self.observer.on_updates(updates)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("SamplingObserver({})::on_abort", self.id);
+        if std::mem::take(&mut self.sampled) {
+            self.observer.on_abort()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn on_barrier(&mut self) -> Result<(), E> {
+        trace!("SamplingObserver({})::on_barrier", self.id);
+        self.observer.on_barrier()
+    }
+
+    fn on_init_complete(&mut self) -> Result<(), E> {
+        trace!("SamplingObserver({})::on_init_complete", self.id);
+        self.observer.on_init_complete()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("SamplingObserver({})::on_completed", self.id);
+        self.sampled = false;
+        self.observer.on_completed()
+    }
+}
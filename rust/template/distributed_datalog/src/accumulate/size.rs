@@ -0,0 +1,120 @@
+use std::mem::size_of;
+
+/// A type whose values can estimate the memory they occupy, e.g., to
+/// estimate the memory used by the state of an accumulator.
+pub trait ApproxSize {
+    /// Estimate the number of bytes occupied by the value, including
+    /// the memory it owns on the heap.
+    fn approx_size(&self) -> usize;
+}
+
+macro_rules! impl_approx_size_inline {
+    ($($ty:ty),*) => {
+        $(
+            impl ApproxSize for $ty {
+                fn approx_size(&self) -> usize {
+                    size_of::<Self>()
+                }
+            }
+        )*
+    };
+}
+
+impl_approx_size_inline!(
+    (),
+    bool,
+    char,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    f32,
+    f64
+);
+
+impl ApproxSize for String {
+    fn approx_size(&self) -> usize {
+        size_of::<Self>() + self.capacity()
+    }
+}
+
+impl<T> ApproxSize for Box<T>
+where
+    T: ApproxSize,
+{
+    fn approx_size(&self) -> usize {
+        size_of::<Self>() + self.as_ref().approx_size()
+    }
+}
+
+impl<T> ApproxSize for Option<T>
+where
+    T: ApproxSize,
+{
+    fn approx_size(&self) -> usize {
+        // the inline part of the value is covered by the option itself
+        size_of::<Self>()
+            + self
+                .as_ref()
+                .map_or(0, |v| v.approx_size() - size_of::<T>())
+    }
+}
+
+impl<T> ApproxSize for Vec<T>
+where
+    T: ApproxSize,
+{
+    fn approx_size(&self) -> usize {
+        let unused = self.capacity() - self.len();
+        size_of::<Self>() + unused * size_of::<T>() + self.iter().map(T::approx_size).sum::<usize>()
+    }
+}
+
+impl<A, B> ApproxSize for (A, B)
+where
+    A: ApproxSize,
+    B: ApproxSize,
+{
+    fn approx_size(&self) -> usize {
+        // the inline parts of the elements are covered by the tuple
+        size_of::<Self>() + self.0.approx_size() - size_of::<A>() + self.1.approx_size()
+            - size_of::<B>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that the estimated size covers the memory owned on the
+    /// heap.
+    #[test]
+    fn approx_size() {
+        assert_eq!(42u64.approx_size(), 8);
+        assert_eq!(
+            String::with_capacity(16).approx_size(),
+            size_of::<String>() + 16
+        );
+        assert_eq!(None::<String>.approx_size(), size_of::<Option<String>>());
+        assert_eq!(
+            Some(String::with_capacity(16)).approx_size(),
+            size_of::<Option<String>>() + 16
+        );
+        assert_eq!(
+            vec![1u32, 2, 3].approx_size(),
+            size_of::<Vec<u32>>() + 3 * size_of::<u32>()
+        );
+        assert_eq!(
+            (1u8, String::with_capacity(4)).approx_size(),
+            size_of::<(u8, String)>() + 4
+        );
+    }
+}
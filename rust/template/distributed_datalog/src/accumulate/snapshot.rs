@@ -0,0 +1,167 @@
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use differential_datalog::program::RelId;
+use differential_datalog::program::Update;
+
+/// A serializable snapshot of the state of an `AccumulatingObserver`,
+/// used to checkpoint an accumulator and to later restore it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(bound(
+    serialize = "V: Debug + Eq + Hash + Serialize",
+    deserialize = "V: Eq + Hash + serde::de::DeserializeOwned"
+))]
+pub struct AccumulatorSnapshot<V>
+where
+    V: Eq + Hash,
+{
+    /// The data accumulated so far.
+    pub data: HashMap<RelId, HashSet<V>>,
+    /// The multiplicity of each value accumulated so far.
+    pub weights: HashMap<RelId, HashMap<V, isize>>,
+    /// The updates of the transaction in progress, if any.
+    pub buffer: Option<Vec<Update<V>>>,
+}
+
+impl<V> AccumulatorSnapshot<V>
+where
+    V: Clone + Eq + Hash,
+{
+    /// Compute the updates transforming the state captured in `old`
+    /// into the one captured in `new`: a `DeleteValue` for every value
+    /// only present in `old` and an `Insert` for every value only present
+    /// in `new`. Updates are grouped by relation, in ascending order of
+    /// relation IDs, with the deletions of a relation preceding its
+    /// insertions. In-flight transactions are not taken into account.
+    pub fn diff(old: &Self, new: &Self) -> Vec<Update<V>> {
+        diff_states(&old.data, &new.data)
+    }
+}
+
+/// Compute the updates transforming the state `old` into the state
+/// `new`, as described for `AccumulatorSnapshot::diff`.
+pub fn diff_states<V>(
+    old: &HashMap<RelId, HashSet<V>>,
+    new: &HashMap<RelId, HashSet<V>>,
+) -> Vec<Update<V>>
+where
+    V: Clone + Eq + Hash,
+{
+    let empty = HashSet::new();
+    let relids = old
+        .keys()
+        .chain(new.keys())
+        .copied()
+        .collect::<BTreeSet<_>>();
+
+    relids
+        .into_iter()
+        .flat_map(|relid| {
+            let old = old.get(&relid).unwrap_or(&empty);
+            let new = new.get(&relid).unwrap_or(&empty);
+            let deletes = old.difference(new).map(move |v| Update::DeleteValue {
+                relid,
+                v: v.clone(),
+            });
+            let inserts = new.difference(old).map(move |v| Update::Insert {
+                relid,
+                v: v.clone(),
+            });
+            deletes.chain(inserts)
+        })
+        .collect()
+}
+
+/// Compute the updates transforming the weighted state `old` into the
+/// weighted state `new`, i.e., inserting or deleting each value as
+/// often as its multiplicity differs, with the deletions of each
+/// relation preceding its insertions.
+pub fn diff_weighted_states<V>(
+    old: &HashMap<RelId, HashMap<V, isize>>,
+    new: &HashMap<RelId, HashMap<V, isize>>,
+) -> Vec<Update<V>>
+where
+    V: Clone + Eq + Hash,
+{
+    let empty = HashMap::new();
+    let relids = old
+        .keys()
+        .chain(new.keys())
+        .copied()
+        .collect::<BTreeSet<_>>();
+
+    let mut updates = Vec::new();
+    for relid in relids {
+        let old = old.get(&relid).unwrap_or(&empty);
+        let new = new.get(&relid).unwrap_or(&empty);
+        let weight = |vs: &HashMap<V, isize>, v: &V| vs.get(v).copied().unwrap_or(0);
+        for (v, w) in old {
+            for _ in weight(new, v)..*w {
+                updates.push(Update::DeleteValue {
+                    relid,
+                    v: v.clone(),
+                });
+            }
+        }
+        for (v, w) in new {
+            for _ in weight(old, v)..*w {
+                updates.push(Update::Insert {
+                    relid,
+                    v: v.clone(),
+                });
+            }
+        }
+    }
+    updates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::accumulate::accumulator::tests::get_usize_updates_1;
+    use crate::accumulate::accumulator::tests::get_usize_updates_3;
+    use crate::accumulate::DistributingAccumulator;
+    use crate::Accumulator;
+    use crate::Observer;
+
+    fn snapshot_of(updates: Box<dyn Iterator<Item = Update<usize>>>) -> AccumulatorSnapshot<usize> {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(accumulator.on_updates(updates), Ok(()));
+        assert_eq!(accumulator.on_commit(), Ok(()));
+        accumulator.snapshot()
+    }
+
+    /// Test the computation of the updates transforming one snapshot
+    /// into another.
+    #[test]
+    fn snapshot_diff() {
+        let old = snapshot_of(get_usize_updates_1());
+        let new = snapshot_of(get_usize_updates_3());
+
+        let diff = AccumulatorSnapshot::diff(&old, &new);
+        let relids = diff.iter().map(Update::relid).collect::<Vec<_>>();
+        assert_eq!(relids, vec![1, 2, 3, 4, 4, 4, 4]);
+        assert_eq!(
+            diff.iter()
+                .filter(|u| matches!(u, Update::DeleteValue { .. }))
+                .count(),
+            3
+        );
+        assert_eq!(
+            diff.iter()
+                .filter(|u| matches!(u, Update::Insert { .. }))
+                .count(),
+            4
+        );
+
+        assert!(AccumulatorSnapshot::diff(&new, &new).is_empty());
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use differential_datalog::program::RelId;
+
+/// A cheaply clonable, read-only handle to the state of an accumulator.
+///
+/// The handle shares the state with the accumulator it was retrieved
+/// from, i.e., transactions committed to the accumulator become visible
+/// through all of its handles. It can be handed to other threads to
+/// query the state without access to the accumulator itself.
+#[derive(Debug)]
+pub struct StateHandle<V> {
+    /// The state shared with the accumulator.
+    data: Arc<Mutex<HashMap<RelId, HashSet<V>>>>,
+}
+
+impl<V> StateHandle<V>
+where
+    V: Clone + Eq + Hash,
+{
+    /// Create a new `StateHandle` providing access to the given state.
+    pub fn new(data: Arc<Mutex<HashMap<RelId, HashSet<V>>>>) -> Self {
+        Self { data }
+    }
+
+    /// Return the current state of the data.
+    pub fn get_current_state(&self) -> HashMap<RelId, HashSet<V>> {
+        self.data.lock().unwrap().clone()
+    }
+
+    /// Return the values currently accumulated for the given relation.
+    pub fn get_relation(&self, relid: RelId) -> HashSet<V> {
+        self.data
+            .lock()
+            .unwrap()
+            .get(&relid)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Check whether the given value is currently accumulated for the
+    /// given relation.
+    pub fn contains(&self, relid: RelId, v: &V) -> bool {
+        self.data
+            .lock()
+            .unwrap()
+            .get(&relid)
+            .is_some_and(|values| values.contains(v))
+    }
+
+    /// Return a value of the given relation satisfying the predicate,
+    /// if any, without copying the relation.
+    pub fn find<P>(&self, relid: RelId, predicate: P) -> Option<V>
+    where
+        P: Fn(&V) -> bool,
+    {
+        self.data
+            .lock()
+            .unwrap()
+            .get(&relid)?
+            .iter()
+            .find(|v| predicate(v))
+            .cloned()
+    }
+}
+
+// Manual implementation of `Clone` because the derived one would
+// require `V: Clone` while we only clone the `Arc`.
+impl<V> Clone for StateHandle<V> {
+    fn clone(&self) -> Self {
+        Self {
+            data: self.data.clone(),
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use std::time::Instant;
+
+/// Statistics about the updates an accumulator processed for a single
+/// relation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RelStats {
+    /// The number of insertions processed over the accumulator's
+    /// lifetime.
+    pub inserts: u64,
+    /// The number of deletions processed over the accumulator's
+    /// lifetime.
+    pub deletes: u64,
+    /// The number of values currently accumulated.
+    pub current_size: usize,
+}
+
+/// Statistics about a single subscription to a `TxnDistributor`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SubStats {
+    /// The time the subscription was created at.
+    pub created_at: Instant,
+    /// The number of updates sent to initialize the observer upon
+    /// subscription, if any.
+    pub init_updates: usize,
+    /// The number of updates distributed to the observer since it
+    /// subscribed.
+    pub live_updates: u64,
+}
+
+impl SubStats {
+    /// Create the statistics of a subscription created just now.
+    pub(crate) fn new() -> Self {
+        Self {
+            created_at: Instant::now(),
+            init_updates: 0,
+            live_updates: 0,
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::sync::Arc;
+
+use log::trace;
+use uid::Id;
+
+use differential_datalog::program::RelId;
+use differential_datalog::program::Update;
+
+use crate::accumulate::filter::UpdatePredicate;
+use crate::accumulate::map::map_update;
+use crate::Observer;
+
+/// A function transforming the values of the updates of a stream.
+type Transform<V> = Arc<dyn Fn(&V) -> V + Send + Sync>;
+
+/// The configuration of a named output stream of an accumulator, as
+/// created via `DistributingAccumulator::create_named_stream`. By
+/// default, a stream carries all updates unchanged.
+pub struct StreamConfig<V> {
+    /// The relations whose updates the stream carries, if restricted.
+    relids: Option<HashSet<RelId>>,
+    /// The function transforming the values of the updates, if any.
+    transform: Option<Transform<V>>,
+}
+
+impl<V> StreamConfig<V> {
+    /// Create a new `StreamConfig` for a stream carrying all updates
+    /// unchanged.
+    pub fn new() -> Self {
+        Self {
+            relids: None,
+            transform: None,
+        }
+    }
+
+    /// Restrict the stream to the updates of the given relations.
+    /// Transactions without any such updates are not emitted at all.
+    pub fn relids(mut self, relids: HashSet<RelId>) -> Self {
+        self.relids = Some(relids);
+        self
+    }
+
+    /// Transform the values of the updates of the stream with `f`,
+    /// preserving the variant and the relation of each update, e.g., to
+    /// redact values. `f` is supposed to be deterministic, so that
+    /// deletions of values match their earlier insertions.
+    pub fn transform<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&V) -> V + Send + Sync + 'static,
+    {
+        self.transform = Some(Arc::new(f));
+        self
+    }
+
+    /// Create the predicate selecting the updates of the stream.
+    pub(crate) fn predicate(&self) -> UpdatePredicate<V> {
+        match self.relids.clone() {
+            Some(relids) => Box::new(move |u: &Update<V>| relids.contains(&u.relid())),
+            None => Box::new(|_: &Update<V>| true),
+        }
+    }
+}
+
+impl<V> Default for StreamConfig<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Manual implementation of `Debug` because the transform is not debug
+// printable.
+impl<V> Debug for StreamConfig<V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("StreamConfig")
+            .field("relids", &self.relids)
+            .field("transform", &self.transform.is_some())
+            .finish()
+    }
+}
+
+/// An observer transforming the values of the updates it receives
+/// according to the configuration of a named stream, if at all, before
+/// forwarding them.
+pub(crate) struct StreamObserver<O, V> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The name of the stream.
+    name: String,
+    /// The observer we forward the updates to.
+    observer: O,
+    /// The function transforming the values of the updates, if any.
+    transform: Option<Transform<V>>,
+}
+
+impl<O, V> StreamObserver<O, V> {
+    /// Create a new `StreamObserver` for the stream of the given name,
+    /// forwarding updates to `observer`.
+    pub fn new(name: &str, observer: O, config: &StreamConfig<V>) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("StreamObserver({})::new({})", id, name);
+
+        Self {
+            id,
+            name: name.to_string(),
+            observer,
+            transform: config.transform.clone(),
+        }
+    }
+}
+
+// Manual implementation of `Debug` because the transform is not debug
+// printable.
+impl<O, V> Debug for StreamObserver<O, V>
+where
+    O: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("StreamObserver")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .field("observer", &self.observer)
+            .finish()
+    }
+}
+
+impl<O, V, E> Observer<Update<V>, E> for StreamObserver<O, V>
+where
+    O: Observer<Update<V>, E>,
+    V: Debug + Send,
+    E: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("StreamObserver({})::on_start", self.id);
+        self.observer.on_start()
+    }
+
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        trace!("StreamObserver({})::on_start_seq({})", self.id, seq);
+        self.observer.on_start_seq(seq)
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("StreamObserver({})::on_commit", self.id);
+        self.observer.on_commit()
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("StreamObserver({})::on_updates", self.id);
+        match &self.transform {
+            Some(transform) => {
+                let f = &**transform;
+                self.observer
+                    .on_updates(Box::new(updates.map(move |u| map_update(u, &f))))
+            }
+            None => self.observer.on_updates(updates),
+        }
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("StreamObserver({})::on_abort", self.id);
+        self.observer.on_abort()
+    }
+
+    fn on_barrier(&mut self) -> Result<(), E> {
+        trace!("StreamObserver({})::on_barrier", self.id);
+        self.observer.on_barrier()
+    }
+
+    fn on_init_complete(&mut self) -> Result<(), E> {
+        trace!("StreamObserver({})::on_init_complete", self.id);
+        self.observer.on_init_complete()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("StreamObserver({})::on_completed", self.id);
+        self.observer.on_completed()
+    }
+}
@@ -1,6 +1,9 @@
 use log::trace;
 
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::thread::sleep;
+use std::time::Duration;
 
 use crate::Observer;
 
@@ -60,6 +63,9 @@ where
     pub called_on_completed: usize,
     /// The updates the observer has seen.
     pub received_updates: Vec<T>,
+    /// The sequence numbers of the transactions the observer has seen
+    /// started via `on_start_seq`.
+    pub received_sequences: Vec<u64>,
 }
 
 impl<T> UpdatesMockObserver<T>
@@ -74,6 +80,7 @@ where
             called_on_updates: 0,
             called_on_completed: 0,
             received_updates: vec![],
+            received_sequences: vec![],
         }
     }
 }
@@ -89,6 +96,12 @@ where
         Ok(())
     }
 
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        trace!("MockObserver::on_start_seq({})", seq);
+        self.received_sequences.push(seq);
+        self.on_start()
+    }
+
     fn on_commit(&mut self) -> Result<(), E> {
         trace!("MockObserver::on_commit");
         self.called_on_commit += 1;
@@ -109,3 +122,167 @@ where
         Ok(())
     }
 }
+
+/// An event an `Observer` can receive, as scripted for a
+/// `FaultyObserver`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ObserverCall {
+    /// A call to `on_start`.
+    Start,
+    /// A call to `on_commit`.
+    Commit,
+    /// A call to `on_updates`.
+    Updates,
+    /// A call to `on_abort`.
+    Abort,
+    /// A call to `on_completed`.
+    Completed,
+}
+
+/// The misbehavior of a `FaultyObserver` upon a particular call.
+#[derive(Debug)]
+enum Fault<E> {
+    /// Fail the call with the given error.
+    Fail(E),
+    /// Panic.
+    Panic,
+    /// Sleep for the given duration before processing the call.
+    Delay(Duration),
+    /// Drop this and all later calls, as if the observer was gone.
+    Disconnect,
+}
+
+/// A variant of the `UpdatesMockObserver` that can be scripted to
+/// misbehave on particular calls, e.g., to fail on its second
+/// `on_updates`, for testing how observables cope with faulty
+/// observers. Calls of each kind are counted from one.
+#[derive(Debug)]
+pub struct FaultyObserver<T, E>
+where
+    T: Debug,
+{
+    /// The number of calls of each kind the observer has seen,
+    /// including those it misbehaved on.
+    pub calls: HashMap<ObserverCall, usize>,
+    /// The updates the observer processed successfully.
+    pub received_updates: Vec<T>,
+    /// Whether the observer disconnected.
+    pub disconnected: bool,
+    /// The scripted faults not yet triggered, by call kind and index.
+    faults: HashMap<(ObserverCall, usize), Fault<E>>,
+}
+
+impl<T, E> FaultyObserver<T, E>
+where
+    T: Debug,
+{
+    /// Create a new `FaultyObserver` behaving like an
+    /// `UpdatesMockObserver` until scripted otherwise.
+    pub fn new() -> Self {
+        Self {
+            calls: HashMap::new(),
+            received_updates: Vec::new(),
+            disconnected: false,
+            faults: HashMap::new(),
+        }
+    }
+
+    /// Fail the `nth` call of the given kind with `error`.
+    pub fn fail_on(mut self, call: ObserverCall, nth: usize, error: E) -> Self {
+        let _ = self.faults.insert((call, nth), Fault::Fail(error));
+        self
+    }
+
+    /// Panic on the `nth` call of the given kind.
+    pub fn panic_on(mut self, call: ObserverCall, nth: usize) -> Self {
+        let _ = self.faults.insert((call, nth), Fault::Panic);
+        self
+    }
+
+    /// Delay processing the `nth` call of the given kind by `delay`.
+    pub fn delay_on(mut self, call: ObserverCall, nth: usize, delay: Duration) -> Self {
+        let _ = self.faults.insert((call, nth), Fault::Delay(delay));
+        self
+    }
+
+    /// Drop the `nth` call of the given kind and all calls after it,
+    /// as if the observer went away.
+    pub fn disconnect_on(mut self, call: ObserverCall, nth: usize) -> Self {
+        let _ = self.faults.insert((call, nth), Fault::Disconnect);
+        self
+    }
+
+    /// Retrieve the number of calls of the given kind the observer has
+    /// seen.
+    pub fn called(&self, call: ObserverCall) -> usize {
+        self.calls.get(&call).copied().unwrap_or(0)
+    }
+
+    /// Count a call of the given kind and misbehave as scripted for
+    /// it. Returns whether the call is to be processed.
+    fn call(&mut self, call: ObserverCall) -> Result<bool, E> {
+        let count = self.calls.entry(call).or_insert(0);
+        *count += 1;
+        let nth = *count;
+        if self.disconnected {
+            return Ok(false);
+        }
+
+        match self.faults.remove(&(call, nth)) {
+            Some(Fault::Fail(error)) => Err(error),
+            Some(Fault::Panic) => panic!("FaultyObserver panicking on {:?} #{}", call, nth),
+            Some(Fault::Delay(delay)) => {
+                sleep(delay);
+                Ok(true)
+            }
+            Some(Fault::Disconnect) => {
+                self.disconnected = true;
+                Ok(false)
+            }
+            None => Ok(true),
+        }
+    }
+}
+
+impl<T, E> Default for FaultyObserver<T, E>
+where
+    T: Debug,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, E> Observer<T, E> for FaultyObserver<T, E>
+where
+    T: Debug + Send,
+    E: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("FaultyObserver::on_start");
+        self.call(ObserverCall::Start).map(|_| ())
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("FaultyObserver::on_commit");
+        self.call(ObserverCall::Commit).map(|_| ())
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        trace!("FaultyObserver::on_updates");
+        if self.call(ObserverCall::Updates)? {
+            self.received_updates.extend(updates);
+        }
+        Ok(())
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("FaultyObserver::on_abort");
+        self.call(ObserverCall::Abort).map(|_| ())
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("FaultyObserver::on_completed");
+        self.call(ObserverCall::Completed).map(|_| ())
+    }
+}
@@ -1,23 +1,296 @@
+use std::any::Any;
+use std::cell::Cell;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::panic::catch_unwind;
+use std::panic::resume_unwind;
+use std::panic::AssertUnwindSafe;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
 
+use log::error;
 use log::trace;
+use log::warn;
 use uid::Id;
 
+use crate::accumulate::SubStats;
 use crate::Observer;
 use crate::ObserverBox;
 use crate::OptionalObserver;
 use crate::SharedObserver;
 use crate::{Observable, UpdatesObservable};
 
+/// A handler invoked with the subscription and the error of an
+/// observer that failed to process an event.
+pub struct ErrorHandler<E>(Arc<Mutex<dyn Fn(usize, E) + Send>>);
+
+// Manual implementation of `Clone` to not require `E: Clone`.
+impl<E> Clone for ErrorHandler<E> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<E> Debug for ErrorHandler<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str("ErrorHandler")
+    }
+}
+
+/// A handler invoked with the subscription of an observer that panicked
+/// while processing an event, along with the panic's message.
+#[derive(Clone)]
+pub struct PanicHandler(Arc<Mutex<dyn Fn(usize, String) + Send>>);
+
+impl Debug for PanicHandler {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str("PanicHandler")
+    }
+}
+
+/// Retrieve the message of a panic from its payload.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+thread_local! {
+    /// The number of events being delivered to observers of any
+    /// `TxnDistributor` on the current thread.
+    static DELIVERING: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Invoke the given function delivering an event to an observer,
+/// accounting for the delivery on the current thread.
+pub(crate) fn delivering<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    DELIVERING.with(|delivering| delivering.set(delivering.get() + 1));
+    let result = catch_unwind(AssertUnwindSafe(f));
+    DELIVERING.with(|delivering| delivering.set(delivering.get() - 1));
+    result.unwrap_or_else(|payload| resume_unwind(payload))
+}
+
+/// Check whether an event is being delivered to an observer on the
+/// current thread, i.e., whether we are called from within the callback
+/// of an observer, directly or indirectly.
+pub(crate) fn is_delivering() -> bool {
+    DELIVERING.with(Cell::get) > 0
+}
+
+/// A transaction in progress, as seen by a `TxnDistributor`.
 #[derive(Debug)]
-pub struct TxnDistributor<T, E> {
-    id: usize,
+struct Transaction {
+    /// The subscriptions taking part in the transaction, in delivery
+    /// order. Observers subscribing while the transaction is in progress
+    /// only take part in the next one.
+    subscriptions: Vec<usize>,
+    /// The sequence number the transaction was started with, if any.
+    seq: Option<u64>,
+}
+
+/// An observer passing events on to the observer registered for an
+/// observable, but only the transactions that started while an observer
+/// was subscribed to the observable. An observer subscribing to the
+/// observable while a transaction is in progress, e.g., while a batch
+/// of transactions is open, thus only receives the events of the next
+/// transaction on, instead of those of the rest of the transaction.
+#[derive(Debug)]
+struct ObservableGate<T, E> {
+    /// The observer registered for the observable.
+    observer: ObserverBox<T, E>,
+    /// The slot of the observer subscribed to the observable.
+    slot: SharedObserver<OptionalObserver<ObserverBox<T, E>>>,
+    /// Whether the transaction in progress, if any, is passed on.
+    attached: bool,
+}
+
+impl<T, E> ObservableGate<T, E> {
+    /// Start a transaction, passing it on if an observer is subscribed
+    /// to the observable.
+    fn start<F>(&mut self, f: F) -> Result<(), E>
+    where
+        F: FnOnce(&mut ObserverBox<T, E>) -> Result<(), E>,
+    {
+        self.attached = self.slot.lock().unwrap().is_some();
+        self.deliver(f)
+    }
+
+    /// Invoke the given function on the registered observer, provided
+    /// the transaction in progress is passed on.
+    fn deliver<F>(&mut self, f: F) -> Result<(), E>
+    where
+        F: FnOnce(&mut ObserverBox<T, E>) -> Result<(), E>,
+    {
+        if self.attached {
+            f(&mut self.observer)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<T, E> Observer<T, E> for ObservableGate<T, E>
+where
+    T: Debug + Send,
+    E: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.start(|o| o.on_start())
+    }
+
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        self.start(|o| o.on_start_seq(seq))
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        let result = self.deliver(|o| o.on_commit());
+        self.attached = true;
+        result
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.deliver(|o| o.on_updates(updates))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        let result = self.deliver(|o| o.on_abort());
+        self.attached = true;
+        result
+    }
+
+    fn on_barrier(&mut self) -> Result<(), E> {
+        self.observer.on_barrier()
+    }
+
+    fn on_init_complete(&mut self) -> Result<(), E> {
+        self.observer.on_init_complete()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.attached = true;
+        self.observer.on_completed()
+    }
+}
+
+/// The state shared between all handles to a `TxnDistributor`.
+#[derive(Debug)]
+struct Subscribers<T, E> {
     /// A list of references to the `Observers` subscribed to us, if any.
     /// The map is indexed by an ID indicating the subscription of each observer.
-    observers: HashMap<usize, SharedObserver<OptionalObserver<ObserverBox<T, E>>>>,
+    /// Subscription IDs are handed out in increasing order, so iterating
+    /// the map visits observers in the order they subscribed.
+    observers: BTreeMap<usize, SharedObserver<OptionalObserver<ObserverBox<T, E>>>>,
+    /// The handler to report errors of individual observers to, if any.
+    error_handler: Option<ErrorHandler<E>>,
+    /// The handler to report panics of individual observers to, if any.
+    panic_handler: Option<PanicHandler>,
+    /// The priority of each subscription deviating from the default
+    /// priority of zero.
+    priorities: HashMap<usize, i32>,
+    /// The statistics of each subscription.
+    stats: HashMap<usize, SubStats>,
+    /// The transaction in progress, if any.
+    txn: Option<Transaction>,
+    /// Whether we warned about the lock having been poisoned.
+    poisoned: bool,
+}
+
+impl<T, E> Subscribers<T, E> {
+    /// Add the observer of a new subscription.
+    fn insert(
+        &mut self,
+        subscription: usize,
+        observer: SharedObserver<OptionalObserver<ObserverBox<T, E>>>,
+    ) {
+        let _ = self.stats.insert(subscription, SubStats::new());
+        let _ = self.observers.insert(subscription, observer);
+    }
+
+    /// Remove the observer of the given subscription, if any.
+    fn remove(
+        &mut self,
+        subscription: &usize,
+    ) -> Option<SharedObserver<OptionalObserver<ObserverBox<T, E>>>> {
+        let _ = self.priorities.remove(subscription);
+        let _ = self.stats.remove(subscription);
+        self.observers.remove(subscription)
+    }
+
+    /// Retrieve the IDs of all subscriptions in delivery order, i.e., in
+    /// descending order of their priority and, among subscriptions of
+    /// the same priority, in the order they subscribed.
+    fn delivery_order(&self) -> Vec<usize> {
+        let mut subscriptions = self.observers.keys().copied().collect::<Vec<_>>();
+        // the sort is stable, so observers of the same priority keep
+        // the order they subscribed in
+        subscriptions.sort_by_key(|subscription| {
+            Reverse(self.priorities.get(subscription).copied().unwrap_or(0))
+        });
+        subscriptions
+    }
+
+    /// Retrieve the observers taking part in the transaction in
+    /// progress that are still subscribed, in delivery order. If no
+    /// transaction is in progress, all observers are retrieved.
+    fn participants(&self) -> Vec<(usize, SharedObserver<OptionalObserver<ObserverBox<T, E>>>)> {
+        let subscriptions = match &self.txn {
+            Some(txn) => txn.subscriptions.clone(),
+            None => self.delivery_order(),
+        };
+        subscriptions
+            .into_iter()
+            .filter_map(|subscription| {
+                self.observers
+                    .get(&subscription)
+                    .map(|observer| (subscription, observer.clone()))
+            })
+            .collect()
+    }
+
+    /// Account for the given number of updates distributed to the
+    /// observer of the given subscription, if it is still subscribed.
+    fn record_live_updates(&mut self, subscription: usize, count: usize) {
+        if let Some(stats) = self.stats.get_mut(&subscription) {
+            stats.live_updates += count as u64;
+        }
+    }
+}
+
+/// A cheaply clonable handle to a set of observers that events are
+/// distributed to. All clones share the same observers.
+///
+/// Events are delivered without holding the lock protecting the set of
+/// observers, so that an observer may subscribe or unsubscribe through
+/// a handle to the distributor from within its own callbacks.
+#[derive(Debug)]
+pub struct TxnDistributor<T, E> {
+    id: usize,
+    /// The observers subscribed to us, shared with all our clones.
+    subscribers: Arc<Mutex<Subscribers<T, E>>>,
+}
+
+// Manual implementation of `Clone` to not require `T: Clone` and
+// `E: Clone`.
+impl<T, E> Clone for TxnDistributor<T, E> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            subscribers: self.subscribers.clone(),
+        }
+    }
 }
 
 impl<T, E> TxnDistributor<T, E>
@@ -31,10 +304,40 @@ where
 
         Self {
             id,
-            observers: HashMap::new(),
+            subscribers: Arc::new(Mutex::new(Subscribers {
+                observers: BTreeMap::new(),
+                error_handler: None,
+                panic_handler: None,
+                priorities: HashMap::new(),
+                stats: HashMap::new(),
+                txn: None,
+                poisoned: false,
+            })),
         }
     }
 
+    /// Register a handler that is invoked with the subscription and the
+    /// error of every observer failing to process an event. Errors
+    /// reported to the handler are not propagated to the caller.
+    pub fn on_observer_error<F>(&mut self, handler: F)
+    where
+        F: Fn(usize, E) + Send + 'static,
+    {
+        trace!("TxnDistributor({})::on_observer_error", self.id);
+        self.subscribers().error_handler = Some(ErrorHandler(Arc::new(Mutex::new(handler))));
+    }
+
+    /// Register a handler that is invoked with the subscription of every
+    /// observer panicking while processing an event, along with the
+    /// panic's message. Such observers are unsubscribed in any case.
+    pub fn on_observer_panic<F>(&mut self, handler: F)
+    where
+        F: Fn(usize, String) + Send + 'static,
+    {
+        trace!("TxnDistributor({})::on_observer_panic", self.id);
+        self.subscribers().panic_handler = Some(PanicHandler(Arc::new(Mutex::new(handler))));
+    }
+
     pub fn create_observable(&mut self) -> UpdatesObservable<T, E> {
         let subscription = Id::<()>::new().get();
         trace!(
@@ -44,9 +347,150 @@ where
         );
 
         let observer = SharedObserver::default();
-        let _ = self.observers.insert(subscription, observer.clone());
+        let gate = ObservableGate {
+            observer: Box::new(observer.clone()),
+            slot: observer.clone(),
+            attached: true,
+        };
+        self.subscribers()
+            .insert(subscription, Arc::new(Mutex::new(Some(Box::new(gate)))));
         UpdatesObservable { observer }
     }
+
+    /// Retrieve the number of subscriptions, including those of
+    /// observables created through `create_observable`.
+    pub fn observer_count(&self) -> usize {
+        self.subscribers().observers.len()
+    }
+
+    /// Set the priority of the observer of the given subscription.
+    /// Events are delivered to observers in descending order of their
+    /// priority and, among observers of the same priority, in the order
+    /// they subscribed. The default priority is zero.
+    pub fn set_priority(&mut self, subscription: usize, priority: i32) {
+        trace!(
+            "TxnDistributor({})::set_priority({}, {})",
+            self.id,
+            subscription,
+            priority
+        );
+        let mut subscribers = self.subscribers();
+        if subscribers.observers.contains_key(&subscription) {
+            let _ = subscribers.priorities.insert(subscription, priority);
+        }
+    }
+
+    /// Retrieve the statistics of each subscription, including those of
+    /// observables created through `create_observable`.
+    pub fn subscription_stats(&self) -> HashMap<usize, SubStats> {
+        self.subscribers().stats.clone()
+    }
+
+    /// Record the number of updates sent to initialize the observer of
+    /// the given subscription, e.g., with the state accumulated before
+    /// it subscribed.
+    pub fn record_init_updates(&mut self, subscription: usize, count: usize) {
+        if let Some(stats) = self.subscribers().stats.get_mut(&subscription) {
+            stats.init_updates = count;
+        }
+    }
+
+    /// Retrieve the IDs of all subscriptions, in ascending order.
+    pub fn subscription_ids(&self) -> Vec<usize> {
+        self.subscribers().observers.keys().copied().collect()
+    }
+
+    /// Invoke the given function on the observer of the given
+    /// subscription only. Returns `None` if there is no such
+    /// subscription.
+    pub fn deliver_to<F>(&mut self, subscription: usize, f: F) -> Option<Result<(), E>>
+    where
+        F: FnOnce(&mut SharedObserver<OptionalObserver<ObserverBox<T, E>>>) -> Result<(), E>,
+    {
+        trace!("TxnDistributor({})::deliver_to({})", self.id, subscription);
+        // do not hold the lock while invoking the observer
+        let observer = self.subscribers().observers.get(&subscription).cloned();
+        observer.map(|mut observer| delivering(|| f(&mut observer)))
+    }
+
+    /// Let the observer of the given subscription, which subscribed while
+    /// a transaction is in progress, take part in that transaction from
+    /// now on, starting it for the observer. Returns `None` if there is
+    /// no such subscription or no transaction is in progress.
+    pub fn join_transaction(&mut self, subscription: usize) -> Option<Result<(), E>> {
+        trace!(
+            "TxnDistributor({})::join_transaction({})",
+            self.id,
+            subscription
+        );
+        let (mut observer, seq) = {
+            let mut subscribers = self.subscribers();
+            let observer = subscribers.observers.get(&subscription).cloned()?;
+            let txn = subscribers.txn.as_mut()?;
+            if !txn.subscriptions.contains(&subscription) {
+                txn.subscriptions.push(subscription);
+            }
+            (observer, txn.seq)
+        };
+        Some(delivering(|| match seq {
+            Some(seq) => observer.on_start_seq(seq),
+            None => observer.on_start(),
+        }))
+    }
+
+    /// Create a new observable whose events pass through an adapter
+    /// before reaching the observer subscribed to it. The adapter is
+    /// provided with the observable's (initially empty) observer slot
+    /// and returns the observer to register with the distributor.
+    pub fn create_observable_with<F>(&mut self, adapt: F) -> UpdatesObservable<T, E>
+    where
+        F: FnOnce(SharedObserver<OptionalObserver<ObserverBox<T, E>>>) -> ObserverBox<T, E>,
+    {
+        let subscription = Id::<()>::new().get();
+        trace!(
+            "TxnDistributor({:?})::create_observable_with({:?})",
+            self.id,
+            subscription
+        );
+
+        let observer = SharedObserver::default();
+        let gate = ObservableGate {
+            observer: adapt(observer.clone()),
+            slot: observer.clone(),
+            attached: true,
+        };
+        self.subscribers()
+            .insert(subscription, Arc::new(Mutex::new(Some(Box::new(gate)))));
+        UpdatesObservable { observer }
+    }
+
+    /// Subscribe the observer created by the given function, which is
+    /// provided with a function cancelling the subscription. The latter
+    /// does not keep the distributor alive and has no effect once the
+    /// distributor was dropped.
+    pub fn subscribe_with<F>(&mut self, create: F) -> usize
+    where
+        F: FnOnce(Box<dyn FnOnce() + Send>) -> ObserverBox<T, E>,
+    {
+        let id = Id::<()>::new().get();
+        trace!("TxnDistributor({})::subscribe_with({})", self.id, id);
+
+        let subscribers = Arc::downgrade(&self.subscribers);
+        let cancel = Box::new(move || {
+            if let Some(subscribers) = subscribers.upgrade() {
+                // drop the observer only after releasing the lock
+                let observer = subscribers
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .remove(&id);
+                drop(observer)
+            }
+        });
+        let observer = create(cancel);
+        self.subscribers()
+            .insert(id, Arc::new(Mutex::new(Some(observer))));
+        id
+    }
 }
 
 impl<T, E> Observable<T, E> for TxnDistributor<T, E>
@@ -64,76 +508,299 @@ where
         trace!("TxnDistributor({})::subscribe({})", self.id, id);
 
         // TODO: can the same observer subscribe multiple times?
-        let _ = self
-            .observers
+        self.subscribers()
             .insert(id, Arc::new(Mutex::new(Some(observer))));
         Ok(id)
     }
 
     fn unsubscribe(&mut self, subscription: &Self::Subscription) -> Option<ObserverBox<T, E>> {
         trace!("TxnDistributor({})::unsubscribe({})", self.id, subscription);
-        match self.observers.remove(subscription) {
+        let observer = self.subscribers().remove(subscription);
+        match observer {
             Some(observer) => Some(Box::new(observer)),
             None => None,
         }
     }
 }
 
+impl<T, E> TxnDistributor<T, E> {
+    /// Lock the state shared between all handles. An observer panicking
+    /// while the lock is held does not render the distributor unusable,
+    /// as the state is consistent at all times. We warn about it once.
+    fn subscribers(&self) -> MutexGuard<'_, Subscribers<T, E>> {
+        self.subscribers.lock().unwrap_or_else(|poisoned| {
+            let mut subscribers = poisoned.into_inner();
+            if !subscribers.poisoned {
+                subscribers.poisoned = true;
+                warn!(
+                    "TxnDistributor({}) recovering from a panic while holding the lock",
+                    self.id
+                );
+            }
+            subscribers
+        })
+    }
+}
+
+impl<T, E> TxnDistributor<T, E>
+where
+    T: Send,
+    E: Send + Debug,
+{
+    /// Invoke the given function on every observer. Errors are reported
+    /// to the error handler if one is registered, otherwise the first
+    /// error encountered is returned once all observers were invoked.
+    fn for_each_observer<F>(&mut self, f: F) -> Result<(), E>
+    where
+        F: FnMut(&mut SharedObserver<OptionalObserver<ObserverBox<T, E>>>) -> Result<(), E>,
+    {
+        self.for_each_observer_in(false, f)
+    }
+
+    /// Invoke the given function on every observer, as
+    /// `for_each_observer` does, optionally in reverse order.
+    fn for_each_observer_in<F>(&mut self, reverse: bool, f: F) -> Result<(), E>
+    where
+        F: FnMut(&mut SharedObserver<OptionalObserver<ObserverBox<T, E>>>) -> Result<(), E>,
+    {
+        // Take a snapshot of the observers, so that we do not hold the
+        // lock while invoking them.
+        let (observers, error_handler) = {
+            let subscribers = self.subscribers();
+            let mut observers = subscribers
+                .delivery_order()
+                .into_iter()
+                .map(|subscription| (subscription, subscribers.observers[&subscription].clone()))
+                .collect::<Vec<_>>();
+            if reverse {
+                observers.reverse();
+            }
+            (observers, subscribers.error_handler.clone())
+        };
+        self.deliver_each(observers, &error_handler, f)
+    }
+
+    /// Invoke the given function on every observer taking part in the
+    /// transaction in progress, as `for_each_observer` does.
+    fn for_each_participant<F>(&mut self, f: F) -> Result<(), E>
+    where
+        F: FnMut(&mut SharedObserver<OptionalObserver<ObserverBox<T, E>>>) -> Result<(), E>,
+    {
+        let (observers, error_handler) = {
+            let subscribers = self.subscribers();
+            (
+                subscribers.participants(),
+                subscribers.error_handler.clone(),
+            )
+        };
+        self.deliver_each(observers, &error_handler, f)
+    }
+
+    /// Start a transaction with the given sequence number, if any,
+    /// taking a snapshot of the subscriptions taking part in it.
+    fn start_transaction(&mut self, seq: Option<u64>) {
+        let mut subscribers = self.subscribers();
+        subscribers.txn = Some(Transaction {
+            subscriptions: subscribers.delivery_order(),
+            seq,
+        });
+    }
+
+    /// Invoke the given function on each of the given observers in
+    /// turn, reporting their errors.
+    fn deliver_each<F>(
+        &self,
+        observers: Vec<(usize, SharedObserver<OptionalObserver<ObserverBox<T, E>>>)>,
+        error_handler: &Option<ErrorHandler<E>>,
+        mut f: F,
+    ) -> Result<(), E>
+    where
+        F: FnMut(&mut SharedObserver<OptionalObserver<ObserverBox<T, E>>>) -> Result<(), E>,
+    {
+        let mut result = Ok(());
+        for (subscription, mut observer) in observers {
+            self.deliver(
+                subscription,
+                &mut observer,
+                &mut f,
+                error_handler,
+                &mut result,
+            );
+        }
+        result
+    }
+
+    /// Invoke the given function on the observer of the given
+    /// subscription, reporting its error, if any. An observer panicking
+    /// is unsubscribed and the panic is reported to the panic handler,
+    /// if one is registered.
+    fn deliver<F>(
+        &self,
+        subscription: usize,
+        observer: &mut SharedObserver<OptionalObserver<ObserverBox<T, E>>>,
+        f: F,
+        error_handler: &Option<ErrorHandler<E>>,
+        result: &mut Result<(), E>,
+    ) where
+        F: FnOnce(&mut SharedObserver<OptionalObserver<ObserverBox<T, E>>>) -> Result<(), E>,
+    {
+        match catch_unwind(AssertUnwindSafe(|| delivering(|| f(observer)))) {
+            Ok(Ok(())) => (),
+            Ok(Err(error)) => self.report_error(subscription, error, error_handler, result),
+            Err(payload) => {
+                let message = panic_message(payload.as_ref());
+                error!(
+                    "TxnDistributor({}) observer {} panicked: {}",
+                    self.id, subscription, message
+                );
+                // drop the observer only after releasing the lock
+                let (observer, panic_handler) = {
+                    let mut subscribers = self.subscribers();
+                    let observer = subscribers.remove(&subscription);
+                    (observer, subscribers.panic_handler.clone())
+                };
+                drop(observer);
+                if let Some(PanicHandler(handler)) = panic_handler {
+                    (handler.lock().unwrap_or_else(PoisonError::into_inner))(subscription, message)
+                }
+            }
+        }
+    }
+
+    /// Report the error of the observer of the given subscription to the
+    /// error handler, if any, or record it in `result` unless an earlier
+    /// error was recorded already.
+    fn report_error(
+        &self,
+        subscription: usize,
+        error: E,
+        error_handler: &Option<ErrorHandler<E>>,
+        result: &mut Result<(), E>,
+    ) {
+        trace!(
+            "TxnDistributor({}) observer {} failed: {:?}",
+            self.id,
+            subscription,
+            error
+        );
+        match error_handler {
+            Some(ErrorHandler(handler)) => {
+                (handler.lock().unwrap_or_else(PoisonError::into_inner))(subscription, error)
+            }
+            None if result.is_ok() => *result = Err(error),
+            None => (),
+        }
+    }
+}
+
 /// Receives the values, clones them and sends them to each observer
 impl<T, E> Observer<T, E> for TxnDistributor<T, E>
 where
     T: Send + Debug + Clone,
     E: Send + Debug,
 {
+    /// Start a transaction for all observers. Only the observers
+    /// subscribed at this point take part in the transaction, i.e.,
+    /// observers subscribing while it is in progress, e.g., from within
+    /// a callback, only receive the next one.
     fn on_start(&mut self) -> Result<(), E> {
         trace!("TxnDistributor({})::on_start", self.id);
+        self.start_transaction(None);
+        self.for_each_participant(|o| o.on_start())
+    }
 
-        match self.observers.values_mut()
-            .map(|o| o.on_start())
-            .collect::<Result<Vec<_>, E>>() // collects all results into a single result
-        {
-            Ok(_) => Ok(()),
-            Err(error) => Err(error)
-        }
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        trace!("TxnDistributor({})::on_start_seq({})", self.id, seq);
+        self.start_transaction(Some(seq));
+        self.for_each_participant(|o| o.on_start_seq(seq))
     }
 
     fn on_commit(&mut self) -> Result<(), E> {
         trace!("TxnDistributor({})::on_commit", self.id);
-
-        match self.observers.values_mut()
-            .map(|o| o.on_commit())
-            .collect::<Result<Vec<_>, E>>() // collects all results into a single result
-        {
-            Ok(_) => Ok(()),
-            Err(error) => Err(error)
-        }
+        let result = self.for_each_participant(|o| o.on_commit());
+        self.subscribers().txn = None;
+        result
     }
 
+    /// Deliver the updates to all observers taking part in the
+    /// transaction. Observers are invoked in descending order of their
+    /// priority and, among observers of the same priority, in ascending
+    /// order of their subscription ID, i.e., in the order in which they
+    /// subscribed (or their observable was created).
+    ///
+    /// A single observer consumes the updates lazily, as they are
+    /// produced. With multiple observers, the updates are collected into
+    /// a buffer first, which each observer then iterates over, cloning
+    /// the updates as it consumes them. The buffer holds all of the
+    /// updates at once, i.e., the updates are no longer processed
+    /// lazily.
     fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
         trace!("TxnDistributor({})::on_updates", self.id);
 
-        // clone updates for each observer
-        let upd_vec = updates.collect::<Vec<T>>();
-        match self.observers.values_mut()
-            .map(move |o|
-                o.on_updates(Box::new(upd_vec.clone().into_iter()))
+        let (mut observers, error_handler) = {
+            let subscribers = self.subscribers();
+            (
+                subscribers.participants(),
+                subscribers.error_handler.clone(),
             )
-            .collect::<Result<Vec<_>, E>>() // collects all results into a single result
-        {
-            Ok(_) => Ok(()),
-            Err(error) => Err(error)
+        };
+
+        if observers.len() == 1 {
+            // a single observer can consume the updates directly, so
+            // count them as they are consumed
+            let (subscription, mut observer) = observers.remove(0);
+            let count = Cell::new(0);
+            let updates = updates.inspect(|_| count.set(count.get() + 1));
+            let mut result = Ok(());
+            self.deliver(
+                subscription,
+                &mut observer,
+                |o| o.on_updates(Box::new(updates)),
+                &error_handler,
+                &mut result,
+            );
+            self.subscribers()
+                .record_live_updates(subscription, count.get());
+            return result;
         }
+
+        // buffer the updates once, for each observer to clone them
+        let upd_vec = updates.collect::<Vec<T>>();
+        let subscriptions = observers
+            .iter()
+            .map(|(subscription, _)| *subscription)
+            .collect::<Vec<_>>();
+        let result = self.deliver_each(observers, &error_handler, |o| {
+            o.on_updates(Box::new(upd_vec.iter().cloned()))
+        });
+        let mut subscribers = self.subscribers();
+        for subscription in subscriptions {
+            subscribers.record_live_updates(subscription, upd_vec.len());
+        }
+        result
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("TxnDistributor({})::on_abort", self.id);
+        let result = self.for_each_participant(|o| o.on_abort());
+        self.subscribers().txn = None;
+        result
     }
 
+    fn on_barrier(&mut self) -> Result<(), E> {
+        trace!("TxnDistributor({})::on_barrier", self.id);
+        self.for_each_observer(|o| o.on_barrier())
+    }
+
+    /// Deliver the completion to all observers in reverse of the order
+    /// other events are delivered in, i.e., observers of the same
+    /// priority are completed in reverse of the order they subscribed
+    /// in, so that observers subscribed later, which may depend on
+    /// earlier ones, are torn down first.
     fn on_completed(&mut self) -> Result<(), E> {
         trace!("TxnDistributor({})::on_completed", self.id);
-        match self.observers.values_mut()
-            .map(|o| o.on_completed())
-            .collect::<Result<Vec<_>, E>>() // collects all results into a single result
-        {
-            Ok(_) => Ok(()),
-            Err(error) => Err(error)
-        }
+        self.subscribers().txn = None;
+        self.for_each_observer_in(true, |o| o.on_completed())
     }
 }
 
@@ -154,6 +821,9 @@ mod tests {
         assert!(subscription.is_ok());
         assert!(distributor.unsubscribe(&subscription.unwrap()).is_some());
         assert!(distributor
+            .subscribers
+            .lock()
+            .unwrap()
             .observers
             .values()
             .collect::<Vec<_>>()
@@ -195,6 +865,57 @@ mod tests {
         assert_eq!(mock2.lock().unwrap().called_on_completed, 1);
     }
 
+    /// Test that a single observer receives updates directly and that
+    /// a second observer subscribing while a transaction is in progress
+    /// only takes part in the next one.
+    #[test]
+    fn single_subscribe_distributor() {
+        let mut distributor = TxnDistributor::<_, ()>::new();
+        let mock1 = Arc::new(Mutex::new(MockObserver::new()));
+        let mock2 = Arc::new(Mutex::new(MockObserver::new()));
+
+        assert!(distributor.subscribe(Box::new(mock1.clone())).is_ok());
+        assert_eq!(distributor.on_start(), Ok(()));
+        assert_eq!(distributor.on_updates(Box::new([1, 3, 2].iter())), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_updates, 3);
+
+        assert!(distributor.subscribe(Box::new(mock2.clone())).is_ok());
+        assert_eq!(distributor.on_updates(Box::new([4].iter())), Ok(()));
+        assert_eq!(distributor.on_commit(), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_updates, 4);
+        assert_eq!(mock2.lock().unwrap().called_on_updates, 0);
+        assert_eq!(mock2.lock().unwrap().called_on_commit, 0);
+
+        assert_eq!(distributor.on_start(), Ok(()));
+        assert_eq!(distributor.on_updates(Box::new([5].iter())), Ok(()));
+        assert_eq!(distributor.on_commit(), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_updates, 5);
+        let mock2 = mock2.lock().unwrap();
+        assert_eq!(mock2.called_on_start, 1);
+        assert_eq!(mock2.called_on_updates, 1);
+        assert_eq!(mock2.called_on_commit, 1);
+    }
+
+    /// Test that an observer subscribing while a transaction is in
+    /// progress can be made to join it.
+    #[test]
+    fn join_transaction() {
+        let mut distributor = TxnDistributor::<_, ()>::new();
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+
+        assert_eq!(distributor.on_start(), Ok(()));
+        let subscription = distributor.subscribe(Box::new(mock.clone())).unwrap();
+        assert_eq!(distributor.join_transaction(subscription), Some(Ok(())));
+        assert_eq!(distributor.on_updates(Box::new([1, 2].iter())), Ok(()));
+        assert_eq!(distributor.on_commit(), Ok(()));
+        assert_eq!(distributor.join_transaction(subscription), None);
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, 1);
+        assert_eq!(mock.called_on_updates, 2);
+        assert_eq!(mock.called_on_commit, 1);
+    }
+
     /// Test multiple indirect subscriptions via `create_observable` to a `TxnDistributor`.
     #[test]
     fn multiple_subscribe_indirect_distributor() {
@@ -219,4 +940,127 @@ mod tests {
         assert_eq!(mock1.lock().unwrap().called_on_commit, 1);
         assert_eq!(mock2.lock().unwrap().called_on_commit, 1);
     }
+
+    /// An observer recording its index in a shared list whenever it
+    /// receives updates.
+    #[derive(Debug)]
+    struct OrderObserver {
+        index: usize,
+        order: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl Observer<usize, ()> for OrderObserver {
+        fn on_start(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn on_updates<'a>(&mut self, _: Box<dyn Iterator<Item = usize> + 'a>) -> Result<(), ()> {
+            self.order.lock().unwrap().push(self.index);
+            Ok(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    /// Test that updates are delivered in the order of subscription.
+    #[test]
+    fn deterministic_delivery_order() {
+        let mut distributor = TxnDistributor::<usize, ()>::new();
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut observables = Vec::new();
+
+        for index in 0..8 {
+            let observer = Box::new(OrderObserver {
+                index,
+                order: order.clone(),
+            });
+            if index % 2 == 0 {
+                assert!(distributor.subscribe(observer).is_ok());
+            } else {
+                let mut observable = distributor.create_observable();
+                assert!(observable.subscribe(observer).is_ok());
+                observables.push(observable);
+            }
+        }
+
+        assert_eq!(distributor.on_start(), Ok(()));
+        assert_eq!(
+            distributor.on_updates(Box::new(vec![1, 2].into_iter())),
+            Ok(())
+        );
+        assert_eq!(
+            distributor.on_updates(Box::new(vec![3].into_iter())),
+            Ok(())
+        );
+        assert_eq!(distributor.on_commit(), Ok(()));
+
+        let expected = (0..8).chain(0..8).collect::<Vec<_>>();
+        assert_eq!(*order.lock().unwrap(), expected);
+    }
+
+    /// Test that an observer panicking while processing updates is
+    /// unsubscribed and reported, while the others keep receiving them.
+    #[test]
+    fn panicking_observer() {
+        let mut distributor = TxnDistributor::<_, ()>::new();
+        let panics = Arc::new(Mutex::new(Vec::new()));
+        let panics_clone = panics.clone();
+        distributor.on_observer_panic(move |subscription, message| {
+            panics_clone.lock().unwrap().push((subscription, message))
+        });
+
+        let mut panicking = MockObserver::new();
+        panicking.panic_on_updates = true;
+        let healthy = Arc::new(Mutex::new(MockObserver::new()));
+        let subscription = distributor.subscribe(Box::new(panicking)).unwrap();
+        assert!(distributor.subscribe(Box::new(healthy.clone())).is_ok());
+
+        assert_eq!(distributor.on_start(), Ok(()));
+        assert_eq!(distributor.on_updates(Box::new([1, 3, 2].iter())), Ok(()));
+        assert_eq!(distributor.observer_count(), 1);
+        assert_eq!(
+            *panics.lock().unwrap(),
+            vec![(
+                subscription,
+                "MockObserver panicking on updates".to_string()
+            )]
+        );
+
+        assert_eq!(distributor.on_updates(Box::new([4].iter())), Ok(()));
+        assert_eq!(distributor.on_commit(), Ok(()));
+        assert_eq!(healthy.lock().unwrap().called_on_start, 1);
+        assert_eq!(healthy.lock().unwrap().called_on_updates, 4);
+        assert_eq!(healthy.lock().unwrap().called_on_commit, 1);
+        assert!(distributor.unsubscribe(&subscription).is_none());
+    }
+
+    /// Test that the distributor keeps working after a thread panicked
+    /// while holding its lock.
+    #[test]
+    fn poisoned_lock() {
+        let mut distributor = TxnDistributor::<_, ()>::new();
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        assert!(distributor.subscribe(Box::new(mock.clone())).is_ok());
+
+        let clone = distributor.clone();
+        let result = std::thread::spawn(move || {
+            let _guard = clone.subscribers.lock().unwrap();
+            panic!("panicking while holding the lock")
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(distributor.subscribers.is_poisoned());
+
+        assert_eq!(distributor.on_start(), Ok(()));
+        assert_eq!(distributor.on_updates(Box::new([1, 2].iter())), Ok(()));
+        assert_eq!(distributor.on_commit(), Ok(()));
+        assert_eq!(distributor.observer_count(), 1);
+        assert_eq!(mock.lock().unwrap().called_on_updates, 2);
+    }
 }
@@ -0,0 +1,392 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use log::trace;
+use uid::Id;
+
+use differential_datalog::program::RelId;
+use differential_datalog::program::Update;
+
+use crate::accumulate::StateHandle;
+use crate::Observable;
+use crate::ObservableBox;
+use crate::Observer;
+use crate::ObserverBox;
+
+/// The state of a single subscription to a `UnionObservable`, shared by
+/// the observers subscribed to both sources.
+#[derive(Debug)]
+pub(crate) struct Union<V, E> {
+    /// The observer we forward the union to, if still subscribed.
+    observer: Option<ObserverBox<Update<V>, E>>,
+    /// The sources each value of the union is present in, as forwarded
+    /// to the observer.
+    present: HashMap<RelId, HashMap<V, [bool; 2]>>,
+    /// Whether each of the sources completed.
+    completed: [bool; 2],
+}
+
+impl<V, E> Union<V, E> {
+    /// Create a new `Union` forwarding to the given observer, starting
+    /// out with the values present in the sources as given.
+    pub(crate) fn new(
+        observer: ObserverBox<Update<V>, E>,
+        present: HashMap<RelId, HashMap<V, [bool; 2]>>,
+    ) -> Self {
+        Self {
+            observer: Some(observer),
+            present,
+            completed: [false; 2],
+        }
+    }
+}
+
+impl<V, E> Union<V, E>
+where
+    V: Clone + Eq + Hash,
+{
+    /// Record an update of the given source, returning the update to
+    /// forward if it changes the union.
+    fn apply(&mut self, source: usize, update: Update<V>) -> Option<Update<V>> {
+        match update {
+            Update::Insert { relid, v } => {
+                let sources = self
+                    .present
+                    .entry(relid)
+                    .or_default()
+                    .entry(v.clone())
+                    .or_default();
+                let absent = !sources[0] && !sources[1];
+                sources[source] = true;
+                if absent {
+                    Some(Update::Insert { relid, v })
+                } else {
+                    None
+                }
+            }
+            Update::DeleteValue { relid, v } => {
+                let values = self.present.get_mut(&relid)?;
+                let sources = values.get_mut(&v)?;
+                sources[source] = false;
+                if sources[0] || sources[1] {
+                    None
+                } else {
+                    let _ = values.remove(&v);
+                    Some(Update::DeleteValue { relid, v })
+                }
+            }
+            update => Some(update),
+        }
+    }
+}
+
+/// An observer feeding the transactions of one source of a
+/// `UnionObservable` into the union. Transactions are held back until
+/// they are committed, so that transactions of the two sources are not
+/// interleaved downstream.
+#[derive(Debug)]
+pub(crate) struct UnionObserver<V, E> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The index of the source we observe.
+    source: usize,
+    /// The union the source is part of.
+    union: Arc<Mutex<Union<V, E>>>,
+    /// The updates of the transaction in progress, if any.
+    updates: Option<Vec<Update<V>>>,
+}
+
+impl<V, E> UnionObserver<V, E> {
+    /// Create a new `UnionObserver` feeding the given source into the
+    /// union.
+    pub(crate) fn new(id: usize, source: usize, union: Arc<Mutex<Union<V, E>>>) -> Self {
+        Self {
+            id,
+            source,
+            union,
+            updates: None,
+        }
+    }
+}
+
+impl<V, E> Observer<Update<V>, E> for UnionObserver<V, E>
+where
+    V: Clone + Debug + Eq + Hash + Send,
+    E: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("UnionObserver({})::on_start", self.id);
+        self.updates = Some(Vec::new());
+        Ok(())
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("UnionObserver({})::on_commit", self.id);
+        let updates = self
+            .updates
+            .take()
+            .expect("on_commit was not preceded by an on_start event");
+
+        let mut union = self.union.lock().unwrap();
+        let source = self.source;
+        let updates = updates
+            .into_iter()
+            .filter_map(|u| union.apply(source, u))
+            .collect::<Vec<_>>();
+        match &mut union.observer {
+            Some(observer) if !updates.is_empty() => {
+                observer.on_start()?;
+                observer.on_updates(Box::new(updates.into_iter()))?;
+                observer.on_commit()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("UnionObserver({})::on_updates", self.id);
+        self.updates
+            .as_mut()
+            .expect("on_updates was not preceded by an on_start event")
+            .extend(updates);
+        Ok(())
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("UnionObserver({})::on_abort", self.id);
+        self.updates = None;
+        Ok(())
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("UnionObserver({})::on_completed", self.id);
+        let mut union = self.union.lock().unwrap();
+        union.completed[self.source] = true;
+        if union.completed[0] && union.completed[1] {
+            if let Some(observer) = &mut union.observer {
+                return observer.on_completed();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An observable emitting the set union of the states of two
+/// accumulators, e.g., to provide a combined view of accumulators
+/// tracking overlapping relations.
+///
+/// Upon subscription, the current union is sent as a transaction of
+/// insertions, after which the transactions of both accumulators are
+/// forwarded. Each value is emitted once, no matter whether it is
+/// present in one or both of them, and its deletion is only forwarded
+/// once it is absent from both.
+#[derive(Debug)]
+pub struct UnionObservable<V, E> {
+    /// The observable's unique ID.
+    id: usize,
+    /// The states of the two accumulators.
+    states: [StateHandle<V>; 2],
+    /// The observables emitting the updates of the two accumulators.
+    observables: [ObservableBox<Update<V>, E>; 2],
+    /// The subscriptions to both observables along with the union they
+    /// feed, for each subscription.
+    subscriptions: HashMap<usize, (Vec<Box<dyn Any + Send>>, Arc<Mutex<Union<V, E>>>)>,
+}
+
+impl<V, E> UnionObservable<V, E> {
+    /// Create a new `UnionObservable` for two accumulators, each given
+    /// by a handle to its state and an observable emitting its updates
+    /// without the accumulated state, as created via
+    /// `Accumulator::create_observable`.
+    pub fn new(
+        left: StateHandle<V>,
+        left_updates: ObservableBox<Update<V>, E>,
+        right: StateHandle<V>,
+        right_updates: ObservableBox<Update<V>, E>,
+    ) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("UnionObservable({})::new", id);
+
+        Self {
+            id,
+            states: [left, right],
+            observables: [left_updates, right_updates],
+            subscriptions: HashMap::new(),
+        }
+    }
+}
+
+impl<V, E> Observable<Update<V>, E> for UnionObservable<V, E>
+where
+    V: Clone + Debug + Eq + Hash + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    type Subscription = usize;
+
+    fn subscribe(
+        &mut self,
+        mut observer: ObserverBox<Update<V>, E>,
+    ) -> Result<Self::Subscription, ObserverBox<Update<V>, E>> {
+        let id = Id::<()>::new().get();
+        trace!("UnionObservable({})::subscribe({})", self.id, id);
+
+        let mut present = HashMap::<RelId, HashMap<V, [bool; 2]>>::new();
+        for (source, state) in self.states.iter().enumerate() {
+            for (relid, values) in state.get_current_state() {
+                let relation = present.entry(relid).or_default();
+                for v in values {
+                    relation.entry(v).or_default()[source] = true;
+                }
+            }
+        }
+
+        let init_updates = present
+            .iter()
+            .flat_map(|(relid, values)| {
+                values.keys().map(move |v| Update::Insert {
+                    relid: *relid,
+                    v: v.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
+        if !init_updates.is_empty() {
+            let _ = observer.on_start();
+            let _ = observer.on_updates(Box::new(init_updates.into_iter()));
+            let _ = observer.on_commit();
+        }
+
+        let union = Arc::new(Mutex::new(Union::new(observer, present)));
+        let mut subscriptions = Vec::new();
+        for (source, observable) in self.observables.iter_mut().enumerate() {
+            let feed = UnionObserver::new(id, source, union.clone());
+            match observable.subscribe_any(Box::new(feed)) {
+                Ok(subscription) => subscriptions.push(subscription),
+                Err(_) => {
+                    for (observable, subscription) in self.observables.iter_mut().zip(subscriptions)
+                    {
+                        let _ = observable.unsubscribe_any(subscription.as_ref());
+                    }
+                    let observer = union.lock().unwrap().observer.take().unwrap();
+                    return Err(observer);
+                }
+            }
+        }
+
+        let _ = self.subscriptions.insert(id, (subscriptions, union));
+        Ok(id)
+    }
+
+    fn unsubscribe(
+        &mut self,
+        subscription: &Self::Subscription,
+    ) -> Option<ObserverBox<Update<V>, E>> {
+        trace!(
+            "UnionObservable({})::unsubscribe({})",
+            self.id,
+            subscription
+        );
+        let (subscriptions, union) = self.subscriptions.remove(subscription)?;
+        for (observable, subscription) in self.observables.iter_mut().zip(subscriptions) {
+            let _ = observable.unsubscribe_any(subscription.as_ref());
+        }
+        let observer = union.lock().unwrap().observer.take();
+        observer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashSet;
+
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::Accumulator;
+    use crate::DistributingAccumulator;
+
+    /// Commit a transaction inserting the given values into relation 1.
+    fn insert(accumulator: &mut DistributingAccumulator<Update<usize>, usize, ()>, vs: &[usize]) {
+        let updates = vs
+            .iter()
+            .map(|v| Update::Insert { relid: 1, v: *v })
+            .collect::<Vec<_>>();
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(updates.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+    }
+
+    /// Test that a subscriber to a `UnionObservable` sees each value of
+    /// the union exactly once and deletions only once a value is absent
+    /// from both accumulators.
+    #[test]
+    fn union_of_states() {
+        let mut left = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        let mut right = DistributingAccumulator::<Update<usize>, usize, ()>::new();
+        insert(&mut left, &[1, 2]);
+        insert(&mut right, &[2, 3]);
+
+        let mut union = UnionObservable::new(
+            left.state_handle(),
+            Box::new(left.create_observable()),
+            right.state_handle(),
+            Box::new(right.create_observable()),
+        );
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let subscription = union.subscribe(Box::new(mock.clone())).unwrap();
+
+        let received = mock.lock().unwrap().received_updates.clone();
+        let mut values = received
+            .iter()
+            .map(|u| match u {
+                Update::Insert { relid: 1, v } => *v,
+                u => panic!("unexpected update {:?}", u),
+            })
+            .collect::<Vec<_>>();
+        values.sort_unstable();
+        assert_eq!(values, vec![1, 2, 3]);
+
+        // values already part of the union are not emitted again
+        insert(&mut left, &[3, 4]);
+        let received = mock.lock().unwrap().received_updates.clone();
+        assert_eq!(received.len(), 4);
+        assert!(matches!(received[3], Update::Insert { relid: 1, v: 4 }));
+
+        // a value is only deleted once it is absent from both
+        let deletes = vec![Update::DeleteValue { relid: 1, v: 2 }];
+        assert_eq!(right.on_start(), Ok(()));
+        assert_eq!(
+            right.on_updates(Box::new(deletes.clone().into_iter())),
+            Ok(())
+        );
+        assert_eq!(right.on_commit(), Ok(()));
+        assert_eq!(mock.lock().unwrap().received_updates.len(), 4);
+
+        assert_eq!(left.on_start(), Ok(()));
+        assert_eq!(left.on_updates(Box::new(deletes.into_iter())), Ok(()));
+        assert_eq!(left.on_commit(), Ok(()));
+        let received = mock.lock().unwrap().received_updates.clone();
+        assert_eq!(received.len(), 5);
+        assert!(matches!(
+            received[4],
+            Update::DeleteValue { relid: 1, v: 2 }
+        ));
+
+        let mut expected = left.get_current_state()[&1].clone();
+        expected.extend(right.get_current_state()[&1].iter().cloned());
+        assert_eq!(expected, vec![1, 3, 4].into_iter().collect::<HashSet<_>>());
+
+        assert!(union.unsubscribe(&subscription).is_some());
+        insert(&mut right, &[5]);
+        assert_eq!(mock.lock().unwrap().received_updates.len(), 5);
+    }
+}
@@ -0,0 +1,288 @@
+//! A write-ahead log of the transactions an observer receives, for
+//! recovering the state of an accumulator after a crash by replaying
+//! the log.
+//!
+//! Each committed transaction is appended to the log as a single
+//! record: its length as a little endian `u64`, followed by the
+//! events of the transaction, serialized by a `SnapshotCodec`.
+
+use std::convert::TryInto;
+use std::fmt::Debug;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Read;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::path::Path;
+
+use log::trace;
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use uid::Id;
+
+use differential_datalog::program::Update;
+
+use crate::accumulate::BincodeCodec;
+use crate::accumulate::RecordedEvent;
+use crate::accumulate::SnapshotCodec;
+use crate::Observer;
+
+/// The size of the header of a record, holding the length of its
+/// payload.
+const HEADER_SIZE: usize = 8;
+
+/// An observer appending every transaction it receives to a log file,
+/// which is synced to disk before the commit is acknowledged. Records
+/// are serialized by the codec `C`.
+#[derive(Debug)]
+pub struct WalObserver<V, E, C = BincodeCodec> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The log file.
+    file: File,
+    /// The updates of the transaction in progress, if any.
+    transaction: Option<Vec<Update<V>>>,
+    _phantom: PhantomData<(E, C)>,
+}
+
+impl<V, E> WalObserver<V, E>
+where
+    Update<V>: Serialize,
+{
+    /// Create a new `WalObserver` appending to the log file at the given
+    /// path, which is created if it does not exist, serializing records
+    /// with bincode.
+    pub fn new<P>(path: P) -> Result<Self, String>
+    where
+        P: AsRef<Path>,
+    {
+        Self::with_codec(path)
+    }
+}
+
+impl<V, E, C> WalObserver<V, E, C>
+where
+    Update<V>: Serialize,
+    C: SnapshotCodec,
+{
+    /// Create a new `WalObserver` appending to the log file at the given
+    /// path, which is created if it does not exist, serializing records
+    /// with the codec `C`. The log has to be read back via
+    /// `recover_with_codec` using the same codec.
+    pub fn with_codec<P>(path: P) -> Result<Self, String>
+    where
+        P: AsRef<Path>,
+    {
+        let id = Id::<()>::new().get();
+        trace!("WalObserver({})::new({})", id, path.as_ref().display());
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path.as_ref())
+            .map_err(|e| format!("failed to open log {}: {}", path.as_ref().display(), e))?;
+
+        Ok(Self {
+            id,
+            file,
+            transaction: None,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Append a record comprising the given events to the log and sync
+    /// it to disk.
+    fn append(&mut self, events: &[RecordedEvent<V>]) -> Result<(), String> {
+        let mut payload = Vec::new();
+        C::encode(&mut payload, events)
+            .map_err(|e| format!("failed to serialize log record: {}", e))?;
+        let mut record = Vec::with_capacity(HEADER_SIZE + payload.len());
+        record.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        record.extend_from_slice(&payload);
+
+        self.file
+            .write_all(&record)
+            .and_then(|_| self.file.sync_data())
+            .map_err(|e| format!("failed to append log record: {}", e))
+    }
+}
+
+impl<V, E, C> Observer<Update<V>, E> for WalObserver<V, E, C>
+where
+    V: Debug + Send,
+    E: Debug + Send + From<String>,
+    C: SnapshotCodec + Send,
+    Update<V>: Serialize,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("WalObserver({})::on_start", self.id);
+        self.transaction = Some(Vec::new());
+        Ok(())
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("WalObserver({})::on_commit", self.id);
+        let updates = self
+            .transaction
+            .take()
+            .expect("on_commit was not preceded by an on_start event");
+        let mut events = vec![RecordedEvent::Start];
+        if !updates.is_empty() {
+            events.push(RecordedEvent::Updates(updates));
+        }
+        events.push(RecordedEvent::Commit);
+        self.append(&events).map_err(E::from)
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("WalObserver({})::on_updates", self.id);
+        self.transaction
+            .as_mut()
+            .expect("on_updates was not preceded by an on_start event")
+            .extend(updates);
+        Ok(())
+    }
+
+    /// Discards the transaction in progress, which never reaches the
+    /// log.
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("WalObserver({})::on_abort", self.id);
+        let _ = self.transaction.take();
+        Ok(())
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("WalObserver({})::on_completed", self.id);
+        let _ = self.transaction.take();
+        self.append(&[RecordedEvent::Completed]).map_err(E::from)
+    }
+}
+
+/// Decode the record at the start of the given bytes, returning its
+/// size along with its events, unless it is incomplete or corrupted.
+fn read_record<C, V>(bytes: &[u8]) -> Option<(usize, Vec<RecordedEvent<V>>)>
+where
+    C: SnapshotCodec,
+    Update<V>: DeserializeOwned,
+{
+    let header = bytes.get(..HEADER_SIZE)?;
+    let len = u64::from_le_bytes(header.try_into().unwrap()) as usize;
+    let payload = bytes.get(HEADER_SIZE..HEADER_SIZE.checked_add(len)?)?;
+    let events = C::decode(payload).ok()?;
+    Some((HEADER_SIZE + len, events))
+}
+
+/// Read back the events logged by a `WalObserver` to the log file at
+/// the given path, e.g., to `replay` them after a crash.
+///
+/// A torn record at the end of the log, as left behind by a crash
+/// while appending it, is discarded and truncated from the file, so
+/// that the log can be appended to again.
+pub fn recover<V, P>(path: P) -> Result<Vec<RecordedEvent<V>>, String>
+where
+    P: AsRef<Path>,
+    Update<V>: DeserializeOwned,
+{
+    recover_with_codec::<BincodeCodec, V, P>(path)
+}
+
+/// Read back the events logged by a `WalObserver` created via
+/// `with_codec` to the log file at the given path, as `recover` does.
+/// The codec `C` has to match the one the log was written with.
+pub fn recover_with_codec<C, V, P>(path: P) -> Result<Vec<RecordedEvent<V>>, String>
+where
+    C: SnapshotCodec,
+    P: AsRef<Path>,
+    Update<V>: DeserializeOwned,
+{
+    let path = path.as_ref();
+    trace!("recover_with_codec({})", path.display());
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map_err(|e| format!("failed to open log {}: {}", path.display(), e))?;
+    let mut bytes = Vec::new();
+    let _ = file
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("failed to read log {}: {}", path.display(), e))?;
+
+    let mut events = Vec::new();
+    let mut offset = 0;
+    while let Some((len, record)) = read_record::<C, V>(&bytes[offset..]) {
+        events.extend(record);
+        offset += len;
+    }
+
+    if offset < bytes.len() {
+        warn!(
+            "discarding {} bytes of torn record at the end of log {}",
+            bytes.len() - offset,
+            path.display()
+        );
+        file.set_len(offset as u64)
+            .map_err(|e| format!("failed to truncate log {}: {}", path.display(), e))?;
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::metadata;
+
+    use tempfile::NamedTempFile;
+
+    use crate::accumulate::accumulator::tests::get_usize_updates_1;
+    use crate::accumulate::accumulator::tests::get_usize_updates_3;
+    use crate::accumulate::replay;
+    use crate::Accumulator;
+    use crate::DistributingAccumulator;
+    use crate::Observable;
+
+    /// Test that the transactions logged before a crash are recovered,
+    /// while a torn transaction at the end of the log is discarded.
+    #[test]
+    fn recover_truncated() {
+        let log = NamedTempFile::new().unwrap();
+        let wal = WalObserver::<usize, String>::new(log.path()).unwrap();
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, String>::new();
+        assert!(accumulator.subscribe(Box::new(wal)).is_ok());
+
+        for updates in [get_usize_updates_1(), get_usize_updates_3()] {
+            assert_eq!(accumulator.on_start(), Ok(()));
+            assert_eq!(accumulator.on_updates(updates), Ok(()));
+            assert_eq!(accumulator.on_commit(), Ok(()));
+        }
+        let intact = metadata(log.path()).unwrap().len();
+
+        let updates = vec![Update::DeleteValue { relid: 1, v: 1 }];
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(updates.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        // simulate a crash while appending the third transaction
+        let file = OpenOptions::new().write(true).open(log.path()).unwrap();
+        file.set_len(metadata(log.path()).unwrap().len() - 3)
+            .unwrap();
+
+        let events = recover::<usize, _>(log.path()).unwrap();
+        assert_eq!(events.len(), 6);
+        assert_eq!(metadata(log.path()).unwrap().len(), intact);
+
+        let mut recovered = DistributingAccumulator::<Update<usize>, usize, String>::new();
+        assert_eq!(replay(&events, &mut recovered), Ok(()));
+        let state = recovered.get_current_state();
+        assert_eq!(state[&1].len(), 1);
+        assert_eq!(state[&4].len(), 4);
+    }
+}
@@ -66,8 +66,65 @@ pub mod sources;
 /// A module providing functionality for using d3log with ZooKeeper.
 pub mod zookeeper;
 
+pub use accumulate::recover;
+pub use accumulate::recover_with_codec;
+pub use accumulate::replay;
+pub use accumulate::AbsentValueError;
+pub use accumulate::AccumulatingObserver;
+pub use accumulate::Accumulator;
+pub use accumulate::AccumulatorBuilder;
+pub use accumulate::AccumulatorError;
+pub use accumulate::AccumulatorSnapshot;
+pub use accumulate::AckHandle;
+pub use accumulate::ApproxSize;
+pub use accumulate::BincodeCodec;
+pub use accumulate::BoundedDistributingAccumulator;
+#[cfg(feature = "cbor")]
+pub use accumulate::CborCodec;
+#[cfg(feature = "tokio")]
+pub use accumulate::ChannelEvent;
+#[cfg(feature = "tokio")]
+pub use accumulate::ChannelObservable;
+#[cfg(feature = "tokio")]
+pub use accumulate::ChannelObserver;
+pub use accumulate::ConcurrentInput;
+pub use accumulate::DistributingAccumulator;
+pub use accumulate::DuplicateError;
+pub use accumulate::DuplicatePolicy;
+pub use accumulate::EnrichedUpdate;
+pub use accumulate::EnrichingObservable;
+pub use accumulate::EvictionPolicy;
+pub use accumulate::JsonCodec;
+pub use accumulate::LatchObservable;
+pub use accumulate::MapObservable;
+pub use accumulate::MergeDedupeObserver;
+pub use accumulate::MergeDedupePort;
+pub use accumulate::MergingAccumulator;
+pub use accumulate::OverflowPolicy;
+pub use accumulate::PartitionedAccumulator;
+pub use accumulate::ProtocolPolicy;
+pub use accumulate::ProtocolViolation;
+pub use accumulate::RateLimitedObservable;
+pub use accumulate::RecordedEvent;
+pub use accumulate::RecordingObserver;
+pub use accumulate::RelIdMapObservable;
+pub use accumulate::RelIdMapObserver;
+pub use accumulate::RelStats;
+pub use accumulate::RelationRegistry;
+pub use accumulate::ReplayingObservable;
+pub use accumulate::RetryPolicy;
+pub use accumulate::SchemaGuardObserver;
+pub use accumulate::SnapshotCodec;
+pub use accumulate::StateHandle;
+pub use accumulate::StreamConfig;
+pub use accumulate::SubStats;
+pub use accumulate::UnionObservable;
+pub use accumulate::UnknownRelation;
+pub use accumulate::WalObserver;
+pub use accumulate::WouldBlock;
 pub use instantiate::instantiate;
 pub use instantiate::Realization;
+pub use observe::CallbackObserver;
 pub use observe::Observable;
 pub use observe::ObservableBox;
 pub use observe::Observer;
@@ -89,6 +146,9 @@ pub use schema::SysCfg;
 pub use server::DDlogServer;
 pub use tcp_channel::TcpReceiver;
 pub use tcp_channel::TcpSender;
+pub use txnmux::SourceId;
+pub use txnmux::SourcePort;
+pub use txnmux::SourceTaggingMux;
 pub use txnmux::TxnMux;
 
 #[cfg(any(test, feature = "test"))]
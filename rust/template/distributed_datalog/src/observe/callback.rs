@@ -0,0 +1,150 @@
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::marker::PhantomData;
+
+use crate::observe::observer::Observer;
+
+/// A boxed up closure invoked on a lifecycle event.
+type Callback = Box<dyn FnMut() + Send>;
+
+/// An `Observer` invoking user supplied closures, useful for ad-hoc
+/// consumers that do not warrant a dedicated type.
+///
+/// Batches of updates are passed to the closure provided at
+/// construction time; lifecycle events are ignored unless closures for
+/// them are registered.
+pub struct CallbackObserver<F, T, E> {
+    /// The closure invoked with each batch of updates.
+    on_updates: F,
+    /// The closure invoked on `on_start`, if any.
+    on_start: Option<Callback>,
+    /// The closure invoked on `on_commit`, if any.
+    on_commit: Option<Callback>,
+    /// The closure invoked on `on_completed`, if any.
+    on_completed: Option<Callback>,
+    _phantom: PhantomData<(T, E)>,
+}
+
+impl<F, T, E> CallbackObserver<F, T, E>
+where
+    F: for<'a> FnMut(Box<dyn Iterator<Item = T> + 'a>),
+{
+    /// Create a new `CallbackObserver` invoking `on_updates` with each
+    /// batch of updates.
+    pub fn new(on_updates: F) -> Self {
+        Self {
+            on_updates,
+            on_start: None,
+            on_commit: None,
+            on_completed: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Invoke the given closure on every `on_start` event.
+    pub fn with_start<G>(mut self, on_start: G) -> Self
+    where
+        G: FnMut() + Send + 'static,
+    {
+        self.on_start = Some(Box::new(on_start));
+        self
+    }
+
+    /// Invoke the given closure on every `on_commit` event.
+    pub fn with_commit<G>(mut self, on_commit: G) -> Self
+    where
+        G: FnMut() + Send + 'static,
+    {
+        self.on_commit = Some(Box::new(on_commit));
+        self
+    }
+
+    /// Invoke the given closure on the `on_completed` event.
+    pub fn with_completed<G>(mut self, on_completed: G) -> Self
+    where
+        G: FnMut() + Send + 'static,
+    {
+        self.on_completed = Some(Box::new(on_completed));
+        self
+    }
+}
+
+// Manual implementation of `Debug` because closures are not debug
+// printable.
+impl<F, T, E> Debug for CallbackObserver<F, T, E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("CallbackObserver")
+            .field("on_start", &self.on_start.is_some())
+            .field("on_commit", &self.on_commit.is_some())
+            .field("on_completed", &self.on_completed.is_some())
+            .finish()
+    }
+}
+
+impl<F, T, E> Observer<T, E> for CallbackObserver<F, T, E>
+where
+    F: for<'a> FnMut(Box<dyn Iterator<Item = T> + 'a>) + Send,
+    T: Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        if let Some(on_start) = &mut self.on_start {
+            on_start()
+        }
+        Ok(())
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        if let Some(on_commit) = &mut self.on_commit {
+            on_commit()
+        }
+        Ok(())
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        (self.on_updates)(updates);
+        Ok(())
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        if let Some(on_completed) = &mut self.on_completed {
+            on_completed()
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    /// Test that a `CallbackObserver` invokes the provided closures.
+    #[test]
+    fn count_updates() {
+        let count = Arc::new(Mutex::new(0));
+        let commits = Arc::new(Mutex::new(0));
+        let observer = {
+            let count = count.clone();
+            let commits = commits.clone();
+            CallbackObserver::new(move |batch| *count.lock().unwrap() += batch.count())
+                .with_commit(move || *commits.lock().unwrap() += 1)
+        };
+        let observer = &mut Box::new(observer) as &mut dyn Observer<usize, ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![1, 3, 2].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_updates(Box::new(vec![4].into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(observer.on_completed(), Ok(()));
+
+        assert_eq!(*count.lock().unwrap(), 4);
+        assert_eq!(*commits.lock().unwrap(), 1);
+    }
+}
@@ -1,10 +1,12 @@
 //! A publish-subscribe infrastructure for differential-datalog programs.
 
+mod callback;
 mod observable;
 mod observer;
 #[cfg(any(test, feature = "test"))]
 mod test;
 
+pub use callback::CallbackObserver;
 pub use observable::Observable;
 pub use observable::ObservableAny;
 pub use observable::ObservableBox;
@@ -16,6 +16,18 @@ where
     /// Observable.
     fn on_start(&mut self) -> Result<(), E>;
 
+    /// Action to perform before data starts coming in from the
+    /// Observable, for a transaction identified by the given sequence
+    /// number, e.g., to correlate updates across a topology. Sequence
+    /// numbers are assigned by accumulators in increasing order, from
+    /// one, to the transactions they forward.
+    ///
+    /// The default implementation ignores the sequence number and
+    /// performs `on_start`.
+    fn on_start_seq(&mut self, _seq: u64) -> Result<(), E> {
+        self.on_start()
+    }
+
     /// Action to perform when a series of incoming data from the
     /// Observable is committed.
     fn on_commit(&mut self) -> Result<(), E>;
@@ -23,6 +35,37 @@ where
     /// Process a series of incoming items.
     fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E>;
 
+    /// Action to perform when the transaction in progress, i.e., the
+    /// data that came in since the last `on_start`, is rolled back by
+    /// the `Observable` instead of being committed.
+    ///
+    /// The default implementation does nothing, for observers without
+    /// any state associated with a transaction.
+    fn on_abort(&mut self) -> Result<(), E> {
+        Ok(())
+    }
+
+    /// Block until all events received so far were processed, including
+    /// by observers they are handed to asynchronously, e.g., to make sure
+    /// everything sent downstream was consumed before a checkpoint.
+    ///
+    /// The default implementation returns immediately, for observers
+    /// processing events synchronously.
+    fn on_barrier(&mut self) -> Result<(), E> {
+        Ok(())
+    }
+
+    /// Action to perform once the transaction replaying the state
+    /// accumulated before the subscription was received, marking the
+    /// boundary to the live transactions, e.g., to switch from bulk
+    /// loading to streaming. Invoked even if there was no state to
+    /// replay, but not for subscriptions without a replay.
+    ///
+    /// The default implementation does nothing.
+    fn on_init_complete(&mut self) -> Result<(), E> {
+        Ok(())
+    }
+
     /// Action to perform when the `Observable` is about to shut down.
     ///
     /// This method is typically used to clean up any state associated
@@ -43,6 +86,10 @@ where
         self.deref_mut().on_start()
     }
 
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        self.deref_mut().on_start_seq(seq)
+    }
+
     fn on_commit(&mut self) -> Result<(), E> {
         self.deref_mut().on_commit()
     }
@@ -51,6 +98,18 @@ where
         self.deref_mut().on_updates(updates)
     }
 
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.deref_mut().on_abort()
+    }
+
+    fn on_barrier(&mut self) -> Result<(), E> {
+        self.deref_mut().on_barrier()
+    }
+
+    fn on_init_complete(&mut self) -> Result<(), E> {
+        self.deref_mut().on_init_complete()
+    }
+
     fn on_completed(&mut self) -> Result<(), E> {
         self.deref_mut().on_completed()
     }
@@ -69,6 +128,10 @@ where
         self.lock().unwrap().on_start()
     }
 
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        self.lock().unwrap().on_start_seq(seq)
+    }
+
     fn on_commit(&mut self) -> Result<(), E> {
         self.lock().unwrap().on_commit()
     }
@@ -77,6 +140,18 @@ where
         self.lock().unwrap().on_updates(updates)
     }
 
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.lock().unwrap().on_abort()
+    }
+
+    fn on_barrier(&mut self) -> Result<(), E> {
+        self.lock().unwrap().on_barrier()
+    }
+
+    fn on_init_complete(&mut self) -> Result<(), E> {
+        self.lock().unwrap().on_init_complete()
+    }
+
     fn on_completed(&mut self) -> Result<(), E> {
         self.lock().unwrap().on_completed()
     }
@@ -96,6 +171,10 @@ where
         self.as_mut().map_or(Ok(()), Observer::on_start)
     }
 
+    fn on_start_seq(&mut self, seq: u64) -> Result<(), E> {
+        self.as_mut().map_or(Ok(()), |o| o.on_start_seq(seq))
+    }
+
     fn on_commit(&mut self) -> Result<(), E> {
         self.as_mut().map_or(Ok(()), Observer::on_commit)
     }
@@ -104,6 +183,18 @@ where
         self.as_mut().map_or(Ok(()), |o| o.on_updates(updates))
     }
 
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.as_mut().map_or(Ok(()), Observer::on_abort)
+    }
+
+    fn on_barrier(&mut self) -> Result<(), E> {
+        self.as_mut().map_or(Ok(()), Observer::on_barrier)
+    }
+
+    fn on_init_complete(&mut self) -> Result<(), E> {
+        self.as_mut().map_or(Ok(()), Observer::on_init_complete)
+    }
+
     fn on_completed(&mut self) -> Result<(), E> {
         self.as_mut().map_or(Ok(()), Observer::on_completed)
     }
@@ -11,8 +11,12 @@ pub struct MockObserver {
     pub called_on_commit: usize,
     /// The number of updates the observer has received.
     pub called_on_updates: usize,
+    /// The number of `on_abort` calls the observer has seen.
+    pub called_on_abort: usize,
     /// The number of `on_completed` calls the observer has seen.
     pub called_on_completed: usize,
+    /// Whether to panic upon receiving updates.
+    pub panic_on_updates: bool,
 }
 
 impl MockObserver {
@@ -22,7 +26,9 @@ impl MockObserver {
             called_on_start: 0,
             called_on_commit: 0,
             called_on_updates: 0,
+            called_on_abort: 0,
             called_on_completed: 0,
+            panic_on_updates: false,
         }
     }
 }
@@ -46,10 +52,19 @@ where
 
     fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
         trace!("MockObserver::on_updates");
+        if self.panic_on_updates {
+            panic!("MockObserver panicking on updates");
+        }
         self.called_on_updates += updates.count();
         Ok(())
     }
 
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("MockObserver::on_abort");
+        self.called_on_abort += 1;
+        Ok(())
+    }
+
     fn on_completed(&mut self) -> Result<(), E> {
         trace!("MockObserver::on_completed");
         self.called_on_completed += 1;
@@ -185,6 +185,18 @@ where
         }
     }
 
+    /// Roll back the transaction started on the input deltas. Nothing
+    /// reached the outlets yet, as they only see committed changes.
+    fn on_abort(&mut self) -> Result<(), String> {
+        trace!("DDlogServer({})::on_abort", self.id);
+
+        if let Some(ref mut prog) = self.prog {
+            prog.transaction_rollback()
+        } else {
+            Ok(())
+        }
+    }
+
     fn on_completed(&mut self) -> Result<(), String> {
         trace!("DDlogServer({})::on_completed", self.id);
         println!(
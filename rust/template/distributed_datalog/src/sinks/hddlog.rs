@@ -0,0 +1,401 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+use log::trace;
+use uid::Id;
+
+use differential_datalog::ddval::DDValue;
+use differential_datalog::program::RelId;
+use differential_datalog::program::Update;
+use differential_datalog::DDlog;
+
+use crate::Observer;
+
+/// An observer feeding the updates it receives into a DDlog program,
+/// with each transaction of the observable mapped onto a transaction
+/// of the program.
+///
+/// Updates are converted into `Update<DDValue>` using the provided
+/// conversion function and are applied to the relation they refer
+/// to, unless a different relation was configured via
+/// `map_relation`. If a batch of updates cannot be converted or
+/// applied, the transaction of the program is rolled back and the
+/// error is reported; the remainder of the transaction is rejected.
+#[derive(Debug)]
+pub struct HDDlogObserver<P, V, E>
+where
+    P: DDlog,
+{
+    /// The observer's unique ID.
+    id: usize,
+    /// The program updates are fed into.
+    prog: P,
+    /// The relations of the program to apply updates to, for the
+    /// relations that do not map onto themselves.
+    relids: HashMap<RelId, RelId>,
+    /// The function used for converting values.
+    convert: fn(V) -> Result<DDValue, String>,
+    /// Whether a transaction of the program is in progress.
+    in_transaction: bool,
+    _unused: PhantomData<E>,
+}
+
+impl<P, V, E> HDDlogObserver<P, V, E>
+where
+    P: DDlog,
+{
+    /// Create a new `HDDlogObserver` feeding updates into the given
+    /// program, converting values using the given function.
+    pub fn new(prog: P, convert: fn(V) -> Result<DDValue, String>) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("HDDlogObserver({})::new", id);
+
+        Self {
+            id,
+            prog,
+            relids: HashMap::new(),
+            convert,
+            in_transaction: false,
+            _unused: PhantomData,
+        }
+    }
+
+    /// Apply the updates of relation `from` to relation `to` of the
+    /// program.
+    pub fn map_relation(mut self, from: RelId, to: RelId) -> Self {
+        let _ = self.relids.insert(from, to);
+        self
+    }
+
+    /// Retrieve a reference to the wrapped program.
+    pub fn program(&self) -> &P {
+        &self.prog
+    }
+
+    /// Retrieve the wrapped program, rolling back any transaction in
+    /// progress.
+    pub fn into_inner(mut self) -> P {
+        if self.in_transaction {
+            let _ = self.prog.transaction_rollback();
+            self.in_transaction = false;
+        }
+        self.prog
+    }
+
+    /// Convert an update into the form understood by the program.
+    fn convert_update(&self, update: Update<V>) -> Result<Update<DDValue>, String>
+    where
+        V: Debug,
+    {
+        let relid = |relid| *self.relids.get(&relid).unwrap_or(&relid);
+        let convert = |relid, v| {
+            (self.convert)(v)
+                .map_err(|e| format!("failed to convert value of relation {}: {}", relid, e))
+        };
+
+        match update {
+            Update::Insert { relid: r, v } => Ok(Update::Insert {
+                relid: relid(r),
+                v: convert(r, v)?,
+            }),
+            Update::InsertOrUpdate { relid: r, v } => Ok(Update::InsertOrUpdate {
+                relid: relid(r),
+                v: convert(r, v)?,
+            }),
+            Update::DeleteValue { relid: r, v } => Ok(Update::DeleteValue {
+                relid: relid(r),
+                v: convert(r, v)?,
+            }),
+            Update::DeleteKey { relid: r, k } => Ok(Update::DeleteKey {
+                relid: relid(r),
+                k: convert(r, k)?,
+            }),
+            update => Err(format!("unsupported update: {:?}", update)),
+        }
+    }
+}
+
+impl<P, V, E> Observer<Update<V>, E> for HDDlogObserver<P, V, E>
+where
+    P: Debug + Send + DDlog,
+    V: Debug + Send,
+    E: Debug + Send + From<String>,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("HDDlogObserver({})::on_start", self.id);
+        self.prog.transaction_start()?;
+        self.in_transaction = true;
+        Ok(())
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("HDDlogObserver({})::on_commit", self.id);
+        if !self.in_transaction {
+            return Err(E::from(
+                "no transaction to commit; it may have been rolled back".to_string(),
+            ));
+        }
+        self.in_transaction = false;
+        self.prog.transaction_commit().map_err(E::from)
+    }
+
+    /// Convert all updates before applying them, so that a conversion
+    /// error leaves the program's transaction untouched before it is
+    /// rolled back.
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("HDDlogObserver({})::on_updates", self.id);
+        if !self.in_transaction {
+            return Err(E::from(
+                "received updates outside of a transaction".to_string(),
+            ));
+        }
+
+        let result = updates
+            .map(|update| self.convert_update(update))
+            .collect::<Result<Vec<_>, _>>()
+            .and_then(|updates| self.prog.apply_valupdates(updates.into_iter()));
+
+        if let Err(e) = result {
+            let _ = self.prog.transaction_rollback();
+            self.in_transaction = false;
+            return Err(E::from(e));
+        }
+        Ok(())
+    }
+
+    /// Roll back the transaction of the program, if any.
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("HDDlogObserver({})::on_abort", self.id);
+        if self.in_transaction {
+            self.in_transaction = false;
+            self.prog.transaction_rollback()?;
+        }
+        Ok(())
+    }
+
+    /// Roll back a transaction that was started but never committed.
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("HDDlogObserver({})::on_completed", self.id);
+        if self.in_transaction {
+            self.in_transaction = false;
+            self.prog.transaction_rollback()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+    use std::collections::BTreeSet;
+    use std::ops::Deref;
+    use std::sync::Mutex;
+
+    use differential_datalog::ddval::DDValConvert;
+    use differential_datalog::program::IdxId;
+    use differential_datalog::record::Record;
+    use differential_datalog::record::UpdCmd;
+    use differential_datalog::test_value::U64;
+    use differential_datalog::Callback;
+    use differential_datalog::DDlogConvert;
+    use differential_datalog::DeltaMap;
+
+    #[derive(Debug)]
+    struct DummyConverter;
+
+    impl DDlogConvert for DummyConverter {
+        fn relid2name(rel_id: RelId) -> Option<&'static str> {
+            panic!("unexpected RelId {}", rel_id)
+        }
+
+        fn indexid2name(idx_id: IdxId) -> Option<&'static str> {
+            panic!("unexpected IdxId {}", idx_id)
+        }
+
+        fn updcmd2upd(upd_cmd: &UpdCmd) -> Result<Update<DDValue>, String> {
+            panic!("unsupported UpdCmd: {:?}", upd_cmd)
+        }
+    }
+
+    /// A minimal DDlog program with input relations only, storing the
+    /// values of each relation.
+    #[derive(Debug, Default)]
+    struct DummyProgram {
+        /// The committed values of each relation.
+        relations: Mutex<BTreeMap<RelId, BTreeSet<DDValue>>>,
+        /// The updates of the transaction in progress, if any.
+        pending: Mutex<Option<Vec<Update<DDValue>>>>,
+    }
+
+    impl DummyProgram {
+        fn relation(&self, relid: RelId) -> BTreeSet<DDValue> {
+            let relations = self.relations.lock().unwrap();
+            relations.get(&relid).cloned().unwrap_or_default()
+        }
+    }
+
+    impl DDlog for DummyProgram {
+        type Convert = DummyConverter;
+
+        fn run<F>(
+            _workers: usize,
+            _do_store: bool,
+            _cb: F,
+        ) -> Result<(Self, DeltaMap<DDValue>), String>
+        where
+            F: Callback,
+        {
+            unimplemented!()
+        }
+
+        fn transaction_start(&self) -> Result<(), String> {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.is_some() {
+                return Err("transaction already in progress".to_string());
+            }
+            *pending = Some(Vec::new());
+            Ok(())
+        }
+
+        fn transaction_commit_dump_changes(&self) -> Result<DeltaMap<DDValue>, String> {
+            unimplemented!()
+        }
+
+        fn transaction_commit(&self) -> Result<(), String> {
+            let updates = self
+                .pending
+                .lock()
+                .unwrap()
+                .take()
+                .ok_or_else(|| "no transaction in progress".to_string())?;
+
+            let mut relations = self.relations.lock().unwrap();
+            for update in updates {
+                match update {
+                    Update::Insert { relid, v } => {
+                        let _ = relations.entry(relid).or_default().insert(v);
+                    }
+                    Update::DeleteValue { relid, v } => {
+                        let _ = relations.entry(relid).or_default().remove(&v);
+                    }
+                    update => return Err(format!("unsupported update: {:?}", update)),
+                }
+            }
+            Ok(())
+        }
+
+        fn transaction_rollback(&self) -> Result<(), String> {
+            let _ = self.pending.lock().unwrap().take();
+            Ok(())
+        }
+
+        fn apply_updates<V, I>(&self, _upds: I) -> Result<(), String>
+        where
+            V: Deref<Target = UpdCmd>,
+            I: Iterator<Item = V>,
+        {
+            unimplemented!()
+        }
+
+        fn apply_valupdates<I>(&self, upds: I) -> Result<(), String>
+        where
+            I: Iterator<Item = Update<DDValue>>,
+        {
+            self.pending
+                .lock()
+                .unwrap()
+                .as_mut()
+                .ok_or_else(|| "no transaction in progress".to_string())?
+                .extend(upds);
+            Ok(())
+        }
+
+        fn query_index(&self, _index: IdxId, _key: DDValue) -> Result<BTreeSet<DDValue>, String> {
+            unimplemented!()
+        }
+
+        fn query_index_rec(
+            &self,
+            _index: IdxId,
+            _key: &Record,
+        ) -> Result<BTreeSet<DDValue>, String> {
+            unimplemented!()
+        }
+
+        fn dump_index(&self, _index: IdxId) -> Result<BTreeSet<DDValue>, String> {
+            unimplemented!()
+        }
+
+        fn stop(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    /// Convert odd values, fail for even ones.
+    fn convert_odd(v: u64) -> Result<DDValue, String> {
+        if v % 2 == 1 {
+            Ok(U64(v).into_ddvalue())
+        } else {
+            Err(format!("{} is even", v))
+        }
+    }
+
+    /// Test that updates are applied to the (mapped) relations of the
+    /// program transactionally.
+    #[test]
+    fn updates_land_in_relations() {
+        let mut observer =
+            HDDlogObserver::<_, _, String>::new(DummyProgram::default(), convert_odd)
+                .map_relation(2, 5);
+
+        let updates = vec![
+            Update::Insert { relid: 1, v: 1 },
+            Update::Insert { relid: 1, v: 3 },
+            Update::Insert { relid: 2, v: 5 },
+        ];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert!(observer.program().relation(1).is_empty());
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let expected = vec![U64(1).into_ddvalue(), U64(3).into_ddvalue()];
+        assert_eq!(
+            observer.program().relation(1),
+            expected.into_iter().collect()
+        );
+        assert!(observer.program().relation(2).is_empty());
+        assert_eq!(observer.program().relation(5).len(), 1);
+
+        // A conversion error rolls back the entire transaction.
+        let updates = vec![
+            Update::DeleteValue { relid: 1, v: 1 },
+            Update::Insert { relid: 1, v: 4 },
+        ];
+        assert_eq!(observer.on_start(), Ok(()));
+        let result = observer.on_updates(Box::new(updates.into_iter()));
+        assert_eq!(
+            result,
+            Err("failed to convert value of relation 1: 4 is even".to_string())
+        );
+        assert!(observer.on_commit().is_err());
+        assert_eq!(observer.program().relation(1).len(), 2);
+
+        let updates = vec![Update::DeleteValue { relid: 1, v: 1 }];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(observer.on_completed(), Ok(()));
+
+        let program = observer.into_inner();
+        assert_eq!(
+            program.relation(1),
+            vec![U64(3).into_ddvalue()].into_iter().collect()
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use std::fmt::Debug;
+use std::io::Write;
+use std::marker::PhantomData;
+
+use log::trace;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::to_writer;
+use uid::Id;
+
+use differential_datalog::program::RelId;
+use differential_datalog::program::Update;
+
+use crate::Observer;
+
+/// The operation a `JsonRecord` describes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonOp {
+    /// The insertion of a value.
+    Insert,
+    /// The deletion of a value.
+    Delete,
+    /// The start of a transaction.
+    Start,
+    /// The commit of a transaction.
+    Commit,
+    /// The abort of a transaction, revoking the insertions and
+    /// deletions written since its start.
+    Abort,
+    /// The completion of the observable.
+    Completed,
+}
+
+/// A single line emitted by a `JsonObserver`. The relation and the
+/// value are only present for insertions and deletions.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct JsonRecord<V> {
+    /// The operation described.
+    pub op: JsonOp,
+    /// The relation of the value inserted or deleted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relid: Option<RelId>,
+    /// The value inserted or deleted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<V>,
+}
+
+/// An object implementing the `Observer` interface and writing the
+/// updates it receives to a writer as newline delimited JSON, i.e.,
+/// one `JsonRecord` per line, e.g., for processing them with external
+/// tooling.
+#[derive(Debug)]
+pub struct JsonObserver<V, E, W> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The writer we write the records to.
+    writer: W,
+    /// Whether to emit records for the start and commit of
+    /// transactions and the completion of the observable.
+    lifecycle: bool,
+    /// Unused phantom data.
+    _unused: PhantomData<(V, E)>,
+}
+
+impl<V, E, W> JsonObserver<V, E, W>
+where
+    V: Serialize,
+    W: Write,
+{
+    /// Create a new `JsonObserver` writing to the given writer. Records
+    /// for lifecycle events, i.e., the start and commit of transactions
+    /// and the completion of the observable, are only emitted if
+    /// `lifecycle` is set.
+    pub fn new(writer: W, lifecycle: bool) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("JsonObserver({})::new", id);
+
+        Self {
+            id,
+            writer,
+            lifecycle,
+            _unused: PhantomData,
+        }
+    }
+
+    /// Retrieve a reference to the writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Write a single record, terminated by a newline.
+    fn write(&mut self, record: &JsonRecord<&V>) -> Result<(), String> {
+        to_writer(&mut self.writer, record)
+            .map_err(|e| format!("failed to serialize record: {}", e))?;
+        self.writer
+            .write_all(b"\n")
+            .map_err(|e| format!("failed to write record: {}", e))
+    }
+
+    /// Write a record for a lifecycle event, if so configured.
+    fn write_lifecycle(&mut self, op: JsonOp) -> Result<(), String> {
+        if !self.lifecycle {
+            return Ok(());
+        }
+        self.write(&JsonRecord {
+            op,
+            relid: None,
+            value: None,
+        })
+    }
+}
+
+impl<V, E, W> Observer<Update<V>, E> for JsonObserver<V, E, W>
+where
+    V: Debug + Send + Serialize,
+    E: Debug + Send + From<String>,
+    W: Debug + Send + Write,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("JsonObserver({})::on_start", self.id);
+        self.write_lifecycle(JsonOp::Start).map_err(E::from)
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("JsonObserver({})::on_commit", self.id);
+        self.write_lifecycle(JsonOp::Commit)
+            .and_then(|_| {
+                self.writer
+                    .flush()
+                    .map_err(|e| format!("failed to flush records: {}", e))
+            })
+            .map_err(E::from)
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("JsonObserver({})::on_updates", self.id);
+        for update in updates {
+            let (op, relid, value) = match &update {
+                Update::Insert { relid, v } => (JsonOp::Insert, relid, v),
+                Update::DeleteValue { relid, v } => (JsonOp::Delete, relid, v),
+                update => return Err(E::from(format!("unsupported update {:?}", update))),
+            };
+            self.write(&JsonRecord {
+                op,
+                relid: Some(*relid),
+                value: Some(value),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Writes an abort record even if lifecycle records are not
+    /// emitted, as the updates of the aborted transaction were already
+    /// written.
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("JsonObserver({})::on_abort", self.id);
+        self.write(&JsonRecord {
+            op: JsonOp::Abort,
+            relid: None,
+            value: None,
+        })
+        .map_err(E::from)
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("JsonObserver({})::on_completed", self.id);
+        self.write_lifecycle(JsonOp::Completed)
+            .and_then(|_| {
+                self.writer
+                    .flush()
+                    .map_err(|e| format!("failed to flush records: {}", e))
+            })
+            .map_err(E::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use serde_json::from_str;
+
+    use crate::Accumulator;
+    use crate::DistributingAccumulator;
+    use crate::Observable;
+
+    /// Test that the records written by a `JsonObserver` parse back to
+    /// the updates it received.
+    #[test]
+    fn ndjson_round_trip() {
+        let mut accumulator = DistributingAccumulator::<Update<usize>, usize, String>::new();
+        let json = Arc::new(Mutex::new(JsonObserver::<usize, String, _>::new(
+            Vec::new(),
+            true,
+        )));
+        assert!(accumulator.subscribe(Box::new(json.clone())).is_ok());
+
+        let updates = vec![
+            Update::Insert { relid: 1, v: 42 },
+            Update::Insert { relid: 2, v: 7 },
+            Update::DeleteValue { relid: 1, v: 42 },
+        ];
+        assert_eq!(accumulator.on_start(), Ok(()));
+        assert_eq!(
+            accumulator.on_updates(Box::new(updates.into_iter())),
+            Ok(())
+        );
+        assert_eq!(accumulator.on_commit(), Ok(()));
+
+        let output = String::from_utf8(json.lock().unwrap().get_ref().clone()).unwrap();
+        let records = output
+            .lines()
+            .map(|line| from_str::<JsonRecord<usize>>(line).unwrap())
+            .collect::<Vec<_>>();
+        let record = |op, relid, value| JsonRecord { op, relid, value };
+        assert_eq!(
+            records,
+            vec![
+                record(JsonOp::Start, None, None),
+                record(JsonOp::Insert, Some(1), Some(42)),
+                record(JsonOp::Insert, Some(2), Some(7)),
+                record(JsonOp::Delete, Some(1), Some(42)),
+                record(JsonOp::Commit, None, None),
+            ]
+        );
+    }
+}
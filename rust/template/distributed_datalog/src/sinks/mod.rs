@@ -1,5 +1,11 @@
 //! Various sinks for forwarding data from a distributed computation.
 
 mod file;
+mod hddlog;
+mod json;
 
 pub use file::File;
+pub use hddlog::HDDlogObserver;
+pub use json::JsonObserver;
+pub use json::JsonOp;
+pub use json::JsonRecord;
@@ -0,0 +1,227 @@
+use std::fmt::Debug;
+use std::io::BufRead;
+use std::mem::take;
+
+use log::trace;
+use serde::de::DeserializeOwned;
+use serde_json::from_str;
+use uid::Id;
+
+use differential_datalog::program::Update;
+
+use crate::sinks::JsonOp;
+use crate::sinks::JsonRecord;
+use crate::Observable;
+use crate::Observer;
+use crate::ObserverBox;
+
+/// Forward the given updates to the observer as a single transaction,
+/// unless there are none.
+fn flush<V, E>(
+    observer: &mut dyn Observer<Update<V>, E>,
+    updates: &mut Vec<Update<V>>,
+) -> Result<(), E>
+where
+    V: Send,
+    E: Send,
+{
+    if updates.is_empty() {
+        return Ok(());
+    }
+    observer.on_start()?;
+    observer.on_updates(Box::new(take(updates).into_iter()))?;
+    observer.on_commit()
+}
+
+/// An object adapting a reader providing newline delimited JSON, as
+/// written by a `JsonObserver`, to the `Observable` interface, e.g., to
+/// replay previously recorded updates into an accumulator.
+///
+/// The records read are forwarded to the subscriber once `replay` is
+/// invoked. Insertions and deletions are grouped into a transaction
+/// that ends with a commit record or at the end of the input, while
+/// an abort record discards the updates read since the last commit,
+/// start records are ignored and a completion record completes the
+/// subscriber.
+#[derive(Debug)]
+pub struct JsonSource<V, E, R> {
+    /// The source's unique ID.
+    id: usize,
+    /// The reader we read the records from.
+    reader: R,
+    /// The subscribed observer, if any.
+    observer: Option<ObserverBox<Update<V>, E>>,
+}
+
+impl<V, E, R> JsonSource<V, E, R>
+where
+    V: DeserializeOwned + Debug + Send,
+    E: Debug + Send + From<String>,
+    R: BufRead,
+{
+    /// Create a new `JsonSource` reading from the given reader.
+    pub fn new(reader: R) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("JsonSource({})::new", id);
+
+        Self {
+            id,
+            reader,
+            observer: None,
+        }
+    }
+
+    /// Read all remaining records and forward them to the subscribed
+    /// observer, if any.
+    ///
+    /// Reading stops at the first line that cannot be read or parsed,
+    /// discarding the updates of the transaction it is part of, just
+    /// like an abort record does.
+    pub fn replay(&mut self) -> Result<(), E> {
+        trace!("JsonSource({})::replay", self.id);
+
+        let observer = match &mut self.observer {
+            Some(observer) => observer,
+            None => return Ok(()),
+        };
+
+        let mut updates = Vec::new();
+        for (index, line) in (&mut self.reader).lines().enumerate() {
+            let record = line
+                .map_err(|e| format!("failed to read line {}: {}", index + 1, e))
+                .and_then(|line| {
+                    if line.trim().is_empty() {
+                        Ok(None)
+                    } else {
+                        from_str::<JsonRecord<V>>(&line)
+                            .map(Some)
+                            .map_err(|e| format!("malformed record on line {}: {}", index + 1, e))
+                    }
+                });
+            let record = match record {
+                Ok(Some(record)) => record,
+                Ok(None) => continue,
+                Err(e) => return Err(E::from(e)),
+            };
+
+            match (record.op, record.relid, record.value) {
+                (JsonOp::Insert, Some(relid), Some(v)) => updates.push(Update::Insert { relid, v }),
+                (JsonOp::Delete, Some(relid), Some(v)) => {
+                    updates.push(Update::DeleteValue { relid, v })
+                }
+                (JsonOp::Insert, ..) | (JsonOp::Delete, ..) => {
+                    return Err(E::from(format!(
+                        "record on line {} lacks a relation or value",
+                        index + 1
+                    )));
+                }
+                (JsonOp::Start, ..) => (),
+                (JsonOp::Commit, ..) => flush(observer.as_mut(), &mut updates)?,
+                (JsonOp::Abort, ..) => updates.clear(),
+                (JsonOp::Completed, ..) => {
+                    flush(observer.as_mut(), &mut updates)?;
+                    observer.on_completed()?;
+                }
+            }
+        }
+        flush(observer.as_mut(), &mut updates)
+    }
+}
+
+impl<V, E, R> Observable<Update<V>, E> for JsonSource<V, E, R>
+where
+    V: Debug + Send,
+    E: Debug + Send,
+    R: Debug + Send,
+{
+    type Subscription = ();
+
+    fn subscribe(
+        &mut self,
+        observer: ObserverBox<Update<V>, E>,
+    ) -> Result<Self::Subscription, ObserverBox<Update<V>, E>> {
+        trace!("JsonSource({})::subscribe", self.id);
+
+        if self.observer.is_some() {
+            Err(observer)
+        } else {
+            self.observer = Some(observer);
+            Ok(())
+        }
+    }
+
+    fn unsubscribe(
+        &mut self,
+        _subscription: &Self::Subscription,
+    ) -> Option<ObserverBox<Update<V>, E>> {
+        trace!("JsonSource({})::unsubscribe", self.id);
+        self.observer.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use crate::accumulate::UpdatesMockObserver;
+
+    /// Test that replaying newline delimited JSON drives the subscriber
+    /// through the transactions recorded and that a malformed line is
+    /// reported as an error, discarding the unterminated transaction it
+    /// is part of.
+    #[test]
+    fn replay_ndjson() {
+        let input = br#"{"op":"start"}
+{"op":"insert","relid":1,"value":42}
+{"op":"insert","relid":2,"value":7}
+{"op":"commit"}
+{"op":"delete","relid":1,"value":42}
+
+{"op":"insert","relid":1,"value":
+"#;
+        let mut source = JsonSource::<usize, String, _>::new(&input[..]);
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(source.subscribe(Box::new(mock.clone())).is_ok());
+
+        let error = source.replay().unwrap_err();
+        assert!(error.contains("line 7"), "{}", error);
+
+        let received = mock.lock().unwrap().received_updates.clone();
+        assert_eq!(received.len(), 2);
+        assert!(matches!(received[0], Update::Insert { relid: 1, v: 42 }));
+        assert!(matches!(received[1], Update::Insert { relid: 2, v: 7 }));
+    }
+
+    /// Test that a malformed line in the middle of a transaction does
+    /// not commit the updates of the transaction read before it.
+    #[test]
+    fn replay_malformed_in_transaction() {
+        let input = br#"{"op":"start"}
+{"op":"insert","relid":1,"value":42}
+{"op":"commit"}
+{"op":"start"}
+{"op":"insert","relid":2,"value":7}
+{"op":"delete","relid":2}
+{"op":"insert","relid":3,"value":8}
+{"op":"commit"}
+"#;
+        let mut source = JsonSource::<usize, String, _>::new(&input[..]);
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        assert!(source.subscribe(Box::new(mock.clone())).is_ok());
+
+        let error = source.replay().unwrap_err();
+        assert!(error.contains("line 6"), "{}", error);
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, 1);
+        assert_eq!(mock.called_on_commit, 1);
+        assert_eq!(mock.received_updates.len(), 1);
+        assert!(matches!(
+            mock.received_updates[0],
+            Update::Insert { relid: 1, v: 42 }
+        ));
+    }
+}
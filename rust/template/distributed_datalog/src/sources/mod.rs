@@ -1,5 +1,7 @@
 //! Various sources for feeding data into a distributed computation.
 
 mod file;
+mod json;
 
 pub use file::File;
+pub use json::JsonSource;
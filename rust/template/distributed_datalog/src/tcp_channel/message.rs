@@ -15,6 +15,7 @@ pub enum Message<T> {
     UpdateList(LinkedList<Vec<T>>),
     Commit,
     Complete,
+    Abort,
 }
 
 impl<T> Display for Message<T> {
@@ -25,6 +26,7 @@ impl<T> Display for Message<T> {
             Message::UpdateList(_) => "on_updates",
             Message::Commit => "on_commit",
             Message::Complete => "on_completed",
+            Message::Abort => "on_abort",
         };
         formatter.write_str(s)
     }
@@ -86,6 +86,10 @@ where
         self.0.as_mut().map_or(Ok(()), |o| o.on_updates(updates))
     }
 
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.0.as_mut().map_or(Ok(()), |o| o.on_abort())
+    }
+
     fn on_completed(&mut self) -> Result<(), E> {
         self.0.as_mut().map_or(Ok(()), |o| o.on_completed())
     }
@@ -263,6 +267,7 @@ where
                 }
                 Message::Commit => observer.on_commit(),
                 Message::Complete => observer.on_completed(),
+                Message::Abort => observer.on_abort(),
             };
 
             if let Err(e) = result {
@@ -129,6 +129,12 @@ where
         self.buffer.lock().unwrap().on_commit()
     }
 
+    /// Signal the abort of the transaction in progress.
+    fn on_abort(&mut self) -> Result<(), String> {
+        trace!("TcpSender({})::on_abort", self.id);
+        self.buffer.lock().unwrap().on_abort()
+    }
+
     fn on_completed(&mut self) -> Result<(), String> {
         trace!("TcpSender({})::on_completed", self.id);
         self.buffer.lock().unwrap().on_completed()
@@ -169,6 +169,23 @@ where
         Ok(())
     }
 
+    /// Discard the buffered updates of the transaction in progress or
+    /// signal its abort.
+    fn on_abort(&mut self) -> Result<(), String> {
+        match self {
+            TxnBuf::Updates { ongoing, .. } => {
+                if ongoing.take().is_none() {
+                    panic!("on_abort was not preceded by an on_start event")
+                }
+            }
+            TxnBuf::Writer(writer) => {
+                Self::handle_msg(writer, &Message::<T>::Abort)?;
+                writer.flush().map_err(|e| e.to_string())?
+            }
+        }
+        Ok(())
+    }
+
     fn on_completed(&mut self) -> Result<(), String> {
         match self {
             TxnBuf::Updates { on_completed, .. } => *on_completed = true,
@@ -247,5 +264,23 @@ mod tests {
             buffer.on_start()?;
             Ok(())
         });
+
+        // an aborted transaction is discarded, allowing for a new one
+        let updates = vec![vec![3]].into_iter().collect();
+        let expected = vec![
+            Message::Start,
+            Message::UpdateList(updates),
+            Message::Commit,
+        ];
+        test(expected, |buffer| {
+            buffer.on_start()?;
+            buffer.on_updates(Box::new(vec![1, 2].into_iter()))?;
+            buffer.on_abort()?;
+
+            buffer.on_start()?;
+            buffer.on_updates(Box::new(vec![3].into_iter()))?;
+            buffer.on_commit()?;
+            Ok(())
+        });
     }
 }
@@ -83,6 +83,15 @@ where
         Ok(())
     }
 
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("CachingObserver({})::on_abort", self.id);
+
+        if self.data.take().is_none() {
+            panic!("on_abort was not preceded by an on_start event")
+        }
+        Ok(())
+    }
+
     fn on_completed(&mut self) -> Result<(), E> {
         trace!("CachingObserver({})::on_completed", self.id);
         self.observer.on_completed()
@@ -194,6 +203,135 @@ where
     }
 }
 
+/// The identifier of a source of a `SourceTaggingMux`.
+pub type SourceId = usize;
+
+/// An input port of a `SourceTaggingMux`, tagging all updates pushed
+/// into it with the port's `SourceId`.
+#[derive(Debug)]
+pub struct SourcePort<T, E> {
+    /// The ID of the source feeding this port.
+    id: SourceId,
+    /// The observer serializing our transactions with those of the
+    /// other ports of the multiplexer.
+    cacher: CachingObserver<OptionalObserver<ObserverBox<(SourceId, T), E>>, (SourceId, T)>,
+}
+
+impl<T, E> SourcePort<T, E> {
+    /// Retrieve the ID of the source feeding this port.
+    pub fn id(&self) -> SourceId {
+        self.id
+    }
+}
+
+impl<T, E> Observer<T, E> for SourcePort<T, E>
+where
+    T: Debug + Send,
+    E: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        trace!("SourcePort({})::on_start", self.id);
+        self.cacher.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        trace!("SourcePort({})::on_commit", self.id);
+        self.cacher.on_commit()
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        trace!("SourcePort({})::on_updates", self.id);
+        let id = self.id;
+        self.cacher
+            .on_updates(Box::new(updates.map(move |update| (id, update))))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("SourcePort({})::on_abort", self.id);
+        self.cacher.on_abort()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        trace!("SourcePort({})::on_completed", self.id);
+        self.cacher.on_completed()
+    }
+}
+
+/// A multiplexer for transactions that, just like `TxnMux`, serializes
+/// the transactions of multiple sources, but additionally tags each
+/// update with the ID of the source that produced it.
+#[derive(Debug, Default)]
+pub struct SourceTaggingMux<T, E>
+where
+    T: Debug + Send,
+    E: Debug + Send,
+{
+    /// The multiplexer's unique ID.
+    id: usize,
+    /// A reference to the `Observer` subscribed to us, if any.
+    observer: SharedObserver<OptionalObserver<ObserverBox<(SourceId, T), E>>>,
+}
+
+impl<T, E> SourceTaggingMux<T, E>
+where
+    T: Debug + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    /// Create a new `SourceTaggingMux`, without any sources.
+    pub fn new() -> Self {
+        let id = Id::<()>::new().get();
+        trace!("SourceTaggingMux({})::new", id);
+
+        Self {
+            id,
+            observer: SharedObserver::default(),
+        }
+    }
+
+    /// Create a new input port for a source. The port's ID is used to
+    /// tag all updates pushed into it.
+    pub fn create_observer(&mut self) -> SourcePort<T, E> {
+        let id = Id::<()>::new().get();
+        trace!("SourceTaggingMux({})::create_observer({})", self.id, id);
+
+        SourcePort {
+            id,
+            cacher: CachingObserver::new(self.observer.clone()),
+        }
+    }
+}
+
+impl<T, E> Observable<(SourceId, T), E> for SourceTaggingMux<T, E>
+where
+    T: Debug + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    type Subscription = ();
+
+    fn subscribe(
+        &mut self,
+        observer: ObserverBox<(SourceId, T), E>,
+    ) -> Result<Self::Subscription, ObserverBox<(SourceId, T), E>> {
+        trace!("SourceTaggingMux({})::subscribe", self.id);
+
+        let mut guard = self.observer.lock().unwrap();
+        if guard.is_some() {
+            Err(observer)
+        } else {
+            let _ = guard.replace(observer);
+            Ok(())
+        }
+    }
+
+    fn unsubscribe(
+        &mut self,
+        _subscription: &Self::Subscription,
+    ) -> Option<ObserverBox<(SourceId, T), E>> {
+        trace!("SourceTaggingMux({})::unsubscribe", self.id);
+        self.observer.lock().unwrap().take()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -223,4 +361,66 @@ mod tests {
         assert_eq!(mock.lock().unwrap().as_ref().unwrap().called_on_updates, 6);
         assert_eq!(mock.lock().unwrap().as_ref().unwrap().called_on_commit, 1);
     }
+
+    /// An observer recording all updates it receives.
+    #[derive(Debug, Default)]
+    struct RecordingObserver<T> {
+        transactions: usize,
+        updates: Vec<T>,
+    }
+
+    impl<T> Observer<T, ()> for RecordingObserver<T>
+    where
+        T: Debug + Send,
+    {
+        fn on_start(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), ()> {
+            self.transactions += 1;
+            Ok(())
+        }
+
+        fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), ()> {
+            self.updates.extend(updates);
+            Ok(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    /// Test that a `SourceTaggingMux` tags updates with the ID of the
+    /// source that produced them.
+    #[test]
+    fn source_tagging() {
+        let mut mux = SourceTaggingMux::<usize, ()>::new();
+        let recorder = Arc::new(Mutex::new(RecordingObserver::default()));
+        assert!(mux.subscribe(Box::new(recorder.clone())).is_ok());
+
+        let mut source1 = mux.create_observer();
+        let mut source2 = mux.create_observer();
+        assert_ne!(source1.id(), source2.id());
+
+        // interleave the transactions of both sources
+        assert_eq!(source1.on_start(), Ok(()));
+        assert_eq!(source2.on_start(), Ok(()));
+        assert_eq!(source1.on_updates(Box::new(vec![1, 2].into_iter())), Ok(()));
+        assert_eq!(source2.on_updates(Box::new(vec![3].into_iter())), Ok(()));
+        assert_eq!(source1.on_updates(Box::new(vec![4].into_iter())), Ok(()));
+        assert_eq!(source2.on_commit(), Ok(()));
+        assert_eq!(source1.on_commit(), Ok(()));
+
+        let recorder = recorder.lock().unwrap();
+        let expected = vec![
+            (source2.id(), 3),
+            (source1.id(), 1),
+            (source1.id(), 2),
+            (source1.id(), 4),
+        ];
+        assert_eq!(recorder.transactions, 2);
+        assert_eq!(recorder.updates, expected);
+    }
 }